@@ -15,7 +15,12 @@ pub(crate) struct AnalyticsQuery;
 #[juniper::graphql_object(context = Context)]
 impl AnalyticsQuery {
     async fn redirect_hits(context: &Context) -> AbacusGraphQLResult<Vec<Redirect>> {
-        rbac::verify_permissions(&context.user, &Analytics(GetRedirectHits)).await?;
+        rbac::verify_permissions(
+            &context.rbac_enforcer,
+            &context.user,
+            &Analytics(GetRedirectHits),
+        )
+        .await?;
         Ok(get_redirect_hits(&context.pool).await?)
     }
 }
@@ -0,0 +1,353 @@
+//! Scriptable in-memory stand-in for the ArangoDB HTTP API so the DAL functions can be tested
+//! without a running database:
+//!
+//! ```rust, ignore
+//! let client = FakeClient::default();
+//! client.respond_cursor(json!([{ "_id": "orders/1", "status": "PAID" }]));
+//!
+//! let pool = get_database_connection_pool_fake(&client);
+//! let order = complete_order(&pool, …).await.unwrap();
+//!
+//! let requests = client.requests();
+//! assert_eq!(requests[0].body["bindVars"]["new_status"], "PAID");
+//! ```
+//!
+//! Each scripted response is served only once (in the order they were scripted) to the first
+//! request with matching method and path. Requests without any scripted response fail with
+//! ArangoDB-like 404 error. The fake only automatically answers `GET _api/database/current`
+//! (database info) which is being requested whenever a database is acquired from the pool.
+use http::header::{HeaderMap, SERVER};
+use http::{HeaderValue, Method, Request, Response};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+use crate::arango::client::ClientExt;
+use crate::arango::transaction::TRANSACTION_HEADER;
+use crate::arango::ClientError;
+
+type Responder = Arc<dyn Fn(&RecordedRequest) -> (u16, Value) + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub struct FakeClient {
+    headers: HeaderMap,
+    state: Arc<Mutex<FakeState>>,
+}
+
+#[derive(Default)]
+struct FakeState {
+    responses: Vec<ScriptedResponse>,
+    requests: Vec<RecordedRequest>,
+}
+
+struct ScriptedResponse {
+    method: Method,
+    path: String,
+    responder: Responder,
+}
+
+/// Request received by the fake (except the automatically answered database info requests).
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedRequest {
+    pub method: Method,
+    /// Path relative to the database, for example: `_api/cursor`
+    pub path: String,
+    /// Parsed JSON body (`Value::Null` for requests without a body)
+    pub body: Value,
+    /// ID of the stream transaction this request was part of (if any)
+    pub transaction_id: Option<String>,
+}
+
+impl std::fmt::Debug for FakeClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("FakeClient")
+            .field("scripted_responses", &state.responses.len())
+            .field("recorded_requests", &state.requests.len())
+            .finish()
+    }
+}
+
+impl FakeClient {
+    /// Scripts a response for the next request with the specified method and path (relative to the
+    /// database, for example `_api/document/orders`).
+    pub fn respond(&self, method: Method, path: &str, status: u16, body: Value) -> &Self {
+        self.respond_with(method, path, move |_| (status, body.to_owned()))
+    }
+
+    /// Similar to `respond` except the response is computed from the received request (to echo
+    /// the AQL bind variables back, for example).
+    pub fn respond_with<F>(&self, method: Method, path: &str, responder: F) -> &Self
+    where
+        F: Fn(&RecordedRequest) -> (u16, Value) + Send + Sync + 'static,
+    {
+        self.state.lock().unwrap().responses.push(ScriptedResponse {
+            method,
+            path: path.to_string(),
+            responder: Arc::new(responder),
+        });
+        self
+    }
+
+    /// Scripts a result of the next AQL query (`result` must be a JSON array).
+    pub fn respond_cursor(&self, result: Value) -> &Self {
+        self.respond(
+            Method::POST,
+            "_api/cursor",
+            201,
+            json!({
+                "error": false,
+                "code": 201,
+                "cached": false,
+                "hasMore": false,
+                "result": result,
+            }),
+        )
+    }
+
+    /// Scripts a response of the next document creation in the specified collection.
+    pub fn respond_document_created(&self, collection: &str, key: &str) -> &Self {
+        self.respond(
+            Method::POST,
+            &format!("_api/document/{}", collection),
+            201,
+            json!({
+                "_id": format!("{}/{}", collection, key),
+                "_key": key,
+                "_rev": "_fake_rev",
+            }),
+        )
+    }
+
+    /// Scripts a response of the next edge creation in the specified graph.
+    pub fn respond_graph_edge_created(
+        &self,
+        graph: &str,
+        edge_collection: &str,
+        key: &str,
+    ) -> &Self {
+        self.respond(
+            Method::POST,
+            &format!("_api/gharial/{}/edge/{}", graph, edge_collection),
+            202,
+            json!({
+                "error": false,
+                "code": 202,
+                "edge": {
+                    "_id": format!("{}/{}", edge_collection, key),
+                    "_key": key,
+                    "_rev": "_fake_rev",
+                },
+            }),
+        )
+    }
+
+    /// Scripts ArangoDB error response (see: https://www.arangodb.com/docs/stable/appendix-error-codes.html).
+    pub fn respond_error(
+        &self,
+        method: Method,
+        path: &str,
+        code: u16,
+        error_num: u16,
+        error_message: &str,
+    ) -> &Self {
+        self.respond(
+            method,
+            path,
+            code,
+            json!({
+                "error": true,
+                "code": code,
+                "errorNum": error_num,
+                "errorMessage": error_message,
+            }),
+        )
+    }
+
+    /// Returns all the requests received so far (in order).
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.to_owned()
+    }
+
+    /// Returns `true` when all the scripted responses were served.
+    pub fn is_exhausted(&self) -> bool {
+        self.state.lock().unwrap().responses.is_empty()
+    }
+
+    fn transaction_id(&self) -> Option<String> {
+        self.headers
+            .get(TRANSACTION_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    }
+}
+
+/// Strips `/_db/{name}/` prefix (if any) so the paths are relative to the database.
+fn relative_path(path: &str) -> String {
+    let path = path.trim_start_matches('/');
+    match path.strip_prefix("_db/") {
+        Some(path) => match path.split_once('/') {
+            Some((_database_name, path)) => path.to_string(),
+            None => String::new(),
+        },
+        None => path.to_string(),
+    }
+}
+
+#[async_trait::async_trait]
+impl ClientExt for FakeClient {
+    fn new<U: Into<Option<HeaderMap>>>(headers: U) -> Result<Self, ClientError> {
+        Ok(FakeClient {
+            headers: headers.into().unwrap_or_default(),
+            state: Default::default(),
+        })
+    }
+
+    fn headers(&mut self) -> &mut HeaderMap<HeaderValue> {
+        &mut self.headers
+    }
+
+    /// The transaction client shares the scripted responses with the original client.
+    fn clone_with_transaction(&self, transaction_id: String) -> Result<Self, ClientError> {
+        let mut client = self.clone();
+        client
+            .headers
+            .insert(TRANSACTION_HEADER, transaction_id.parse().unwrap());
+        Ok(client)
+    }
+
+    async fn request(&self, request: Request<String>) -> Result<Response<String>, ClientError> {
+        let url = url::Url::parse(&request.uri().to_string())
+            .map_err(|error| ClientError::HttpClient(format!("{:?}", error)))?;
+        let path = relative_path(url.path());
+
+        let (status, body) = if request.method() == Method::GET && path == "_api/database/current" {
+            (
+                200,
+                json!({
+                    "error": false,
+                    "code": 200,
+                    "result": {
+                        "name": "fake_database",
+                        "id": "1",
+                        "path": "",
+                        "isSystem": false,
+                    },
+                }),
+            )
+        } else {
+            let recorded_request = RecordedRequest {
+                method: request.method().to_owned(),
+                path,
+                body: serde_json::from_str(request.body()).unwrap_or(Value::Null),
+                transaction_id: self.transaction_id(),
+            };
+
+            let mut state = self.state.lock().unwrap();
+            state.requests.push(recorded_request.to_owned());
+
+            match state.responses.iter().position(|response| {
+                response.method == recorded_request.method && response.path == recorded_request.path
+            }) {
+                Some(index) => {
+                    let scripted_response = state.responses.remove(index);
+                    (scripted_response.responder)(&recorded_request)
+                }
+                None => (
+                    404,
+                    json!({
+                        "error": true,
+                        "code": 404,
+                        "errorNum": 404,
+                        "errorMessage": format!(
+                            "FakeClient: no response scripted for {} {}",
+                            recorded_request.method, recorded_request.path
+                        ),
+                    }),
+                ),
+            }
+        };
+
+        Response::builder()
+            .status(status)
+            .header(SERVER, "ArangoDB")
+            .body(body.to_string())
+            .map_err(|error| ClientError::HttpClient(format!("{:?}", error)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arango::{get_database_connection_pool_fake, resolve_aql_vector};
+
+    #[test]
+    fn relative_path_test() {
+        assert_eq!(relative_path("/_db/abacus/_api/cursor"), "_api/cursor");
+        assert_eq!(relative_path("/_db/abacus/"), "");
+        assert_eq!(relative_path("/_open/auth"), "_open/auth");
+    }
+
+    #[tokio::test]
+    async fn cursor_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([1, 2, 3]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let result: Vec<i32> = resolve_aql_vector(
+            &pool,
+            "FOR i IN @numbers RETURN i",
+            hashmap_json!["numbers" => [1, 2, 3]],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result, vec![1, 2, 3]);
+        assert!(client.is_exhausted());
+
+        let requests = client.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, Method::POST);
+        assert_eq!(requests[0].path, "_api/cursor");
+        assert_eq!(requests[0].body["query"], "FOR i IN @numbers RETURN i");
+        assert_eq!(requests[0].body["bindVars"]["numbers"], json!([1, 2, 3]));
+    }
+
+    #[tokio::test]
+    async fn unscripted_request_test() {
+        let client = FakeClient::default();
+        let pool = get_database_connection_pool_fake(&client);
+
+        assert_eq!(
+            resolve_aql_vector::<Value, _>(&pool, "RETURN 1", hashmap_json![])
+                .await
+                .unwrap_err()
+                .to_string(),
+            "Error from server: FakeClient: no response scripted for POST _api/cursor(404)"
+        );
+    }
+
+    #[tokio::test]
+    async fn respond_with_test() {
+        let client = FakeClient::default();
+        client.respond_with(Method::POST, "_api/cursor", |request| {
+            (
+                201,
+                json!({
+                    "error": false,
+                    "code": 201,
+                    "cached": false,
+                    "hasMore": false,
+                    "result": [request.body["bindVars"]["echo"]],
+                }),
+            )
+        });
+
+        let pool = get_database_connection_pool_fake(&client);
+        let result: Vec<String> =
+            resolve_aql_vector(&pool, "RETURN @echo", hashmap_json!["echo" => "hello"])
+                .await
+                .unwrap();
+
+        assert_eq!(result, vec!["hello"]);
+    }
+}
@@ -3,10 +3,12 @@ use url::Url;
 
 use crate::arango::ClientError;
 
+#[cfg(test)]
+pub mod fake;
 pub mod reqwest;
 
 #[async_trait::async_trait]
-pub trait ClientExt: Sync + Clone {
+pub trait ClientExt: Send + Sync + Clone + 'static {
     fn new<U: Into<Option<HeaderMap>>>(headers: U) -> Result<Self, ClientError>
     where
         Self: Sized;
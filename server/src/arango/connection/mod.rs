@@ -319,6 +319,26 @@ impl<C: ClientExt> GenericConnection<C, Normal> {
         GenericConnection::establish(arango_url, Auth::jwt(username, password)).await
     }
 
+    /// Creates a connection with an already configured HTTP client without contacting the server
+    /// (no server validation and no authentication). It's meant for clients which are not talking
+    /// to a real ArangoDB server (see `FakeClient`).
+    pub fn with_session(
+        arango_url: &str,
+        session: C,
+    ) -> Result<GenericConnection<C, Normal>, ClientError> {
+        let arango_url = Url::parse(arango_url)
+            .map_err(|_| ClientError::InvalidServer(format!("invalid url: {}", arango_url)))?
+            .join("/")
+            .unwrap();
+
+        Ok(GenericConnection {
+            arango_url,
+            username: String::from("root"),
+            session: Arc::new(session),
+            state: Normal,
+        })
+    }
+
     async fn jwt_login<T: Into<String>>(
         arango_url: &Url,
         username: T,
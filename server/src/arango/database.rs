@@ -134,7 +134,6 @@ impl<'a, C: ClientExt> Database<C> {
     ///
     /// # Note
     /// this function would make a request to arango server.
-    #[allow(dead_code)]
     pub async fn create_collection(&self, name: &str) -> Result<Collection<C>, ClientError> {
         self.create_collection_with_options(
             CreateOptions::builder().name(name).build(),
@@ -143,7 +142,6 @@ impl<'a, C: ClientExt> Database<C> {
         .await
     }
 
-    #[allow(dead_code)]
     pub async fn create_edge_collection(&self, name: &str) -> Result<Collection<C>, ClientError> {
         self.create_collection_with_options(
             CreateOptions::builder()
@@ -178,7 +176,6 @@ impl<'a, C: ClientExt> Database<C> {
     ///
     /// # Note
     /// this function would make a request to arango server.
-    #[allow(dead_code)]
     pub async fn arango_version(&self) -> Result<Version, ClientError> {
         let url = self.base_url.join("_api/version").unwrap();
         let resp = self.session.get(url, "").await?;
@@ -439,6 +436,7 @@ impl<'a, C: ClientExt> Database<C> {
     /// of the vertex recursively (see edge remove).
     ///
     /// https://www.arangodb.com/docs/3.7/http/gharial-vertices.html#remove-a-vertex
+    #[allow(dead_code)]
     pub async fn remove_graph_vertex(
         &self,
         graph: &str,
@@ -613,7 +611,6 @@ impl<'a, C: ClientExt> Database<C> {
     ///
     /// # Note
     /// this function would make a request to arango server.
-    #[allow(dead_code)]
     pub async fn create_view(&self, view_options: ViewOptions) -> Result<View, ClientError> {
         let url = self.base_url.join("_api/view").unwrap();
 
@@ -630,7 +627,6 @@ impl<'a, C: ClientExt> Database<C> {
     ///
     /// # Note
     /// this function would make a request to arango server.
-    #[allow(dead_code)]
     pub async fn view(&self, view_name: &str) -> Result<ViewDescription, ClientError> {
         let url = self
             .base_url
@@ -742,7 +738,6 @@ impl<'a, C: ClientExt> Database<C> {
     ///
     /// # Note
     /// this function would make a request to arango server.
-    #[allow(dead_code)]
     pub async fn create_analyzer(
         &self,
         analyzer: AnalyzerInfo,
@@ -762,7 +757,6 @@ impl<'a, C: ClientExt> Database<C> {
     ///
     /// # Note
     /// this function would make a request to arango server.
-    #[allow(dead_code)]
     pub async fn analyzer(&self, analyzer_name: &str) -> Result<AnalyzerInfo, ClientError> {
         let url = self
             .base_url
@@ -24,13 +24,21 @@
 //! > connection -> databases -> collections -> documents/edges
 //!
 
+#[cfg(test)]
+use crate::arango::client::fake::FakeClient;
+use crate::arango::client::reqwest::ReqwestClient;
+use crate::arango::client::ClientExt;
+use crate::arango::connection::GenericConnection;
 use crate::arango::pool::ConnectionManager;
+use crate::arango::transaction::{Transaction, TransactionCollections, TransactionSettings};
 #[cfg(test)]
 use deadpool::managed::Object;
 use deadpool::managed::Pool;
 use serde::Deserialize;
 use serde_json::Value;
 use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
 
 pub use crate::arango::connection::Connection;
 pub use crate::arango::{
@@ -57,15 +65,17 @@ pub(crate) mod view;
 mod pool;
 mod response;
 
+/// Pool of ArangoDB connections. It's generic over the HTTP client so the DAL functions can be
+/// tested with `FakeClient` instead of a real database (the real `ReqwestClient` is the default).
 #[derive(Clone)]
-pub struct ConnectionPool {
-    pub pool: Pool<ConnectionManager>,
+pub struct ConnectionPool<C: ClientExt = ReqwestClient> {
+    pub pool: Pool<ConnectionManager<C>>,
     db_name: String,
 }
 
 /// Resolves the provided AQL query and returns the first result or `Err`.
-pub(crate) async fn resolve_aql<T: for<'de> Deserialize<'de>>(
-    pool: &ConnectionPool,
+pub(crate) async fn resolve_aql<T: for<'de> Deserialize<'de>, C: ClientExt>(
+    pool: &ConnectionPool<C>,
     query: &str,
     bind_vars: HashMap<&str, Value>,
 ) -> anyhow::Result<T> {
@@ -78,8 +88,8 @@ pub(crate) async fn resolve_aql<T: for<'de> Deserialize<'de>>(
 }
 
 /// Similar to `resolve_aql` except it returns the whole vector (not only the first result) or `Err`.
-pub(crate) async fn resolve_aql_vector<T: for<'de> Deserialize<'de>>(
-    pool: &ConnectionPool,
+pub(crate) async fn resolve_aql_vector<T: for<'de> Deserialize<'de>, C: ClientExt>(
+    pool: &ConnectionPool<C>,
     query: &str,
     bind_vars: HashMap<&str, Value>,
 ) -> anyhow::Result<Vec<T>> {
@@ -90,10 +100,22 @@ pub(crate) async fn resolve_aql_vector<T: for<'de> Deserialize<'de>>(
     }
 }
 
-pub type DatabaseType = crate::arango::Database<crate::arango::client::reqwest::ReqwestClient>;
+/// Similar to `resolve_aql` except the query runs as a part of the provided stream transaction
+/// (see `ConnectionPool::run_in_transaction`).
+pub(crate) async fn resolve_aql_in_transaction<T: for<'de> Deserialize<'de>, C: ClientExt>(
+    transaction: &Transaction<C>,
+    query: &str,
+    bind_vars: HashMap<&str, Value>,
+) -> anyhow::Result<T> {
+    let result_vector = transaction.aql_bind_vars::<T>(query, bind_vars).await?;
+    match result_vector.into_iter().next() {
+        Some(result) => Ok(result),
+        None => anyhow::bail!("database didn't return any item"),
+    }
+}
 
-impl ConnectionPool {
-    pub async fn db(&self) -> DatabaseType {
+impl<C: ClientExt> ConnectionPool<C> {
+    pub async fn db(&self) -> Database<C> {
         let status = &self.pool.status();
         tracing::trace!(
             "Connection pool status: max_size={}, size={}, available={}",
@@ -117,8 +139,74 @@ impl ConnectionPool {
         get_or_create_db(connection, &self.db_name).await
     }
 
+    /// Similar to `db` except it returns `Err` instead of panicking when the database cannot be
+    /// reached (and it never creates the database). Used by the readiness checks.
+    pub async fn try_db(&self) -> anyhow::Result<Database<C>> {
+        let connection = &self
+            .pool
+            .get()
+            .await
+            .map_err(|error| anyhow::anyhow!("could not get database connection: {}", error))?;
+
+        Ok(connection.db(&self.db_name).await?)
+    }
+
+    /// Runs `f` inside an ArangoDB stream transaction with write access to the specified
+    /// collections. The transaction is committed when `f` returns `Ok` and aborted otherwise (the
+    /// original error is returned in such case, even when the abort fails).
+    ///
+    /// Only the DB writes are transactional: external side effects (deleting files from S3, for
+    /// example) cannot be rolled back so they should happen only after this function returns `Ok`.
+    pub(crate) async fn run_in_transaction<T, F, Fut>(
+        &self,
+        write_collections: &[&str],
+        f: F,
+    ) -> anyhow::Result<T>
+    where
+        F: FnOnce(Arc<Transaction<C>>) -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let db = self.db().await;
+        let transaction = Arc::new(
+            db.begin_transaction(
+                TransactionSettings::builder()
+                    .collections(
+                        TransactionCollections::builder()
+                            .write(
+                                write_collections
+                                    .iter()
+                                    .map(|collection| collection.to_string())
+                                    .collect(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?,
+        );
+
+        match f(Arc::clone(&transaction)).await {
+            Ok(result) => {
+                transaction.commit().await?;
+                Ok(result)
+            }
+            Err(error) => {
+                // the original error is more important (the transaction expires on its own anyway)
+                if let Err(abort_error) = transaction.abort().await {
+                    tracing::error!("Could not abort the transaction: {}", abort_error);
+                }
+                Err(error)
+            }
+        }
+    }
+
+    /// Returns the current status of the connection pool (sizes and available connections).
+    pub fn status(&self) -> deadpool::managed::Status {
+        self.pool.status()
+    }
+
     #[cfg(test)]
-    pub async fn connection(&self) -> Object<ConnectionManager> {
+    pub async fn connection(&self) -> Object<ConnectionManager<C>> {
         self.pool
             .get()
             .await
@@ -126,7 +214,10 @@ impl ConnectionPool {
     }
 }
 
-async fn get_or_create_db(connection: &Connection, db_name: &str) -> DatabaseType {
+async fn get_or_create_db<C: ClientExt>(
+    connection: &GenericConnection<C>,
+    db_name: &str,
+) -> Database<C> {
     match connection.db(db_name).await {
         Ok(database) => database,
         Err(ClientError::Arango(ArangoError { .. })) => {
@@ -162,6 +253,7 @@ pub fn get_database_connection_pool(
         db_name: arangodb_database.to_string(),
         username: arangodb_username.to_string(),
         password: arangodb_password.to_string(),
+        session: None,
     })
     .max_size(max_pool_size)
     .build()
@@ -187,3 +279,113 @@ pub fn get_database_connection_pool_mock() -> ConnectionPool {
         "mock_arangodb_password",
     )
 }
+
+/// Connection pool backed by the in-memory `FakeClient` (no ArangoDB server is needed). All the
+/// connections share the responses scripted via the `client`.
+#[cfg(test)]
+pub fn get_database_connection_pool_fake(client: &FakeClient) -> ConnectionPool<FakeClient> {
+    let connection_pool = deadpool::managed::Pool::builder(pool::ConnectionManager {
+        db_host: String::from("http://fake.arangodb:8529"),
+        db_name: String::from("fake_database"),
+        username: String::from("fake_username"),
+        password: String::from("fake_password"),
+        session: Some(client.to_owned()),
+    })
+    .max_size(1)
+    .build()
+    .unwrap();
+
+    ConnectionPool {
+        pool: connection_pool,
+        db_name: String::from("fake_database"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Method;
+    use serde_json::json;
+
+    fn respond_transaction(client: &FakeClient, method: Method, path: &str, status: &str) {
+        client.respond(
+            method,
+            path,
+            200,
+            json!({
+                "error": false,
+                "code": 200,
+                "result": { "id": "1", "status": status },
+            }),
+        );
+    }
+
+    #[tokio::test]
+    async fn run_in_transaction_commit_test() {
+        let client = FakeClient::default();
+        respond_transaction(&client, Method::POST, "_api/transaction/begin", "running");
+        client.respond_cursor(json!([42]));
+        respond_transaction(&client, Method::PUT, "_api/transaction/1", "committed");
+
+        let pool = get_database_connection_pool_fake(&client);
+        let result: i32 = pool
+            .run_in_transaction(&["products"], |transaction| async move {
+                resolve_aql_in_transaction(&transaction, "RETURN 42", hashmap_json![]).await
+            })
+            .await
+            .unwrap();
+        assert_eq!(result, 42);
+        assert!(client.is_exhausted());
+
+        let requests = client.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(
+            requests[0].body["collections"]["write"],
+            json!(["products"])
+        );
+        assert_eq!(requests[0].transaction_id, None);
+        assert_eq!(requests[1].transaction_id, Some(String::from("1")));
+        assert_eq!(requests[2].method, Method::PUT);
+    }
+
+    #[tokio::test]
+    async fn run_in_transaction_abort_test() {
+        let client = FakeClient::default();
+        respond_transaction(&client, Method::POST, "_api/transaction/begin", "running");
+        respond_transaction(&client, Method::DELETE, "_api/transaction/1", "aborted");
+
+        let pool = get_database_connection_pool_fake(&client);
+        let error = pool
+            .run_in_transaction(&["products"], |_transaction| async move {
+                Err::<(), _>(anyhow::anyhow!("something went wrong"))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "something went wrong");
+        assert!(client.is_exhausted());
+        assert_eq!(client.requests()[1].method, Method::DELETE);
+    }
+
+    #[tokio::test]
+    async fn run_in_transaction_abort_failure_test() {
+        let client = FakeClient::default();
+        respond_transaction(&client, Method::POST, "_api/transaction/begin", "running");
+        client.respond_error(
+            Method::DELETE,
+            "_api/transaction/1",
+            404,
+            1655,
+            "transaction not found",
+        );
+
+        let pool = get_database_connection_pool_fake(&client);
+        let error = pool
+            .run_in_transaction(&["products"], |_transaction| async move {
+                Err::<(), _>(anyhow::anyhow!("something went wrong"))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "something went wrong");
+        assert!(client.is_exhausted());
+    }
+}
@@ -1,13 +1,19 @@
-use crate::arango::{ClientError, Connection};
+use crate::arango::client::reqwest::ReqwestClient;
+use crate::arango::client::ClientExt;
+use crate::arango::connection::GenericConnection;
+use crate::arango::ClientError;
 use deadpool::managed::Metrics as DeadpoolMetrics;
 use deadpool::managed::RecycleError as DeadpoolRecycleError;
 use deadpool::managed::RecycleResult as DeadpoolRecycleResult;
 
-pub struct ConnectionManager {
+pub struct ConnectionManager<C: ClientExt = ReqwestClient> {
     pub db_host: String,
     pub db_name: String,
     pub username: String,
     pub password: String,
+    /// Preconfigured HTTP client which is used instead of establishing a new authenticated
+    /// connection (see `FakeClient`). It's always `None` for the real ArangoDB connections.
+    pub session: Option<C>,
 }
 
 /// Opening a new database connection every time one is both inefficient and can lead to
@@ -22,22 +28,26 @@ pub struct ConnectionManager {
 /// - When the application reaches maximum allowed connection number, other requests for a new
 ///   connection are queued and waiting for it to be available. This means that the application
 ///   might be responding slowly when it's choking. We might change this to panic instead (?).
-impl deadpool::managed::Manager for ConnectionManager {
-    type Type = Connection;
+impl<C: ClientExt> deadpool::managed::Manager for ConnectionManager<C> {
+    type Type = GenericConnection<C>;
     type Error = ClientError;
 
     /// Creates a new instance of the ArangoDB connection.
-    async fn create(&self) -> Result<Connection, ClientError> {
+    async fn create(&self) -> Result<GenericConnection<C>, ClientError> {
         tracing::debug!("Creating a new ArangoDB connection 💎");
-        let connection =
-            crate::arango::Connection::establish_jwt(&self.db_host, &self.username, &self.password);
-        connection.await
+        match &self.session {
+            Some(session) => GenericConnection::with_session(&self.db_host, session.to_owned()),
+            None => {
+                GenericConnection::establish_jwt(&self.db_host, &self.username, &self.password)
+                    .await
+            }
+        }
     }
 
     /// Tries to recycle a connection returning `Err` if the object could not be recycled.
     async fn recycle(
         &self,
-        conn: &mut Connection,
+        conn: &mut GenericConnection<C>,
         _: &DeadpoolMetrics,
     ) -> DeadpoolRecycleResult<ClientError> {
         match conn.db(&self.db_name).await {
@@ -0,0 +1,169 @@
+use crate::arango::client::ClientExt;
+use crate::arango::transaction::Transaction;
+use crate::arango::{resolve_aql, resolve_aql_in_transaction, resolve_aql_vector, ConnectionPool};
+use crate::archive::ArchiveItem;
+use crate::pagination::{PaginatedResult, Pagination};
+use serde::Serialize;
+
+/// See `crate::archive::archive_struct` for more details.
+pub(in crate::archive) async fn create_archive_item<T, C: ClientExt>(
+    transaction: &Transaction<C>,
+    original_id: &str,
+    original_collection_name: &str,
+    original_payload: T,
+) -> anyhow::Result<ArchiveItem>
+where
+    T: Serialize,
+{
+    resolve_aql_in_transaction(
+        transaction,
+        r#"
+            INSERT {
+              original_id: @original_id,
+              original_collection_name: @original_collection_name,
+              original_payload: @original_payload,
+              created: DATE_ISO8601(DATE_NOW()),
+            } INTO archive
+            RETURN NEW
+        "#,
+        hashmap_json![
+            "original_id" => original_id,
+            "original_collection_name" => original_collection_name,
+            "original_payload" => serde_json::to_string(&original_payload)?,
+        ],
+    )
+    .await
+}
+
+/// Returns archived items of the specified collection (the most recently archived first).
+pub(in crate::archive) async fn search_archive_items<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    original_collection_name: &str,
+    pagination: &Pagination,
+) -> anyhow::Result<PaginatedResult<ArchiveItem>> {
+    resolve_aql(
+        pool,
+        r#"
+            LET all_items = (
+              FOR item IN archive
+                FILTER item.original_collection_name == @original_collection_name
+                RETURN item
+            )
+
+            LET items = (
+              FOR item IN all_items
+                SORT item.created DESC, item._key DESC
+                LIMIT @offset, @limit
+                RETURN item
+            )
+
+            RETURN { total_count: LENGTH(all_items), items }
+        "#,
+        hashmap_json![
+            "original_collection_name" => original_collection_name,
+            "offset" => pagination.offset(),
+            "limit" => pagination.limit(),
+        ],
+    )
+    .await
+}
+
+/// Returns the archived item by its KEY or ID (`None` when it doesn't exist).
+pub(in crate::archive) async fn get_archive_item<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    archive_item_key_or_id: &str,
+) -> anyhow::Result<Option<ArchiveItem>> {
+    let items = resolve_aql_vector(
+        pool,
+        r#"
+            FOR item IN archive
+              FILTER item._key == @archive_item_key_or_id OR item._id == @archive_item_key_or_id
+              RETURN item
+        "#,
+        hashmap_json![
+            "archive_item_key_or_id" => archive_item_key_or_id,
+        ],
+    )
+    .await?;
+
+    Ok(items.into_iter().next())
+}
+
+/// Hard-deletes the archived item (for example, when it was restored).
+pub(in crate::archive) async fn delete_archive_item<C: ClientExt>(
+    transaction: &Transaction<C>,
+    archive_item_id: &str,
+) -> anyhow::Result<ArchiveItem> {
+    resolve_aql_in_transaction(
+        transaction,
+        r#"
+            REMOVE PARSE_IDENTIFIER(@archive_item_id).key IN archive
+            RETURN OLD
+        "#,
+        hashmap_json![
+            "archive_item_id" => archive_item_id,
+        ],
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arango::client::fake::FakeClient;
+    use crate::arango::get_database_connection_pool_fake;
+    use serde_json::json;
+
+    fn archive_item_json() -> serde_json::Value {
+        json!({
+            "_id": "archive/1",
+            "_rev": "_fake_rev",
+            "_key": "1",
+            "original_id": "products/42",
+            "original_collection_name": "products",
+            "original_payload": r#"{"_key":"42"}"#,
+            "created": "2022-11-01T00:00:00.000Z",
+        })
+    }
+
+    #[tokio::test]
+    async fn search_archive_items_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([{
+            "total_count": 1,
+            "items": [archive_item_json()],
+        }]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let result = search_archive_items(
+            &pool,
+            "products",
+            &Pagination::new(&Some(10), &None).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.total_count, 1);
+        assert_eq!(result.items[0].original_id, "products/42");
+        assert_eq!(
+            client.requests()[0].body["bindVars"],
+            json!({
+                "original_collection_name": "products",
+                "offset": 0,
+                "limit": 10,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn get_archive_item_missing_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        assert!(get_archive_item(&pool, "archive/1")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(client.is_exhausted());
+    }
+}
@@ -1,6 +1,16 @@
-use crate::arango::{resolve_aql, ConnectionPool};
+use crate::arango::client::ClientExt;
+use crate::arango::transaction::Transaction;
+use crate::arango::ConnectionPool;
+use crate::auth::rbac;
+use crate::auth::rbac::Actions::Archive;
+use crate::auth::rbac::ArchiveActions::GetArchiveItems;
+use crate::graphql::AbacusGraphQLResult;
+use crate::graphql_context::Context;
+use crate::pagination::{PageInfo, PaginatedResult, Pagination};
 use serde::{Deserialize, Serialize};
 
+mod dal;
+
 #[derive(Deserialize, Serialize)]
 pub(crate) struct ArchiveItem {
     _id: String,
@@ -9,17 +19,144 @@ pub(crate) struct ArchiveItem {
     original_id: String,
     original_collection_name: String,
     original_payload: String,
+    created: String,
+}
+
+#[juniper::graphql_object]
+impl ArchiveItem {
+    fn id(&self) -> juniper::ID {
+        juniper::ID::from(self._id.to_owned())
+    }
+
+    /// ID of the document before it was archived (it's being reused when restoring the document).
+    fn original_id(&self) -> juniper::ID {
+        juniper::ID::from(self.original_id.to_owned())
+    }
+
+    /// Name of the collection the document was archived from (`products` for example).
+    fn original_collection_name(&self) -> String {
+        self.original_collection_name.to_owned()
+    }
+
+    /// The archived document serialized as JSON (as it was at the moment of archiving).
+    fn original_payload(&self) -> String {
+        self.original_payload.to_owned()
+    }
+
+    /// When the document was archived (ISO 8601).
+    fn created(&self) -> String {
+        self.created.to_owned()
+    }
+}
+
+impl ArchiveItem {
+    pub(crate) fn id_ref(&self) -> &str {
+        &self._id
+    }
+
+    /// Deserializes the archived document. It fails when the document was archived from
+    /// a different collection than expected.
+    pub(crate) fn original_payload<T>(&self, original_collection_name: &str) -> anyhow::Result<T>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        if self.original_collection_name != original_collection_name {
+            anyhow::bail!(
+                "archived item '{}' doesn't belong to '{}' collection",
+                self._id,
+                original_collection_name
+            )
+        }
+        Ok(serde_json::from_str(&self.original_payload)?)
+    }
+}
+
+/// An edge in a connection (Relay specification).
+#[derive(juniper::GraphQLObject)]
+pub(crate) struct ArchiveItemEdge {
+    /// The item at the end of the edge.
+    node: ArchiveItem,
+    /// A cursor for use in pagination.
+    cursor: String,
+}
+
+/// A list of archived items with pagination information (Relay specification).
+#[derive(juniper::GraphQLObject)]
+pub(crate) struct ArchiveItemConnection {
+    edges: Vec<ArchiveItemEdge>,
+    page_info: PageInfo,
+    /// Total number of archived items in the collection (ignoring the pagination).
+    total_count: i32,
+}
+
+impl ArchiveItemConnection {
+    fn new(result: PaginatedResult<ArchiveItem>, pagination: &Pagination) -> Self {
+        ArchiveItemConnection {
+            page_info: pagination.page_info(result.items.len(), result.total_count),
+            total_count: result.total_count,
+            edges: result
+                .items
+                .into_iter()
+                .enumerate()
+                .map(|(index, archive_item)| ArchiveItemEdge {
+                    node: archive_item,
+                    cursor: pagination.cursor(index),
+                })
+                .collect(),
+        }
+    }
+}
+
+pub(crate) struct ArchiveQuery;
+
+#[juniper::graphql_object(context = Context)]
+impl ArchiveQuery {
+    /// Returns archived items of the specified collection (`products` for example), the most
+    /// recently archived first. This query requires admin permissions so it should be used only in
+    /// backoffice.
+    async fn items(
+        context: &Context,
+        original_collection_name: String,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> AbacusGraphQLResult<ArchiveItemConnection> {
+        rbac::verify_permissions(
+            &context.rbac_enforcer,
+            &context.user,
+            &Archive(GetArchiveItems),
+        )
+        .await?;
+        let pagination = Pagination::new(&first, &after)?;
+        let result =
+            dal::search_archive_items(&context.pool, &original_collection_name, &pagination)
+                .await?;
+        Ok(ArchiveItemConnection::new(result, &pagination))
+    }
+
+    /// Returns the archived item by its ID (or KEY) so it can be inspected before restoring it.
+    async fn item(context: &Context, id: juniper::ID) -> AbacusGraphQLResult<Option<ArchiveItem>> {
+        rbac::verify_permissions(
+            &context.rbac_enforcer,
+            &context.user,
+            &Archive(GetArchiveItems),
+        )
+        .await?;
+        Ok(dal::get_archive_item(&context.pool, &id).await?)
+    }
 }
 
 /// The purpose or this archivation is to be able to hard-delete documents from DB collections but
-/// be able to still keep them in case we need to revert them for example (currently not implemented).
+/// be able to still keep them in case we need to revert them for example (see `restore_product`).
 /// These archived structs can be either left there forever or deleted via DB collection TTL Indexes.
 ///
 /// Why not just soft-delete? https://stackoverflow.com/a/2549940/3135248
 ///
 /// Moreover archive + hard-delete offloads the collections in ArangoDB making it more performant.
-pub(crate) async fn archive_struct<T>(
-    pool: &ConnectionPool,
+///
+/// The archive item is created inside the provided transaction so the caller can hard-delete the
+/// original document in the same transaction (see `ConnectionPool::run_in_transaction`).
+pub(crate) async fn archive_struct<T, C: ClientExt>(
+    transaction: &Transaction<C>,
     original_id: &str,
     original_collection_name: &str,
     original_payload: T,
@@ -27,22 +164,68 @@ pub(crate) async fn archive_struct<T>(
 where
     T: Serialize,
 {
-    resolve_aql(
-        pool,
-        r#"
-            INSERT {
-              original_id: @original_id,
-              original_collection_name: @original_collection_name,
-              original_payload: @original_payload,
-              created: DATE_ISO8601(DATE_NOW()),
-            } INTO archive
-            RETURN NEW
-        "#,
-        hashmap_json![
-            "original_id" => original_id,
-            "original_collection_name" => original_collection_name,
-            "original_payload" => serde_json::to_string(&original_payload)?,
-        ],
+    dal::create_archive_item(
+        transaction,
+        original_id,
+        original_collection_name,
+        original_payload,
     )
     .await
 }
+
+/// Returns the archived item (without any permission checks, the caller is responsible for them).
+pub(crate) async fn get_archive_item<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    archive_item_id: &str,
+) -> anyhow::Result<ArchiveItem> {
+    match dal::get_archive_item(pool, archive_item_id).await? {
+        Some(archive_item) => Ok(archive_item),
+        None => anyhow::bail!("archived item '{}' doesn't exist", archive_item_id),
+    }
+}
+
+/// Deletes the archived item once it was restored (without any permission checks, the caller is
+/// responsible for them). It runs in the same transaction as the restoration itself.
+pub(crate) async fn delete_archive_item<C: ClientExt>(
+    transaction: &Transaction<C>,
+    archive_item: &ArchiveItem,
+) -> anyhow::Result<ArchiveItem> {
+    dal::delete_archive_item(transaction, archive_item.id_ref()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Payload {
+        _key: String,
+    }
+
+    fn archive_item(original_collection_name: &str) -> ArchiveItem {
+        ArchiveItem {
+            _id: String::from("archive/1"),
+            _rev: String::from("_fake_rev"),
+            _key: String::from("1"),
+            original_id: String::from("products/42"),
+            original_collection_name: original_collection_name.to_string(),
+            original_payload: String::from(r#"{"_key":"42"}"#),
+            created: String::from("2022-11-01T00:00:00.000Z"),
+        }
+    }
+
+    #[test]
+    fn original_payload_test() {
+        let payload: Payload = archive_item("products")
+            .original_payload("products")
+            .unwrap();
+        assert_eq!(payload._key, "42");
+    }
+
+    #[test]
+    fn original_payload_wrong_collection_test() {
+        assert!(archive_item("orders")
+            .original_payload::<Payload>("products")
+            .is_err());
+    }
+}
@@ -1,8 +1,19 @@
-use crate::auth::dal::users::list_all_users;
+use crate::auth::api_key::ApiKey;
+use crate::auth::dal::api_keys::{create_new_api_key, delete_api_key, list_all_api_keys};
+use crate::auth::dal::sessions::{
+    delete_all_user_sessions, delete_other_user_sessions, delete_session_of_user,
+    find_user_sessions,
+};
+use crate::auth::dal::users::{get_user_by_id, list_all_users, set_user_active};
 use crate::auth::rbac;
 use crate::auth::rbac::Actions::Users;
-use crate::auth::rbac::UsersActions::GetAllUsers;
-use crate::auth::users::{AnyUser, User};
+use crate::auth::rbac::UserRole;
+use crate::auth::rbac::UsersActions::{
+    ActivateUser, AssignUserRole, CreateApiKey, DeactivateUser, GetAllUsers, GetUserPermissions,
+    ListApiKeys, RevokeApiKey, RevokeUserSessions, UnassignUserRole,
+};
+use crate::auth::session::{derive_session_token_hash, generate_session_token, Session};
+use crate::auth::users::{AnyUser, SignedUser, User};
 use crate::graphql::AbacusGraphQLResult;
 use crate::graphql_context::Context;
 use juniper::FieldResult;
@@ -20,35 +31,139 @@ impl AuthQuery {
     async fn list_users(context: &Context) -> AbacusGraphQLResult<Vec<AnyUser>> {
         Ok(crate::auth::api::list_users(context).await?)
     }
+
+    /// Returns roles and effective permissions of the user (including the permissions inherited
+    /// via roles).
+    async fn user_permissions(
+        context: &Context,
+        user_id: juniper::ID,
+    ) -> AbacusGraphQLResult<UserPermissions> {
+        Ok(crate::auth::api::user_permissions(context, &user_id).await?)
+    }
+
+    /// Returns all active sessions of the current user ("active devices"), the most recently used
+    /// first.
+    async fn my_sessions(context: &Context) -> AbacusGraphQLResult<Vec<Session>> {
+        Ok(crate::auth::api::my_sessions(context).await?)
+    }
+
+    /// Returns all the machine API keys (including the expired ones), the newest first.
+    async fn list_api_keys(context: &Context) -> AbacusGraphQLResult<Vec<ApiKey>> {
+        Ok(crate::auth::api::list_api_keys(context).await?)
+    }
 }
 
 #[juniper::graphql_object(context = Context)]
 impl AuthMutation {
-    /// This function accepts Google ID token (after receiving it from Google Sign-In in a webapp)
-    /// and returns authorization payload. There is no concept of sign-in and sign-up: every
-    /// whitelisted user with a valid JWT ID token will be authorized. Invalid tokens and users
-    /// that are not whitelisted will be rejected.
+    /// This function accepts OpenID Connect ID token of the provider (`google` for example, after
+    /// receiving it from Google Sign-In in a webapp) and returns authorization payload. There is
+    /// no concept of sign-in and sign-up: every whitelisted user with a valid JWT ID token will be
+    /// authorized. Invalid tokens, unknown providers and users that are not whitelisted will be
+    /// rejected.
     ///
     /// Repeated calls will result in a new session token (old sessions are not removed to support
     /// multiple logins on multiple devices). Original session token is returned back only once
     /// and cannot be retrieved later (it's irreversibly hashed in the database).
     async fn authorize_webapp(
-        google_id_token: String,
+        provider: String,
+        id_token: String,
         context: &Context,
     ) -> FieldResult<crate::auth::api::AuthorizeWebappPayload> {
-        crate::auth::api::authorize_webapp(&google_id_token, context).await
+        crate::auth::api::authorize_webapp(&provider, &id_token, context).await
     }
 
-    /// The purpose of this `deauthorize` mutation is to remove the active sessions and effectively
-    /// make the mobile application/webapp unsigned. Applications should remove the session token
-    /// once de-authorized.
+    /// The purpose of this `deauthorize` mutation is to remove the current session (the one sending
+    /// this request) and effectively make the mobile application/webapp unsigned. Applications
+    /// should remove the session token once de-authorized.
     ///
     /// Repeated calls will result in failure since it's not possible to deauthorize twice.
-    async fn deauthorize(
-        session_token: String, // TODO: this could be removed (?) - we can use the user from context
+    async fn deauthorize(context: &Context) -> crate::auth::api::DeauthorizePayload {
+        crate::auth::api::deauthorize(context).await
+    }
+
+    /// Revokes the session of the current user (signs out the device). Returns `false` when the
+    /// session doesn't exist (or belongs to somebody else).
+    async fn revoke_my_session(
+        context: &Context,
+        session_id: juniper::ID,
+    ) -> AbacusGraphQLResult<bool> {
+        Ok(crate::auth::api::revoke_my_session(context, &session_id).await?)
+    }
+
+    /// Revokes all sessions of the current user except the current one (signs out all the other
+    /// devices) and returns the number of revoked sessions.
+    async fn revoke_my_other_sessions(context: &Context) -> AbacusGraphQLResult<i32> {
+        Ok(crate::auth::api::revoke_my_other_sessions(context).await?)
+    }
+
+    /// Activates the user so they can sign in. New users are always created inactive.
+    async fn activate_user(
+        context: &Context,
+        user_id: juniper::ID,
+    ) -> AbacusGraphQLResult<AnyUser> {
+        Ok(crate::auth::api::activate_user(context, &user_id).await?)
+    }
+
+    /// Deactivates the user and revokes all their sessions (signs them out from all the devices).
+    async fn deactivate_user(
+        context: &Context,
+        user_id: juniper::ID,
+    ) -> AbacusGraphQLResult<AnyUser> {
+        Ok(crate::auth::api::deactivate_user(context, &user_id).await?)
+    }
+
+    /// Assigns the role to the user. The change is applied immediately.
+    async fn assign_user_role(
+        context: &Context,
+        user_id: juniper::ID,
+        role: UserRole,
+    ) -> AbacusGraphQLResult<UserPermissions> {
+        Ok(crate::auth::api::assign_user_role(context, &user_id, &role).await?)
+    }
+
+    /// Unassigns the role from the user. The change is applied immediately.
+    async fn unassign_user_role(
+        context: &Context,
+        user_id: juniper::ID,
+        role: UserRole,
+    ) -> AbacusGraphQLResult<UserPermissions> {
+        Ok(crate::auth::api::unassign_user_role(context, &user_id, &role).await?)
+    }
+
+    /// Revokes all sessions of the user (signs them out from all the devices) and returns the
+    /// number of revoked sessions. The user can sign in again (unless deactivated).
+    async fn revoke_user_sessions(
+        context: &Context,
+        user_id: juniper::ID,
+    ) -> AbacusGraphQLResult<i32> {
+        Ok(crate::auth::api::revoke_user_sessions(context, &user_id).await?)
+    }
+
+    /// Creates a machine API key of the account (for integrations or devices like POS kiosk) with
+    /// the specified roles. The API key never expires unless `expiresInDays` is specified.
+    ///
+    /// The API key should be sent as `Authorization: ApiKey XYZ` header. It's returned back only
+    /// once and cannot be retrieved later (it's irreversibly hashed in the database).
+    async fn create_api_key(
         context: &Context,
-    ) -> crate::auth::api::DeauthorizePayload {
-        crate::auth::api::deauthorize(&session_token, context).await
+        name: String,
+        account_id: juniper::ID,
+        roles: Vec<UserRole>,
+        expires_in_days: Option<i32>,
+    ) -> AbacusGraphQLResult<CreateApiKeyPayload> {
+        Ok(
+            crate::auth::api::create_api_key(context, &name, &account_id, &roles, &expires_in_days)
+                .await?,
+        )
+    }
+
+    /// Revokes the API key (it cannot be used anymore) together with its roles. Returns `false`
+    /// when the API key doesn't exist.
+    async fn revoke_api_key(
+        context: &Context,
+        api_key_id: juniper::ID,
+    ) -> AbacusGraphQLResult<bool> {
+        Ok(crate::auth::api::revoke_api_key(context, &api_key_id).await?)
     }
 }
 
@@ -77,11 +192,17 @@ pub(crate) async fn whoami(context: &Context) -> WhoamiPayload {
             human_readable_type: Some(String::from("anonymous user")),
             is_debug_assertions_enabled: cfg!(debug_assertions),
         },
+        User::ApiKeyUser(user) => WhoamiPayload {
+            id: Some(juniper::ID::from(user.id())),
+            human_readable_type: Some(String::from("api key")),
+            is_debug_assertions_enabled: cfg!(debug_assertions),
+        },
     }
 }
 
 pub(crate) async fn list_users(context: &Context) -> anyhow::Result<Vec<AnyUser>> {
-    match rbac::verify_permissions(&context.user, &Users(GetAllUsers)).await {
+    match rbac::verify_permissions(&context.rbac_enforcer, &context.user, &Users(GetAllUsers)).await
+    {
         // only admin can list all the users
         Ok(_) => match list_all_users(&context.pool).await {
             Ok(list) => Ok(list),
@@ -91,8 +212,250 @@ pub(crate) async fn list_users(context: &Context) -> anyhow::Result<Vec<AnyUser>
     }
 }
 
+#[derive(juniper::GraphQLObject)]
+pub(crate) struct UserPermission {
+    /// Module the action belongs to (`commerce` for example).
+    module: String,
+    /// Action allowed in the module (`publish_product` for example).
+    action: String,
+}
+
+#[derive(juniper::GraphQLObject)]
+pub(crate) struct UserPermissions {
+    user_id: juniper::ID,
+
+    /// Roles assigned directly to the user (`admin`, `employee`, …).
+    roles: Vec<String>,
+
+    /// All roles of the user including the inherited ones (`admin` inherits `commerce_admin` for
+    /// example).
+    implicit_roles: Vec<String>,
+
+    /// All actions the user is allowed to perform.
+    permissions: Vec<UserPermission>,
+}
+
+/// Returns the user or `Err` when such user doesn't exist. The user ID can be specified either as
+/// an ID (`users/3`) or as a KEY (`3`) so callers should use the ID of the returned user when
+/// working with roles or sessions (they always use the full ID).
+async fn get_existing_user(context: &Context, user_id: &str) -> anyhow::Result<AnyUser> {
+    match get_user_by_id(&context.pool, user_id).await? {
+        Some(user) => Ok(user),
+        None => anyhow::bail!("user '{}' doesn't exist", user_id),
+    }
+}
+
+async fn get_user_permissions(context: &Context, user_id: &str) -> anyhow::Result<UserPermissions> {
+    let (roles, implicit_roles) = context.rbac_enforcer.roles_for_user(user_id).await?;
+    let permissions = context
+        .rbac_enforcer
+        .permissions_for_user(user_id)
+        .await?
+        .into_iter()
+        .map(|(module, action)| UserPermission { module, action })
+        .collect();
+
+    Ok(UserPermissions {
+        user_id: juniper::ID::from(user_id.to_string()),
+        roles,
+        implicit_roles,
+        permissions,
+    })
+}
+
+pub(crate) async fn user_permissions(
+    context: &Context,
+    user_id: &str,
+) -> anyhow::Result<UserPermissions> {
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Users(GetUserPermissions),
+    )
+    .await?;
+    let user = get_existing_user(context, user_id).await?;
+    get_user_permissions(context, user.id_ref()).await
+}
+
 ///// Mutations:
 
+pub(crate) async fn activate_user(context: &Context, user_id: &str) -> anyhow::Result<AnyUser> {
+    rbac::verify_permissions(&context.rbac_enforcer, &context.user, &Users(ActivateUser)).await?;
+    set_user_active(&context.pool, user_id, true).await
+}
+
+pub(crate) async fn deactivate_user(context: &Context, user_id: &str) -> anyhow::Result<AnyUser> {
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Users(DeactivateUser),
+    )
+    .await?;
+    let user = get_existing_user(context, user_id).await?;
+    if let User::SignedUser(signed_user) = &context.user {
+        if signed_user.id_ref() == user.id_ref() {
+            anyhow::bail!("users cannot deactivate themselves");
+        }
+    }
+
+    let user = set_user_active(&context.pool, user.id_ref(), false).await?;
+    // sessions are not bound to the user activity so we have to remove them as well
+    delete_all_user_sessions(&context.pool, user.id_ref()).await?;
+    Ok(user)
+}
+
+pub(crate) async fn assign_user_role(
+    context: &Context,
+    user_id: &str,
+    role: &UserRole,
+) -> anyhow::Result<UserPermissions> {
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Users(AssignUserRole),
+    )
+    .await?;
+    let user = get_existing_user(context, user_id).await?;
+    context
+        .rbac_enforcer
+        .assign_role(user.id_ref(), role)
+        .await?;
+    get_user_permissions(context, user.id_ref()).await
+}
+
+pub(crate) async fn unassign_user_role(
+    context: &Context,
+    user_id: &str,
+    role: &UserRole,
+) -> anyhow::Result<UserPermissions> {
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Users(UnassignUserRole),
+    )
+    .await?;
+    let user = get_existing_user(context, user_id).await?;
+    context
+        .rbac_enforcer
+        .unassign_role(user.id_ref(), role)
+        .await?;
+    get_user_permissions(context, user.id_ref()).await
+}
+
+pub(crate) async fn revoke_user_sessions(context: &Context, user_id: &str) -> anyhow::Result<i32> {
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Users(RevokeUserSessions),
+    )
+    .await?;
+    let user = get_existing_user(context, user_id).await?;
+    let revoked_sessions = delete_all_user_sessions(&context.pool, user.id_ref()).await?;
+    Ok(i32::try_from(revoked_sessions.len())?)
+}
+
+#[derive(juniper::GraphQLObject)]
+#[graphql(context = Context)]
+pub(crate) struct CreateApiKeyPayload {
+    api_key: ApiKey,
+
+    /// The API key itself which should be sent as `Authorization: ApiKey XYZ` header. It's
+    /// returned only once (only its hash is stored in the database).
+    api_key_token: String,
+}
+
+pub(crate) async fn list_api_keys(context: &Context) -> anyhow::Result<Vec<ApiKey>> {
+    rbac::verify_permissions(&context.rbac_enforcer, &context.user, &Users(ListApiKeys)).await?;
+    list_all_api_keys(&context.pool).await
+}
+
+pub(crate) async fn create_api_key(
+    context: &Context,
+    name: &str,
+    account_id: &str,
+    roles: &[UserRole],
+    expires_in_days: &Option<i32>,
+) -> anyhow::Result<CreateApiKeyPayload> {
+    rbac::verify_permissions(&context.rbac_enforcer, &context.user, &Users(CreateApiKey)).await?;
+    if name.trim().is_empty() {
+        anyhow::bail!("API key name cannot be empty");
+    }
+    if matches!(expires_in_days, Some(days) if *days < 1) {
+        anyhow::bail!("API key must be valid for at least one day");
+    }
+
+    // API keys are generated and hashed exactly like the session tokens
+    let api_key_token = generate_session_token();
+    let api_key = match create_new_api_key(
+        &context.pool,
+        &derive_session_token_hash(&api_key_token),
+        name.trim(),
+        account_id,
+        expires_in_days,
+    )
+    .await?
+    {
+        Some(api_key) => api_key,
+        None => anyhow::bail!("account '{}' doesn't exist", account_id),
+    };
+    if let Err(error) = assign_api_key_roles(context, &api_key, roles).await {
+        // the API key would be unusable without its roles (and nobody knows its token) so we
+        // rather remove it completely
+        if let Err(rollback_error) = context
+            .rbac_enforcer
+            .unassign_all_roles(api_key.id_ref())
+            .await
+        {
+            tracing::error!(
+                "Could not unassign roles of API key {}: {}",
+                api_key.id_ref(),
+                rollback_error
+            );
+        }
+        if let Err(rollback_error) = delete_api_key(&context.pool, api_key.id_ref()).await {
+            tracing::error!(
+                "Could not delete API key {}: {}",
+                api_key.id_ref(),
+                rollback_error
+            );
+        }
+        return Err(error);
+    }
+
+    Ok(CreateApiKeyPayload {
+        api_key,
+        api_key_token,
+    })
+}
+
+async fn assign_api_key_roles(
+    context: &Context,
+    api_key: &ApiKey,
+    roles: &[UserRole],
+) -> anyhow::Result<()> {
+    for role in roles {
+        context
+            .rbac_enforcer
+            .assign_role(api_key.id_ref(), role)
+            .await?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn revoke_api_key(context: &Context, api_key_id: &str) -> anyhow::Result<bool> {
+    rbac::verify_permissions(&context.rbac_enforcer, &context.user, &Users(RevokeApiKey)).await?;
+    match delete_api_key(&context.pool, api_key_id).await? {
+        Some(api_key) => {
+            context
+                .rbac_enforcer
+                .unassign_all_roles(api_key.id_ref())
+                .await?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
 #[derive(juniper::GraphQLObject)]
 pub(crate) struct AuthorizeWebappPayload {
     success: bool,
@@ -111,15 +474,27 @@ pub(crate) struct DeauthorizePayload {
 }
 
 /// Webapp is currently using very simplified and minimalistic flow: it basically allows only
-/// specific Google subjects to be authorized (admins). The thinking is that webapp login is needed
+/// specific (whitelisted) subjects to be authorized (admins). The thinking is that webapp login is needed
 /// only for the backoffice and the access should be limited to admins. This should be easily
 /// changeable in the future.
 pub(crate) async fn authorize_webapp(
-    google_id_token: &str,
+    provider: &str,
+    id_token: &str,
     context: &Context,
 ) -> FieldResult<AuthorizeWebappPayload> {
     let connection_pool = context.pool.to_owned();
-    let session_token = crate::auth::authorize(&connection_pool, google_id_token).await;
+    let session_token = match context.global_configuration.oidc_providers.get(provider) {
+        Some(oidc_provider) => {
+            crate::auth::authorize(
+                &connection_pool,
+                oidc_provider,
+                id_token,
+                &context.session_device,
+            )
+            .await
+        }
+        None => Err(anyhow::anyhow!("unknown provider '{}'", provider)),
+    };
     match session_token {
         Ok(session_token) => Ok(AuthorizeWebappPayload {
             success: true,
@@ -137,13 +512,54 @@ pub(crate) async fn authorize_webapp(
     }
 }
 
-pub(crate) async fn deauthorize(
-    session_token: &str, // TODO: this could be removed (?) - we can use the user from context
-    context: &Context,
-) -> DeauthorizePayload {
-    let connection_pool = context.pool.to_owned();
-    match crate::auth::deauthorize(&connection_pool, session_token).await {
-        Ok(_) => DeauthorizePayload { success: true },
-        Err(_) => DeauthorizePayload { success: false },
+pub(crate) async fn deauthorize(context: &Context) -> DeauthorizePayload {
+    let session_token_hash = match get_signed_user(context) {
+        Ok(user) => user.session_token_hash(),
+        Err(_) => None,
+    };
+    match session_token_hash {
+        Some(session_token_hash) => {
+            match crate::auth::deauthorize(&context.pool, session_token_hash).await {
+                Ok(_) => DeauthorizePayload { success: true },
+                Err(_) => DeauthorizePayload { success: false },
+            }
+        }
+        None => DeauthorizePayload { success: false },
+    }
+}
+
+/// Returns the current user or `Err` when the user is not signed in.
+fn get_signed_user(context: &Context) -> anyhow::Result<&SignedUser> {
+    match &context.user {
+        User::SignedUser(user) => Ok(user),
+        User::AnonymousUser(_) => anyhow::bail!("user is not signed in"),
+        User::ApiKeyUser(_) => anyhow::bail!("API keys don't have sessions"),
     }
 }
+
+pub(crate) async fn my_sessions(context: &Context) -> anyhow::Result<Vec<Session>> {
+    let user = get_signed_user(context)?;
+    find_user_sessions(
+        &context.pool,
+        user.id_ref(),
+        &context.global_configuration.sessions,
+    )
+    .await
+}
+
+pub(crate) async fn revoke_my_session(context: &Context, session_id: &str) -> anyhow::Result<bool> {
+    let user = get_signed_user(context)?;
+    let revoked_session = delete_session_of_user(&context.pool, user.id_ref(), session_id).await?;
+    Ok(revoked_session.is_some())
+}
+
+pub(crate) async fn revoke_my_other_sessions(context: &Context) -> anyhow::Result<i32> {
+    let user = get_signed_user(context)?;
+    let session_token_hash = match user.session_token_hash() {
+        Some(session_token_hash) => session_token_hash,
+        None => anyhow::bail!("user is not signed in via session"),
+    };
+    let revoked_sessions =
+        delete_other_user_sessions(&context.pool, user.id_ref(), session_token_hash).await?;
+    Ok(i32::try_from(revoked_sessions.len())?)
+}
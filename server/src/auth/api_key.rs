@@ -0,0 +1,69 @@
+use crate::graphql::AbacusGraphQLResult;
+use crate::graphql_context::Context;
+use serde::Deserialize;
+
+/// Machine API key used by integrations and devices (POS kiosk for example) instead of
+/// impersonating a human via Google sign-in. The raw key is generated the same way as the session
+/// token (see `generate_session_token`), and only its hash is stored as the document key (see
+/// `derive_session_token_hash`) so it's returned back only once when created.
+///
+/// Every API key is its own RBAC subject (its ID, for example `api_keys/abc…`), so it can be
+/// scoped to specific roles independently of the users.
+#[derive(Clone, Deserialize)]
+pub struct ApiKey {
+    _id: String,
+    _rev: String,
+    _key: String,
+    name: String,
+    account_id: String,
+    created: String,
+    expires: Option<String>,
+    last_used: Option<String>,
+}
+
+impl ApiKey {
+    pub(crate) fn id_ref(&self) -> &str {
+        self._id.as_ref()
+    }
+
+    pub(crate) fn account_id_ref(&self) -> &str {
+        self.account_id.as_ref()
+    }
+}
+
+#[juniper::graphql_object(context = Context)]
+impl ApiKey {
+    pub(crate) fn id(&self) -> juniper::ID {
+        juniper::ID::from(self._id.to_owned())
+    }
+
+    /// Human readable name of the API key ("POS kiosk 1" for example).
+    pub(crate) fn name(&self) -> String {
+        self.name.to_owned()
+    }
+
+    /// Account the API key belongs to.
+    pub(crate) fn account_id(&self) -> juniper::ID {
+        juniper::ID::from(self.account_id.to_owned())
+    }
+
+    pub(crate) fn created(&self) -> String {
+        self.created.to_owned()
+    }
+
+    /// When does the API key expire (`null` when it never expires).
+    pub(crate) fn expires(&self) -> Option<String> {
+        self.expires.to_owned()
+    }
+
+    /// When was the API key used for the last time (`null` when it was never used).
+    pub(crate) fn last_used(&self) -> Option<String> {
+        self.last_used.to_owned()
+    }
+
+    /// Roles assigned to the API key (they limit what the API key is allowed to do).
+    pub(crate) async fn roles(&self, context: &Context) -> AbacusGraphQLResult<Vec<String>> {
+        let (roles, _) = context.rbac_enforcer.roles_for_user(&self._id).await?;
+        Ok(roles)
+    }
+}
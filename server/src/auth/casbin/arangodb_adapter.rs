@@ -0,0 +1,272 @@
+use crate::arango::client::reqwest::ReqwestClient;
+use crate::arango::client::ClientExt;
+use crate::arango::ConnectionPool;
+use crate::auth::dal::casbin_rules::{
+    create_casbin_rules, delete_casbin_rules, get_all_casbin_rules, replace_all_casbin_rules,
+    CasbinRule,
+};
+use async_trait::async_trait;
+use casbin::error::AdapterError;
+use casbin::{Adapter, Error, Filter, Model, Result};
+
+/// Adapter storing the RBAC policies in `casbin_rules` ArangoDB collection so they can be changed
+/// without redeploying the server (see `CSVAdapter` for the original file-based policies).
+pub struct ArangodbAdapter<C: ClientExt = ReqwestClient> {
+    pool: ConnectionPool<C>,
+    is_filtered: bool,
+}
+
+impl<C: ClientExt> ArangodbAdapter<C> {
+    pub fn new(pool: ConnectionPool<C>) -> ArangodbAdapter<C> {
+        ArangodbAdapter {
+            pool,
+            is_filtered: false,
+        }
+    }
+}
+
+fn adapter_error(error: anyhow::Error) -> Error {
+    Error::from(AdapterError(error.into()))
+}
+
+fn casbin_rule(ptype: &str, rule: Vec<String>) -> CasbinRule {
+    CasbinRule {
+        ptype: ptype.to_string(),
+        rule,
+    }
+}
+
+/// Returns `true` when the rule matches all the (non-empty) filter values.
+fn matches_filter(rule: &[String], field_index: usize, field_values: &[&str]) -> bool {
+    field_values
+        .iter()
+        .enumerate()
+        .all(|(index, value)| match rule.get(field_index + index) {
+            Some(field) => value.is_empty() || field == value,
+            None => value.is_empty(),
+        })
+}
+
+fn load_casbin_rule(casbin_rule: CasbinRule, m: &mut dyn Model) {
+    let mut tokens = vec![casbin_rule.ptype];
+    tokens.extend(casbin_rule.rule);
+    crate::auth::casbin::util::load_policy_line(&tokens, m);
+}
+
+#[async_trait]
+impl<C: ClientExt> Adapter for ArangodbAdapter<C> {
+    async fn load_policy(&mut self, m: &mut dyn Model) -> Result<()> {
+        let casbin_rules = get_all_casbin_rules(&self.pool)
+            .await
+            .map_err(adapter_error)?;
+
+        for casbin_rule in casbin_rules {
+            load_casbin_rule(casbin_rule, m);
+        }
+
+        self.is_filtered = false;
+        Ok(())
+    }
+
+    async fn load_filtered_policy<'a>(&mut self, m: &mut dyn Model, f: Filter<'a>) -> Result<()> {
+        let casbin_rules = get_all_casbin_rules(&self.pool)
+            .await
+            .map_err(adapter_error)?;
+
+        for casbin_rule in casbin_rules {
+            let filter = match casbin_rule.ptype.chars().next() {
+                Some('p') => &f.p,
+                Some('g') => &f.g,
+                _ => continue,
+            };
+            if matches_filter(&casbin_rule.rule, 0, filter) {
+                load_casbin_rule(casbin_rule, m);
+            }
+        }
+
+        self.is_filtered = true;
+        Ok(())
+    }
+
+    async fn save_policy(&mut self, m: &mut dyn Model) -> Result<()> {
+        let mut casbin_rules = vec![];
+        for sec in ["p", "g"] {
+            if let Some(ast_map) = m.get_model().get(sec) {
+                for (ptype, ast) in ast_map {
+                    for rule in ast.get_policy() {
+                        casbin_rules.push(casbin_rule(ptype, rule.to_owned()));
+                    }
+                }
+            }
+        }
+
+        replace_all_casbin_rules(&self.pool, &casbin_rules)
+            .await
+            .map_err(adapter_error)
+    }
+
+    async fn clear_policy(&mut self) -> Result<()> {
+        replace_all_casbin_rules(&self.pool, &[])
+            .await
+            .map_err(adapter_error)
+    }
+
+    fn is_filtered(&self) -> bool {
+        self.is_filtered
+    }
+
+    async fn add_policy(&mut self, sec: &str, ptype: &str, rule: Vec<String>) -> Result<bool> {
+        self.add_policies(sec, ptype, vec![rule]).await
+    }
+
+    async fn add_policies(
+        &mut self,
+        _sec: &str,
+        ptype: &str,
+        rules: Vec<Vec<String>>,
+    ) -> Result<bool> {
+        let casbin_rules: Vec<_> = rules
+            .into_iter()
+            .map(|rule| casbin_rule(ptype, rule))
+            .collect();
+
+        let created_rules = create_casbin_rules(&self.pool, &casbin_rules)
+            .await
+            .map_err(adapter_error)?;
+        Ok(!created_rules.is_empty())
+    }
+
+    async fn remove_policy(&mut self, sec: &str, ptype: &str, rule: Vec<String>) -> Result<bool> {
+        self.remove_policies(sec, ptype, vec![rule]).await
+    }
+
+    async fn remove_policies(
+        &mut self,
+        _sec: &str,
+        ptype: &str,
+        rules: Vec<Vec<String>>,
+    ) -> Result<bool> {
+        let casbin_rules: Vec<_> = rules
+            .into_iter()
+            .map(|rule| casbin_rule(ptype, rule))
+            .collect();
+
+        let deleted_rules = delete_casbin_rules(&self.pool, &casbin_rules)
+            .await
+            .map_err(adapter_error)?;
+        Ok(!deleted_rules.is_empty())
+    }
+
+    async fn remove_filtered_policy(
+        &mut self,
+        _sec: &str,
+        ptype: &str,
+        field_index: usize,
+        field_values: Vec<String>,
+    ) -> Result<bool> {
+        let field_values: Vec<&str> = field_values.iter().map(String::as_str).collect();
+        let casbin_rules: Vec<_> = get_all_casbin_rules(&self.pool)
+            .await
+            .map_err(adapter_error)?
+            .into_iter()
+            .filter(|casbin_rule| {
+                casbin_rule.ptype == ptype
+                    && matches_filter(&casbin_rule.rule, field_index, &field_values)
+            })
+            .collect();
+
+        if casbin_rules.is_empty() {
+            return Ok(false);
+        }
+
+        let deleted_rules = delete_casbin_rules(&self.pool, &casbin_rules)
+            .await
+            .map_err(adapter_error)?;
+        Ok(!deleted_rules.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arango::client::fake::FakeClient;
+    use crate::arango::get_database_connection_pool_fake;
+    use casbin::DefaultModel;
+    use serde_json::json;
+
+    #[test]
+    fn matches_filter_test() {
+        let rule = vec![
+            String::from("cats_admin"),
+            String::from("cats"),
+            String::from("list_all_cats"),
+        ];
+        assert!(matches_filter(&rule, 0, &["cats_admin"]));
+        assert!(matches_filter(&rule, 0, &["", "cats"]));
+        assert!(matches_filter(&rule, 1, &["cats", "list_all_cats"]));
+        assert!(!matches_filter(&rule, 0, &["cats_viewer"]));
+        assert!(!matches_filter(&rule, 2, &["list_all_cats", "allow"]));
+    }
+
+    #[tokio::test]
+    async fn load_policy_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([
+            { "ptype": "g", "rule": ["users/2", "admin"] },
+            { "ptype": "p", "rule": ["admin", "cats", "list_all_cats", "allow"] },
+        ]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let mut model = DefaultModel::from_str(include_str!("../rbac_model.conf"))
+            .await
+            .unwrap();
+        ArangodbAdapter::new(pool)
+            .load_policy(&mut model)
+            .await
+            .unwrap();
+
+        assert!(model.has_policy(
+            "p",
+            "p",
+            vec![
+                String::from("admin"),
+                String::from("cats"),
+                String::from("list_all_cats"),
+                String::from("allow"),
+            ]
+        ));
+        assert!(model.has_policy(
+            "g",
+            "g",
+            vec![String::from("users/2"), String::from("admin")]
+        ));
+    }
+
+    #[tokio::test]
+    async fn remove_filtered_policy_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([
+            { "ptype": "g", "rule": ["users/2", "admin"] },
+            { "ptype": "g", "rule": ["users/3", "admin"] },
+            { "ptype": "g", "rule": ["users/3", "employee"] },
+        ]));
+        client.respond_cursor(json!([
+            { "ptype": "g", "rule": ["users/3", "admin"] },
+            { "ptype": "g", "rule": ["users/3", "employee"] },
+        ]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        assert!(ArangodbAdapter::new(pool)
+            .remove_filtered_policy("g", "g", 0, vec![String::from("users/3")])
+            .await
+            .unwrap());
+        assert_eq!(
+            client.requests()[1].body["bindVars"]["casbin_rules"],
+            json!([
+                { "ptype": "g", "rule": ["users/3", "admin"] },
+                { "ptype": "g", "rule": ["users/3", "employee"] },
+            ])
+        );
+        assert!(client.is_exhausted());
+    }
+}
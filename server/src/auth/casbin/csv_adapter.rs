@@ -1,7 +1,8 @@
 use async_trait::async_trait;
 use casbin::{Adapter, Filter, Model, Result};
 
-// TODO: convert to `ArandodbAdapter`
+/// Read-only adapter loading the policies from a CSV string. It's used only to seed the database
+/// policies (see `ArangodbAdapter`) from the original `rbac_policy.csv` file.
 pub struct CSVAdapter {
     csv_content: String,
 }
@@ -20,17 +21,8 @@ impl Adapter for CSVAdapter {
         let mut lines = self.csv_content.lines();
 
         for line in lines.by_ref() {
-            // load_policy_line:
             if let Some(tokens) = crate::auth::casbin::util::parse_csv_line(line) {
-                let key = &tokens[0];
-
-                if let Some(ref sec) = key.chars().next().map(|x| x.to_string()) {
-                    if let Some(ast_map) = m.get_mut_model().get_mut(sec) {
-                        if let Some(ast) = ast_map.get_mut(key) {
-                            ast.policy.insert(tokens[1..].to_vec());
-                        }
-                    }
-                }
+                crate::auth::casbin::util::load_policy_line(&tokens, m);
             }
         }
 
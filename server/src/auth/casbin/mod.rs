@@ -1,3 +1,4 @@
+pub(crate) mod arangodb_adapter;
 pub(crate) mod csv_adapter;
 
-mod util;
+pub(in crate::auth) mod util;
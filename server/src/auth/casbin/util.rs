@@ -1,3 +1,4 @@
+use casbin::Model;
 use lazy_static::lazy_static;
 use regex::Regex;
 
@@ -28,6 +29,20 @@ pub fn parse_csv_line<S: AsRef<str>>(line: S) -> Option<Vec<String>> {
     }
 }
 
+/// Loads one policy line (for example `["p", "admin", "cats", "list_all_cats", "allow"]`) into the
+/// model. The first token is the policy type, its first letter is the model section.
+pub fn load_policy_line(tokens: &[String], m: &mut dyn Model) {
+    if let Some(key) = tokens.first() {
+        if let Some(ref sec) = key.chars().next().map(|x| x.to_string()) {
+            if let Some(ast_map) = m.get_mut_model().get_mut(sec) {
+                if let Some(ast) = ast_map.get_mut(key) {
+                    ast.policy.insert(tokens[1..].to_vec());
+                }
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
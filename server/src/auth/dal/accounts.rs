@@ -1,9 +1,10 @@
+use crate::arango::client::ClientExt;
 use crate::arango::{resolve_aql, resolve_aql_vector, Document};
 use crate::auth::account::Account;
 use crate::auth::users::AnyUser;
 
-pub(crate) async fn find_user_accounts(
-    pool: &crate::arango::ConnectionPool,
+pub(crate) async fn find_user_accounts<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
     user: &AnyUser,
 ) -> anyhow::Result<Vec<Document<Account>>> {
     resolve_aql_vector(
@@ -20,8 +21,8 @@ pub(crate) async fn find_user_accounts(
     .await
 }
 
-pub(crate) async fn create_new_account(
-    pool: &crate::arango::ConnectionPool,
+pub(crate) async fn create_new_account<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
     user: &AnyUser,
 ) -> anyhow::Result<Document<Account>> {
     resolve_aql(
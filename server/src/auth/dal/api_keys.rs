@@ -0,0 +1,180 @@
+use crate::arango::client::ClientExt;
+use crate::arango::{resolve_aql, resolve_aql_vector};
+use crate::auth::api_key::ApiKey;
+
+/// Creates a new API key of the account (the API key hash is used as the document key, similarly
+/// to sessions). Returns `None` when the account doesn't exist.
+pub(crate) async fn create_new_api_key<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    api_key_hash: &str,
+    name: &str,
+    account_id: &str,
+    expires_in_days: &Option<i32>,
+) -> anyhow::Result<Option<ApiKey>> {
+    let result_vector = resolve_aql_vector(
+        pool,
+        r#"
+            LET account = DOCUMENT(accounts, @account_id)
+            FILTER account != null
+            INSERT {
+              _key: @api_key_hash,
+              name: @name,
+              account_id: account._id,
+              created: DATE_ISO8601(DATE_NOW()),
+              expires: @expires_in_days == null
+                ? null
+                : DATE_ISO8601(DATE_ADD(DATE_NOW(), @expires_in_days, "days")),
+              last_used: null,
+            } INTO api_keys
+            RETURN NEW
+        "#,
+        hashmap_json![
+            "api_key_hash" => api_key_hash,
+            "name" => name,
+            "account_id" => account_id,
+            "expires_in_days" => expires_in_days,
+        ],
+    )
+    .await?;
+    Ok(result_vector.into_iter().next())
+}
+
+/// Returns all the API keys (including the expired ones), the newest first.
+pub(crate) async fn list_all_api_keys<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+) -> anyhow::Result<Vec<ApiKey>> {
+    resolve_aql_vector(
+        pool,
+        r#"
+            FOR api_key IN api_keys
+              SORT api_key.created DESC
+              RETURN api_key
+        "#,
+        hashmap_json![],
+    )
+    .await
+}
+
+/// Returns the (not expired) API key by its HASH and updates its last usage time. Returns an error
+/// when there is no such API key.
+pub(crate) async fn get_api_key_by_hash<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    api_key_hash: &str,
+) -> anyhow::Result<ApiKey> {
+    resolve_aql(
+        pool,
+        r#"
+            LET api_key = DOCUMENT(api_keys, @api_key_hash)
+            FILTER api_key != null
+            FILTER api_key.expires == null OR api_key.expires > DATE_ISO8601(DATE_NOW())
+            UPDATE api_key WITH { last_used: DATE_ISO8601(DATE_NOW()) } IN api_keys
+            OPTIONS {
+              // the same API key is often used by many concurrent requests (see the sessions in
+              // `get_user_by_session_token_hash` for more info)
+              exclusive: true
+            }
+            RETURN NEW
+        "#,
+        hashmap_json![
+            "api_key_hash" => api_key_hash,
+        ],
+    )
+    .await
+}
+
+/// Removes the API key and returns it or `None` when there was no such API key.
+pub(crate) async fn delete_api_key<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    api_key_id: &str,
+) -> anyhow::Result<Option<ApiKey>> {
+    let result_vector = resolve_aql_vector(
+        pool,
+        r#"
+            FOR api_key IN api_keys
+              FILTER api_key._id == @api_key_id
+              REMOVE api_key IN api_keys
+              RETURN OLD
+        "#,
+        hashmap_json![
+            "api_key_id" => api_key_id,
+        ],
+    )
+    .await?;
+    Ok(result_vector.into_iter().next())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arango::client::fake::FakeClient;
+    use crate::arango::get_database_connection_pool_fake;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn create_new_api_key_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([{
+            "_id": "api_keys/api_key_hash",
+            "_rev": "_fake_rev",
+            "_key": "api_key_hash",
+            "name": "POS kiosk",
+            "account_id": "accounts/1",
+            "created": "2022-11-01T00:00:00.000Z",
+            "expires": "2022-12-01T00:00:00.000Z",
+            "last_used": null,
+        }]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let api_key =
+            create_new_api_key(&pool, "api_key_hash", "POS kiosk", "accounts/1", &Some(30))
+                .await
+                .unwrap()
+                .unwrap();
+
+        assert_eq!(api_key.id_ref(), "api_keys/api_key_hash");
+        assert_eq!(api_key.account_id_ref(), "accounts/1");
+        assert_eq!(
+            client.requests()[0].body["bindVars"],
+            json!({
+                "api_key_hash": "api_key_hash",
+                "name": "POS kiosk",
+                "account_id": "accounts/1",
+                "expires_in_days": 30,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn create_new_api_key_missing_account_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        assert!(
+            create_new_api_key(&pool, "api_key_hash", "POS kiosk", "accounts/0", &None)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            client.requests()[0].body["bindVars"]["expires_in_days"],
+            json!(null)
+        );
+    }
+
+    #[tokio::test]
+    async fn get_api_key_by_hash_expired_test() {
+        // expired (and non-existent) API keys are filtered out in the query
+        let client = FakeClient::default();
+        client.respond_cursor(json!([]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        assert!(get_api_key_by_hash(&pool, "api_key_hash").await.is_err());
+        assert_eq!(
+            client.requests()[0].body["bindVars"],
+            json!({
+                "api_key_hash": "api_key_hash",
+            })
+        );
+    }
+}
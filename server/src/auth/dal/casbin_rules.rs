@@ -0,0 +1,140 @@
+use crate::arango::client::ClientExt;
+use crate::arango::{resolve_aql_vector, ConnectionPool};
+use serde::{Deserialize, Serialize};
+
+/// One RBAC policy rule as stored in `casbin_rules` collection, for example:
+///
+/// - `{ ptype: "p", rule: ["cats_admin", "cats", "list_all_cats", "allow"] }`
+/// - `{ ptype: "g", rule: ["users/2", "admin"] }`
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct CasbinRule {
+    pub(crate) ptype: String,
+    pub(crate) rule: Vec<String>,
+}
+
+pub(crate) async fn get_all_casbin_rules<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+) -> anyhow::Result<Vec<CasbinRule>> {
+    resolve_aql_vector(
+        pool,
+        r#"
+            FOR casbin_rule IN casbin_rules
+              SORT casbin_rule.ptype, casbin_rule._key
+              RETURN { ptype: casbin_rule.ptype, rule: casbin_rule.rule }
+        "#,
+        hashmap_json![],
+    )
+    .await
+}
+
+/// Creates the rules which don't exist yet and returns only the newly created rules (the existing
+/// rules are not duplicated).
+pub(crate) async fn create_casbin_rules<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    casbin_rules: &[CasbinRule],
+) -> anyhow::Result<Vec<CasbinRule>> {
+    let created_rules: Vec<Option<CasbinRule>> = resolve_aql_vector(
+        pool,
+        r#"
+            FOR casbin_rule IN @casbin_rules
+              UPSERT { ptype: casbin_rule.ptype, rule: casbin_rule.rule }
+              INSERT { ptype: casbin_rule.ptype, rule: casbin_rule.rule }
+              UPDATE {}
+              IN casbin_rules
+              RETURN OLD == null ? { ptype: NEW.ptype, rule: NEW.rule } : null
+        "#,
+        hashmap_json![
+            "casbin_rules" => casbin_rules,
+        ],
+    )
+    .await?;
+    Ok(created_rules.into_iter().flatten().collect())
+}
+
+/// Deletes all the rules which are exactly the same as the specified rules and returns the deleted
+/// rules (there could be fewer of them in case some of the rules didn't exist).
+pub(crate) async fn delete_casbin_rules<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    casbin_rules: &[CasbinRule],
+) -> anyhow::Result<Vec<CasbinRule>> {
+    resolve_aql_vector(
+        pool,
+        r#"
+            FOR casbin_rule IN casbin_rules
+              FILTER { ptype: casbin_rule.ptype, rule: casbin_rule.rule } IN @casbin_rules
+              REMOVE casbin_rule IN casbin_rules
+              RETURN { ptype: OLD.ptype, rule: OLD.rule }
+        "#,
+        hashmap_json![
+            "casbin_rules" => casbin_rules,
+        ],
+    )
+    .await
+}
+
+/// Replaces all the existing rules with the specified rules. It runs in a transaction so the
+/// policies are never observed half-saved.
+pub(crate) async fn replace_all_casbin_rules<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    casbin_rules: &[CasbinRule],
+) -> anyhow::Result<()> {
+    pool.run_in_transaction(&["casbin_rules"], |transaction| async move {
+        transaction
+            .aql_bind_vars::<serde_json::Value>(
+                r#"
+                    FOR casbin_rule IN casbin_rules
+                      REMOVE casbin_rule IN casbin_rules
+                "#,
+                hashmap_json![],
+            )
+            .await?;
+        transaction
+            .aql_bind_vars::<serde_json::Value>(
+                r#"
+                    FOR casbin_rule IN @casbin_rules
+                      INSERT { ptype: casbin_rule.ptype, rule: casbin_rule.rule } INTO casbin_rules
+                "#,
+                hashmap_json![
+                    "casbin_rules" => casbin_rules,
+                ],
+            )
+            .await?;
+        Ok(())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arango::client::fake::FakeClient;
+    use crate::arango::get_database_connection_pool_fake;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn delete_casbin_rules_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([{ "ptype": "g", "rule": ["users/3", "admin"] }]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let casbin_rules = vec![
+            CasbinRule {
+                ptype: String::from("g"),
+                rule: vec![String::from("users/3"), String::from("admin")],
+            },
+            CasbinRule {
+                ptype: String::from("g"),
+                rule: vec![String::from("users/3"), String::from("employee")],
+            },
+        ];
+        let deleted_rules = delete_casbin_rules(&pool, &casbin_rules).await.unwrap();
+        assert_eq!(deleted_rules, casbin_rules[0..1]);
+        assert_eq!(
+            client.requests()[0].body["bindVars"]["casbin_rules"],
+            json!([
+                { "ptype": "g", "rule": ["users/3", "admin"] },
+                { "ptype": "g", "rule": ["users/3", "employee"] },
+            ])
+        );
+    }
+}
@@ -1,3 +1,6 @@
 pub mod accounts;
+pub mod api_keys;
+pub mod casbin_rules;
+pub mod risc_events;
 pub mod sessions;
 pub mod users;
@@ -0,0 +1,63 @@
+use crate::arango::client::ClientExt;
+use crate::arango::{resolve_aql, resolve_aql_vector};
+use crate::auth::risc::{RiscAction, SecurityEvent};
+
+/// Checks whether the security event was already processed. Security event tokens never expire so
+/// Google (or anyone replaying the token) can deliver the same event again.
+pub(crate) async fn is_risc_event_recorded<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    jti: &str,
+    event_type: &str,
+) -> anyhow::Result<bool> {
+    let result_vector = resolve_aql_vector::<bool, _>(
+        pool,
+        r#"
+            FOR risc_event IN risc_events
+              FILTER risc_event.jti == @jti
+              FILTER risc_event.event_type == @event_type
+              LIMIT 1
+              RETURN true
+        "#,
+        hashmap_json![
+            "jti" => jti,
+            "event_type" => event_type,
+        ],
+    )
+    .await?;
+    Ok(!result_vector.is_empty())
+}
+
+/// Records the received RISC security event (together with the action taken) for audit purposes.
+/// The `user_id` is `None` when no user matches the event subject. Recording the same event twice
+/// fails (see the unique `risc_events_jti` index).
+pub(crate) async fn record_risc_event<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    jti: &str,
+    event_type: &str,
+    event: &SecurityEvent,
+    user_id: &Option<String>,
+    action: &RiscAction,
+) -> anyhow::Result<serde_json::Value> {
+    resolve_aql(
+        pool,
+        r#"
+            INSERT {
+              jti: @jti,
+              event_type: @event_type,
+              event: @event,
+              user_id: @user_id,
+              action: @action,
+              received: DATE_ISO8601(DATE_NOW()),
+            } INTO risc_events
+            RETURN NEW
+        "#,
+        hashmap_json![
+            "jti" => jti,
+            "event_type" => event_type,
+            "event" => event,
+            "user_id" => user_id,
+            "action" => action,
+        ],
+    )
+    .await
+}
@@ -1,46 +1,55 @@
+use crate::arango::client::ClientExt;
 use crate::arango::{resolve_aql, resolve_aql_vector};
-use crate::auth::session::Session;
+use crate::auth::session::{Session, SessionDevice};
 use crate::auth::users::AnyUser;
+use crate::global_configuration::SessionsConfiguration;
 
-pub(crate) async fn find_session_by_user(
-    pool: &crate::arango::ConnectionPool,
-    user: &AnyUser,
-) -> Option<Session> {
-    let result_vector = resolve_aql_vector(
+/// Returns all the (not expired) sessions of the user, the most recently used first.
+pub(crate) async fn find_user_sessions<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    user_id: &str,
+    sessions_configuration: &SessionsConfiguration,
+) -> anyhow::Result<Vec<Session>> {
+    resolve_aql_vector(
         pool,
         r#"
-            FOR vertex IN 1..1 OUTBOUND @user_id GRAPH sessions
-            RETURN vertex
+            FOR session IN 1..1 OUTBOUND @user_id GRAPH 'sessions'
+              // sessions without `created` are not backfilled yet (see `backfill_sessions_created`)
+              LET created = NOT_NULL(session.created, session.last_access)
+              FILTER created > DATE_ISO8601(DATE_NOW() - @max_lifetime)
+              FILTER session.last_access > DATE_ISO8601(DATE_NOW() - @inactivity_timeout)
+              SORT session.last_access DESC
+              RETURN MERGE(session, { created })
         "#,
         hashmap_json![
-            "user_id" => user.id(),
+            "user_id" => user_id,
+            "max_lifetime" => sessions_configuration.max_lifetime.as_millis() as u64,
+            "inactivity_timeout" => sessions_configuration.inactivity_timeout.as_millis() as u64,
         ],
     )
-    .await;
-
-    match result_vector {
-        Ok(result_vector) => result_vector.into_iter().next(),
-        Err(err) => {
-            tracing::error!("{}", err);
-            None
-        }
-    }
+    .await
 }
 
-/// Creates a new user session and links it with the user.
-pub(crate) async fn create_new_user_session(
-    pool: &crate::arango::ConnectionPool,
+/// Creates a new user session and links it with the user. The device is recorded so the user can
+/// later recognize (and revoke) the session.
+pub(crate) async fn create_new_user_session<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
     session_token_hash: &str,
     user: &AnyUser,
+    session_device: &SessionDevice,
 ) -> anyhow::Result<Session> {
     // we first create a sessions and then create a session edge (how to do it better (?))
     resolve_aql(
         pool,
         r#"
             LET new_session = FIRST(
+              LET now = DATE_ISO8601(DATE_NOW())
               INSERT {
                 _key: @session_token_hash,
-                last_access: DATE_ISO8601(DATE_NOW()),
+                created: now,
+                last_access: now,
+                user_agent: @session_device.user_agent,
+                ip_address: @session_device.ip_address,
               } INTO sessions
               RETURN NEW
             )
@@ -55,7 +64,8 @@ pub(crate) async fn create_new_user_session(
         "#,
         hashmap_json![
             "session_token_hash" => session_token_hash,
-            "user_id" => user.id()
+            "user_id" => user.id(),
+            "session_device" => session_device,
         ],
     )
     .await
@@ -65,8 +75,8 @@ pub(crate) async fn create_new_user_session(
 /// from the database. Removing the session effectively means "log out".
 ///
 /// TODO(004) add integration tests
-pub(crate) async fn delete_user_session(
-    pool: &crate::arango::ConnectionPool,
+pub(crate) async fn delete_user_session<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
     session_token_hash: &str,
 ) -> anyhow::Result<Session> {
     resolve_aql(
@@ -86,3 +96,266 @@ pub(crate) async fn delete_user_session(
     )
     .await
 }
+
+/// Removes all the sessions of the user (signs the user out from all the devices). Returns the
+/// removed sessions.
+pub(crate) async fn delete_all_user_sessions<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    user_id: &str,
+) -> anyhow::Result<Vec<Session>> {
+    resolve_aql_vector(
+        pool,
+        r#"
+            LET user_sessions_keys = (
+              FOR session, edge IN 1..1 OUTBOUND @user_id GRAPH 'sessions'
+              RETURN { session_key: session._key, edge_key: edge._key }
+            )
+            LET r = (
+              FOR keys IN user_sessions_keys
+              REMOVE keys.edge_key IN user_sessions
+            )
+            FOR keys IN user_sessions_keys
+              REMOVE keys.session_key IN sessions
+              RETURN OLD
+        "#,
+        hashmap_json![
+            "user_id" => user_id,
+        ],
+    )
+    .await
+}
+
+/// Removes the session only when it belongs to the user (so users cannot sign out each other).
+/// Returns the removed session or `None` when there was no such session.
+pub(crate) async fn delete_session_of_user<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    user_id: &str,
+    session_id: &str,
+) -> anyhow::Result<Option<Session>> {
+    let removed_sessions = resolve_aql_vector(
+        pool,
+        r#"
+            LET user_sessions_keys = (
+              FOR session, edge IN 1..1 OUTBOUND @user_id GRAPH 'sessions'
+              FILTER session._id == @session_id
+              RETURN { session_key: session._key, edge_key: edge._key }
+            )
+            LET r = (
+              FOR keys IN user_sessions_keys
+              REMOVE keys.edge_key IN user_sessions
+            )
+            FOR keys IN user_sessions_keys
+              REMOVE keys.session_key IN sessions
+              RETURN OLD
+        "#,
+        hashmap_json![
+            "user_id" => user_id,
+            "session_id" => session_id,
+        ],
+    )
+    .await?;
+    Ok(removed_sessions.into_iter().next())
+}
+
+/// Removes all the sessions of the user except the specified one (signs the user out from all the
+/// other devices). Returns the removed sessions.
+pub(crate) async fn delete_other_user_sessions<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    user_id: &str,
+    session_token_hash: &str,
+) -> anyhow::Result<Vec<Session>> {
+    resolve_aql_vector(
+        pool,
+        r#"
+            LET user_sessions_keys = (
+              FOR session, edge IN 1..1 OUTBOUND @user_id GRAPH 'sessions'
+              FILTER session._key != @session_token_hash
+              RETURN { session_key: session._key, edge_key: edge._key }
+            )
+            LET r = (
+              FOR keys IN user_sessions_keys
+              REMOVE keys.edge_key IN user_sessions
+            )
+            FOR keys IN user_sessions_keys
+              REMOVE keys.session_key IN sessions
+              RETURN OLD
+        "#,
+        hashmap_json![
+            "user_id" => user_id,
+            "session_token_hash" => session_token_hash,
+        ],
+    )
+    .await
+}
+
+/// Removes all the expired sessions (see `SessionsConfiguration`) together with their session
+/// edges. Returns the removed sessions.
+pub(crate) async fn delete_expired_sessions<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    sessions_configuration: &SessionsConfiguration,
+) -> anyhow::Result<Vec<Session>> {
+    resolve_aql_vector(
+        pool,
+        r#"
+            LET expired_sessions_ids = (
+              FOR session IN sessions
+              // sessions without `created` are not backfilled yet (see `backfill_sessions_created`)
+              FILTER NOT_NULL(session.created, session.last_access)
+                  <= DATE_ISO8601(DATE_NOW() - @max_lifetime)
+                OR session.last_access <= DATE_ISO8601(DATE_NOW() - @inactivity_timeout)
+              RETURN session._id
+            )
+            LET r = (
+              FOR edge IN user_sessions
+              FILTER edge._to IN expired_sessions_ids
+              REMOVE edge IN user_sessions
+            )
+            FOR session_id IN expired_sessions_ids
+              REMOVE PARSE_IDENTIFIER(session_id).key IN sessions
+              RETURN MERGE(OLD, { created: NOT_NULL(OLD.created, OLD.last_access) })
+        "#,
+        hashmap_json![
+            "max_lifetime" => sessions_configuration.max_lifetime.as_millis() as u64,
+            "inactivity_timeout" => sessions_configuration.inactivity_timeout.as_millis() as u64,
+        ],
+    )
+    .await
+}
+
+/// Sessions created before we started tracking their creation time don't have the `created` field.
+/// We use the last access time instead so these sessions don't expire immediately.
+pub(crate) async fn backfill_sessions_created<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+) -> anyhow::Result<()> {
+    resolve_aql_vector::<serde_json::Value, _>(
+        pool,
+        r#"
+            FOR session IN sessions
+              FILTER session.created == null
+              UPDATE session WITH { created: session.last_access } IN sessions
+        "#,
+        hashmap_json![],
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arango::client::fake::FakeClient;
+    use crate::arango::get_database_connection_pool_fake;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn create_new_user_session_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([{
+            "_id": "sessions/session_token_hash",
+            "_rev": "_fake_rev",
+            "_key": "session_token_hash",
+            "created": "2022-11-01T00:00:00.000Z",
+            "last_access": "2022-11-01T00:00:00.000Z",
+            "user_agent": "Mozilla/5.0",
+            "ip_address": "127.0.0.1",
+        }]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let user = AnyUser::mock(&Some(String::from("users/1")));
+        let session_device = SessionDevice {
+            user_agent: Some(String::from("Mozilla/5.0")),
+            ip_address: Some(String::from("127.0.0.1")),
+        };
+        let session = create_new_user_session(&pool, "session_token_hash", &user, &session_device)
+            .await
+            .unwrap();
+
+        assert_eq!(session.session_token_hash(), "session_token_hash");
+        assert_eq!(
+            client.requests()[0].body["bindVars"],
+            json!({
+                "session_token_hash": "session_token_hash",
+                "user_id": "users/1",
+                "session_device": {
+                    "user_agent": "Mozilla/5.0",
+                    "ip_address": "127.0.0.1",
+                },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_session_of_user_missing_test() {
+        // sessions of other users are never removed (nothing is returned)
+        let client = FakeClient::default();
+        client.respond_cursor(json!([]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let removed_session = delete_session_of_user(&pool, "users/1", "sessions/other")
+            .await
+            .unwrap();
+
+        assert!(removed_session.is_none());
+        assert_eq!(
+            client.requests()[0].body["bindVars"],
+            json!({
+                "user_id": "users/1",
+                "session_id": "sessions/other",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn find_user_sessions_error_test() {
+        // database errors are propagated (not treated as "no sessions")
+        let client = FakeClient::default();
+        client.respond_error(
+            http::Method::POST,
+            "_api/cursor",
+            500,
+            4,
+            "internal server error",
+        );
+
+        let pool = get_database_connection_pool_fake(&client);
+        assert!(
+            find_user_sessions(&pool, "users/1", &SessionsConfiguration::default())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_expired_sessions_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([{
+            "_id": "sessions/session_token_hash",
+            "_rev": "_fake_rev",
+            "_key": "session_token_hash",
+            "created": "2022-11-01T00:00:00.000Z",
+            "last_access": "2022-11-01T00:00:00.000Z",
+        }]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let sessions_configuration = SessionsConfiguration {
+            max_lifetime: std::time::Duration::from_secs(7200),
+            inactivity_timeout: std::time::Duration::from_secs(60),
+        };
+        let deleted_sessions = delete_expired_sessions(&pool, &sessions_configuration)
+            .await
+            .unwrap();
+
+        assert_eq!(deleted_sessions.len(), 1);
+        assert_eq!(
+            deleted_sessions[0].session_token_hash(),
+            "session_token_hash"
+        );
+        assert_eq!(
+            client.requests()[0].body["bindVars"],
+            json!({
+                "max_lifetime": 7200000,
+                "inactivity_timeout": 60000,
+            })
+        );
+    }
+}
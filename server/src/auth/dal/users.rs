@@ -1,11 +1,13 @@
+use crate::arango::client::ClientExt;
 use crate::arango::{resolve_aql, resolve_aql_vector};
-use crate::auth::google::Claims;
+use crate::auth::oidc::{Claims, OidcProvider};
 use crate::auth::users::AnyUser;
+use crate::global_configuration::SessionsConfiguration;
 
 /// Returns all users except anonymous one - so almost all users (anonymous is not really a user
 /// but rather a special case used in anonymous analytics/tracking for example).
-pub(crate) async fn list_all_users(
-    pool: &crate::arango::ConnectionPool,
+pub(crate) async fn list_all_users<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
 ) -> anyhow::Result<Vec<AnyUser>> {
     resolve_aql_vector(
         pool,
@@ -19,43 +21,101 @@ pub(crate) async fn list_all_users(
     .await
 }
 
-/// Returns user based on Google Claims (`sub`) or `None` if such user couldn't be found.
-pub(crate) async fn find_user_by_google_claims(
-    pool: &crate::arango::ConnectionPool,
+/// Returns user by its ID or `None` if such user doesn't exist (the anonymous user is never
+/// returned).
+pub(crate) async fn get_user_by_id<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    user_id: &str,
+) -> anyhow::Result<Option<AnyUser>> {
+    let result_vector = resolve_aql_vector(
+        pool,
+        r#"
+            LET user = DOCUMENT(users, @user_id)
+            FILTER user != null
+            FILTER user._id != "users/1" // hardcoded anonymous user
+            RETURN user
+        "#,
+        hashmap_json![
+            "user_id" => user_id,
+        ],
+    )
+    .await?;
+    Ok(result_vector.into_iter().next())
+}
+
+/// Activates or deactivates the user. Only active users can sign in (see `crate::auth::authorize`).
+pub(crate) async fn set_user_active<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    user_id: &str,
+    is_active: bool,
+) -> anyhow::Result<AnyUser> {
+    resolve_aql(
+        pool,
+        r#"
+            LET user = DOCUMENT(users, @user_id)
+            FILTER user != null
+            FILTER user._id != "users/1" // hardcoded anonymous user
+            UPDATE user WITH { is_active: @is_active } IN users
+            RETURN NEW
+        "#,
+        hashmap_json![
+            "user_id" => user_id,
+            "is_active" => is_active,
+        ],
+    )
+    .await
+}
+
+/// Returns user based on the provider Claims (`sub`) or `None` if such user couldn't be found.
+pub(crate) async fn find_user_by_claims<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    oidc_provider: &OidcProvider,
     subject: &str,
 ) -> Option<AnyUser> {
-    let resolved = resolve_aql(
+    match try_find_user_by_claims(pool, oidc_provider, subject).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!("{}", e);
+            None
+        }
+    }
+}
+
+/// The same as `find_user_by_claims` but DB failures are returned as errors (instead of being
+/// treated as a missing user) so the caller can retry.
+pub(crate) async fn try_find_user_by_claims<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    oidc_provider: &OidcProvider,
+    subject: &str,
+) -> anyhow::Result<Option<AnyUser>> {
+    let result_vector = resolve_aql_vector(
         pool,
         r#"
             FOR user IN users
               FILTER user._id != "users/1" // hardcoded anonymous user
-              FILTER user.google.sub == @sub
+              FILTER user.@claims_path.sub == @sub
               LIMIT 1
               RETURN user
         "#,
         hashmap_json![
+            "claims_path" => oidc_provider.claims_path(),
             // only `sub` identifies the user reliably, everything else might potentially change
             "sub" => subject,
         ],
     )
-    .await;
-
-    match resolved {
-        Ok(user) => Some(user),
-        Err(e) => {
-            tracing::error!("{}", e);
-            None
-        }
-    }
+    .await?;
+    Ok(result_vector.into_iter().next())
 }
 
 /// Returns user by session token HASH. It also tries to updates the existing session (last access
-/// time) or returns an error if the session doesn't exist (so the user is not logged in).
+/// time) or returns an error if the session doesn't exist (so the user is not logged in). Expired
+/// sessions (see `SessionsConfiguration`) are treated as non-existent.
 ///
 /// TODO(004) add integration tests
-pub async fn get_user_by_session_token_hash(
-    pool: &crate::arango::ConnectionPool,
+pub async fn get_user_by_session_token_hash<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
     session_token_hash: &str,
+    sessions_configuration: &SessionsConfiguration,
 ) -> anyhow::Result<AnyUser> {
     resolve_aql(
         pool,
@@ -63,6 +123,14 @@ pub async fn get_user_by_session_token_hash(
             LET session_key = @session_token_hash
             LET session_id = CONCAT_SEPARATOR('/', 'sessions', session_key)
 
+            // expired sessions are ignored (they are deleted later by the sweeper)
+            LET current_session = DOCUMENT(session_id)
+            FILTER current_session != null
+            // sessions without `created` are not backfilled yet (see `backfill_sessions_created`)
+            FILTER NOT_NULL(current_session.created, current_session.last_access)
+              > DATE_ISO8601(DATE_NOW() - @max_lifetime)
+            FILTER current_session.last_access > DATE_ISO8601(DATE_NOW() - @inactivity_timeout)
+
             FOR user IN 1..1 INBOUND session_id GRAPH 'sessions'
 
             LET session = FIRST(
@@ -92,26 +160,155 @@ pub async fn get_user_by_session_token_hash(
         "#,
         hashmap_json![
             "session_token_hash" => session_token_hash,
+            "max_lifetime" => sessions_configuration.max_lifetime.as_millis() as u64,
+            "inactivity_timeout" => sessions_configuration.inactivity_timeout.as_millis() as u64,
         ],
     ).await
 }
 
-pub(crate) async fn create_inactive_user_by_google_claims(
-    pool: &crate::arango::ConnectionPool,
+/// Creates a new (inactive) user with the provider claims stored in `google` or `oidc.<provider>`
+/// attribute (see `OidcProvider::claims_path`).
+pub(crate) async fn create_inactive_user_by_claims<C: ClientExt>(
+    pool: &crate::arango::ConnectionPool<C>,
+    oidc_provider: &OidcProvider,
     claims: &Claims,
 ) -> anyhow::Result<AnyUser> {
+    let claims_document = oidc_provider.claims_path().into_iter().rev().fold(
+        serde_json::to_value(claims)?,
+        |value, key| {
+            let mut document = serde_json::Map::new();
+            document.insert(key, value);
+            serde_json::Value::Object(document)
+        },
+    );
+
     resolve_aql(
         pool,
         r#"
-            INSERT {
+            INSERT MERGE(@claims_document, {
               is_active: false, // must be always FALSE first!
-              google: @claims_json,
-            } INTO users
+            }) INTO users
             RETURN NEW
         "#,
         hashmap_json![
-            "claims_json" => claims,
+            "claims_document" => claims_document,
         ],
     )
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arango::client::fake::FakeClient;
+    use crate::arango::get_database_connection_pool_fake;
+    use crate::auth::google::google_provider;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn set_user_active_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([{
+            "_id": "users/3",
+            "_rev": "_fake_rev",
+            "_key": "3",
+            "is_active": true,
+            "google": null,
+        }]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let user = set_user_active(&pool, "users/3", true).await.unwrap();
+        assert!(user.is_active());
+        assert_eq!(
+            client.requests()[0].body["bindVars"],
+            json!({
+                "user_id": "users/3",
+                "is_active": true,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn get_user_by_id_missing_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        assert!(get_user_by_id(&pool, "users/1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn get_user_by_session_token_hash_expired_test() {
+        // expired (and non-existent) sessions are filtered out in the query
+        let client = FakeClient::default();
+        client.respond_cursor(json!([]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let sessions_configuration = SessionsConfiguration {
+            max_lifetime: std::time::Duration::from_secs(7200),
+            inactivity_timeout: std::time::Duration::from_secs(60),
+        };
+        assert!(get_user_by_session_token_hash(
+            &pool,
+            "session_token_hash",
+            &sessions_configuration
+        )
+        .await
+        .is_err());
+        assert_eq!(
+            client.requests()[0].body["bindVars"],
+            json!({
+                "session_token_hash": "session_token_hash",
+                "max_lifetime": 7200000,
+                "inactivity_timeout": 60000,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn create_inactive_user_by_claims_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([{
+            "_id": "users/3",
+            "_rev": "_fake_rev",
+            "_key": "3",
+            "is_active": false,
+            "oidc": { "test": Claims::mock("12345") },
+        }]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let oidc_provider = OidcProvider::new(
+            "test",
+            &["https://id.example.com"],
+            "https://id.example.com/jwks",
+            &["abacus-client-id"],
+        );
+        let user = create_inactive_user_by_claims(&pool, &oidc_provider, &Claims::mock("12345"))
+            .await
+            .unwrap();
+
+        assert!(!user.is_active());
+        assert_eq!(
+            client.requests()[0].body["bindVars"]["claims_document"]["oidc"]["test"]["sub"],
+            json!("12345")
+        );
+    }
+
+    #[tokio::test]
+    async fn find_user_by_claims_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        assert!(find_user_by_claims(&pool, &google_provider(), "12345")
+            .await
+            .is_none());
+        assert_eq!(
+            client.requests()[0].body["bindVars"],
+            json!({
+                "claims_path": ["google"],
+                "sub": "12345",
+            })
+        );
+    }
+}
@@ -2,18 +2,27 @@ use crate::arango;
 use crate::auth::account::Account;
 use crate::auth::certs::CachedCerts;
 use crate::auth::dal::accounts;
-use crate::auth::dal::sessions::{create_new_user_session, delete_user_session};
+use crate::auth::dal::api_keys::get_api_key_by_hash;
+use crate::auth::dal::sessions::{
+    backfill_sessions_created, create_new_user_session, delete_expired_sessions,
+    delete_user_session,
+};
 use crate::auth::dal::users::{
-    create_inactive_user_by_google_claims, find_user_by_google_claims,
-    get_user_by_session_token_hash,
+    create_inactive_user_by_claims, find_user_by_claims, get_user_by_session_token_hash,
 };
-use crate::auth::google::verify_id_token_integrity;
-use crate::auth::session::derive_session_token_hash;
-use crate::auth::users::{AnonymousUser, AnyUser, SignedUser, User};
-use crate::headers::parse_authorization_header;
+use crate::auth::oidc::{verify_id_token_integrity, OidcProvider};
+use crate::auth::session::{derive_session_token_hash, SessionDevice};
+use crate::auth::users::{AnonymousUser, AnyUser, ApiKeyUser, SignedUser, User};
+use crate::global_configuration::SessionsConfiguration;
+use crate::headers::{parse_authorization_header, AuthorizationHeader};
+use std::time::Duration;
 
 pub(crate) mod api;
+pub(crate) mod api_key;
+pub(crate) mod oidc;
 pub(crate) mod rbac;
+pub(crate) mod risc;
+pub(crate) mod session;
 pub(crate) mod users;
 
 mod account;
@@ -22,13 +31,14 @@ mod casbin;
 mod certs;
 mod dal;
 mod google;
-mod session;
 
-/// This function tries to authorize a user by Google ID token (registers otherwise). It essentially
-/// transforms Google ID token to our Session Token, which is vendor independent, and we have it
-/// under absolute control.
+const EXPIRED_SESSIONS_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// This function tries to authorize a user by OpenID Connect ID token of the provider (Google for
+/// example, registers otherwise). It essentially transforms the ID token to our Session Token,
+/// which is vendor independent, and we have it under absolute control.
 ///
-/// 1.  Validate the Google ID token (and immediately reject if not valid).
+/// 1.  Validate the ID token (and immediately reject if not valid).
 /// 2a. Fetch existing user and create a new session even if it already exists. Please note: it's
 ///     not possible to simply return the session token after first authorization because it's
 ///     hashed in the DB and we cannot reverse it. We have to always generate a new one because it
@@ -38,12 +48,14 @@ mod session;
 ///     user (inactive) so it can be later activated (whitelisted).
 pub(in crate::auth) async fn authorize(
     pool: &arango::ConnectionPool,
-    google_id_token: &str,
+    oidc_provider: &OidcProvider,
+    id_token: &str,
+    session_device: &SessionDevice,
 ) -> anyhow::Result<String> {
-    let mut cached_certs = certs::CachedCertsProduction::new();
-    let token_data = verify_id_token_integrity(google_id_token, &mut cached_certs).await?; // (1.)
+    let mut cached_certs = certs::CachedCertsProduction::new(oidc_provider.jwks_url());
+    let token_data = verify_id_token_integrity(oidc_provider, id_token, &mut cached_certs).await?; // (1.)
 
-    let user = find_user_by_google_claims(pool, token_data.claims.subject()).await;
+    let user = find_user_by_claims(pool, oidc_provider, token_data.claims.subject()).await;
 
     let session_token = match user {
         // 2a. the user already exists
@@ -57,13 +69,13 @@ pub(in crate::auth) async fn authorize(
             // create a new session (don't delete old ones so we can login from multiple devices)
             let session_token = session::generate_session_token();
             let session_token_hash = derive_session_token_hash(&session_token);
-            create_new_user_session(&pool, &session_token_hash, &user).await?;
+            create_new_user_session(&pool, &session_token_hash, &user, session_device).await?;
             session_token
         }
         None => {
-            // 2b. new user with valid Google ID token - create and reject it
+            // 2b. new user with valid ID token - create and reject it
             let inactive_user =
-                create_inactive_user_by_google_claims(pool, &token_data.claims).await?;
+                create_inactive_user_by_claims(pool, oidc_provider, &token_data.claims).await?;
 
             ensure_user_account_exists(&pool, &inactive_user).await?;
 
@@ -99,10 +111,9 @@ async fn ensure_user_account_exists(
 /// It returns `true` if the operation was successful.
 pub(in crate::auth) async fn deauthorize(
     pool: &arango::ConnectionPool,
-    session_token: &str,
+    session_token_hash: &str,
 ) -> anyhow::Result<bool> {
-    let session_token_hash = derive_session_token_hash(session_token);
-    delete_user_session(pool, &session_token_hash).await?;
+    delete_user_session(pool, session_token_hash).await?;
     Ok(true) // success
 }
 
@@ -112,27 +123,51 @@ pub(in crate::auth) async fn deauthorize(
 /// invalid format).
 pub(crate) async fn get_current_user(
     pool: &arango::ConnectionPool,
+    sessions_configuration: &SessionsConfiguration,
     authorization_header: &Option<String>,
 ) -> Result<User, String> {
     match authorization_header {
         Some(authorization_header) => {
             // auth header exists, let's try to parse it
             match parse_authorization_header(authorization_header) {
-                Ok(session_token) => {
+                Ok(AuthorizationHeader::Bearer(session_token)) => {
                     // auth header successfully parsed (unverified)
-                    match crate::auth::resolve_user_from_session_token(pool, &session_token).await {
-                        User::AnonymousUser(_) => {
-                            tracing::error!("Unmatched session token 🛑");
-                            Err(String::from("Session token doesn't match any user."))
-                        }
+                    match crate::auth::resolve_user_from_session_token(
+                        pool,
+                        sessions_configuration,
+                        &session_token,
+                    )
+                    .await
+                    {
                         User::SignedUser(user) => {
                             tracing::debug!("Using SIGNED user: {}", user.id());
                             Ok(User::SignedUser(user))
                         }
+                        _ => {
+                            tracing::error!("Unmatched session token 🛑");
+                            Err(String::from("Session token doesn't match any user."))
+                        }
+                    }
+                }
+                Ok(AuthorizationHeader::ApiKey(api_key)) => {
+                    // auth header successfully parsed (unverified)
+                    match crate::auth::resolve_user_from_api_key(pool, &api_key).await {
+                        User::ApiKeyUser(user) => {
+                            tracing::debug!(
+                                "Using API KEY user: {} (account: {})",
+                                user.id(),
+                                user.account_id_ref()
+                            );
+                            Ok(User::ApiKeyUser(user))
+                        }
+                        _ => {
+                            tracing::error!("Unmatched API key 🛑");
+                            Err(String::from("API key doesn't match any active API key."))
+                        }
                     }
                 }
                 Err(_) => Err(String::from(
-                    "Unable to parse 'authorization' header (should be 'Bearer XYZ').",
+                    "Unable to parse 'authorization' header (should be 'Bearer XYZ' or 'ApiKey XYZ').",
                 )),
             }
         }
@@ -147,11 +182,27 @@ pub(crate) async fn get_current_user(
 /// This function verifies the session token and returns either authorized OR anonymous user.
 async fn resolve_user_from_session_token(
     pool: &arango::ConnectionPool,
+    sessions_configuration: &SessionsConfiguration,
     session_token: &str,
 ) -> User {
     let session_token_hash = derive_session_token_hash(session_token);
-    match get_user_by_session_token_hash(pool, &session_token_hash).await {
-        Ok(user) => User::SignedUser(SignedUser::from(user)),
+    match get_user_by_session_token_hash(pool, &session_token_hash, sessions_configuration).await {
+        Ok(user) => {
+            User::SignedUser(SignedUser::from(user).with_session_token_hash(&session_token_hash))
+        }
+        Err(error) => {
+            tracing::error!("{}", error);
+            User::AnonymousUser(AnonymousUser::new())
+        }
+    }
+}
+
+/// This function verifies the API key and returns either API key user OR anonymous user. API keys
+/// are hashed the same way as the session tokens.
+async fn resolve_user_from_api_key(pool: &arango::ConnectionPool, api_key: &str) -> User {
+    let api_key_hash = derive_session_token_hash(api_key);
+    match get_api_key_by_hash(pool, &api_key_hash).await {
+        Ok(api_key) => User::ApiKeyUser(ApiKeyUser::from(api_key)),
         Err(error) => {
             tracing::error!("{}", error);
             User::AnonymousUser(AnonymousUser::new())
@@ -159,6 +210,33 @@ async fn resolve_user_from_session_token(
     }
 }
 
+/// Periodically deletes the expired sessions (together with their session edges). Expired
+/// sessions are rejected even before they are deleted (see `get_user_by_session_token_hash`), so
+/// the sweeper only keeps the `sessions` collection small.
+pub(crate) fn spawn_expired_sessions_sweeper(
+    pool: arango::ConnectionPool,
+    sessions_configuration: SessionsConfiguration,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EXPIRED_SESSIONS_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match delete_expired_sessions(&pool, &sessions_configuration).await {
+                Ok(deleted_sessions) if deleted_sessions.is_empty() => {}
+                Ok(deleted_sessions) => {
+                    tracing::info!("🧹 Deleted {} expired sessions", deleted_sessions.len())
+                }
+                Err(error) => tracing::error!("unable to delete expired sessions: {}", error),
+            }
+        }
+    });
+}
+
+/// Sets the missing creation time of the existing sessions (see `migrations`).
+pub(crate) async fn backfill_sessions(pool: &arango::ConnectionPool) -> anyhow::Result<()> {
+    backfill_sessions_created(pool).await
+}
+
 #[cfg(test)]
 mod tests {
     use crate::arango::get_database_connection_pool_mock;
@@ -169,7 +247,9 @@ mod tests {
     async fn get_current_user_without_authorization_header() {
         let pool = get_database_connection_pool_mock();
         assert!(matches!(
-            get_current_user(&pool, &None).await.unwrap(),
+            get_current_user(&pool, &SessionsConfiguration::default(), &None)
+                .await
+                .unwrap(),
             User::AnonymousUser { .. }
         ));
     }
@@ -178,11 +258,11 @@ mod tests {
     async fn get_current_user_with_empty_authorization_header() {
         let pool = get_database_connection_pool_mock();
         insta::assert_snapshot!(
-            get_current_user(&pool, &Some(String::from("")))
+            get_current_user(&pool, &SessionsConfiguration::default(), &Some(String::from("")))
             .await
             .err()
             .unwrap(),
-             @"Unable to parse 'authorization' header (should be 'Bearer XYZ')."
+             @"Unable to parse 'authorization' header (should be 'Bearer XYZ' or 'ApiKey XYZ')."
         );
     }
 
@@ -190,11 +270,11 @@ mod tests {
     async fn get_current_user_with_unparseable_authorization_header() {
         let pool = get_database_connection_pool_mock();
         insta::assert_snapshot!(
-            get_current_user(&pool, &Some(String::from("XYZ")))
+            get_current_user(&pool, &SessionsConfiguration::default(), &Some(String::from("XYZ")))
             .await
             .err()
             .unwrap(),
-             @"Unable to parse 'authorization' header (should be 'Bearer XYZ')."
+             @"Unable to parse 'authorization' header (should be 'Bearer XYZ' or 'ApiKey XYZ')."
         );
     }
 }
@@ -0,0 +1,401 @@
+use crate::auth::certs::{CachedCerts, CertKey};
+use crate::auth::google::{google_provider, GOOGLE_PROVIDER_NAME};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, TokenData, Validation};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Claims of the OpenID Connect ID token. Only `iss`, `sub`, `aud`, `iat` and `exp` are required
+/// by the spec, everything else depends on the provider and the granted scopes.
+///
+/// See: https://openid.net/specs/openid-connect-core-1_0.html#IDToken
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    iss: String,         // Issuer (who created and signed this token)
+    sub: String,         // Subject (whom the token refers to)
+    azp: Option<String>, // Authorized party (the party to which this token was issued)
+    aud: ClaimsAudience, // Audience (who or what the token is intended for)
+    iat: usize,          // Issued at (seconds since Unix epoch)
+    exp: usize,          // Expiration time (seconds since Unix epoch)
+
+    // These fields are usually included only when the user has granted the "profile" and "email"
+    // OAuth scopes to the application.
+    email: Option<String>,
+    email_verified: Option<bool>,
+    name: Option<String>,
+    picture: Option<String>,
+    given_name: Option<String>,
+    family_name: Option<String>,
+    locale: Option<String>,
+}
+
+/// Audience can be either a single client ID or an array of them (both are valid).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ClaimsAudience {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Claims {
+    pub(crate) fn subject(&self) -> &String {
+        &self.sub
+    }
+
+    pub(crate) fn name(&self) -> &Option<String> {
+        &self.name
+    }
+
+    pub(crate) fn given_name(&self) -> &Option<String> {
+        &self.given_name
+    }
+
+    pub(crate) fn family_name(&self) -> &Option<String> {
+        &self.family_name
+    }
+
+    pub(crate) fn is_email_verified(&self) -> &Option<bool> {
+        &self.email_verified
+    }
+
+    #[cfg(test)]
+    pub(crate) fn mock(sub: &str) -> Claims {
+        Claims {
+            iss: String::from(""),
+            sub: sub.to_string(),
+            azp: None,
+            aud: ClaimsAudience::Single(String::from("")),
+            iat: 0,
+            exp: 0,
+            email: None,
+            email_verified: None,
+            name: None,
+            picture: None,
+            given_name: None,
+            family_name: None,
+            locale: None,
+        }
+    }
+}
+
+/// OpenID Connect provider whose ID tokens we accept (see `google_provider` for example).
+#[derive(Clone, Debug, PartialEq)]
+pub struct OidcProvider {
+    /// Unique name of the provider (`google` for example) used in `authorizeWebapp` mutation.
+    name: String,
+    /// Accepted values of the `iss` claim.
+    issuers: Vec<String>,
+    /// Public keys used to verify the ID token signature.
+    jwks_url: String,
+    /// Accepted values of the `aud` claim (our client IDs registered with the provider).
+    audiences: Vec<String>,
+}
+
+impl OidcProvider {
+    pub fn new(name: &str, issuers: &[&str], jwks_url: &str, audiences: &[&str]) -> Self {
+        OidcProvider {
+            name: name.to_string(),
+            issuers: issuers.iter().map(|issuer| issuer.to_string()).collect(),
+            jwks_url: jwks_url.to_string(),
+            audiences: audiences
+                .iter()
+                .map(|audience| audience.to_string())
+                .collect(),
+        }
+    }
+
+    pub(crate) fn jwks_url(&self) -> &str {
+        &self.jwks_url
+    }
+
+    pub(crate) fn audiences(&self) -> &[String] {
+        &self.audiences
+    }
+
+    /// Path of the provider claims in the `users` documents. Google claims are stored in `google`
+    /// attribute (historically the only provider), claims of the other providers in `oidc.<name>`.
+    pub(crate) fn claims_path(&self) -> Vec<String> {
+        if self.name == GOOGLE_PROVIDER_NAME {
+            vec![self.name.to_owned()]
+        } else {
+            vec![String::from("oidc"), self.name.to_owned()]
+        }
+    }
+
+    fn create_id_token_validation(&self) -> Validation {
+        let mut validation = Validation::new(
+            Algorithm::RS256, // we assume it's always RS256 - is it true?
+        );
+        validation.leeway = 30; // seconds
+        validation.validate_exp = !cfg!(test); // disabled in tests
+        validation.validate_nbf = false; // NBF (not before) not present in the token
+        validation.set_audience(&self.audiences);
+        validation.set_issuer(&self.issuers);
+        validation
+    }
+}
+
+/// Parses the provider from `name,issuer,jwks_url,audience` format (see `--oidc-provider`).
+impl FromStr for OidcProvider {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+        match parts[..] {
+            [name, issuer, jwks_url, audience]
+                if !issuer.is_empty() && !jwks_url.is_empty() && !audience.is_empty() =>
+            {
+                // the name is used as an attribute name in the `users` documents
+                if name.is_empty()
+                    || !name
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+                {
+                    anyhow::bail!("invalid OpenID Connect provider name: '{}'", name);
+                }
+                Ok(OidcProvider::new(name, &[issuer], jwks_url, &[audience]))
+            }
+            _ => anyhow::bail!(
+                "OpenID Connect provider must be in 'name,issuer,jwks_url,audience' format: '{}'",
+                value
+            ),
+        }
+    }
+}
+
+/// Registry of the OpenID Connect providers users can sign in with. Google is always registered,
+/// other providers are configured via `--oidc-provider` (and they can replace the built-in Google
+/// provider when using the same name).
+#[derive(Clone)]
+pub struct OidcProviders {
+    providers: Vec<OidcProvider>,
+}
+
+impl OidcProviders {
+    pub fn new(configured_providers: Vec<OidcProvider>) -> Self {
+        let mut providers = vec![google_provider()];
+        for configured_provider in configured_providers {
+            providers.retain(|provider| provider.name != configured_provider.name);
+            providers.push(configured_provider);
+        }
+        OidcProviders { providers }
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&OidcProvider> {
+        self.providers.iter().find(|provider| provider.name == name)
+    }
+
+    /// Google provider (built-in or configured one), it's used to verify RISC security events.
+    pub(crate) fn google(&self) -> &OidcProvider {
+        self.get(GOOGLE_PROVIDER_NAME)
+            .expect("Google provider is always registered")
+    }
+}
+
+fn validate_id_token(
+    provider: &OidcProvider,
+    id_token: &str,
+    cert: &CertKey,
+) -> anyhow::Result<TokenData<Claims>> {
+    let decoding_key = &DecodingKey::from_rsa_components(cert.modulus(), cert.exponent())?;
+    match decode(
+        id_token,
+        decoding_key,
+        &provider.create_id_token_validation(),
+    ) {
+        Ok(token_data) => Ok(token_data),
+        Err(err) => anyhow::bail!(err),
+    }
+}
+
+/// To verify that the token is valid, ensure that the following criteria are satisfied:
+///
+/// 1. The ID token is properly signed by the provider. Use provider's public keys (JWKS) to verify
+///    the token's signature. These keys are regularly rotated (see `CachedCertsProduction`).
+/// 2. The value of `aud` in the ID token is equal to one of your app's client IDs. This check is
+///    necessary to prevent ID tokens issued to a malicious app being used to access data about the
+///    same user on your app's backend server.
+/// 3. The value of `iss` in the ID token is equal to one of the provider issuers.
+/// 4. The expiry time (`exp`) of the ID token has not passed.
+///
+/// See: https://openid.net/specs/openid-connect-core-1_0.html#IDTokenValidation
+pub async fn verify_id_token_integrity<T: CachedCerts>(
+    provider: &OidcProvider,
+    id_token: &str,
+    cached_certs: &mut T,
+) -> anyhow::Result<TokenData<Claims>> {
+    // unsafe_* to remind that this header was not verified
+    let unsafe_header = decode_header(id_token)?;
+    if let Some(kid) = unsafe_header.kid {
+        if let Some(key) = cached_certs.get_key_by_kid(&kid).await {
+            validate_id_token(provider, id_token, &key)
+        } else {
+            anyhow::bail!(
+                "cannot obtain '{}' certificate for key ID: '{}'",
+                provider.name,
+                kid
+            );
+        }
+    } else {
+        anyhow::bail!("cannot get 'kid' from the token header")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::certs::CachedCertsLocal;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    fn test_provider() -> OidcProvider {
+        OidcProvider::new(
+            "test",
+            &["https://id.example.com"],
+            "https://id.example.com/jwks",
+            &["abacus-client-id"],
+        )
+    }
+
+    fn sign_id_token(claims: serde_json::Value) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(String::from("test-key-1"));
+        let encoding_key =
+            EncodingKey::from_rsa_pem(include_bytes!("fixtures/oidc_private_key.pem")).unwrap();
+        encode(&header, &claims, &encoding_key).unwrap()
+    }
+
+    fn local_certs() -> CachedCertsLocal {
+        CachedCertsLocal::from_jwks(include_str!("fixtures/oidc_jwks.json"))
+    }
+
+    #[test]
+    fn oidc_provider_from_str_test() {
+        assert_eq!(
+            OidcProvider::from_str(
+                "test, https://id.example.com, https://id.example.com/jwks, abacus-client-id"
+            )
+            .unwrap(),
+            test_provider()
+        );
+        assert_eq!(
+            format!("{}", OidcProvider::from_str("test,https://id.example.com").unwrap_err()),
+            "OpenID Connect provider must be in 'name,issuer,jwks_url,audience' format: 'test,https://id.example.com'"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                OidcProvider::from_str("Test.Provider,a,b,c").unwrap_err()
+            ),
+            "invalid OpenID Connect provider name: 'Test.Provider'"
+        );
+    }
+
+    #[test]
+    fn oidc_providers_test() {
+        let providers = OidcProviders::new(vec![test_provider()]);
+        assert_eq!(providers.get("google"), Some(&google_provider()));
+        assert_eq!(providers.get("test"), Some(&test_provider()));
+        assert_eq!(providers.get("unknown"), None);
+
+        // configured providers replace the built-in ones
+        let custom_google = OidcProvider::new(
+            "google",
+            &["https://accounts.google.com"],
+            "https://www.googleapis.com/oauth2/v3/certs",
+            &["custom-client-id"],
+        );
+        let providers = OidcProviders::new(vec![custom_google.to_owned()]);
+        assert_eq!(providers.get("google"), Some(&custom_google));
+        assert_eq!(providers.google(), &custom_google);
+    }
+
+    #[test]
+    fn oidc_provider_claims_path_test() {
+        assert_eq!(google_provider().claims_path(), vec!["google"]);
+        assert_eq!(test_provider().claims_path(), vec!["oidc", "test"]);
+    }
+
+    #[tokio::test]
+    async fn verify_id_token_integrity_valid() {
+        let id_token = sign_id_token(json!({
+            "iss": "https://id.example.com",
+            "sub": "12345",
+            "aud": ["abacus-client-id", "other-client-id"],
+            "iat": 1700000000,
+            "exp": 1700003600,
+            "email": "john@example.com",
+            "name": "John Doe",
+        }));
+
+        let token_data = verify_id_token_integrity(&test_provider(), &id_token, &mut local_certs())
+            .await
+            .unwrap();
+        assert_eq!(token_data.claims.subject(), "12345");
+        assert_eq!(token_data.claims.name(), &Some(String::from("John Doe")));
+    }
+
+    #[tokio::test]
+    async fn verify_id_token_integrity_invalid_audience() {
+        let id_token = sign_id_token(json!({
+            "iss": "https://id.example.com",
+            "sub": "12345",
+            "aud": "malicious-client-id",
+            "iat": 1700000000,
+            "exp": 1700003600,
+        }));
+
+        assert_eq!(
+            format!(
+                "{:?}",
+                verify_id_token_integrity(&test_provider(), &id_token, &mut local_certs())
+                    .await
+                    .err()
+                    .unwrap()
+            ),
+            "InvalidAudience"
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_id_token_integrity_invalid_issuer() {
+        // token signed by a trusted key but issued for a different provider
+        let id_token = sign_id_token(json!({
+            "iss": "https://accounts.google.com",
+            "sub": "12345",
+            "aud": "abacus-client-id",
+            "iat": 1700000000,
+            "exp": 1700003600,
+        }));
+
+        assert_eq!(
+            format!(
+                "{:?}",
+                verify_id_token_integrity(&test_provider(), &id_token, &mut local_certs())
+                    .await
+                    .err()
+                    .unwrap()
+            ),
+            "InvalidIssuer"
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_id_token_integrity_unknown_key() {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(String::from("unknown-key"));
+        let encoding_key =
+            EncodingKey::from_rsa_pem(include_bytes!("fixtures/oidc_private_key.pem")).unwrap();
+        let id_token = encode(&header, &json!({ "sub": "12345" }), &encoding_key).unwrap();
+
+        assert_eq!(
+            format!(
+                "{:?}",
+                verify_id_token_integrity(&test_provider(), &id_token, &mut local_certs())
+                    .await
+                    .err()
+                    .unwrap()
+            ),
+            "cannot obtain 'test' certificate for key ID: 'unknown-key'"
+        );
+    }
+}
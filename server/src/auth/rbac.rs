@@ -1,12 +1,25 @@
+use crate::arango::client::reqwest::ReqwestClient;
+use crate::arango::client::ClientExt;
+use crate::arango::ConnectionPool;
+use crate::auth::casbin::arangodb_adapter::ArangodbAdapter;
 use crate::auth::casbin::csv_adapter::CSVAdapter;
+use crate::auth::casbin::util::parse_csv_line;
+use crate::auth::dal::casbin_rules::{create_casbin_rules, get_all_casbin_rules, CasbinRule};
 use crate::auth::users::User;
-use casbin::{CoreApi, DefaultModel, Error as CasbinError};
+use casbin::{Adapter, CoreApi, DefaultModel, Error as CasbinError, RbacApi};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{MappedRwLockWriteGuard, RwLock, RwLockWriteGuard};
 
 #[allow(clippy::enum_variant_names)]
 pub(crate) enum AnalyticsActions {
     GetRedirectHits,
 }
 
+pub(crate) enum ArchiveActions {
+    GetArchiveItems,
+}
+
 pub(crate) enum CatsActions {
     ListAllCats,
 }
@@ -20,6 +33,9 @@ pub(crate) enum CommerceActions {
     GetAllProducts, // means ALL - published/unpublished
     GetAllProductCategories,
     GetAllProductAddons,
+    GetAllOrders,
+    UpdateProductStock,
+    RestoreProduct,
 }
 
 pub(crate) enum FilesActions {
@@ -34,10 +50,20 @@ pub(crate) enum PosActions {
 
 pub(crate) enum UsersActions {
     GetAllUsers,
+    ActivateUser,
+    DeactivateUser,
+    AssignUserRole,
+    UnassignUserRole,
+    GetUserPermissions,
+    RevokeUserSessions,
+    ListApiKeys,
+    CreateApiKey,
+    RevokeApiKey,
 }
 
 pub(crate) enum Actions {
     Analytics(AnalyticsActions),
+    Archive(ArchiveActions),
     Cats(CatsActions),
     Commerce(CommerceActions),
     Files(FilesActions),
@@ -45,6 +71,27 @@ pub(crate) enum Actions {
     Users(UsersActions),
 }
 
+/// Roles which can be assigned to the users via API (see `rbac_policy.csv` for what they allow).
+#[derive(Clone, Copy, Debug, juniper::GraphQLEnum)]
+pub(crate) enum UserRole {
+    Admin,
+    Employee,
+    CatsViewer,
+    CommerceViewer,
+}
+
+impl UserRole {
+    /// Name of the role as used in the RBAC policies.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            UserRole::Admin => "admin",
+            UserRole::Employee => "employee",
+            UserRole::CatsViewer => "cats_viewer",
+            UserRole::CommerceViewer => "commerce_viewer",
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum RbacError {
     #[error("user is not logged in (anonymous)")]
@@ -59,134 +106,405 @@ pub enum RbacError {
     Casbin(#[from] CasbinError),
 }
 
+/// How often the policies are checked for changes (see `RbacEnforcer::reload_if_changed`).
+const RBAC_RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+struct LoadedEnforcer {
+    enforcer: casbin::Enforcer,
+    // policies the enforcer was built from (to detect changes in DB)
+    casbin_rules: Vec<CasbinRule>,
+}
+
+/// Casbin enforcer shared by all the requests (it's stored in the application state). Building the
+/// enforcer means parsing the model and loading all the policies from DB so we do it lazily only
+/// once and then reuse it. The enforcer is rebuilt when the policies change:
+///
+/// - immediately after changing the policies via our API (see `RbacEnforcer::assign_role`)
+/// - periodically when the policies were changed in DB directly (see `RBAC_RELOAD_INTERVAL`)
+#[derive(Clone)]
+pub struct RbacEnforcer<C: ClientExt = ReqwestClient> {
+    pool: ConnectionPool<C>,
+    state: Arc<RwLock<Option<LoadedEnforcer>>>,
+}
+
+impl<C: ClientExt> RbacEnforcer<C> {
+    pub(crate) fn new(pool: ConnectionPool<C>) -> Self {
+        RbacEnforcer {
+            pool,
+            state: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    async fn load(&self) -> anyhow::Result<LoadedEnforcer> {
+        let casbin_rules = get_all_casbin_rules(&self.pool).await?;
+        let model = DefaultModel::from_str(include_str!("rbac_model.conf")).await?;
+        let adapter = ArangodbAdapter::new(self.pool.to_owned());
+        let enforcer = casbin::Enforcer::new(model, adapter)
+            .await
+            .map_err(RbacError::Casbin)?;
+
+        Ok(LoadedEnforcer {
+            enforcer,
+            casbin_rules,
+        })
+    }
+
+    /// Returns the enforcer locked for writing (the enforcer is built when it wasn't built yet).
+    async fn write_loaded(&self) -> anyhow::Result<MappedRwLockWriteGuard<'_, LoadedEnforcer>> {
+        let mut state = self.state.write().await;
+        if state.is_none() {
+            *state = Some(self.load().await?);
+            self.spawn_reloader();
+        }
+        RwLockWriteGuard::try_map(state, |state| state.as_mut())
+            .map_err(|_| anyhow::anyhow!("RBAC enforcer is not loaded"))
+    }
+
+    /// Returns `true` when the subject is allowed to perform the action. The enforcer is built
+    /// during the first call.
+    pub(crate) async fn enforce(&self, sub: &str, obj: &str, act: &str) -> anyhow::Result<bool> {
+        if let Some(loaded) = self.state.read().await.as_ref() {
+            return Ok(loaded
+                .enforcer
+                .enforce((sub, obj, act))
+                .map_err(RbacError::Casbin)?);
+        }
+
+        let loaded = self.write_loaded().await?;
+        Ok(loaded
+            .enforcer
+            .enforce((sub, obj, act))
+            .map_err(RbacError::Casbin)?)
+    }
+
+    /// Assigns the role to the user (the change is saved to DB and applied immediately). Returns
+    /// `false` when the user had the role already.
+    pub(crate) async fn assign_role(&self, user_id: &str, role: &UserRole) -> anyhow::Result<bool> {
+        let mut loaded = self.write_loaded().await?;
+        let assigned = loaded
+            .enforcer
+            .add_role_for_user(user_id, role.name(), None)
+            .await
+            .map_err(RbacError::Casbin)?;
+        // we changed the policies ourselves so there is no need to reload them later
+        loaded.casbin_rules = get_all_casbin_rules(&self.pool).await?;
+        Ok(assigned)
+    }
+
+    /// Unassigns the role from the user (the change is saved to DB and applied immediately).
+    /// Returns `false` when the user didn't have the role.
+    pub(crate) async fn unassign_role(
+        &self,
+        user_id: &str,
+        role: &UserRole,
+    ) -> anyhow::Result<bool> {
+        let mut loaded = self.write_loaded().await?;
+        let unassigned = loaded
+            .enforcer
+            .delete_role_for_user(user_id, role.name(), None)
+            .await
+            .map_err(RbacError::Casbin)?;
+        // we changed the policies ourselves so there is no need to reload them later
+        loaded.casbin_rules = get_all_casbin_rules(&self.pool).await?;
+        Ok(unassigned)
+    }
+
+    /// Unassigns all the roles from the subject (used when removing API keys so no dangling
+    /// policies are left behind). The change is saved to DB and applied immediately.
+    pub(crate) async fn unassign_all_roles(&self, sub: &str) -> anyhow::Result<bool> {
+        let mut loaded = self.write_loaded().await?;
+        let unassigned = loaded
+            .enforcer
+            .delete_roles_for_user(sub, None)
+            .await
+            .map_err(RbacError::Casbin)?;
+        // we changed the policies ourselves so there is no need to reload them later
+        loaded.casbin_rules = get_all_casbin_rules(&self.pool).await?;
+        Ok(unassigned)
+    }
+
+    /// Returns roles assigned directly to the user and all the roles including the inherited ones
+    /// (`admin` inherits `commerce_admin` for example). Both sorted alphabetically.
+    pub(crate) async fn roles_for_user(
+        &self,
+        user_id: &str,
+    ) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+        let mut loaded = self.write_loaded().await?;
+        let mut roles = loaded.enforcer.get_roles_for_user(user_id, None);
+        let mut implicit_roles = loaded.enforcer.get_implicit_roles_for_user(user_id, None);
+        roles.sort();
+        implicit_roles.sort();
+        implicit_roles.dedup();
+        Ok((roles, implicit_roles))
+    }
+
+    /// Returns all the allowed `(obj, act)` pairs of the user including the permissions inherited
+    /// via roles (sorted alphabetically).
+    pub(crate) async fn permissions_for_user(
+        &self,
+        user_id: &str,
+    ) -> anyhow::Result<Vec<(String, String)>> {
+        let mut loaded = self.write_loaded().await?;
+        let mut permissions: Vec<(String, String)> = loaded
+            .enforcer
+            .get_implicit_permissions_for_user(user_id, None)
+            .into_iter()
+            .filter(|policy| policy.get(3).map(String::as_str) == Some("allow"))
+            .filter_map(|policy| match (policy.get(1), policy.get(2)) {
+                (Some(obj), Some(act)) => Some((obj.to_owned(), act.to_owned())),
+                _ => None,
+            })
+            .collect();
+        permissions.sort();
+        permissions.dedup();
+        Ok(permissions)
+    }
+
+    /// Rebuilds the enforcer from the current policies in DB. It should be called after changing
+    /// the policies so the changes are applied immediately.
+    pub(crate) async fn reload(&self) -> anyhow::Result<()> {
+        let loaded = self.load().await?;
+        *self.state.write().await = Some(loaded);
+        Ok(())
+    }
+
+    /// Rebuilds the enforcer only when the policies in DB differ from the policies the enforcer
+    /// was built from. Returns `true` when the enforcer was rebuilt. Enforcer which wasn't built
+    /// yet is not built here (it's built lazily when needed).
+    pub(crate) async fn reload_if_changed(&self) -> anyhow::Result<bool> {
+        let casbin_rules = get_all_casbin_rules(&self.pool).await?;
+        let changed = matches!(
+            self.state.read().await.as_ref(),
+            Some(loaded) if loaded.casbin_rules != casbin_rules
+        );
+        if !changed {
+            return Ok(false);
+        }
+
+        self.reload().await?;
+        Ok(true)
+    }
+
+    /// Periodically reloads the changed policies. The background task stops once the enforcer is
+    /// dropped.
+    fn spawn_reloader(&self) {
+        let pool = self.pool.to_owned();
+        let state = Arc::downgrade(&self.state);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RBAC_RELOAD_INTERVAL);
+            interval.tick().await; // the first tick completes immediately
+            loop {
+                interval.tick().await;
+                let rbac_enforcer = match state.upgrade() {
+                    Some(state) => RbacEnforcer {
+                        pool: pool.to_owned(),
+                        state,
+                    },
+                    None => break,
+                };
+                match rbac_enforcer.reload_if_changed().await {
+                    Ok(true) => tracing::info!("🚦 RBAC policies changed (reloaded)"),
+                    Ok(false) => {}
+                    Err(error) => tracing::error!("unable to reload RBAC policies: {}", error),
+                }
+            }
+        });
+    }
+}
+
 /// Verifies whether the user is signed in AND whether it has the correct permissions according to
 /// our RBAC policies. It should be used as a part of business logic because the actions represent
 /// business actions. For example: archiving a product means to copy the product into archive and
 /// deleting it. We have only one action for it (`CommerceActions.ArchiveProduct`) instead of having
 /// multiple based on what DB requests need to be made.
 ///
-/// Please note (TODO): this is quick'n'dirty solution. We should migrate these policies to the
-/// database instead of storing them in a file.
-pub(crate) async fn verify_permissions(user: &User, actions: &Actions) -> anyhow::Result<()> {
-    match user {
-        User::SignedUser(signed_user) => {
-            let model = DefaultModel::from_str(include_str!("rbac_model.conf")).await?;
-            // TODO: migrate the policies to `ArandodbAdapter` (vv)
-            let adapter = CSVAdapter::new(include_str!("rbac_policy.csv"));
-
-            match casbin::Enforcer::new(model, adapter).await {
-                Ok(enforcer) => {
-                    let sub: &str = signed_user.id_ref();
-                    let obj: &str;
-                    let act: &str;
-
-                    match actions {
-                        Actions::Analytics(analytics_actions) => {
-                            obj = "analytics";
-                            match analytics_actions {
-                                AnalyticsActions::GetRedirectHits => act = "get_redirect_hits",
-                            }
-                        }
-                        Actions::Cats(cats_actions) => {
-                            obj = "cats";
-                            match cats_actions {
-                                CatsActions::ListAllCats => act = "list_all_cats",
-                            }
-                        }
-                        Actions::Commerce(commerce_actions) => {
-                            obj = "commerce";
-                            match commerce_actions {
-                                CommerceActions::CreateProduct => act = "create_product",
-                                CommerceActions::UpdateProduct => act = "update_product",
-                                CommerceActions::ArchiveProduct => act = "archive_product",
-                                CommerceActions::PublishProduct => act = "publish_product",
-                                CommerceActions::UnpublishProduct => act = "unpublish_product",
-                                CommerceActions::GetAllProducts => act = "get_all_products",
-                                CommerceActions::GetAllProductCategories => {
-                                    act = "get_all_product_categories"
-                                }
-                                CommerceActions::GetAllProductAddons => {
-                                    act = "get_all_product_addons"
-                                }
-                            };
-                        }
-                        Actions::Files(files_actions) => {
-                            obj = "files";
-                            match files_actions {
-                                FilesActions::UploadFile => act = "upload_file",
-                                FilesActions::DeleteFile => act = "delete_file",
-                            }
-                        }
-                        Actions::Pos(pos_actions) => {
-                            obj = "pos";
-                            match pos_actions {
-                                PosActions::Checkout => act = "checkout",
-                                PosActions::GetAllPublishedProducts => {
-                                    act = "get_all_published_products"
-                                }
-                            }
-                        }
-                        Actions::Users(users_actions) => {
-                            obj = "users";
-                            match users_actions {
-                                UsersActions::GetAllUsers => act = "get_all_users",
-                            }
-                        }
-                    };
-
-                    match enforcer.enforce((sub, obj, act)) {
-                        Ok(enforce_result) => {
-                            match enforce_result {
-                                true => {
-                                    tracing::info!(
-                                        "🚦 allowing \"{}\" to perform action \"{}\" in \"{}\" module",
-                                        sub,
-                                        act,
-                                        obj
-                                    );
-                                    Ok(()) // verified (sufficient permissions)
-                                }
-                                false => {
-                                    tracing::error!(
-                                        "🚦 disallowing \"{}\" to perform action \"{}\" in \"{}\" module",
-                                        sub,
-                                        act,
-                                        obj
-                                    );
-                                    anyhow::bail!(RbacError::InsufficientPermissions {
-                                        sub: sub.to_string(),
-                                        obj: obj.to_string(),
-                                        act: act.to_string(),
-                                    })
-                                }
-                            }
-                        }
-                        Err(casbin_error) => {
-                            tracing::error!("{}", casbin_error);
-                            anyhow::bail!(RbacError::Casbin(casbin_error))
-                        }
-                    }
-                }
-                Err(casbin_error) => {
-                    tracing::error!("{}", casbin_error);
-                    anyhow::bail!(RbacError::Casbin(casbin_error))
-                }
+/// The policies are stored in the database (see `ArangodbAdapter`) so they can be changed without
+/// redeploying the server. The enforcer is cached (see `RbacEnforcer`).
+pub(crate) async fn verify_permissions<C: ClientExt>(
+    rbac_enforcer: &RbacEnforcer<C>,
+    user: &User,
+    actions: &Actions,
+) -> anyhow::Result<()> {
+    // API keys are RBAC subjects of their own (see `ApiKey`)
+    let sub: &str = match user {
+        User::SignedUser(signed_user) => signed_user.id_ref(),
+        User::ApiKeyUser(api_key_user) => api_key_user.id_ref(),
+        User::AnonymousUser(_) => anyhow::bail!(RbacError::NotLoggedIn),
+    };
+    let obj: &str;
+    let act: &str;
+
+    match actions {
+        Actions::Analytics(analytics_actions) => {
+            obj = "analytics";
+            match analytics_actions {
+                AnalyticsActions::GetRedirectHits => act = "get_redirect_hits",
             }
         }
-        User::AnonymousUser(_) => anyhow::bail!(RbacError::NotLoggedIn),
+        Actions::Archive(archive_actions) => {
+            obj = "archive";
+            match archive_actions {
+                ArchiveActions::GetArchiveItems => act = "get_archive_items",
+            }
+        }
+        Actions::Cats(cats_actions) => {
+            obj = "cats";
+            match cats_actions {
+                CatsActions::ListAllCats => act = "list_all_cats",
+            }
+        }
+        Actions::Commerce(commerce_actions) => {
+            obj = "commerce";
+            match commerce_actions {
+                CommerceActions::CreateProduct => act = "create_product",
+                CommerceActions::UpdateProduct => act = "update_product",
+                CommerceActions::ArchiveProduct => act = "archive_product",
+                CommerceActions::PublishProduct => act = "publish_product",
+                CommerceActions::UnpublishProduct => act = "unpublish_product",
+                CommerceActions::GetAllProducts => act = "get_all_products",
+                CommerceActions::GetAllProductCategories => act = "get_all_product_categories",
+                CommerceActions::GetAllProductAddons => act = "get_all_product_addons",
+                CommerceActions::GetAllOrders => act = "get_all_orders",
+                CommerceActions::UpdateProductStock => act = "update_product_stock",
+                CommerceActions::RestoreProduct => act = "restore_product",
+            };
+        }
+        Actions::Files(files_actions) => {
+            obj = "files";
+            match files_actions {
+                FilesActions::UploadFile => act = "upload_file",
+                FilesActions::DeleteFile => act = "delete_file",
+            }
+        }
+        Actions::Pos(pos_actions) => {
+            obj = "pos";
+            match pos_actions {
+                PosActions::Checkout => act = "checkout",
+                PosActions::GetAllPublishedProducts => act = "get_all_published_products",
+            }
+        }
+        Actions::Users(users_actions) => {
+            obj = "users";
+            match users_actions {
+                UsersActions::GetAllUsers => act = "get_all_users",
+                UsersActions::ActivateUser => act = "activate_user",
+                UsersActions::DeactivateUser => act = "deactivate_user",
+                UsersActions::AssignUserRole => act = "assign_user_role",
+                UsersActions::UnassignUserRole => act = "unassign_user_role",
+                UsersActions::GetUserPermissions => act = "get_user_permissions",
+                UsersActions::RevokeUserSessions => act = "revoke_user_sessions",
+                UsersActions::ListApiKeys => act = "list_api_keys",
+                UsersActions::CreateApiKey => act = "create_api_key",
+                UsersActions::RevokeApiKey => act = "revoke_api_key",
+            }
+        }
+    };
+
+    match rbac_enforcer.enforce(sub, obj, act).await {
+        Ok(true) => {
+            tracing::info!(
+                "🚦 allowing \"{}\" to perform action \"{}\" in \"{}\" module",
+                sub,
+                act,
+                obj
+            );
+            Ok(()) // verified (sufficient permissions)
+        }
+        Ok(false) => {
+            tracing::error!(
+                "🚦 disallowing \"{}\" to perform action \"{}\" in \"{}\" module",
+                sub,
+                act,
+                obj
+            );
+            anyhow::bail!(RbacError::InsufficientPermissions {
+                sub: sub.to_string(),
+                obj: obj.to_string(),
+                act: act.to_string(),
+            })
+        }
+        Err(error) => {
+            tracing::error!("{}", error);
+            Err(error)
+        }
+    }
+}
+
+/// Seeds the database policies from the original `rbac_policy.csv` file. The policies are seeded
+/// only once (when there are no policies in the database yet) so we never overwrite the policies
+/// changed at runtime.
+pub(crate) async fn seed_policies<C: ClientExt>(pool: &ConnectionPool<C>) -> anyhow::Result<()> {
+    if !get_all_casbin_rules(pool).await?.is_empty() {
+        tracing::info!("RBAC policies exist already (skipping)");
+        return Ok(());
+    }
+
+    let mut model = DefaultModel::from_str(include_str!("rbac_model.conf")).await?;
+    CSVAdapter::new(include_str!("rbac_policy.csv"))
+        .load_policy(&mut model)
+        .await?;
+    ArangodbAdapter::new(pool.to_owned())
+        .save_policy(&mut model)
+        .await?;
+    Ok(())
+}
+
+/// Adds the policies (in the `rbac_policy.csv` format) which are missing in the database. The
+/// existing policies are kept untouched. New policies must be added this way (via a migration)
+/// because the CSV file seeds the database only once (see `seed_policies`).
+pub(crate) async fn ensure_policies<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    csv_content: &str,
+) -> anyhow::Result<()> {
+    let existing_rules = get_all_casbin_rules(pool).await?;
+    let missing_rules: Vec<CasbinRule> = csv_content
+        .lines()
+        .filter_map(parse_csv_line)
+        .map(|tokens| CasbinRule {
+            ptype: tokens[0].to_owned(),
+            rule: tokens[1..].to_vec(),
+        })
+        .filter(|casbin_rule| !existing_rules.contains(casbin_rule))
+        .collect();
+
+    if !missing_rules.is_empty() {
+        create_casbin_rules(pool, &missing_rules).await?;
     }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::auth::users::{AnonymousUser, AnyUser, SignedUser};
+    use crate::arango::client::fake::FakeClient;
+    use crate::arango::get_database_connection_pool_fake;
+    use crate::auth::users::{AnonymousUser, AnyUser, ApiKeyUser, SignedUser};
+    use http::Method;
+    use serde_json::json;
+
+    fn policies() -> serde_json::Value {
+        json!([
+            { "ptype": "g", "rule": ["admin", "commerce_admin"] },
+            { "ptype": "g", "rule": ["users/2", "admin"] },
+            { "ptype": "p", "rule": ["commerce_admin", "commerce", "publish_product", "allow"] },
+        ])
+    }
+
+    /// Scripts the responses needed to build the enforcer (the policies are fetched twice: once to
+    /// detect their changes later and once by the casbin adapter).
+    fn respond_enforcer_load(client: &FakeClient, policies: serde_json::Value) {
+        client.respond_cursor(policies.to_owned());
+        client.respond_cursor(policies);
+    }
 
     #[tokio::test]
     async fn test_anonymous_user() {
         // It should reject any user which is not logged into the system.
+        let client = FakeClient::default();
+        let rbac_enforcer = RbacEnforcer::new(get_database_connection_pool_fake(&client));
         assert_eq!(
             verify_permissions(
+                &rbac_enforcer,
                 &User::AnonymousUser(AnonymousUser::new()),
                 &Actions::Commerce(CommerceActions::PublishProduct),
             )
@@ -201,8 +519,12 @@ mod tests {
     async fn test_signed_user_without_permissions() {
         // This user is signed in but it should not have the right permissions based on the RBAC policy.
         // In fact, there is not policy for it at all which should result in automatic "deny" state.
+        let client = FakeClient::default();
+        respond_enforcer_load(&client, policies());
+        let rbac_enforcer = RbacEnforcer::new(get_database_connection_pool_fake(&client));
         assert_eq!(
             verify_permissions(
+                &rbac_enforcer,
                 &User::SignedUser(SignedUser::from(AnyUser::mock(&Some(
                     "rbac-mock-id-123".to_string()
                 )))),
@@ -217,7 +539,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_signed_user_permissions() {
+        let client = FakeClient::default();
+        respond_enforcer_load(&client, policies());
+        let rbac_enforcer = RbacEnforcer::new(get_database_connection_pool_fake(&client));
         assert!(verify_permissions(
+            &rbac_enforcer,
             &User::SignedUser(SignedUser::from(AnyUser::mock(&Some(
                 "users/2".to_string()
             )))),
@@ -227,6 +553,209 @@ mod tests {
         .is_ok())
     }
 
+    #[tokio::test]
+    async fn test_api_key_user_permissions() {
+        // API keys are subjects of their own (independent of the users)
+        let client = FakeClient::default();
+        let mut policies = policies();
+        policies
+            .as_array_mut()
+            .unwrap()
+            .push(json!({ "ptype": "g", "rule": ["api_keys/pos_kiosk", "commerce_admin"] }));
+        respond_enforcer_load(&client, policies);
+        let rbac_enforcer = RbacEnforcer::new(get_database_connection_pool_fake(&client));
+        assert!(verify_permissions(
+            &rbac_enforcer,
+            &User::ApiKeyUser(ApiKeyUser::mock("api_keys/pos_kiosk")),
+            &Actions::Commerce(CommerceActions::PublishProduct),
+        )
+        .await
+        .is_ok());
+        assert_eq!(
+            verify_permissions(
+                &rbac_enforcer,
+                &User::ApiKeyUser(ApiKeyUser::mock("api_keys/other")),
+                &Actions::Commerce(CommerceActions::PublishProduct),
+            )
+            .await
+            .unwrap_err()
+            .to_string(),
+            "'api_keys/other' doesn't have enough permission to perform action 'publish_product' in 'commerce' module"
+        )
+    }
+
+    #[tokio::test]
+    async fn enforcer_is_cached_test() {
+        let client = FakeClient::default();
+        respond_enforcer_load(&client, policies());
+
+        let rbac_enforcer = RbacEnforcer::new(get_database_connection_pool_fake(&client));
+        for _ in 0..3 {
+            assert!(rbac_enforcer
+                .enforce("users/2", "commerce", "publish_product")
+                .await
+                .unwrap());
+        }
+
+        // the enforcer was built only once
+        assert_eq!(client.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn reload_if_changed_test() {
+        let client = FakeClient::default();
+        respond_enforcer_load(&client, policies());
+
+        let rbac_enforcer = RbacEnforcer::new(get_database_connection_pool_fake(&client));
+        assert!(!rbac_enforcer
+            .enforce("users/3", "commerce", "publish_product")
+            .await
+            .unwrap());
+
+        // 1. policies didn't change
+        client.respond_cursor(policies());
+        assert!(!rbac_enforcer.reload_if_changed().await.unwrap());
+
+        // 2. new employee was granted the admin role (directly in DB)
+        let mut changed_policies = policies();
+        changed_policies
+            .as_array_mut()
+            .unwrap()
+            .push(json!({ "ptype": "g", "rule": ["users/3", "admin"] }));
+        client.respond_cursor(changed_policies.to_owned());
+        respond_enforcer_load(&client, changed_policies);
+        assert!(rbac_enforcer.reload_if_changed().await.unwrap());
+        assert!(rbac_enforcer
+            .enforce("users/3", "commerce", "publish_product")
+            .await
+            .unwrap());
+        assert!(client.is_exhausted());
+    }
+
+    #[tokio::test]
+    async fn reload_if_changed_not_loaded_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(policies());
+
+        // the enforcer is built lazily only when it's needed
+        let rbac_enforcer = RbacEnforcer::new(get_database_connection_pool_fake(&client));
+        assert!(!rbac_enforcer.reload_if_changed().await.unwrap());
+        assert_eq!(client.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn assign_role_test() {
+        let client = FakeClient::default();
+        respond_enforcer_load(&client, policies());
+        client.respond_cursor(json!([{ "ptype": "g", "rule": ["users/3", "admin"] }])); // insert
+        client.respond_cursor(json!([])); // refresh the loaded policies
+
+        let rbac_enforcer = RbacEnforcer::new(get_database_connection_pool_fake(&client));
+        assert!(rbac_enforcer
+            .assign_role("users/3", &UserRole::Admin)
+            .await
+            .unwrap());
+        assert_eq!(
+            client.requests()[2].body["bindVars"]["casbin_rules"],
+            json!([{ "ptype": "g", "rule": ["users/3", "admin"] }])
+        );
+
+        // applied immediately (without reloading the policies)
+        assert!(rbac_enforcer
+            .enforce("users/3", "commerce", "publish_product")
+            .await
+            .unwrap());
+        assert!(client.is_exhausted());
+    }
+
+    #[tokio::test]
+    async fn roles_and_permissions_for_user_test() {
+        let client = FakeClient::default();
+        respond_enforcer_load(&client, policies());
+
+        let rbac_enforcer = RbacEnforcer::new(get_database_connection_pool_fake(&client));
+        let (roles, implicit_roles) = rbac_enforcer.roles_for_user("users/2").await.unwrap();
+        assert_eq!(roles, vec!["admin"]);
+        assert_eq!(implicit_roles, vec!["admin", "commerce_admin"]);
+        assert_eq!(
+            rbac_enforcer.permissions_for_user("users/2").await.unwrap(),
+            vec![(String::from("commerce"), String::from("publish_product"))]
+        );
+        assert!(rbac_enforcer
+            .permissions_for_user("users/3")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn ensure_policies_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(policies());
+        client.respond_cursor(json!([]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        ensure_policies(
+            &pool,
+            r#"
+                p, commerce_admin, commerce, publish_product, allow
+                p, users_admin, users, activate_user, allow
+            "#,
+        )
+        .await
+        .unwrap();
+
+        // only the missing policy was created
+        assert_eq!(
+            client.requests()[1].body["bindVars"]["casbin_rules"],
+            json!([{ "ptype": "p", "rule": ["users_admin", "users", "activate_user", "allow"] }])
+        );
+    }
+
+    #[tokio::test]
+    async fn seed_policies_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([])); // no policies yet
+        client.respond(
+            Method::POST,
+            "_api/transaction/begin",
+            200,
+            json!({ "error": false, "code": 200, "result": { "id": "1", "status": "running" } }),
+        );
+        client.respond_cursor(json!([])); // remove
+        client.respond_cursor(json!([])); // insert
+        client.respond(
+            Method::PUT,
+            "_api/transaction/1",
+            200,
+            json!({ "error": false, "code": 200, "result": { "id": "1", "status": "committed" } }),
+        );
+
+        let pool = get_database_connection_pool_fake(&client);
+        seed_policies(&pool).await.unwrap();
+        assert!(client.is_exhausted());
+
+        let seeded_rules = client.requests()[3].body["bindVars"]["casbin_rules"].to_owned();
+        assert!(seeded_rules
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "ptype": "g", "rule": ["users/2", "admin"] })));
+        assert!(seeded_rules.as_array().unwrap().contains(&json!({
+            "ptype": "p",
+            "rule": ["commerce_admin", "commerce", "publish_product", "allow"],
+        })));
+    }
+
+    #[tokio::test]
+    async fn seed_policies_existing_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(policies());
+
+        let pool = get_database_connection_pool_fake(&client);
+        seed_policies(&pool).await.unwrap();
+        assert_eq!(client.requests().len(), 1); // nothing was overwritten
+    }
+
     #[test]
     fn rbac_error_to_string_test() {
         assert_eq!(
@@ -0,0 +1,532 @@
+use crate::arango::client::ClientExt;
+use crate::arango::ConnectionPool;
+use crate::auth::certs::{CachedCerts, CachedCertsProduction};
+use crate::auth::dal::risc_events::{is_risc_event_recorded, record_risc_event};
+use crate::auth::dal::sessions::delete_all_user_sessions;
+use crate::auth::dal::users::{set_user_active, try_find_user_by_claims};
+use crate::auth::oidc::OidcProvider;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Issuer of the Google security event tokens (note the trailing slash, it differs from the ID
+/// tokens issuer).
+///
+/// See: https://accounts.google.com/.well-known/risc-configuration
+const RISC_ISSUER: &str = "https://accounts.google.com/";
+
+const EVENT_TYPE_SESSIONS_REVOKED: &str =
+    "https://schemas.openid.net/secevent/risc/event-type/sessions-revoked";
+const EVENT_TYPE_TOKENS_REVOKED: &str =
+    "https://schemas.openid.net/secevent/oauth/event-type/tokens-revoked";
+const EVENT_TYPE_ACCOUNT_DISABLED: &str =
+    "https://schemas.openid.net/secevent/risc/event-type/account-disabled";
+
+/// Security Event Token (SET) sent by Google to our RISC receiver. Unlike ID tokens, it has no
+/// expiration time (`exp`). Issuer and audience are checked during the validation, so only the
+/// token ID and the events are deserialized.
+///
+/// See: https://developers.google.com/identity/protocols/risc#token_validation
+#[derive(Debug, Deserialize)]
+pub(crate) struct SecurityEventToken {
+    jti: String,                             // Unique ID of the token
+    events: BTreeMap<String, SecurityEvent>, // Event type URI => event
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SecurityEvent {
+    subject: Option<SecurityEventSubject>,
+    reason: Option<String>, // only `account-disabled` events (`hijacking` or `bulk-account`)
+    state: Option<String>,  // only `verification` events
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SecurityEventSubject {
+    subject_type: String,
+    iss: Option<String>,
+    sub: Option<String>,
+}
+
+impl SecurityEvent {
+    /// Google `sub` of the affected user (only `iss-sub` subjects identify the user reliably).
+    fn google_subject(&self) -> Option<&str> {
+        match &self.subject {
+            Some(subject) if subject.subject_type == "iss-sub" => subject.sub.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Action taken in reaction to the security event (recorded together with the event).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RiscAction {
+    /// All sessions of the user were revoked (signed out from all devices).
+    RevokeSessions,
+    /// All sessions of the user were revoked, and the user was deactivated so they cannot sign in
+    /// again until activated by an admin.
+    RevokeSessionsAndDeactivate,
+    /// Nothing was done (verification events, unsupported events or unknown users).
+    None,
+}
+
+impl RiscAction {
+    fn from_event(event_type: &str, event: &SecurityEvent) -> Self {
+        match event_type {
+            EVENT_TYPE_SESSIONS_REVOKED | EVENT_TYPE_TOKENS_REVOKED => RiscAction::RevokeSessions,
+            // Bulk accounts (spam) are not necessarily compromised, so we only sign them out.
+            // Hijacked accounts (or accounts disabled for an unknown reason) must not sign in.
+            EVENT_TYPE_ACCOUNT_DISABLED => match event.reason.as_deref() {
+                Some("bulk-account") => RiscAction::RevokeSessions,
+                _ => RiscAction::RevokeSessionsAndDeactivate,
+            },
+            _ => RiscAction::None,
+        }
+    }
+}
+
+fn create_security_event_token_validation(google_provider: &OidcProvider) -> Validation {
+    let mut validation = Validation::new(Algorithm::RS256);
+    validation.leeway = 30; // seconds
+    validation.validate_exp = false; // SETs never expire
+    validation.validate_nbf = false; // NBF (not before) not present in the token
+    validation.required_spec_claims = HashSet::from([String::from("iss"), String::from("aud")]);
+    validation.set_audience(google_provider.audiences());
+    validation.set_issuer(&[RISC_ISSUER]);
+    validation
+}
+
+/// Verifies the security event token the same way as the ID tokens: the token must be signed by
+/// one of the Google public keys (JWKS), `iss` must be Google and `aud` one of our client IDs.
+pub(crate) async fn verify_security_event_token_integrity<T: CachedCerts>(
+    google_provider: &OidcProvider,
+    token: &str,
+    cached_certs: &mut T,
+) -> anyhow::Result<SecurityEventToken> {
+    // unsafe_* to remind that this header was not verified
+    let unsafe_header = decode_header(token)?;
+    match unsafe_header.kid {
+        Some(kid) => match cached_certs.get_key_by_kid(&kid).await {
+            Some(key) => {
+                let decoding_key = DecodingKey::from_rsa_components(key.modulus(), key.exponent())?;
+                let validation = create_security_event_token_validation(google_provider);
+                Ok(decode::<SecurityEventToken>(token, &decoding_key, &validation)?.claims)
+            }
+            None => anyhow::bail!("cannot obtain RISC certificate for key ID: '{}'", kid),
+        },
+        None => anyhow::bail!("cannot get 'kid' from the token header"),
+    }
+}
+
+pub(crate) async fn verify_security_event_token(
+    google_provider: &OidcProvider,
+    token: &str,
+) -> anyhow::Result<SecurityEventToken> {
+    let mut cached_certs = CachedCertsProduction::new(google_provider.jwks_url());
+    verify_security_event_token_integrity(google_provider, token, &mut cached_certs).await
+}
+
+/// Reacts to the (verified) security events: sessions of the matching `google.sub` user are
+/// revoked, and the user is deactivated when the account was disabled. Every event is recorded
+/// in `risc_events` collection regardless of whether the user exists. Already recorded events
+/// (the same `jti` and event type) are skipped, and DB failures are returned as errors so Google
+/// delivers the token again.
+pub(crate) async fn handle_security_event_token<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    google_provider: &OidcProvider,
+    security_event_token: &SecurityEventToken,
+) -> anyhow::Result<()> {
+    for (event_type, event) in &security_event_token.events {
+        if is_risc_event_recorded(pool, &security_event_token.jti, event_type).await? {
+            tracing::info!(
+                "RISC event '{}' of token '{}' was already processed",
+                event_type,
+                security_event_token.jti
+            );
+            continue;
+        }
+
+        let requested_action = RiscAction::from_event(event_type, event);
+        let user = match event.google_subject() {
+            Some(subject) if requested_action != RiscAction::None => {
+                try_find_user_by_claims(pool, google_provider, subject).await?
+            }
+            _ => None,
+        };
+
+        let action = match &user {
+            Some(user) => {
+                let user_id = user.id().to_string();
+                if requested_action == RiscAction::RevokeSessionsAndDeactivate {
+                    // deactivate first so the user cannot sign in again in the meantime
+                    set_user_active(pool, &user_id, false).await?;
+                }
+                let revoked_sessions = delete_all_user_sessions(pool, &user_id).await?;
+                tracing::info!(
+                    "RISC event '{}' revoked {} session(s) of user '{}'",
+                    event_type,
+                    revoked_sessions.len(),
+                    user_id
+                );
+                requested_action
+            }
+            None => RiscAction::None,
+        };
+
+        record_risc_event(
+            pool,
+            &security_event_token.jti,
+            event_type,
+            event,
+            &user.map(|user| user.id().to_string()),
+            &action,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arango::client::fake::FakeClient;
+    use crate::arango::get_database_connection_pool_fake;
+    use crate::auth::certs::CachedCertsLocal;
+    use crate::auth::google::GOOGLE_JWKS_URL;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    fn test_google_provider() -> OidcProvider {
+        OidcProvider::new(
+            "google",
+            &["https://accounts.google.com"],
+            GOOGLE_JWKS_URL,
+            &["abacus-client-id"],
+        )
+    }
+
+    fn sign_security_event_token(claims: serde_json::Value) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(String::from("test-key-1"));
+        let encoding_key =
+            EncodingKey::from_rsa_pem(include_bytes!("fixtures/oidc_private_key.pem")).unwrap();
+        encode(&header, &claims, &encoding_key).unwrap()
+    }
+
+    fn local_certs() -> CachedCertsLocal {
+        CachedCertsLocal::from_jwks(include_str!("fixtures/oidc_jwks.json"))
+    }
+
+    fn security_event_token(event_type: &str, event: serde_json::Value) -> SecurityEventToken {
+        serde_json::from_value(json!({
+            "iss": RISC_ISSUER,
+            "aud": "abacus-client-id",
+            "iat": 1700000000,
+            "jti": "756E69717565206964656E746966696572",
+            "events": { event_type: event },
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn verify_security_event_token_integrity_valid() {
+        // SETs have no expiration time
+        let token = sign_security_event_token(json!({
+            "iss": "https://accounts.google.com/",
+            "aud": "abacus-client-id",
+            "iat": 1700000000,
+            "jti": "756E69717565206964656E746966696572",
+            "events": {
+                EVENT_TYPE_SESSIONS_REVOKED: {
+                    "subject": {
+                        "subject_type": "iss-sub",
+                        "iss": "https://accounts.google.com/",
+                        "sub": "7375626A656374",
+                    },
+                },
+            },
+        }));
+
+        let security_event_token = verify_security_event_token_integrity(
+            &test_google_provider(),
+            &token,
+            &mut local_certs(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            security_event_token.jti,
+            "756E69717565206964656E746966696572"
+        );
+        assert_eq!(
+            security_event_token.events[EVENT_TYPE_SESSIONS_REVOKED].google_subject(),
+            Some("7375626A656374")
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_security_event_token_integrity_invalid_audience() {
+        let token = sign_security_event_token(json!({
+            "iss": "https://accounts.google.com/",
+            "aud": "malicious-client-id",
+            "iat": 1700000000,
+            "jti": "756E69717565206964656E746966696572",
+            "events": {},
+        }));
+
+        assert_eq!(
+            format!(
+                "{:?}",
+                verify_security_event_token_integrity(
+                    &test_google_provider(),
+                    &token,
+                    &mut local_certs()
+                )
+                .await
+                .err()
+                .unwrap()
+            ),
+            "InvalidAudience"
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_security_event_token_integrity_invalid_issuer() {
+        // ID tokens (without the trailing slash) are not security event tokens
+        let token = sign_security_event_token(json!({
+            "iss": "https://accounts.google.com",
+            "aud": "abacus-client-id",
+            "iat": 1700000000,
+            "jti": "756E69717565206964656E746966696572",
+            "events": {},
+        }));
+
+        assert_eq!(
+            format!(
+                "{:?}",
+                verify_security_event_token_integrity(
+                    &test_google_provider(),
+                    &token,
+                    &mut local_certs()
+                )
+                .await
+                .err()
+                .unwrap()
+            ),
+            "InvalidIssuer"
+        );
+    }
+
+    #[test]
+    fn risc_action_from_event_test() {
+        let event = |reason: Option<&str>| SecurityEvent {
+            subject: None,
+            reason: reason.map(String::from),
+            state: None,
+        };
+
+        assert_eq!(
+            RiscAction::from_event(EVENT_TYPE_SESSIONS_REVOKED, &event(None)),
+            RiscAction::RevokeSessions
+        );
+        assert_eq!(
+            RiscAction::from_event(EVENT_TYPE_TOKENS_REVOKED, &event(None)),
+            RiscAction::RevokeSessions
+        );
+        assert_eq!(
+            RiscAction::from_event(EVENT_TYPE_ACCOUNT_DISABLED, &event(Some("hijacking"))),
+            RiscAction::RevokeSessionsAndDeactivate
+        );
+        assert_eq!(
+            RiscAction::from_event(EVENT_TYPE_ACCOUNT_DISABLED, &event(None)),
+            RiscAction::RevokeSessionsAndDeactivate
+        );
+        assert_eq!(
+            RiscAction::from_event(EVENT_TYPE_ACCOUNT_DISABLED, &event(Some("bulk-account"))),
+            RiscAction::RevokeSessions
+        );
+        assert_eq!(
+            RiscAction::from_event(
+                "https://schemas.openid.net/secevent/risc/event-type/verification",
+                &event(None)
+            ),
+            RiscAction::None
+        );
+    }
+
+    #[tokio::test]
+    async fn handle_security_event_token_account_disabled_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([])); // is_risc_event_recorded
+        client.respond_cursor(json!([{
+            "_id": "users/3",
+            "_rev": "_fake_rev",
+            "_key": "3",
+            "is_active": true,
+            "google": null,
+        }])); // try_find_user_by_claims
+        client.respond_cursor(json!([{
+            "_id": "users/3",
+            "_rev": "_fake_rev",
+            "_key": "3",
+            "is_active": false,
+            "google": null,
+        }])); // set_user_active
+        client.respond_cursor(json!([])); // delete_all_user_sessions
+        client.respond_cursor(json!([{}])); // record_risc_event
+
+        let pool = get_database_connection_pool_fake(&client);
+        let security_event_token = security_event_token(
+            EVENT_TYPE_ACCOUNT_DISABLED,
+            json!({
+                "subject": {
+                    "subject_type": "iss-sub",
+                    "iss": "https://accounts.google.com/",
+                    "sub": "7375626A656374",
+                },
+                "reason": "hijacking",
+            }),
+        );
+        handle_security_event_token(&pool, &test_google_provider(), &security_event_token)
+            .await
+            .unwrap();
+
+        let requests = client.requests();
+        assert_eq!(
+            requests[1].body["bindVars"],
+            json!({
+                "claims_path": ["google"],
+                "sub": "7375626A656374",
+            })
+        );
+        assert_eq!(
+            requests[2].body["bindVars"],
+            json!({
+                "user_id": "users/3",
+                "is_active": false,
+            })
+        );
+        assert_eq!(
+            requests[3].body["bindVars"],
+            json!({
+                "user_id": "users/3",
+            })
+        );
+        assert_eq!(requests[4].body["bindVars"]["user_id"], json!("users/3"));
+        assert_eq!(
+            requests[4].body["bindVars"]["action"],
+            json!("revoke_sessions_and_deactivate")
+        );
+        assert!(client.is_exhausted());
+    }
+
+    #[tokio::test]
+    async fn handle_security_event_token_unknown_user_test() {
+        // the event is still recorded even though there is nobody to sign out
+        let client = FakeClient::default();
+        client.respond_cursor(json!([])); // is_risc_event_recorded
+        client.respond_cursor(json!([])); // try_find_user_by_claims
+        client.respond_cursor(json!([{}])); // record_risc_event
+
+        let pool = get_database_connection_pool_fake(&client);
+        let security_event_token = security_event_token(
+            EVENT_TYPE_SESSIONS_REVOKED,
+            json!({
+                "subject": {
+                    "subject_type": "iss-sub",
+                    "iss": "https://accounts.google.com/",
+                    "sub": "7375626A656374",
+                },
+            }),
+        );
+        handle_security_event_token(&pool, &test_google_provider(), &security_event_token)
+            .await
+            .unwrap();
+
+        let requests = client.requests();
+        assert_eq!(
+            requests[2].body["bindVars"],
+            json!({
+                "jti": "756E69717565206964656E746966696572",
+                "event_type": EVENT_TYPE_SESSIONS_REVOKED,
+                "event": {
+                    "subject": {
+                        "subject_type": "iss-sub",
+                        "iss": "https://accounts.google.com/",
+                        "sub": "7375626A656374",
+                    },
+                    "reason": null,
+                    "state": null,
+                },
+                "user_id": null,
+                "action": "none",
+            })
+        );
+        assert!(client.is_exhausted());
+    }
+
+    #[tokio::test]
+    async fn handle_security_event_token_replayed_test() {
+        // already processed events are neither processed nor recorded again
+        let client = FakeClient::default();
+        client.respond_cursor(json!([true])); // is_risc_event_recorded
+
+        let pool = get_database_connection_pool_fake(&client);
+        let security_event_token = security_event_token(
+            EVENT_TYPE_ACCOUNT_DISABLED,
+            json!({
+                "subject": {
+                    "subject_type": "iss-sub",
+                    "iss": "https://accounts.google.com/",
+                    "sub": "7375626A656374",
+                },
+                "reason": "hijacking",
+            }),
+        );
+        handle_security_event_token(&pool, &test_google_provider(), &security_event_token)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            client.requests()[0].body["bindVars"],
+            json!({
+                "jti": "756E69717565206964656E746966696572",
+                "event_type": EVENT_TYPE_ACCOUNT_DISABLED,
+            })
+        );
+        assert!(client.is_exhausted());
+    }
+
+    #[tokio::test]
+    async fn handle_security_event_token_db_failure_test() {
+        // DB failures must not be mistaken for unknown users (Google would not deliver it again)
+        let client = FakeClient::default();
+        client.respond_cursor(json!([])); // is_risc_event_recorded
+        client.respond_error(
+            http::Method::POST,
+            "_api/cursor",
+            500,
+            4,
+            "internal server error",
+        ); // try_find_user_by_claims
+
+        let pool = get_database_connection_pool_fake(&client);
+        let security_event_token = security_event_token(
+            EVENT_TYPE_ACCOUNT_DISABLED,
+            json!({
+                "subject": {
+                    "subject_type": "iss-sub",
+                    "iss": "https://accounts.google.com/",
+                    "sub": "7375626A656374",
+                },
+                "reason": "hijacking",
+            }),
+        );
+        assert!(
+            handle_security_event_token(&pool, &test_google_provider(), &security_event_token)
+                .await
+                .is_err()
+        );
+        assert!(client.is_exhausted());
+    }
+}
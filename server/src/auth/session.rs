@@ -1,5 +1,7 @@
+use crate::auth::users::User;
+use crate::graphql_context::Context;
 use rand::Rng;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// Generates sessions token which is compatible with RFC6750 - "Authorization Framework: Bearer
 /// Token Usage" syntax (https://tools.ietf.org/html/rfc6750).
@@ -58,15 +60,25 @@ pub(crate) fn derive_session_token_hash(session_token: &str) -> String {
     data_encoding::HEXLOWER.encode(hash.as_ref())
 }
 
+/// Device (client) which created the session so users can recognize their sessions ("active
+/// devices"). The values are informative only, they are sent by the client and can be spoofed.
+#[derive(Clone, Default, Serialize)]
+pub struct SessionDevice {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
 #[derive(Clone, Deserialize)]
 pub struct Session {
     _id: String,
     _rev: String,
     _key: String,
-    // Unused: last_access: String,
+    created: String,
+    last_access: String,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
 }
 
-#[cfg(test)]
 impl Session {
     /// It is not possible to retrieve back the original session token, only hash (similar to how
     /// passwords would work).
@@ -75,6 +87,41 @@ impl Session {
     }
 }
 
+#[juniper::graphql_object(context = Context)]
+impl Session {
+    pub(crate) fn id(&self) -> juniper::ID {
+        juniper::ID::from(self._id.to_owned())
+    }
+
+    /// When was the session created (when the user signed in).
+    pub(crate) fn created(&self) -> String {
+        self.created.to_owned()
+    }
+
+    /// When was the session used for the last time.
+    pub(crate) fn last_access(&self) -> String {
+        self.last_access.to_owned()
+    }
+
+    /// User agent of the device which created the session (unknown for older sessions).
+    pub(crate) fn user_agent(&self) -> Option<String> {
+        self.user_agent.to_owned()
+    }
+
+    /// IP address of the device which created the session (unknown for older sessions).
+    pub(crate) fn ip_address(&self) -> Option<String> {
+        self.ip_address.to_owned()
+    }
+
+    /// Whether this is the session used by the current request.
+    pub(crate) fn is_current(&self, context: &Context) -> bool {
+        match &context.user {
+            User::SignedUser(user) => user.session_token_hash() == Some(self._key.as_str()),
+            User::AnonymousUser(_) | User::ApiKeyUser(_) => false,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,42 @@
+use crate::auth::api_key::ApiKey;
+use serde::Deserialize;
+
+/// API key user is a machine (an integration or a device like POS kiosk) authenticated via API key
+/// instead of a session token. It's not a real user: its RBAC subject is the API key ID, and it
+/// acts on behalf of the account the API key belongs to.
+#[derive(Clone, Deserialize)]
+pub struct ApiKeyUser {
+    api_key_id: String,
+    account_id: String,
+}
+
+impl ApiKeyUser {
+    pub(crate) fn id(&self) -> String {
+        self.api_key_id.to_owned()
+    }
+
+    pub(crate) fn id_ref(&self) -> &str {
+        self.api_key_id.as_ref()
+    }
+
+    pub(crate) fn account_id_ref(&self) -> &str {
+        self.account_id.as_ref()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn mock(api_key_id: &str) -> Self {
+        Self {
+            api_key_id: api_key_id.to_string(),
+            account_id: String::from("accounts/1"),
+        }
+    }
+}
+
+impl From<ApiKey> for ApiKeyUser {
+    fn from(api_key: ApiKey) -> Self {
+        ApiKeyUser {
+            api_key_id: api_key.id_ref().to_string(),
+            account_id: api_key.account_id_ref().to_string(),
+        }
+    }
+}
@@ -1,10 +1,13 @@
 pub use crate::auth::users::anonymous_user::AnonymousUser;
+pub use crate::auth::users::api_key_user::ApiKeyUser;
 pub use crate::auth::users::signed_user::SignedUser;
 
-use crate::auth::google::Claims;
+use crate::auth::oidc::Claims;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 mod anonymous_user;
+mod api_key_user;
 mod signed_user;
 
 #[allow(clippy::large_enum_variant)]
@@ -12,6 +15,7 @@ mod signed_user;
 pub enum User {
     AnonymousUser(AnonymousUser),
     SignedUser(SignedUser),
+    ApiKeyUser(ApiKeyUser),
 }
 
 /// AnyUser represents any generic user in the database which can later be converted to the right
@@ -23,6 +27,8 @@ pub struct AnyUser {
     _key: String,
     is_active: bool,
     google: Option<Claims>,
+    /// Claims of the other OpenID Connect providers (by provider name).
+    oidc: Option<BTreeMap<String, Claims>>,
 }
 
 #[juniper::graphql_object]
@@ -37,37 +43,49 @@ impl AnyUser {
 
     /// Name is a full name of the user ("John Doe").
     pub(crate) fn name(&self) -> Option<String> {
-        match &self.google {
-            Some(google) => google.name().to_owned(),
+        match self.claims() {
+            Some(claims) => claims.name().to_owned(),
             None => None,
         }
     }
 
     /// Given name is "John" in "John Doe".
     pub(crate) fn given_name(&self) -> Option<String> {
-        match &self.google {
-            Some(google) => google.given_name().to_owned(),
+        match self.claims() {
+            Some(claims) => claims.given_name().to_owned(),
             None => None,
         }
     }
 
     /// Family name is "Doe" in "John Doe".
     pub(crate) fn family_name(&self) -> Option<String> {
-        match &self.google {
-            Some(google) => google.family_name().to_owned(),
+        match self.claims() {
+            Some(claims) => claims.family_name().to_owned(),
             None => None,
         }
     }
 
     pub(crate) fn has_email_verified(&self) -> Option<bool> {
-        match &self.google {
-            Some(google) => google.is_email_verified().to_owned(),
+        match self.claims() {
+            Some(claims) => claims.is_email_verified().to_owned(),
             None => None,
         }
     }
 }
 
 impl AnyUser {
+    pub(crate) fn id_ref(&self) -> &str {
+        self._id.as_ref()
+    }
+
+    /// Returns claims of the provider the user signed up with (Google claims are preferred).
+    fn claims(&self) -> Option<&Claims> {
+        match &self.google {
+            Some(google) => Some(google),
+            None => self.oidc.as_ref().and_then(|oidc| oidc.values().next()),
+        }
+    }
+
     #[cfg(test)]
     pub(crate) fn mock(mock_id: &Option<String>) -> Self {
         Self {
@@ -79,6 +97,7 @@ impl AnyUser {
             _key: String::from("42yadada42"),
             is_active: true,
             google: None,
+            oidc: None,
         }
     }
 }
@@ -11,6 +11,9 @@ pub struct SignedUser {
     _rev: String,
     _key: String,
     // Unused: google: Option<Claims>,
+    /// Hash of the session token used to sign in the current request (see `get_current_user`).
+    #[serde(skip)]
+    session_token_hash: Option<String>,
 }
 
 impl SignedUser {
@@ -21,6 +24,17 @@ impl SignedUser {
     pub(crate) fn id_ref(&self) -> &str {
         self._id.as_ref()
     }
+
+    pub(crate) fn session_token_hash(&self) -> Option<&str> {
+        self.session_token_hash.as_deref()
+    }
+
+    pub(crate) fn with_session_token_hash(self, session_token_hash: &str) -> Self {
+        SignedUser {
+            session_token_hash: Some(session_token_hash.to_string()),
+            ..self
+        }
+    }
 }
 
 impl From<AnyUser> for SignedUser {
@@ -30,6 +44,7 @@ impl From<AnyUser> for SignedUser {
             _rev: user._rev,
             _key: user._key,
             // Unused: google: None,
+            session_token_hash: None,
         }
     }
 }
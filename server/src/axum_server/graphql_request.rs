@@ -0,0 +1,344 @@
+use crate::graphql_context::{ContextUploadable, ContextUploadableContentType};
+use axum::extract::{FromRequest, Multipart, Request};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use juniper::http::GraphQLBatchRequest;
+use juniper_axum::extract::JuniperRequest;
+use std::collections::HashMap;
+
+/// GraphQL request which is either a regular juniper request (GET or JSON POST) or a multipart
+/// request with uploaded files (uploadables).
+///
+/// Multipart requests follow the GraphQL multipart request spec
+/// (https://github.com/jaydenseric/graphql-multipart-request-spec): `operations` field contains
+/// the JSON encoded GraphQL request, `map` field maps the file fields to the GraphQL variables and
+/// the rest of the fields are the files themselves. Our GraphQL API references the uploaded files
+/// by their names (see `ProductMultilingualInput.images`) so the uploadables are keyed by the file
+/// names (or by the field names when the file names are missing).
+pub(crate) struct GraphQLRequest {
+    pub(crate) request: GraphQLBatchRequest,
+    pub(crate) uploadables: Option<HashMap<String, ContextUploadable>>,
+}
+
+type GraphQLRequestRejection = (StatusCode, String);
+
+#[axum::async_trait]
+impl FromRequest<()> for GraphQLRequest {
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &()) -> Result<Self, Self::Rejection> {
+        let is_multipart = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("multipart/form-data"));
+
+        if is_multipart {
+            let multipart = Multipart::from_request(request, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            let (request, uploadables) = parse_multipart_request(multipart)
+                .await
+                .map_err(IntoResponse::into_response)?;
+
+            Ok(GraphQLRequest {
+                request,
+                uploadables: if uploadables.is_empty() {
+                    None
+                } else {
+                    Some(uploadables)
+                },
+            })
+        } else {
+            let JuniperRequest(request) = JuniperRequest::from_request(request, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+
+            Ok(GraphQLRequest {
+                request,
+                uploadables: None,
+            })
+        }
+    }
+}
+
+fn bad_request(message: impl Into<String>) -> GraphQLRequestRejection {
+    (StatusCode::BAD_REQUEST, message.into())
+}
+
+async fn parse_multipart_request(
+    mut multipart: Multipart,
+) -> Result<(GraphQLBatchRequest, HashMap<String, ContextUploadable>), GraphQLRequestRejection> {
+    let mut operations = None;
+    let mut map = None;
+    let mut files = HashMap::new();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|error| (error.status(), error.body_text()))?
+    {
+        let field_name = field.name().unwrap_or_default().to_string();
+        match field_name.as_str() {
+            "operations" => {
+                let text = field
+                    .text()
+                    .await
+                    .map_err(|error| (error.status(), error.body_text()))?;
+                operations = Some(serde_json::from_str::<GraphQLBatchRequest>(&text).map_err(
+                    |error| bad_request(format!("invalid 'operations' field: {}", error)),
+                )?);
+            }
+            "map" => {
+                let text = field
+                    .text()
+                    .await
+                    .map_err(|error| (error.status(), error.body_text()))?;
+                map = Some(
+                    serde_json::from_str::<HashMap<String, Vec<String>>>(&text)
+                        .map_err(|error| bad_request(format!("invalid 'map' field: {}", error)))?,
+                );
+            }
+            _ => {
+                let file_name = field.file_name().map(String::from);
+                let declared_content_type = field.content_type().map(String::from);
+                let data = field
+                    .bytes()
+                    .await
+                    .map_err(|error| (error.status(), error.body_text()))?;
+                files.insert(
+                    field_name,
+                    (file_name, declared_content_type, data.to_vec()),
+                );
+            }
+        }
+    }
+
+    let operations = operations.ok_or_else(|| bad_request("missing 'operations' field"))?;
+    let map = map.unwrap_or_default();
+
+    let mut uploadables = HashMap::new();
+    for (field_name, paths) in &map {
+        if paths.is_empty() || paths.iter().any(|path| !path.starts_with("variables.")) {
+            return Err(bad_request(format!(
+                "invalid 'map' paths of the file field '{}'",
+                field_name
+            )));
+        }
+
+        let (file_name, declared_content_type, data) = files
+            .remove(field_name)
+            .ok_or_else(|| bad_request(format!("missing file field '{}'", field_name)))?;
+
+        let content_type = validate_content_type(&data, &declared_content_type)?;
+        let uploadable_name = file_name.unwrap_or_else(|| field_name.to_string());
+        if uploadables.contains_key(&uploadable_name) {
+            return Err(bad_request(format!(
+                "duplicate file name '{}'",
+                uploadable_name
+            )));
+        }
+        uploadables.insert(uploadable_name, ContextUploadable::new(data, content_type));
+    }
+
+    if let Some(field_name) = files.keys().next() {
+        return Err(bad_request(format!(
+            "file field '{}' is not specified in the 'map' field",
+            field_name
+        )));
+    }
+
+    Ok((operations, uploadables))
+}
+
+/// The content type is sniffed from the file itself; the declared content type (if any) must
+/// match it so clients cannot disguise one file type as another.
+fn validate_content_type(
+    data: &[u8],
+    declared_content_type: &Option<String>,
+) -> Result<ContextUploadableContentType, GraphQLRequestRejection> {
+    let content_type = ContextUploadableContentType::sniff(data).ok_or((
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        String::from("unsupported file type (only PNG, JPEG and WebP images are supported)"),
+    ))?;
+
+    match declared_content_type.as_deref() {
+        Some(declared)
+            if declared.starts_with("image/") && declared != content_type.mime_type() =>
+        {
+            Err((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!(
+                    "declared content type '{}' doesn't match the file content ('{}')",
+                    declared,
+                    content_type.mime_type()
+                ),
+            ))
+        }
+        _ => Ok(content_type),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    const BOUNDARY: &str = "------------------------abacus";
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    const JPEG_SIGNATURE: &[u8] = b"\xFF\xD8\xFF\xE0";
+
+    fn multipart_request(parts: &[(&str, Option<&str>, Option<&str>, &[u8])]) -> Request {
+        let mut body = vec![];
+        for (name, file_name, content_type, data) in parts {
+            body.extend(format!("--{}\r\n", BOUNDARY).as_bytes());
+            match file_name {
+                Some(file_name) => body.extend(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n",
+                        name, file_name
+                    )
+                    .as_bytes(),
+                ),
+                None => body.extend(
+                    format!("Content-Disposition: form-data; name=\"{}\"\r\n", name).as_bytes(),
+                ),
+            }
+            if let Some(content_type) = content_type {
+                body.extend(format!("Content-Type: {}\r\n", content_type).as_bytes());
+            }
+            body.extend(b"\r\n");
+            body.extend(*data);
+            body.extend(b"\r\n");
+        }
+        body.extend(format!("--{}--\r\n", BOUNDARY).as_bytes());
+
+        Request::builder()
+            .method("POST")
+            .uri("/graphql")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    const OPERATIONS: &[u8] =
+        br#"{"query":"mutation { __typename }","variables":{"images":["img.png"]}}"#;
+
+    #[tokio::test]
+    async fn multipart_request_test() {
+        let request = multipart_request(&[
+            ("operations", None, None, OPERATIONS),
+            ("map", None, None, br#"{"0":["variables.images.0"]}"#),
+            ("0", Some("img.png"), Some("image/png"), PNG_SIGNATURE),
+        ]);
+
+        let graphql_request = GraphQLRequest::from_request(request, &()).await.unwrap();
+        let uploadables = graphql_request.uploadables.unwrap();
+        assert_eq!(uploadables.len(), 1);
+        assert!(matches!(
+            uploadables.get("img.png").unwrap().content_type(),
+            ContextUploadableContentType::ImagePng
+        ));
+    }
+
+    #[tokio::test]
+    async fn multipart_request_sniffing_test() {
+        // the content type header is missing (or generic) but the file is a JPEG image
+        let request = multipart_request(&[
+            ("operations", None, None, OPERATIONS),
+            ("map", None, None, br#"{"0":["variables.images.0"]}"#),
+            (
+                "0",
+                Some("img.jpg"),
+                Some("application/octet-stream"),
+                JPEG_SIGNATURE,
+            ),
+        ]);
+
+        let graphql_request = GraphQLRequest::from_request(request, &()).await.unwrap();
+        assert!(matches!(
+            graphql_request
+                .uploadables
+                .unwrap()
+                .get("img.jpg")
+                .unwrap()
+                .content_type(),
+            ContextUploadableContentType::ImageJpeg
+        ));
+    }
+
+    #[tokio::test]
+    async fn multipart_request_unsupported_type_test() {
+        let request = multipart_request(&[
+            ("operations", None, None, OPERATIONS),
+            ("map", None, None, br#"{"0":["variables.images.0"]}"#),
+            ("0", Some("img.png"), Some("image/png"), b"<svg></svg>"),
+        ]);
+
+        let response = GraphQLRequest::from_request(request, &())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn multipart_request_disguised_type_test() {
+        let request = multipart_request(&[
+            ("operations", None, None, OPERATIONS),
+            ("map", None, None, br#"{"0":["variables.images.0"]}"#),
+            ("0", Some("img.png"), Some("image/png"), JPEG_SIGNATURE),
+        ]);
+
+        let response = GraphQLRequest::from_request(request, &())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn multipart_request_invalid_map_test() {
+        // file field missing in the map
+        let request = multipart_request(&[
+            ("operations", None, None, OPERATIONS),
+            ("map", None, None, br#"{}"#),
+            ("0", Some("img.png"), Some("image/png"), PNG_SIGNATURE),
+        ]);
+        let response = GraphQLRequest::from_request(request, &())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // map referencing non-existent file field
+        let request = multipart_request(&[
+            ("operations", None, None, OPERATIONS),
+            ("map", None, None, br#"{"1":["variables.images.0"]}"#),
+        ]);
+        let response = GraphQLRequest::from_request(request, &())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // operations are required
+        let request = multipart_request(&[("map", None, None, br#"{}"#)]);
+        let response = GraphQLRequest::from_request(request, &())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn multipart_request_without_files_test() {
+        let request = multipart_request(&[("operations", None, None, OPERATIONS)]);
+
+        let graphql_request = GraphQLRequest::from_request(request, &()).await.unwrap();
+        assert!(graphql_request.uploadables.is_none());
+    }
+}
@@ -1,45 +1,132 @@
+use crate::arango::client::ClientExt;
 use crate::arango::ConnectionPool;
 use crate::auth::get_current_user;
+use crate::auth::rbac::RbacEnforcer;
+use crate::auth::session::SessionDevice;
+use crate::axum_server::graphql_request::GraphQLRequest;
 use crate::global_configuration::GlobalConfiguration;
-use crate::graphql_context::Context;
+use crate::graphql_context::{Context, ContextUploadableContentType};
 use crate::graphql_schema::create_graphql_schema;
-use crate::stripe::webhook::{verify_stripe_signature, StripeWebhookPayload, StripeWebhookType};
-use crate::stripe::CheckoutSession;
+use crate::stripe::webhook::{verify_stripe_signature, StripeWebhookPayload};
 use axum::body::Bytes;
-use axum::extract::Path;
-use axum::http::{HeaderMap, StatusCode};
+use axum::extract::{ConnectInfo, Path};
+use axum::http::{header, HeaderMap, StatusCode};
 use axum::response::{IntoResponse, Redirect};
 use axum::Extension;
-use juniper_axum::extract::JuniperRequest;
 use juniper_axum::response::JuniperResponse;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Maximum time of the ArangoDB readiness check (waiting for a pool connection included) so the
+/// probe doesn't hang when the pool is exhausted or the database doesn't respond.
+const READINESS_DATABASE_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub(crate) async fn graphql_axum_handler(
     headers: HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
     Extension(connection_pool): Extension<ConnectionPool>,
+    Extension(rbac_enforcer): Extension<RbacEnforcer>,
     Extension(global_configuration): Extension<GlobalConfiguration>,
-    JuniperRequest(req): JuniperRequest, // should be the last argument as consumes `Request`
+    graphql_request: GraphQLRequest, // should be the last argument as consumes `Request`
 ) -> impl IntoResponse {
     let authorization_header = headers
         .get("Authorization")
         .map(|value| value.to_str().unwrap_or_default().to_string());
 
-    match get_current_user(&connection_pool, &authorization_header).await {
+    match get_current_user(
+        &connection_pool,
+        &global_configuration.sessions,
+        &authorization_header,
+    )
+    .await
+    {
         Ok(user) => {
             let graphql_schema = create_graphql_schema();
 
             let context = Context {
                 pool: connection_pool,
-                uploadables: None, // TODO: currently not implemented on the server
+                uploadables: graphql_request.uploadables,
                 user,
+                session_device: get_session_device(
+                    &headers,
+                    connect_info.map(|ConnectInfo(address)| address),
+                    global_configuration.http.trust_proxy,
+                ),
+                rbac_enforcer,
                 global_configuration,
             };
 
-            JuniperResponse(req.execute(&graphql_schema, &context).await).into_response()
+            JuniperResponse(
+                graphql_request
+                    .request
+                    .execute(&graphql_schema, &context)
+                    .await,
+            )
+            .into_response()
         }
         Err(_) => StatusCode::UNAUTHORIZED.into_response(),
     }
 }
 
+/// Describes the device sending the request. The IP address is taken from the connection unless
+/// the proxy is trusted (see `--trust-proxy`). In such case, it's the last `X-Forwarded-For`
+/// address since that's the one added by our proxy (the previous ones are sent by the client, so
+/// they cannot be trusted).
+pub(crate) fn get_session_device(
+    headers: &HeaderMap,
+    remote_address: Option<SocketAddr>,
+    trust_proxy: bool,
+) -> SessionDevice {
+    let header_value = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+
+    SessionDevice {
+        user_agent: header_value("User-Agent").map(String::from),
+        ip_address: header_value("X-Forwarded-For")
+            .filter(|_| trust_proxy)
+            .and_then(|forwarded_for| forwarded_for.rsplit(',').next())
+            .map(|ip_address| ip_address.trim().to_string())
+            .filter(|ip_address| !ip_address.is_empty())
+            .or_else(|| remote_address.map(|address| address.ip().to_string())),
+    }
+}
+
+/// Serves images from the local image storage (see `LocalImageStorage`). Images stored in S3 are
+/// never served by this server (404).
+///
+/// URL example:
+/// - http://localhost:5000/images/ef5f060a-ff77-4a76-9581-fa0031cb305d.png
+pub(crate) async fn images_axum_handler(
+    Path(image_name): Path<String>,
+    Extension(global_configuration): Extension<GlobalConfiguration>,
+) -> impl IntoResponse {
+    match global_configuration.image_storage.read(&image_name).await {
+        Ok(Some(data)) => match ContextUploadableContentType::sniff(&data) {
+            Some(content_type) => (
+                [
+                    (header::CONTENT_TYPE, content_type.mime_type()),
+                    // image names are unique (UUID), so the images never change
+                    (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+                ],
+                data,
+            )
+                .into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        },
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(error) => {
+            tracing::error!("Unable to read image '{}': {}", image_name, error);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
 /// Exposes an Axum handler to redirect URLs. It validates the input UUID and reject it if it's
 /// not a valid UUID format (with 404).
 ///
@@ -65,6 +152,41 @@ pub(crate) async fn redirect_axum_handler(
     }
 }
 
+/// Receives Google RISC security event tokens (Cross-Account Protection). The token is the whole
+/// request body (`application/secevent+jwt`), and it's acknowledged with 202 once processed.
+///
+/// See: https://developers.google.com/identity/protocols/risc#receiver
+pub(crate) async fn risc_axum_handler(
+    Extension(connection_pool): Extension<ConnectionPool>,
+    Extension(global_configuration): Extension<GlobalConfiguration>,
+    body: String, // must be last as it consumes the request body
+) -> impl IntoResponse {
+    let google_provider = global_configuration.oidc_providers.google();
+    let security_event_token =
+        match crate::auth::risc::verify_security_event_token(google_provider, body.trim()).await {
+            Ok(security_event_token) => security_event_token,
+            Err(error) => {
+                tracing::error!("Unable to verify RISC security event token: {}", error);
+                return (StatusCode::BAD_REQUEST, "Invalid security event token.").into_response();
+            }
+        };
+
+    match crate::auth::risc::handle_security_event_token(
+        &connection_pool,
+        google_provider,
+        &security_event_token,
+    )
+    .await
+    {
+        Ok(_) => StatusCode::ACCEPTED.into_response(),
+        Err(error) => {
+            let message = "Unable to process RISC security event token.";
+            tracing::error!("{} {}", message, error);
+            (StatusCode::INTERNAL_SERVER_ERROR, message).into_response()
+        }
+    }
+}
+
 pub(crate) async fn webhooks_axum_handler(
     headers: HeaderMap,
     Extension(connection_pool): Extension<ConnectionPool>,
@@ -97,7 +219,16 @@ pub(crate) async fn webhooks_axum_handler(
                         )
                         .await
                         {
-                            Ok(_) => {}
+                            Ok(Some(_)) => {}
+                            Ok(None) => {
+                                // Stripe can deliver the same event more than once, and we have to
+                                // process it only once (see `record_webhook_call` for more info).
+                                tracing::warn!(
+                                    "Stripe webhook with ID '{}' was already received (ignoring).",
+                                    stripe_webhook_payload.id
+                                );
+                                return StatusCode::OK.into_response();
+                            }
                             Err(error) => {
                                 tracing::error!(
                                     "Unable to record Stripe payload in our database: {}",
@@ -112,40 +243,44 @@ pub(crate) async fn webhooks_axum_handler(
                         }
 
                         let webhook_type = &stripe_webhook_payload.r#type;
-                        match webhook_type {
-                            StripeWebhookType::CheckoutSessionCompleted => {
-                                match crate::stripe::webhook_handlers::checkout_session::completed(
+                        let webhook_result = crate::stripe::webhook_handlers::handle_webhook(
+                            &connection_pool,
+                            &stripe_webhook_payload,
+                        )
+                        .await;
+
+                        match webhook_result {
+                            Ok(_) => {
+                                tracing::info!(
+                                    "Stripe webhook with type '{:?}' was executed successfully.",
+                                    webhook_type
+                                );
+                                StatusCode::OK.into_response()
+                            }
+                            Err(error) => {
+                                let message = format!(
+                                    "Stripe webhook with type '{:?}' failed to execute.",
+                                    webhook_type
+                                );
+                                tracing::error!("{} {}", message, error);
+
+                                // The event must be forgotten so that Stripe's retry is not ignored
+                                // as a duplicate.
+                                if let Err(error) = crate::stripe::dal::forget_webhook_call(
                                     &connection_pool,
-                                    &serde_json::from_value::<CheckoutSession>(
-                                        stripe_webhook_payload.data.object,
-                                    )
-                                    .unwrap(), // TODO: fix unwrap
+                                    &stripe_webhook_payload,
                                 )
                                 .await
                                 {
-                                    Ok(_) => {
-                                        tracing::info!("Stripe webhook with type '{:?}' was executed successfully.", webhook_type);
-                                        return StatusCode::OK.into_response();
-                                    }
-                                    Err(_) => {
-                                        let message = format!(
-                                            "Stripe webhook with type '{:?}' failed to execute.",
-                                            webhook_type
-                                        );
-                                        tracing::error!("{}", message);
-                                        return (StatusCode::INTERNAL_SERVER_ERROR, message)
-                                            .into_response();
-                                    }
+                                    tracing::error!(
+                                        "Unable to forget Stripe payload in our database: {}",
+                                        error
+                                    );
                                 }
+
+                                (StatusCode::INTERNAL_SERVER_ERROR, message).into_response()
                             }
-                            _ => {
-                                tracing::warn!(
-                                    "Stripe webhook with type '{:?}' was called but there is no handler for it (ignoring).",
-                                    webhook_type
-                                );
-                                return StatusCode::OK.into_response();
-                            }
-                        };
+                        }
                     }
                     Err(_) => {
                         let message = "Invalid signature.";
@@ -168,3 +303,121 @@ pub(crate) async fn webhooks_axum_handler(
         }
     }
 }
+
+/// Liveness probe: the server is up and able to respond (no dependencies are being checked so
+/// the server is not restarted just because the database is temporarily down).
+pub(crate) async fn status_live_axum_handler() -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        r#"{"status":"ok"}"#,
+    )
+}
+
+/// Readiness probe: the server is able to serve requests, i.e., ArangoDB is reachable and the
+/// Stripe secrets are configured. It responds with HTTP 503 otherwise (see `ReadinessReport`).
+pub(crate) async fn status_ready_axum_handler(
+    Extension(connection_pool): Extension<ConnectionPool>,
+    Extension(global_configuration): Extension<GlobalConfiguration>,
+) -> impl IntoResponse {
+    let report = check_readiness(
+        &connection_pool,
+        &global_configuration,
+        READINESS_DATABASE_TIMEOUT,
+    )
+    .await;
+    let status_code = if report.ready {
+        StatusCode::OK
+    } else {
+        tracing::error!("Readiness check failed: {:?}", report);
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status_code,
+        [(header::CONTENT_TYPE, "application/json")],
+        serde_json::to_string(&report).unwrap(),
+    )
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct ReadinessReport {
+    pub(crate) ready: bool,
+    pub(crate) arangodb: ArangoDbReadiness,
+    pub(crate) stripe: StripeReadiness,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct ArangoDbReadiness {
+    pub(crate) reachable: bool,
+    pub(crate) version: Option<String>,
+    pub(crate) error: Option<String>,
+    pub(crate) pool: ConnectionPoolReadiness,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct ConnectionPoolReadiness {
+    pub(crate) max_size: usize,
+    pub(crate) size: usize,
+    pub(crate) available: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct StripeReadiness {
+    pub(crate) restricted_api_key_configured: bool,
+    pub(crate) webhook_secret_configured: bool,
+}
+
+pub(crate) async fn check_readiness<C: ClientExt>(
+    connection_pool: &ConnectionPool<C>,
+    global_configuration: &GlobalConfiguration,
+    database_timeout: Duration,
+) -> ReadinessReport {
+    let database_check = async {
+        let db = connection_pool.try_db().await?;
+        anyhow::Ok(db.arango_version().await?.version)
+    };
+    let (version, error) = match tokio::time::timeout(database_timeout, database_check).await {
+        Ok(Ok(version)) => (Some(version), None),
+        Ok(Err(error)) => (None, Some(error.to_string())),
+        Err(_) => (
+            None,
+            Some(format!(
+                "database check timed out after {:?}",
+                database_timeout
+            )),
+        ),
+    };
+
+    // the pool status must be read after the check so it includes the checked connection
+    let pool_status = connection_pool.status();
+    let arangodb = ArangoDbReadiness {
+        reachable: error.is_none(),
+        version,
+        error,
+        pool: ConnectionPoolReadiness {
+            max_size: pool_status.max_size,
+            size: pool_status.size,
+            available: pool_status.available,
+        },
+    };
+
+    let stripe = StripeReadiness {
+        restricted_api_key_configured: global_configuration
+            .stripe_restricted_api_key
+            .as_ref()
+            .is_some_and(|key| !key.is_empty()),
+        webhook_secret_configured: global_configuration
+            .stripe_webhook_secret
+            .as_ref()
+            .is_some_and(|secret| !secret.is_empty()),
+    };
+
+    ReadinessReport {
+        ready: arangodb.reachable
+            && stripe.restricted_api_key_configured
+            && stripe.webhook_secret_configured,
+        arangodb,
+        stripe,
+    }
+}
@@ -0,0 +1,67 @@
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::HeaderName;
+use axum::middleware::Next;
+use axum::response::Response;
+use sentry::SentryFutureExt;
+use std::sync::Arc;
+
+/// Returns the request ID (see `SetRequestIdLayer`) or an empty string when it's missing.
+fn request_id<'a>(request: &'a Request<Body>, request_id_header: &HeaderName) -> &'a str {
+    request
+        .headers()
+        .get(request_id_header)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Creates the tracing span of the request (similar to the default `TraceLayer` span) including the
+/// request ID so all the logs of one request can be correlated.
+pub(in crate::axum_server) fn make_request_span(
+    request_id_header: HeaderName,
+) -> impl Fn(&Request<Body>) -> tracing::Span + Clone {
+    move |request: &Request<Body>| {
+        tracing::info_span!(
+            "request",
+            method = %request.method(),
+            uri = %request.uri(),
+            version = ?request.version(),
+            request_id = %request_id(request, &request_id_header),
+        )
+    }
+}
+
+/// Runs the request with its own Sentry hub so the request ID is attached (as a tag) to all the
+/// Sentry events of this request only.
+pub(in crate::axum_server) async fn sentry_request_id_middleware(
+    State(request_id_header): State<HeaderName>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let hub = Arc::new(sentry::Hub::new_from_top(sentry::Hub::current()));
+    let request_id = request_id(&request, &request_id_header).to_string();
+    if !request_id.is_empty() {
+        hub.configure_scope(|scope| scope.set_tag("request_id", request_id));
+    }
+
+    next.run(request).bind_hub(hub).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_id_test() {
+        let header = HeaderName::from_static("x-request-id");
+
+        let request = Request::builder()
+            .header("X-Request-Id", "abc-123")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(request_id(&request, &header), "abc-123");
+
+        let request = Request::builder().body(Body::empty()).unwrap();
+        assert_eq!(request_id(&request, &header), "");
+    }
+}
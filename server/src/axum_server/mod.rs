@@ -1,34 +1,85 @@
+mod graphql_request;
 mod handlers;
+mod middleware;
 
 #[cfg(test)]
 mod tests;
 
 use crate::arango::ConnectionPool;
+use crate::auth::rbac::RbacEnforcer;
 use crate::axum_server::handlers::{
-    graphql_axum_handler, redirect_axum_handler, webhooks_axum_handler,
+    graphql_axum_handler, images_axum_handler, redirect_axum_handler, risc_axum_handler,
+    status_live_axum_handler, status_ready_axum_handler, webhooks_axum_handler,
 };
+use crate::axum_server::middleware::{make_request_span, sentry_request_id_middleware};
 use crate::global_configuration::GlobalConfiguration;
 use axum::{
+    extract::DefaultBodyLimit,
     routing::{get, post},
     Extension, Router,
 };
 use tower_http::cors::CorsLayer;
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::timeout::TimeoutLayer;
 use tower_http::trace::TraceLayer;
 
 pub fn create_axum_server(
     connection_pool: ConnectionPool,
     global_configuration: GlobalConfiguration,
 ) -> Router<()> {
+    let http_configuration = global_configuration.http.to_owned();
+    let request_id_header = http_configuration.request_id_header;
+
+    // The enforcer is shared by all the requests so the policies are not reloaded for each of them:
+    let rbac_enforcer = RbacEnforcer::new(connection_pool.to_owned());
+
     Router::new()
         // Alphabetically sorted routes:
-        .route("/graphql", get(graphql_axum_handler).post(graphql_axum_handler))
+        .route(
+            "/graphql",
+            get(graphql_axum_handler)
+                .post(graphql_axum_handler)
+                // Timed out requests are responded with 408 and too large bodies with 413.
+                .layer(TimeoutLayer::new(http_configuration.graphql_timeout))
+                .layer(RequestBodyLimitLayer::new(
+                    http_configuration.graphql_body_limit,
+                )),
+        )
+        .route("/images/:name", get(images_axum_handler))
         .route("/redirect/:uuid", get(redirect_axum_handler))
-        .route("/status/ping", get(|| async { "pong" })) // TODO: perform some check to make sure the server is healthy (DB check)
-        .route("/webhooks/stripe", post(webhooks_axum_handler))
-        // Common layers:
-        .layer(TraceLayer::new_for_http())
+        .route("/status/live", get(status_live_axum_handler))
+        .route("/status/ping", get(|| async { "pong" })) // deprecated: use `/status/live` instead
+        .route("/status/ready", get(status_ready_axum_handler))
+        .route(
+            "/webhooks/google/risc",
+            post(risc_axum_handler)
+                .layer(TimeoutLayer::new(http_configuration.webhooks_timeout))
+                .layer(RequestBodyLimitLayer::new(
+                    http_configuration.webhooks_body_limit,
+                )),
+        )
+        .route(
+            "/webhooks/stripe",
+            post(webhooks_axum_handler)
+                .layer(TimeoutLayer::new(http_configuration.webhooks_timeout))
+                .layer(RequestBodyLimitLayer::new(
+                    http_configuration.webhooks_body_limit,
+                )),
+        )
+        // Common layers (the last one is the outermost one, so the request ID is set first):
+        .layer(DefaultBodyLimit::disable()) // replaced by the per-route `RequestBodyLimitLayer`
+        .layer(axum::middleware::from_fn_with_state(
+            request_id_header.to_owned(),
+            sentry_request_id_middleware,
+        ))
+        .layer(TraceLayer::new_for_http().make_span_with(make_request_span(
+            request_id_header.to_owned(),
+        )))
         .layer(CorsLayer::permissive())
-        // TODO: implement other layers such as `RequestIdLayer` and `TimeoutLayer` (https://docs.rs/axum/latest/axum/middleware/index.html#commonly-used-middleware)
+        .layer(PropagateRequestIdLayer::new(request_id_header.to_owned()))
+        .layer(SetRequestIdLayer::new(request_id_header, MakeRequestUuid))
         .layer(Extension(connection_pool))
+        .layer(Extension(rbac_enforcer))
         .layer(Extension(global_configuration))
 }
@@ -1,6 +1,8 @@
-use crate::arango::get_database_connection_pool_mock;
+use crate::arango::client::fake::FakeClient;
+use crate::arango::{get_database_connection_pool_fake, get_database_connection_pool_mock};
 use crate::axum_server::create_axum_server;
-use crate::global_configuration::GlobalConfiguration;
+use crate::axum_server::handlers::{check_readiness, get_session_device};
+use crate::global_configuration::{GlobalConfiguration, HttpConfiguration};
 use axum::{
     body::Body,
     extract::connect_info::MockConnectInfo,
@@ -8,6 +10,7 @@ use axum::{
 };
 use http_body_util::BodyExt; // for `collect`
 use serde_json::json;
+use std::time::Duration;
 use tower::{Service, ServiceExt}; // for `call`, `oneshot`, and `ready`
 
 #[tokio::test]
@@ -56,6 +59,146 @@ async fn test_axum_server_ping_pong() {
     assert_eq!(body.collect().await.unwrap().to_bytes(), "pong");
 }
 
+#[tokio::test]
+async fn test_axum_server_status_live() {
+    let app = create_axum_server(
+        get_database_connection_pool_mock(),
+        GlobalConfiguration::default(),
+    );
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/status/live")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let (parts, body) = response.into_parts();
+    assert_eq!(parts.status, StatusCode::OK);
+    assert_eq!(
+        parts.headers.get(http::header::CONTENT_TYPE).unwrap(),
+        mime::APPLICATION_JSON.as_ref()
+    );
+    assert_eq!(
+        body.collect().await.unwrap().to_bytes(),
+        r#"{"status":"ok"}"#
+    );
+}
+
+#[tokio::test]
+async fn test_axum_server_status_ready_unreachable_database() {
+    let app = create_axum_server(
+        get_database_connection_pool_mock(),
+        GlobalConfiguration::default(),
+    );
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/status/ready")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let (parts, body) = response.into_parts();
+    assert_eq!(parts.status, StatusCode::SERVICE_UNAVAILABLE);
+
+    let report: serde_json::Value =
+        serde_json::from_slice(&body.collect().await.unwrap().to_bytes()).unwrap();
+    assert_eq!(report["ready"], false);
+    assert_eq!(report["arangodb"]["reachable"], false);
+    assert_eq!(report["arangodb"]["version"], json!(null));
+    assert!(report["arangodb"]["error"].is_string());
+    assert_eq!(report["stripe"]["restricted_api_key_configured"], true);
+    assert_eq!(report["stripe"]["webhook_secret_configured"], true);
+}
+
+#[tokio::test]
+async fn test_check_readiness() {
+    let client = FakeClient::default();
+    client.respond(
+        http::Method::GET,
+        "_api/version",
+        200,
+        json!({ "server": "arango", "version": "3.11.4", "license": "community" }),
+    );
+
+    let pool = get_database_connection_pool_fake(&client);
+    let report = check_readiness(
+        &pool,
+        &GlobalConfiguration::default(),
+        Duration::from_secs(5),
+    )
+    .await;
+
+    assert!(report.ready);
+    assert!(report.arangodb.reachable);
+    assert_eq!(report.arangodb.version, Some(String::from("3.11.4")));
+    assert_eq!(report.arangodb.error, None);
+    assert_eq!(report.arangodb.pool.max_size, 1);
+    assert_eq!(report.arangodb.pool.size, 1);
+}
+
+#[tokio::test]
+async fn test_check_readiness_timeout() {
+    let client = FakeClient::default();
+    let pool = get_database_connection_pool_fake(&client);
+
+    // hold the only pool connection so the readiness check waits for it
+    let _connection = pool.pool.get().await.unwrap();
+    let report = check_readiness(
+        &pool,
+        &GlobalConfiguration::default(),
+        Duration::from_millis(50),
+    )
+    .await;
+
+    assert!(!report.ready);
+    assert!(!report.arangodb.reachable);
+    assert_eq!(report.arangodb.version, None);
+    assert_eq!(
+        report.arangodb.error,
+        Some(String::from("database check timed out after 50ms"))
+    );
+    assert_eq!(report.arangodb.pool.available, 0);
+    assert!(client.requests().is_empty());
+}
+
+#[tokio::test]
+async fn test_check_readiness_missing_stripe_secrets() {
+    let client = FakeClient::default();
+    client.respond(
+        http::Method::GET,
+        "_api/version",
+        200,
+        json!({ "server": "arango", "version": "3.11.4", "license": "community" }),
+    );
+
+    let pool = get_database_connection_pool_fake(&client);
+    let report = check_readiness(
+        &pool,
+        &GlobalConfiguration {
+            stripe_restricted_api_key: Some(String::from("rk_test_123")),
+            stripe_webhook_secret: None,
+            ..Default::default()
+        },
+        Duration::from_secs(5),
+    )
+    .await;
+
+    assert!(!report.ready);
+    assert!(report.arangodb.reachable);
+    assert!(report.stripe.restricted_api_key_configured);
+    assert!(!report.stripe.webhook_secret_configured);
+}
+
 #[tokio::test]
 async fn test_axum_server_redirect_invalid() {
     let app = create_axum_server(
@@ -105,6 +248,33 @@ async fn test_axum_server_webhook_stripe_no_signature() {
     );
 }
 
+#[tokio::test]
+async fn test_axum_server_webhook_google_risc_invalid_token() {
+    let app = create_axum_server(
+        get_database_connection_pool_mock(),
+        GlobalConfiguration::default(),
+    );
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/webhooks/google/risc")
+                .header(http::header::CONTENT_TYPE, "application/secevent+jwt")
+                .body(Body::from("invalid.security.token"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let (parts, body) = response.into_parts();
+    assert_eq!(parts.status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body.collect().await.unwrap().to_bytes(),
+        "Invalid security event token."
+    );
+}
+
 #[tokio::test]
 async fn test_axum_server_graphql_get_query() {
     let app = create_axum_server(
@@ -213,3 +383,164 @@ async fn test_axum_server_graphql_post_syntax_error() {
     let (parts, body) = response.into_parts();
     assert_eq!(parts.status, StatusCode::BAD_REQUEST);
 }
+
+#[tokio::test]
+async fn test_axum_server_request_id_propagated() {
+    let app = create_axum_server(
+        get_database_connection_pool_mock(),
+        GlobalConfiguration::default(),
+    );
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/status/live")
+                .header("X-Request-Id", "test-request-id")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        response.headers().get("X-Request-Id").unwrap(),
+        "test-request-id"
+    );
+}
+
+#[tokio::test]
+async fn test_axum_server_request_id_generated() {
+    let app = create_axum_server(
+        get_database_connection_pool_mock(),
+        GlobalConfiguration::default(),
+    );
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/status/live")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let request_id = response.headers().get("X-Request-Id").unwrap();
+    assert!(uuid::Uuid::parse_str(request_id.to_str().unwrap()).is_ok());
+}
+
+#[tokio::test]
+async fn test_axum_server_webhook_stripe_body_too_large() {
+    let app = create_axum_server(
+        get_database_connection_pool_mock(),
+        GlobalConfiguration {
+            http: HttpConfiguration {
+                webhooks_body_limit: 10,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/webhooks/stripe")
+                .header("Stripe-Signature", "t=1,v1=yadada")
+                .header(http::header::CONTENT_LENGTH, 11)
+                .body(Body::from("01234567890"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn test_axum_server_images_local() {
+    let global_configuration = GlobalConfiguration::default();
+    let image_name = format!("{}.png", uuid::Uuid::new_v4());
+    let image_data = std::fs::read("src/images/tests/data/img.png").unwrap();
+    global_configuration
+        .image_storage
+        .upload(
+            &image_name,
+            &image_data,
+            &crate::graphql_context::ContextUploadableContentType::ImagePng,
+        )
+        .await
+        .unwrap();
+
+    let app = create_axum_server(get_database_connection_pool_mock(), global_configuration);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri(format!("/images/{}", image_name))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let (parts, body) = response.into_parts();
+    assert_eq!(parts.status, StatusCode::OK);
+    assert_eq!(
+        parts.headers.get(http::header::CONTENT_TYPE).unwrap(),
+        "image/png"
+    );
+    assert_eq!(body.collect().await.unwrap().to_bytes(), image_data);
+}
+
+#[tokio::test]
+async fn test_axum_server_images_not_found() {
+    let app = create_axum_server(
+        get_database_connection_pool_mock(),
+        GlobalConfiguration::default(),
+    );
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/images/..%2Fpasswd")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_get_session_device() {
+    let remote_address = std::net::SocketAddr::from(([10, 0, 0, 1], 5000));
+
+    let mut headers = http::HeaderMap::new();
+    headers.insert(http::header::USER_AGENT, "Mozilla/5.0".parse().unwrap());
+    let session_device = get_session_device(&headers, Some(remote_address), false);
+    assert_eq!(session_device.user_agent.as_deref(), Some("Mozilla/5.0"));
+    assert_eq!(session_device.ip_address.as_deref(), Some("10.0.0.1"));
+
+    // the forwarded addresses are ignored unless the proxy is trusted
+    headers.insert(
+        "x-forwarded-for",
+        "198.51.100.1, 203.0.113.7".parse().unwrap(),
+    );
+    let session_device = get_session_device(&headers, Some(remote_address), false);
+    assert_eq!(session_device.ip_address.as_deref(), Some("10.0.0.1"));
+
+    // the last forwarded address was added by the trusted proxy (the first one could be faked)
+    let session_device = get_session_device(&headers, Some(remote_address), true);
+    assert_eq!(session_device.ip_address.as_deref(), Some("203.0.113.7"));
+
+    let session_device = get_session_device(&http::HeaderMap::new(), None, true);
+    assert_eq!(session_device.user_agent, None);
+    assert_eq!(session_device.ip_address, None);
+}
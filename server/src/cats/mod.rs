@@ -15,7 +15,7 @@ impl CatsQuery {
         context: &Context,
         all_cats_filter: Option<AllCatsFilter>, // TODO: remove `Option` (make required) when FE is migrated
     ) -> AbacusGraphQLResult<Vec<CatInfo>> {
-        rbac::verify_permissions(&context.user, &Cats(ListAllCats)).await?;
+        rbac::verify_permissions(&context.rbac_enforcer, &context.user, &Cats(ListAllCats)).await?;
         Ok(list_all_cats(&context.pool, &all_cats_filter).await?)
     }
 }
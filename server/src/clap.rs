@@ -1,4 +1,4 @@
-use clap::{Arg, Command, ValueHint};
+use clap::{Arg, ArgAction, Command, ValueHint};
 
 pub fn generate_clap_app() -> Command {
     clap::command!()
@@ -50,6 +50,219 @@ pub fn generate_clap_app() -> Command {
                     Stripe.com can send these payloads.",
                 )
                 .num_args(1),
+        )
+        .arg(
+            Arg::new("request-id-header")
+                .long("request-id-header")
+                .env("REQUEST_ID_HEADER")
+                .help("HTTP header with the request ID (generated when missing)")
+                .num_args(1)
+                .default_value("x-request-id"),
+        )
+        .arg(
+            Arg::new("graphql-timeout")
+                .long("graphql-timeout")
+                .env("GRAPHQL_TIMEOUT")
+                .help("Timeout of GraphQL requests in seconds (responded with 408)")
+                .num_args(1)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("30"),
+        )
+        .arg(
+            Arg::new("graphql-body-limit")
+                .long("graphql-body-limit")
+                .env("GRAPHQL_BODY_LIMIT")
+                .help("Maximum size of GraphQL request bodies in bytes (responded with 413)")
+                .long_help(
+                    "Maximum size of GraphQL request bodies in bytes (responded with 413). It's \
+                    raised automatically so that 'images-max-per-product' images of \
+                    'images-max-file-size' (plus 1 MiB for the rest of the multipart request) \
+                    fit into one upload.",
+                )
+                .num_args(1)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("2097152"),
+        )
+        .arg(
+            Arg::new("webhooks-timeout")
+                .long("webhooks-timeout")
+                .env("WEBHOOKS_TIMEOUT")
+                .help("Timeout of webhook requests in seconds (responded with 408)")
+                .num_args(1)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("15"),
+        )
+        .arg(
+            Arg::new("webhooks-body-limit")
+                .long("webhooks-body-limit")
+                .env("WEBHOOKS_BODY_LIMIT")
+                .help("Maximum size of webhook request bodies in bytes (responded with 413)")
+                .num_args(1)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1048576"),
+        )
+        .arg(
+            Arg::new("trust-proxy")
+                .long("trust-proxy")
+                .env("TRUST_PROXY")
+                .help("Trust the 'X-Forwarded-For' header added by the reverse proxy")
+                .long_help(
+                    "Trust the 'X-Forwarded-For' header added by the reverse proxy. Only the \
+                    address added by the proxy (the last one) is used as the client IP address. \
+                    Enable it only when the server is reachable exclusively through the proxy, \
+                    otherwise the clients could fake their IP addresses.",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("images-storage")
+                .long("images-storage")
+                .env("IMAGES_STORAGE")
+                .help("Where to store the uploaded images")
+                .num_args(1)
+                .value_parser(["s3", "local"])
+                .default_value("s3"),
+        )
+        .arg(
+            Arg::new("images-s3-bucket")
+                .long("images-s3-bucket")
+                .env("IMAGES_S3_BUCKET")
+                .help("S3 bucket for the uploaded images")
+                .num_args(1)
+                .default_value("abacus-images-58276402-e657-493a-b0b9-d8c278f3e01d"),
+        )
+        .arg(
+            Arg::new("images-s3-region")
+                .long("images-s3-region")
+                .env("IMAGES_S3_REGION")
+                .help("AWS region of the S3 bucket")
+                .num_args(1)
+                .default_value("us-west-1"),
+        )
+        .arg(
+            Arg::new("images-s3-endpoint")
+                .long("images-s3-endpoint")
+                .env("IMAGES_S3_ENDPOINT")
+                .help("Custom S3 endpoint (MinIO or other S3-compatible storage)")
+                .num_args(1)
+                .value_hint(ValueHint::Url),
+        )
+        .arg(
+            Arg::new("images-local-directory")
+                .long("images-local-directory")
+                .env("IMAGES_LOCAL_DIRECTORY")
+                .help("Directory for the uploaded images (when using 'local' images storage)")
+                .num_args(1)
+                .value_hint(ValueHint::DirPath)
+                .default_value("images"),
+        )
+        .arg(
+            Arg::new("images-public-base-url")
+                .long("images-public-base-url")
+                .env("IMAGES_PUBLIC_BASE_URL")
+                .help("Public base URL of the uploaded images (CDN for example)")
+                .long_help(
+                    "Public base URL of the uploaded images (CDN for example). The images are \
+                    served directly from the S3 bucket or by this server (when using 'local' \
+                    images storage) if not specified. Deployments with a CDN in front of the \
+                    bucket (CloudFront) must set it explicitly.",
+                )
+                .num_args(1)
+                .value_hint(ValueHint::Url),
+        )
+        .arg(
+            Arg::new("images-thumbnail-width")
+                .long("images-thumbnail-width")
+                .env("IMAGES_THUMBNAIL_WIDTH")
+                .help("Maximum width of the thumbnail image variants in pixels")
+                .num_args(1)
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .default_value("160"),
+        )
+        .arg(
+            Arg::new("images-card-width")
+                .long("images-card-width")
+                .env("IMAGES_CARD_WIDTH")
+                .help("Maximum width of the card image variants in pixels")
+                .num_args(1)
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .default_value("640"),
+        )
+        .arg(
+            Arg::new("images-full-width")
+                .long("images-full-width")
+                .env("IMAGES_FULL_WIDTH")
+                .help("Maximum width of the full image variants in pixels")
+                .num_args(1)
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .default_value("1920"),
+        )
+        .arg(
+            Arg::new("images-webp")
+                .long("images-webp")
+                .env("IMAGES_WEBP")
+                .help("Re-encode the image variants to WebP")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("images-max-file-size")
+                .long("images-max-file-size")
+                .env("IMAGES_MAX_FILE_SIZE")
+                .help("Maximum size of a single uploaded image in bytes")
+                .num_args(1)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("2097152"),
+        )
+        .arg(
+            Arg::new("images-max-dimension")
+                .long("images-max-dimension")
+                .env("IMAGES_MAX_DIMENSION")
+                .help("Maximum width and height of the uploaded images in pixels")
+                .num_args(1)
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .default_value("8000"),
+        )
+        .arg(
+            Arg::new("images-max-per-product")
+                .long("images-max-per-product")
+                .env("IMAGES_MAX_PER_PRODUCT")
+                .help("Maximum number of images of a single product")
+                .num_args(1)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("20"),
+        )
+        .arg(
+            Arg::new("sessions-max-lifetime")
+                .long("sessions-max-lifetime")
+                .env("SESSIONS_MAX_LIFETIME")
+                .help("Maximum lifetime of user sessions in seconds (regardless of the activity)")
+                .num_args(1)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("7776000"),
+        )
+        .arg(
+            Arg::new("sessions-inactivity-timeout")
+                .long("sessions-inactivity-timeout")
+                .env("SESSIONS_INACTIVITY_TIMEOUT")
+                .help("Inactivity timeout of user sessions in seconds")
+                .num_args(1)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("2592000"),
+        )
+        .arg(
+            Arg::new("oidc-provider")
+                .long("oidc-provider")
+                .env("OIDC_PROVIDERS")
+                .help("Additional OpenID Connect provider in 'name,issuer,jwks_url,audience' format")
+                .long_help(
+                    "Additional OpenID Connect provider users can sign in with in \
+                    'name,issuer,jwks_url,audience' format (Google is always supported). The \
+                    option can be repeated (providers are separated by ';' in the environment \
+                    variable).",
+                )
+                .num_args(1)
+                .action(ArgAction::Append)
+                .value_delimiter(';'),
         ).subcommand(
             Command::new("generate-cli-completions")
                 .override_help("Generate CLI completions for specified shells.")
@@ -60,6 +273,26 @@ pub fn generate_clap_app() -> Command {
                         .value_parser(["bash", "zsh"])
                         .required(true)
                 )
+        ).subcommand(
+            Command::new("migrate")
+                .about("Manage database migrations.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("up")
+                        .about("Apply all pending database migrations.")
+                )
+                .subcommand(
+                    Command::new("status")
+                        .about("List all database migrations and when they were applied.")
+                )
+        ).subcommand(
+            Command::new("images")
+                .about("Manage uploaded images.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("gc")
+                        .about("Delete images which are not used by any product (nor archived product).")
+                )
         )
 }
 
@@ -80,4 +313,141 @@ mod tests {
             insta::assert_snapshot!(generate_clap_app().render_help().to_string())
         });
     }
+
+    #[test]
+    fn generated_clap_migrate_subcommands_test() {
+        let matches = generate_clap_app()
+            .try_get_matches_from(["server", "migrate", "status"])
+            .unwrap();
+        assert_eq!(
+            matches
+                .subcommand_matches("migrate")
+                .and_then(|migrate| migrate.subcommand_name()),
+            Some("status")
+        );
+
+        // subcommand of `migrate` is required
+        assert!(generate_clap_app()
+            .try_get_matches_from(["server", "migrate"])
+            .is_err());
+    }
+
+    #[test]
+    fn generated_clap_images_subcommands_test() {
+        let matches = generate_clap_app()
+            .try_get_matches_from(["server", "images", "gc"])
+            .unwrap();
+        assert_eq!(
+            matches
+                .subcommand_matches("images")
+                .and_then(|images| images.subcommand_name()),
+            Some("gc")
+        );
+
+        // subcommand of `images` is required
+        assert!(generate_clap_app()
+            .try_get_matches_from(["server", "images"])
+            .is_err());
+    }
+
+    #[test]
+    fn generated_clap_http_defaults_test() {
+        let matches = generate_clap_app()
+            .try_get_matches_from(["server", "--graphql-timeout", "5"])
+            .unwrap();
+        assert_eq!(matches.get_one::<u64>("graphql-timeout"), Some(&5));
+        assert_eq!(matches.get_one::<u64>("webhooks-timeout"), Some(&15));
+        assert_eq!(
+            matches
+                .get_one::<String>("request-id-header")
+                .map(String::as_str),
+            Some("x-request-id")
+        );
+
+        assert!(generate_clap_app()
+            .try_get_matches_from(["server", "--graphql-body-limit", "-1"])
+            .is_err());
+    }
+
+    #[test]
+    fn generated_clap_image_variants_test() {
+        let matches = generate_clap_app()
+            .try_get_matches_from(["server", "--images-card-width", "800", "--images-webp"])
+            .unwrap();
+        assert_eq!(matches.get_one::<u32>("images-thumbnail-width"), Some(&160));
+        assert_eq!(matches.get_one::<u32>("images-card-width"), Some(&800));
+        assert_eq!(matches.get_one::<u32>("images-full-width"), Some(&1920));
+        assert!(matches.get_flag("images-webp"));
+
+        assert!(!generate_clap_app()
+            .try_get_matches_from(["server"])
+            .unwrap()
+            .get_flag("images-webp"));
+        assert!(generate_clap_app()
+            .try_get_matches_from(["server", "--images-full-width", "0"])
+            .is_err());
+    }
+
+    #[test]
+    fn generated_clap_image_limits_test() {
+        let matches = generate_clap_app()
+            .try_get_matches_from(["server", "--images-max-per-product", "5"])
+            .unwrap();
+        assert_eq!(
+            matches.get_one::<usize>("images-max-file-size"),
+            Some(&2097152)
+        );
+        assert_eq!(matches.get_one::<u32>("images-max-dimension"), Some(&8000));
+        assert_eq!(matches.get_one::<usize>("images-max-per-product"), Some(&5));
+
+        assert!(generate_clap_app()
+            .try_get_matches_from(["server", "--images-max-dimension", "0"])
+            .is_err());
+    }
+
+    #[test]
+    fn generated_clap_sessions_test() {
+        let matches = generate_clap_app()
+            .try_get_matches_from(["server", "--sessions-inactivity-timeout", "3600"])
+            .unwrap();
+        assert_eq!(
+            matches.get_one::<u64>("sessions-max-lifetime"),
+            Some(&7776000)
+        );
+        assert_eq!(
+            matches.get_one::<u64>("sessions-inactivity-timeout"),
+            Some(&3600)
+        );
+
+        assert!(generate_clap_app()
+            .try_get_matches_from(["server", "--sessions-max-lifetime", "0"])
+            .is_err());
+    }
+
+    #[test]
+    fn generated_clap_oidc_providers_test() {
+        let matches = generate_clap_app()
+            .try_get_matches_from([
+                "server",
+                "--oidc-provider",
+                "a,https://a.example.com,https://a.example.com/jwks,client-a",
+                "--oidc-provider",
+                "b,https://b.example.com,https://b.example.com/jwks,client-b;c,https://c.example.com,https://c.example.com/jwks,client-c",
+            ])
+            .unwrap();
+        assert_eq!(
+            matches
+                .get_many::<String>("oidc-provider")
+                .unwrap()
+                .map(|provider| provider.split(',').next().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["a", "b", "c"]
+        );
+
+        assert!(generate_clap_app()
+            .try_get_matches_from(["server"])
+            .unwrap()
+            .get_many::<String>("oidc-provider")
+            .is_none());
+    }
 }
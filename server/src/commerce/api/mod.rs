@@ -1,14 +1,21 @@
+pub use crate::commerce::model::inventory::StockReservation;
+pub use crate::commerce::model::orders::OrderLookup;
+pub use crate::commerce::model::orders::OrderStatus;
 pub use crate::commerce::model::product_categories::ProductCategory;
 pub use crate::commerce::model::products::PriceSortDirection;
 pub use crate::commerce::model::products::Product;
 pub use crate::commerce::model::products::ProductMultilingualInput;
 pub use crate::commerce::model::products::ProductMultilingualInputVisibility;
 
+use crate::arango::ConnectionPool;
 use crate::commerce::model::checkout_session::CheckoutSessionInput;
-use crate::commerce::model::product_addons::ProductAddon;
+use crate::commerce::model::orders::Order;
+use crate::commerce::model::product_addons::{ProductAddon, ProductAddonConnection};
+use crate::commerce::model::products::ProductConnection;
 use crate::graphql::AbacusGraphQLResult;
 use crate::graphql_context::Context;
 use crate::locale::SupportedLocale;
+use crate::pagination::Pagination;
 use crate::stripe::CheckoutSession;
 
 #[derive(juniper::GraphQLObject)]
@@ -35,30 +42,36 @@ impl CommerceQuery {
     ///
     /// This query requires admin permissions so it should be used only in backoffice to
     /// administer the products.
+    #[graphql(deprecated = "Use paginated `searchProducts` instead.")]
     async fn search_all_products(
         context: &Context,
         client_locale: SupportedLocale,
         price_sort_direction: PriceSortDirection,
         categories: Option<Vec<juniper::ID>>,
     ) -> AbacusGraphQLResult<Vec<Option<Product>>> {
-        match categories {
-            Some(categories) => Ok(
+        let pagination = Pagination::unlimited();
+        let result = match categories {
+            Some(categories) => {
                 crate::commerce::model::products::search_all_products_in_categories(
                     context,
                     &client_locale,
                     &price_sort_direction,
                     &categories,
+                    &pagination,
                 )
-                .await?,
-            ),
-            // TODO: remove the possibility to fetch ALL products (or at least paginate it?)
-            None => Ok(crate::commerce::model::products::search_all_products(
-                context,
-                &client_locale,
-                &price_sort_direction,
-            )
-            .await?),
-        }
+                .await?
+            }
+            None => {
+                crate::commerce::model::products::search_all_products(
+                    context,
+                    &client_locale,
+                    &price_sort_direction,
+                    &pagination,
+                )
+                .await?
+            }
+        };
+        Ok(result.items.into_iter().map(Some).collect())
     }
 
     /// Searches all published products for the specified visibility. The permission requirements
@@ -66,8 +79,7 @@ impl CommerceQuery {
     ///
     /// Optionally, you can specify categories you'd like to filter instead of
     /// returning all products. The specified categories must be valid (they must exist).
-    ///
-    /// TODO: deprecate this query and use "paginated" version instead
+    #[graphql(deprecated = "Use paginated `searchPublishedProducts` instead.")]
     async fn search_all_published_products(
         context: &Context,
         client_locale: SupportedLocale,
@@ -75,27 +87,143 @@ impl CommerceQuery {
         visibility: ProductMultilingualInputVisibility, // TODO: default value (public ESHOP)
         categories: Option<Vec<juniper::ID>>,
     ) -> AbacusGraphQLResult<Vec<Option<Product>>> {
-        match categories {
-            Some(categories) => Ok(
+        let pagination = Pagination::unlimited();
+        let result = match categories {
+            Some(categories) => {
                 crate::commerce::model::products::search_all_published_products_in_categories(
                     context,
                     &client_locale,
                     &price_sort_direction,
                     &categories,
                     &visibility,
+                    &pagination,
                 )
-                .await?,
-            ),
-            None => Ok(
+                .await?
+            }
+            None => {
                 crate::commerce::model::products::search_all_published_products(
                     context,
                     &client_locale,
                     &price_sort_direction,
                     &visibility,
+                    &pagination,
                 )
-                .await?,
-            ),
-        }
+                .await?
+            }
+        };
+        Ok(result.items.into_iter().map(Some).collect())
+    }
+
+    /// Searches ALL products (published and unpublished) anywhere in the system (no visibility
+    /// restrictions) and returns them page by page. Optionally, you can specify categories you'd
+    /// like to filter. The specified categories must be valid (they must exist).
+    ///
+    /// When `query` is specified, the products are searched by their names and descriptions
+    /// (full-text, typos are tolerated) and sorted by relevance first.
+    ///
+    /// This query requires admin permissions so it should be used only in backoffice to
+    /// administer the products.
+    async fn search_products(
+        context: &Context,
+        client_locale: SupportedLocale,
+        price_sort_direction: PriceSortDirection,
+        categories: Option<Vec<juniper::ID>>,
+        query: Option<String>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> AbacusGraphQLResult<ProductConnection> {
+        let pagination = Pagination::new(&first, &after)?;
+        let result = match (query, categories) {
+            (Some(query), categories) => {
+                crate::commerce::model::products::fulltext_search_all_products(
+                    context,
+                    &client_locale,
+                    &query,
+                    &price_sort_direction,
+                    &categories,
+                    &pagination,
+                )
+                .await?
+            }
+            (None, Some(categories)) => {
+                crate::commerce::model::products::search_all_products_in_categories(
+                    context,
+                    &client_locale,
+                    &price_sort_direction,
+                    &categories,
+                    &pagination,
+                )
+                .await?
+            }
+            (None, None) => {
+                crate::commerce::model::products::search_all_products(
+                    context,
+                    &client_locale,
+                    &price_sort_direction,
+                    &pagination,
+                )
+                .await?
+            }
+        };
+        Ok(ProductConnection::new(result, &pagination))
+    }
+
+    /// Searches all published products for the specified visibility and returns them page by
+    /// page. The permission requirements depend on the visibility (for example, ESHOP is public
+    /// but POS is private).
+    ///
+    /// Optionally, you can specify categories you'd like to filter instead of
+    /// returning all products. The specified categories must be valid (they must exist).
+    ///
+    /// When `query` is specified, the products are searched by their names and descriptions
+    /// (full-text, typos are tolerated) and sorted by relevance first.
+    async fn search_published_products(
+        context: &Context,
+        client_locale: SupportedLocale,
+        price_sort_direction: PriceSortDirection,
+        visibility: ProductMultilingualInputVisibility,
+        categories: Option<Vec<juniper::ID>>,
+        query: Option<String>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> AbacusGraphQLResult<ProductConnection> {
+        let pagination = Pagination::new(&first, &after)?;
+        let result = match (query, categories) {
+            (Some(query), categories) => {
+                crate::commerce::model::products::fulltext_search_published_products(
+                    context,
+                    &client_locale,
+                    &query,
+                    &price_sort_direction,
+                    &categories,
+                    &visibility,
+                    &pagination,
+                )
+                .await?
+            }
+            (None, Some(categories)) => {
+                crate::commerce::model::products::search_all_published_products_in_categories(
+                    context,
+                    &client_locale,
+                    &price_sort_direction,
+                    &categories,
+                    &visibility,
+                    &pagination,
+                )
+                .await?
+            }
+            (None, None) => {
+                crate::commerce::model::products::search_all_published_products(
+                    context,
+                    &client_locale,
+                    &price_sort_direction,
+                    &visibility,
+                    &pagination,
+                )
+                .await?
+            }
+        };
+        Ok(ProductConnection::new(result, &pagination))
     }
 
     /// Returns ALL available product categories that can be applied to any product.
@@ -121,11 +249,35 @@ impl CommerceQuery {
             crate::commerce::model::product_addons::search_all_product_addons(
                 context,
                 &client_locale,
+                &Pagination::unlimited(),
             )
-            .await?,
+            .await?
+            .items
+            .into_iter()
+            .map(Some)
+            .collect(),
         )
     }
 
+    /// Returns available product addons that can be assigned to products (page by page).
+    async fn search_product_addons(
+        context: &Context,
+        client_locale: SupportedLocale,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> AbacusGraphQLResult<ProductAddonConnection> {
+        let pagination = Pagination::new(&first, &after)?;
+        Ok(ProductAddonConnection::new(
+            crate::commerce::model::product_addons::search_all_product_addons(
+                context,
+                &client_locale,
+                &pagination,
+            )
+            .await?,
+            &pagination,
+        ))
+    }
+
     /// Returns one publicly available product by its key. Anyone can call this resolver.
     async fn get_published_product_by_key(
         context: &Context,
@@ -157,6 +309,17 @@ impl CommerceQuery {
             .await?,
         )
     }
+
+    /// Returns all e-shop orders (newest first) regardless of their status. This query requires
+    /// admin permissions so it should be used only in backoffice.
+    async fn orders(context: &Context) -> AbacusGraphQLResult<Vec<Order>> {
+        Ok(crate::commerce::model::orders::get_all_orders(context).await?)
+    }
+
+    /// Returns one e-shop order by its ID. Only admins can call this resolver.
+    async fn order(context: &Context, id: juniper::ID) -> AbacusGraphQLResult<Order> {
+        Ok(crate::commerce::model::orders::get_order_by_key_or_id(context, &id).await?)
+    }
 }
 
 #[juniper::graphql_object(context = Context)]
@@ -192,7 +355,8 @@ impl CommerceMutation {
     ///
     /// Note on updating product images: already existing image names must be send to the server
     /// otherwise they will be deleted. You can optionally specify some extra (new) images to upload
-    /// them via uploadables. This feature will eventually be used even for images re-ordering.
+    /// them via uploadables. The images are ordered the same way as their names in the input (so
+    /// they can be re-ordered by sending the names in a different order).
     async fn product_update(
         context: &Context,
         client_locale: SupportedLocale,
@@ -219,7 +383,7 @@ impl CommerceMutation {
 
     /// Archives product based on the product KEY making it effectively inaccessible. From the user
     /// perspective it's like deleting the product, however, internally the product still exists in
-    /// the archive and could potentially be restored.
+    /// the archive and can be restored (see `productRestore`).
     ///
     /// Note: the product cannot be searched for and cannot be retrieved in any way (other than via
     /// the archive). It can also be hard deleted without prior notice.
@@ -243,6 +407,29 @@ impl CommerceMutation {
         }
     }
 
+    /// Restores the archived product (see `archive.items` query) under its original KEY. The
+    /// restored product is unpublished, and it contains only the images and categories which still
+    /// exist. The archived item is deleted once the product is restored.
+    async fn product_restore(
+        context: &Context,
+        archive_item_id: juniper::ID,
+        client_locale: SupportedLocale,
+    ) -> ProductOrError {
+        match crate::commerce::model::products::restore_product(
+            context,
+            &archive_item_id,
+            &client_locale,
+        )
+        .await
+        {
+            Ok(product) => ProductOrError::Product(product),
+            Err(e) => ProductOrError::ProductError(ProductError {
+                // TODO: do not expose DB and RBAC errors directly
+                message: e.to_string(),
+            }),
+        }
+    }
+
     /// Publishes product based on the product KEY. Various validation requirements must be met
     /// before the product can be published. Published product is available outside of backoffice.
     async fn product_publish(
@@ -287,11 +474,61 @@ impl CommerceMutation {
         }
     }
 
+    /// Sets the product stock to the exact number of units (after counting the inventory, for
+    /// example). Stock `null` disables the stock tracking, and the product can be then sold without
+    /// any limits.
+    async fn product_stock_set(
+        context: &Context,
+        product_key: juniper::ID,
+        stock: Option<i32>,
+        client_locale: SupportedLocale,
+    ) -> ProductOrError {
+        match crate::commerce::model::inventory::set_product_stock(
+            context,
+            &client_locale,
+            &product_key,
+            &stock,
+        )
+        .await
+        {
+            Ok(product) => ProductOrError::Product(product),
+            Err(e) => ProductOrError::ProductError(ProductError {
+                // TODO: do not expose DB and RBAC errors directly
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    /// Adds units to the current product stock (positive `unitsDelta`) or subtracts them (negative
+    /// `unitsDelta`). The stock must be tracked, and it cannot drop below zero.
+    async fn product_stock_adjust(
+        context: &Context,
+        product_key: juniper::ID,
+        units_delta: i32,
+        client_locale: SupportedLocale,
+    ) -> ProductOrError {
+        match crate::commerce::model::inventory::adjust_product_stock(
+            context,
+            &client_locale,
+            &product_key,
+            &units_delta,
+        )
+        .await
+        {
+            Ok(product) => ProductOrError::Product(product),
+            Err(e) => ProductOrError::ProductError(ProductError {
+                // TODO: do not expose DB and RBAC errors directly
+                message: e.to_string(),
+            }),
+        }
+    }
+
     /// Creates checkout session based on the inputs so that users can be redirected to the returned
     /// session URL and finish paying their order.
     ///
     /// Internally, we perform bunch of validation, most notably, we check whether the specified
-    /// prices are still valid and whether there is enough units to be sold.
+    /// prices are still valid and whether there is enough units to be sold. The units are reserved
+    /// until the checkout session expires.
     async fn checkout_session_create(
         context: &Context,
         input: CheckoutSessionInput,
@@ -321,3 +558,61 @@ pub(crate) async fn get_published_products_by_keys(
     )
     .await
 }
+
+// This function is exposed to Stripe webhooks (hence not inlined).
+pub(crate) async fn complete_order(
+    pool: &ConnectionPool,
+    stripe_checkout_session_id: &str,
+    stripe_payment_intent_id: &Option<String>,
+    new_status: &OrderStatus,
+    amount_total: &Option<i32>,
+    amount_subtotal: &Option<i32>,
+) -> anyhow::Result<Option<Order>> {
+    crate::commerce::model::orders::complete_order(
+        pool,
+        stripe_checkout_session_id,
+        stripe_payment_intent_id,
+        new_status,
+        amount_total,
+        amount_subtotal,
+    )
+    .await
+}
+
+// This function is exposed to Stripe webhooks (hence not inlined).
+pub(crate) async fn update_order_status(
+    pool: &ConnectionPool,
+    order_lookup: &OrderLookup,
+    new_status: &OrderStatus,
+) -> anyhow::Result<Option<Order>> {
+    crate::commerce::model::orders::update_order_status(pool, order_lookup, new_status).await
+}
+
+// This function is exposed to Stripe webhooks (hence not inlined).
+pub(crate) async fn order_exists(
+    pool: &ConnectionPool,
+    order_lookup: &OrderLookup,
+) -> anyhow::Result<bool> {
+    crate::commerce::model::orders::order_exists(pool, order_lookup).await
+}
+
+// This function is exposed to POS module (hence not inlined).
+pub(crate) async fn reserve_stock(
+    pool: &ConnectionPool,
+    stock_reservations: &[StockReservation],
+) -> anyhow::Result<()> {
+    crate::commerce::model::inventory::reserve_stock(pool, stock_reservations).await
+}
+
+// This function is exposed to POS module (hence not inlined).
+pub(crate) async fn release_stock(
+    pool: &ConnectionPool,
+    stock_reservations: &[StockReservation],
+) -> anyhow::Result<()> {
+    crate::commerce::model::inventory::release_stock(pool, stock_reservations).await
+}
+
+// This function is exposed to the server startup and migrations (hence not inlined).
+pub(crate) async fn ensure_products_search_view(pool: &ConnectionPool) -> anyhow::Result<()> {
+    crate::commerce::model::products::ensure_products_search_view(pool).await
+}
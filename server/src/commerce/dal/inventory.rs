@@ -0,0 +1,247 @@
+use crate::arango::client::ClientExt;
+use crate::arango::transaction::Transaction;
+use crate::arango::{resolve_aql_vector, ConnectionPool};
+use crate::commerce::model::inventory::StockReservation;
+use crate::commerce::model::products::Product;
+use crate::locale::SupportedLocale;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Debug)]
+struct ProductStock {
+    _id: String,
+    stock: Option<i32>,
+}
+
+/// Sets the product stock to the exact number of units. Stock `None` means that the stock is not
+/// being tracked for this product (unlimited number of units can be sold).
+///
+/// TODO(004) - integration tests
+pub(in crate::commerce) async fn set_product_stock<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    client_locale: &SupportedLocale,
+    product_key: &str,
+    stock: &Option<i32>,
+) -> anyhow::Result<Product> {
+    // TODO: https://www.arangodb.com/docs/stable/aql/extending.html (for merging translations)
+    let updated_products = resolve_aql_vector(
+        pool,
+        r#"
+            LET unit_label_translated = DOCUMENT("product_units/piece")[@client_locale]
+
+            UPDATE {
+              _key: @product_key,
+              stock: @stock,
+              updated: DATE_ISO8601(DATE_NOW()),
+            } IN products OPTIONS { keepNull: true }
+            LET product = NEW
+
+            LET t = FIRST(
+              FOR t IN product.translations
+                FILTER t.name != null AND t.locale == @client_locale
+                RETURN t
+            )
+
+            RETURN MERGE(
+              product,
+              { unit_label: unit_label_translated },
+              { name: t.name, description: t.description }
+            )
+        "#,
+        hashmap_json![
+            "client_locale" => client_locale,
+            "product_key" => product_key,
+            "stock" => stock,
+        ],
+    )
+    .await?;
+
+    match updated_products.into_iter().next() {
+        Some(product) => Ok(product),
+        None => anyhow::bail!("database didn't return any product"),
+    }
+}
+
+/// Adds (or subtracts when negative) the specified number of units to the current product stock.
+/// The stock must be tracked and it can never drop below zero. The whole operation is a single AQL
+/// query so it's atomic.
+///
+/// TODO(004) - integration tests
+pub(in crate::commerce) async fn adjust_product_stock<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    client_locale: &SupportedLocale,
+    product_key: &str,
+    units_delta: &i32,
+) -> anyhow::Result<Product> {
+    // TODO: https://www.arangodb.com/docs/stable/aql/extending.html (for merging translations)
+    let updated_products = resolve_aql_vector(
+        pool,
+        r#"
+            LET unit_label_translated = DOCUMENT("product_units/piece")[@client_locale]
+
+            FOR product IN products
+              FILTER product._key == @product_key
+              FILTER product.stock != null AND product.stock + @units_delta >= 0
+              UPDATE product WITH {
+                stock: product.stock + @units_delta,
+                updated: DATE_ISO8601(DATE_NOW()),
+              } IN products
+              LET updated_product = NEW
+
+              LET t = FIRST(
+                FOR t IN updated_product.translations
+                  FILTER t.name != null AND t.locale == @client_locale
+                  RETURN t
+              )
+
+              RETURN MERGE(
+                updated_product,
+                { unit_label: unit_label_translated },
+                { name: t.name, description: t.description }
+              )
+        "#,
+        hashmap_json![
+            "client_locale" => client_locale,
+            "product_key" => product_key,
+            "units_delta" => units_delta,
+        ],
+    )
+    .await?;
+
+    match updated_products.into_iter().next() {
+        Some(product) => Ok(product),
+        None => anyhow::bail!(
+            "product stock is not being tracked or there is not enough units in stock to subtract"
+        ),
+    }
+}
+
+/// Reserves (subtracts) the product units from the stock of all the specified products. Either all
+/// the products are reserved or none of them.
+///
+/// It runs inside an ArangoDB stream transaction: first, we read the current stock levels and
+/// verify that there is enough units, and only then we subtract the units. When two buyers try to
+/// reserve the same product concurrently, the later transaction fails on a write-write conflict
+/// (instead of overselling the product).
+///
+/// Products without tracked stock are ignored (they can be sold indefinitely).
+///
+/// TODO(004) - integration tests
+pub(in crate::commerce) async fn reserve_stock<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    stock_reservations: &[StockReservation],
+) -> anyhow::Result<()> {
+    pool.run_in_transaction(&["products"], |transaction| async move {
+        reserve_stock_in_transaction(&transaction, stock_reservations).await
+    })
+    .await
+}
+
+async fn reserve_stock_in_transaction<C: ClientExt>(
+    transaction: &Transaction<C>,
+    stock_reservations: &[StockReservation],
+) -> anyhow::Result<()> {
+    let units_by_product_id = units_by_product_id(stock_reservations);
+
+    let products_stock = transaction
+        .aql_bind_vars::<ProductStock>(
+            r#"
+                FOR product IN products
+                  FILTER product._id IN ATTRIBUTES(@units_by_product_id)
+                  RETURN { _id: product._id, stock: product.stock }
+            "#,
+            hashmap_json![
+                "units_by_product_id" => units_by_product_id,
+            ],
+        )
+        .await?;
+
+    for (product_id, product_units) in &units_by_product_id {
+        match products_stock
+            .iter()
+            .find(|product| &product._id == product_id)
+        {
+            Some(ProductStock {
+                stock: Some(stock), ..
+            }) if stock < product_units => {
+                anyhow::bail!(
+                    "Product '{}' doesn't have enough units in stock (requested {}, available {}).",
+                    product_id,
+                    product_units,
+                    stock
+                )
+            }
+            Some(_) => {} // enough units in stock (or the stock is not tracked)
+            None => anyhow::bail!("Product '{}' doesn't exist in the database.", product_id),
+        }
+    }
+
+    transaction
+        .aql_bind_vars::<serde_json::Value>(
+            r#"
+                FOR product IN products
+                  FILTER product._id IN ATTRIBUTES(@units_by_product_id)
+                  FILTER product.stock != null
+                  UPDATE product WITH {
+                    stock: product.stock - @units_by_product_id[product._id],
+                  } IN products
+            "#,
+            hashmap_json![
+                "units_by_product_id" => units_by_product_id,
+            ],
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Returns the previously reserved product units back to the stock (for example, when the checkout
+/// session expired). Products without tracked stock are ignored.
+///
+/// TODO(004) - integration tests
+pub(in crate::commerce) async fn release_stock<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    stock_reservations: &[StockReservation],
+) -> anyhow::Result<()> {
+    pool.run_in_transaction(&["products"], |transaction| async move {
+        release_stock_in_transaction(&transaction, stock_reservations).await
+    })
+    .await
+}
+
+/// The same as `release_stock` but inside an existing transaction (so the stock can be released
+/// together with other changes, for example together with the order status change).
+pub(in crate::commerce) async fn release_stock_in_transaction<C: ClientExt>(
+    transaction: &Transaction<C>,
+    stock_reservations: &[StockReservation],
+) -> anyhow::Result<()> {
+    transaction
+        .aql_bind_vars::<serde_json::Value>(
+            r#"
+                FOR product IN products
+                  FILTER product._id IN ATTRIBUTES(@units_by_product_id)
+                  FILTER product.stock != null
+                  UPDATE product WITH {
+                    stock: product.stock + @units_by_product_id[product._id],
+                  } IN products
+            "#,
+            hashmap_json![
+                "units_by_product_id" => units_by_product_id(stock_reservations),
+            ],
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Sums units of the same products together (the same product can be selected more than once,
+/// for example with different addons in POS).
+fn units_by_product_id(stock_reservations: &[StockReservation]) -> HashMap<String, i32> {
+    let mut units_by_product_id = HashMap::new();
+    for stock_reservation in stock_reservations {
+        *units_by_product_id
+            .entry(stock_reservation.product_id.to_owned())
+            .or_insert(0) += stock_reservation.product_units;
+    }
+    units_by_product_id
+}
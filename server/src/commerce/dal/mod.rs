@@ -1,4 +1,6 @@
+pub(in crate::commerce) mod inventory;
 pub(in crate::commerce) mod orders;
 pub(in crate::commerce) mod product_addons;
 pub(in crate::commerce) mod product_categories;
 pub(in crate::commerce) mod products;
+pub(in crate::commerce) mod products_search;
@@ -1,23 +1,284 @@
-use crate::arango::{resolve_aql_vector, ConnectionPool};
-use crate::commerce::model::product_addons::ProductAddon;
+use crate::arango::client::ClientExt;
+use crate::arango::transaction::Transaction;
+use crate::arango::{resolve_aql, resolve_aql_vector, ConnectionPool};
+use crate::commerce::model::orders::{Order, OrderLineItem, OrderLookup, OrderStatus};
 use crate::locale::SupportedLocale;
 
-/// The following function creates a payment that is awaiting payment. It should not be processed
+/// The following function creates an order that is awaiting payment. It should not be processed
 /// yet until we actually receive the money (confirmation from Stripe.com).
 ///
 /// TODO(004) - integration tests
-pub(in crate::commerce) async fn create_unpaid_order(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn create_unpaid_order<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     client_locale: &SupportedLocale,
-) -> anyhow::Result<Vec<Option<ProductAddon>>> {
+    stripe_checkout_session_id: &str,
+    line_items: &[OrderLineItem],
+) -> anyhow::Result<Order> {
+    resolve_aql(
+        pool,
+        r#"
+            INSERT {
+              status: "AWAITING_PAYMENT",
+              client_locale: @client_locale,
+              line_items: @line_items,
+              stripe_checkout_session_id: @stripe_checkout_session_id,
+              created: DATE_ISO8601(DATE_NOW()),
+              updated: DATE_ISO8601(DATE_NOW()),
+            } INTO orders
+            RETURN NEW
+        "#,
+        hashmap_json![
+            "client_locale" => client_locale,
+            "line_items" => line_items,
+            "stripe_checkout_session_id" => stripe_checkout_session_id,
+        ],
+    )
+    .await
+}
+
+/// TODO(004) - integration tests
+pub(in crate::commerce) async fn get_all_orders<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+) -> anyhow::Result<Vec<Order>> {
     resolve_aql_vector(
         pool,
         r#"
-            TODO
+            FOR order IN orders
+              SORT order.created DESC
+              RETURN order
+        "#,
+        hashmap_json![],
+    )
+    .await
+}
+
+/// Returns a single order (or error). It is possible to search for the orders by their ArangoDB
+/// `_key` as well as by their `_id`.
+///
+/// TODO(004) - integration tests
+pub(in crate::commerce) async fn get_order_by_key_or_id<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    order_key_or_id: &str,
+) -> anyhow::Result<Order> {
+    resolve_aql(
+        pool,
+        r#"
+            FOR order IN DOCUMENT(orders, [@order_key_or_id])
+              RETURN order
         "#,
         hashmap_json![
-            "client_locale" => client_locale
+            "order_key_or_id" => order_key_or_id,
         ],
     )
     .await
 }
+
+/// Checks whether there is any order matching the `order_lookup` (regardless of its status).
+pub(in crate::commerce) async fn order_exists<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    order_lookup: &OrderLookup,
+) -> anyhow::Result<bool> {
+    let (lookup_field, lookup_value) = order_lookup_field(order_lookup);
+    let orders = resolve_aql_vector::<bool, _>(
+        pool,
+        r#"
+            FOR order IN orders
+              FILTER order[@lookup_field] == @lookup_value
+              LIMIT 1
+              RETURN true
+        "#,
+        hashmap_json![
+            "lookup_field" => lookup_field,
+            "lookup_value" => lookup_value,
+        ],
+    )
+    .await?;
+
+    Ok(!orders.is_empty())
+}
+
+fn order_lookup_field(order_lookup: &OrderLookup) -> (&'static str, &str) {
+    match order_lookup {
+        OrderLookup::StripeCheckoutSessionId(id) => ("stripe_checkout_session_id", id.as_str()),
+        OrderLookup::StripePaymentIntentId(id) => ("stripe_payment_intent_id", id.as_str()),
+    }
+}
+
+/// Updates the order which is awaiting payment based on the completed Stripe checkout session.
+/// Orders which are not awaiting payment anymore are ignored (`None` is returned).
+///
+/// TODO(004) - integration tests
+pub(in crate::commerce) async fn complete_order<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    stripe_checkout_session_id: &str,
+    stripe_payment_intent_id: &Option<String>,
+    new_status: &OrderStatus,
+    amount_total: &Option<i32>,
+    amount_subtotal: &Option<i32>,
+) -> anyhow::Result<Option<Order>> {
+    let updated_orders = resolve_aql_vector(
+        pool,
+        r#"
+            FOR order IN orders
+              FILTER order.stripe_checkout_session_id == @stripe_checkout_session_id
+              FILTER order.status == "AWAITING_PAYMENT"
+              UPDATE order WITH {
+                status: @new_status,
+                stripe_payment_intent_id: @stripe_payment_intent_id,
+                amount_total: @amount_total,
+                amount_subtotal: @amount_subtotal,
+                updated: DATE_ISO8601(DATE_NOW()),
+              } IN orders
+              RETURN NEW
+        "#,
+        hashmap_json![
+            "stripe_checkout_session_id" => stripe_checkout_session_id,
+            "stripe_payment_intent_id" => stripe_payment_intent_id,
+            "new_status" => new_status,
+            "amount_total" => amount_total,
+            "amount_subtotal" => amount_subtotal,
+        ],
+    )
+    .await?;
+
+    Ok(updated_orders.into_iter().next())
+}
+
+/// Updates status of the order found by `order_lookup`, but only if the order is currently in one
+/// of the `previous_statuses` (otherwise `None` is returned). It runs inside a transaction so that
+/// the related stock changes are committed together with the status (see
+/// `crate::commerce::model::orders::update_order_status`).
+///
+/// TODO(004) - integration tests
+pub(in crate::commerce) async fn update_order_status<C: ClientExt>(
+    transaction: &Transaction<C>,
+    order_lookup: &OrderLookup,
+    previous_statuses: &[OrderStatus],
+    new_status: &OrderStatus,
+) -> anyhow::Result<Option<Order>> {
+    let (lookup_field, lookup_value) = order_lookup_field(order_lookup);
+
+    let updated_orders = transaction
+        .aql_bind_vars::<Order>(
+            r#"
+            FOR order IN orders
+              FILTER order[@lookup_field] == @lookup_value
+              FILTER order.status IN @previous_statuses
+              UPDATE order WITH {
+                status: @new_status,
+                updated: DATE_ISO8601(DATE_NOW()),
+              } IN orders
+              RETURN NEW
+        "#,
+            hashmap_json![
+            "lookup_field" => lookup_field,
+            "lookup_value" => lookup_value,
+            "previous_statuses" => previous_statuses,
+            "new_status" => new_status,
+            ],
+        )
+        .await?;
+
+    Ok(updated_orders.into_iter().next())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arango::client::fake::FakeClient;
+    use crate::arango::get_database_connection_pool_fake;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn order_exists_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        assert!(!order_exists(
+            &pool,
+            &OrderLookup::StripePaymentIntentId(String::from("pi_test_123"))
+        )
+        .await
+        .unwrap());
+        assert_eq!(
+            client.requests()[0].body["bindVars"],
+            json!({
+                "lookup_field": "stripe_payment_intent_id",
+                "lookup_value": "pi_test_123",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn complete_order_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([{
+            "_id": "orders/1",
+            "_rev": "_fake_rev",
+            "_key": "1",
+            "status": "PAID",
+            "client_locale": "en_US",
+            "line_items": [],
+            "stripe_checkout_session_id": "cs_test_123",
+            "stripe_payment_intent_id": "pi_test_123",
+            "amount_total": 10000,
+            "amount_subtotal": 10000,
+            "created": "2022-11-01T00:00:00.000Z",
+            "updated": "2022-11-02T00:00:00.000Z",
+        }]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let order = complete_order(
+            &pool,
+            "cs_test_123",
+            &Some(String::from("pi_test_123")),
+            &OrderStatus::Paid,
+            &Some(10000),
+            &Some(10000),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        let order = serde_json::to_value(order).unwrap();
+        assert_eq!(order["_id"], "orders/1");
+        assert_eq!(order["status"], "PAID");
+        assert_eq!(order["amount_total"], 10000);
+
+        let requests = client.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "_api/cursor");
+        assert_eq!(
+            requests[0].body["bindVars"],
+            json!({
+                "stripe_checkout_session_id": "cs_test_123",
+                "stripe_payment_intent_id": "pi_test_123",
+                "new_status": "PAID",
+                "amount_total": 10000,
+                "amount_subtotal": 10000,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn complete_order_not_awaiting_payment_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let order = complete_order(
+            &pool,
+            "cs_test_123",
+            &None,
+            &OrderStatus::Paid,
+            &None,
+            &None,
+        )
+        .await
+        .unwrap();
+
+        assert!(order.is_none());
+        assert!(client.is_exhausted());
+    }
+}
@@ -1,37 +1,50 @@
-use crate::arango::{resolve_aql_vector, ConnectionPool};
+use crate::arango::client::ClientExt;
+use crate::arango::{resolve_aql, resolve_aql_vector, ConnectionPool};
 use crate::commerce::model::product_addons::ProductAddon;
 use crate::locale::SupportedLocale;
+use crate::pagination::{PaginatedResult, Pagination};
 
+/// Returns product addons sorted by their translated name (and by `_key` to keep the order stable
+/// for the pagination).
+///
 /// TODO(004) - integration tests
-pub(in crate::commerce) async fn search_product_addons(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn search_product_addons<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     client_locale: &SupportedLocale,
-) -> anyhow::Result<Vec<Option<ProductAddon>>> {
-    resolve_aql_vector(
+    pagination: &Pagination,
+) -> anyhow::Result<PaginatedResult<ProductAddon>> {
+    resolve_aql(
         pool,
         r#"
-            FOR product_addon IN product_addons
-              LET t = FIRST(
-                FOR t IN product_addon.translations
-                  FILTER t.name != null AND t.locale == @client_locale
-                  RETURN t
-              )
-              SORT t.name ASC
-              RETURN MERGE(
-                product_addon,
-                { name: t.name }
-              )
+            LET items = (
+              FOR product_addon IN product_addons
+                LET t = FIRST(
+                  FOR t IN product_addon.translations
+                    FILTER t.name != null AND t.locale == @client_locale
+                    RETURN t
+                )
+                SORT t.name ASC, product_addon._key ASC
+                LIMIT @offset, @limit
+                RETURN MERGE(
+                  product_addon,
+                  { name: t.name }
+                )
+            )
+
+            RETURN { total_count: LENGTH(product_addons), items }
         "#,
         hashmap_json![
-            "client_locale" => client_locale
+            "client_locale" => client_locale,
+            "offset" => pagination.offset(),
+            "limit" => pagination.limit(),
         ],
     )
     .await
 }
 
 /// TODO(004) - integration tests
-pub(in crate::commerce) async fn get_product_addons_by_ids(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn get_product_addons_by_ids<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     client_locale: &SupportedLocale,
     product_addon_ids: &[String],
 ) -> anyhow::Result<Vec<Option<ProductAddon>>> {
@@ -1,9 +1,11 @@
+use crate::arango::client::ClientExt;
+use crate::arango::transaction::Transaction;
 use crate::arango::{resolve_aql_vector, ConnectionPool};
 use crate::commerce::model::product_categories::ProductCategory;
 use crate::locale::SupportedLocale;
 
-pub(in crate::commerce) async fn assign_product_categories(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn assign_product_categories<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     product_id: &str,
     product_category_ids: &[String],
     client_locale: &SupportedLocale,
@@ -12,7 +14,7 @@ pub(in crate::commerce) async fn assign_product_categories(
 
     // First, we delete ALL old categories so we can save new set of categories. Also, this way, we
     // won't assign one category twice by accident.
-    resolve_aql_vector::<ProductCategory>(
+    resolve_aql_vector::<ProductCategory, _>(
         pool,
         r#"
             WITH product_categories
@@ -54,9 +56,32 @@ pub(in crate::commerce) async fn assign_product_categories(
     Ok(())
 }
 
+/// Assigns the categories to the just restored product (it cannot have any categories yet since
+/// they are removed together with the product when archiving it).
+pub(in crate::commerce) async fn restore_product_categories<C: ClientExt>(
+    transaction: &Transaction<C>,
+    product_id: &str,
+    product_category_ids: &[String],
+) -> anyhow::Result<()> {
+    transaction
+        .aql_bind_vars::<serde_json::Value>(
+            r#"
+                FOR product_category_id IN @product_category_ids
+                  INSERT { _from: @product_id, _to: product_category_id }
+                  INTO product_categories_edges
+            "#,
+            hashmap_json![
+                "product_id" => product_id,
+                "product_category_ids" => product_category_ids,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
 // TODO: integration tests
-pub(in crate::commerce) async fn search_all_product_categories(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn search_all_product_categories<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     client_locale: &SupportedLocale,
 ) -> anyhow::Result<Vec<Option<ProductCategory>>> {
     resolve_aql_vector(
@@ -82,8 +107,8 @@ pub(in crate::commerce) async fn search_all_product_categories(
     .await
 }
 
-pub(in crate::commerce) async fn get_product_categories_by_ids(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn get_product_categories_by_ids<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     client_locale: &SupportedLocale,
     product_category_ids: &[String],
 ) -> anyhow::Result<Vec<Option<ProductCategory>>> {
@@ -110,8 +135,8 @@ pub(in crate::commerce) async fn get_product_categories_by_ids(
 }
 
 /// TODO(004) add integration tests
-pub(in crate::commerce) async fn get_assigned_product_categories(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn get_assigned_product_categories<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     client_locale: &SupportedLocale,
     product_id: &str,
 ) -> anyhow::Result<Vec<Option<ProductCategory>>> {
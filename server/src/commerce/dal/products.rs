@@ -1,14 +1,17 @@
-use crate::arango::{resolve_aql, resolve_aql_vector, ConnectionPool};
+use crate::arango::client::ClientExt;
+use crate::arango::transaction::Transaction;
+use crate::arango::{resolve_aql, resolve_aql_in_transaction, resolve_aql_vector, ConnectionPool};
 use crate::commerce::model::products::{
     PriceSortDirection, Product, ProductMultilingualInput, ProductMultilingualInputVisibility,
 };
 use crate::images::Image;
 use crate::locale::SupportedLocale;
+use crate::pagination::{PaginatedResult, Pagination};
 use serde_json::json;
 
 /// Takes care of creating the product inside ArangoDB.
-pub(in crate::commerce) async fn create_product(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn create_product<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     client_locale: &SupportedLocale,
     product_multilingual_input: &ProductMultilingualInput,
     images: &[Image],
@@ -21,6 +24,7 @@ pub(in crate::commerce) async fn create_product(
 
             INSERT {
               images: @product_images,
+              image_cover: @product_image_cover,
               unit_label: "product_units/piece", // TODO: dynamic `unit_label`
               is_published: false,
               visibility: @product_visibility,
@@ -50,6 +54,7 @@ pub(in crate::commerce) async fn create_product(
         hashmap_json![
             "client_locale" => client_locale,
             "product_images" => images,
+            "product_image_cover" => product_multilingual_input.image_cover,
             "product_visibility" => product_multilingual_input.visibility(),
             "product_addons" => product_multilingual_input.addons(),
             "product_price_unit_amount" => product_multilingual_input.price.unit_amount,
@@ -59,9 +64,58 @@ pub(in crate::commerce) async fn create_product(
     .await
 }
 
+/// Re-inserts the archived product under its original KEY. The restored product is always
+/// unpublished. It fails when a product with the same KEY exists already.
+pub(in crate::commerce) async fn restore_product<C: ClientExt>(
+    transaction: &Transaction<C>,
+    client_locale: &SupportedLocale,
+    product: &Product,
+) -> anyhow::Result<Product> {
+    resolve_aql_in_transaction(
+        transaction,
+        r#"
+            LET unit_label_translated = DOCUMENT("product_units/piece")[@client_locale]
+
+            INSERT {
+              _key: @product._key,
+              images: @product.images,
+              image_cover: @product.image_cover,
+              unit_label: "product_units/piece", // TODO: dynamic `unit_label`
+              is_published: false,
+              visibility: @product.visibility,
+              addons: @product.addons,
+              stock: @product.stock,
+              created: DATE_ISO8601(DATE_NOW()),
+              updated: DATE_ISO8601(DATE_NOW()),
+              restored: DATE_ISO8601(DATE_NOW()),
+              price: @product.price,
+              translations: @product.translations
+            } INTO products
+            LET product = NEW
+
+            LET t = FIRST(
+              FOR t IN product.translations
+                FILTER t.name != null AND t.locale == @client_locale
+                RETURN t
+            )
+
+            RETURN MERGE(
+              product,
+              { unit_label: unit_label_translated },
+              { name: t.name, description: t.description }
+            )
+        "#,
+        hashmap_json![
+            "client_locale" => client_locale,
+            "product" => product,
+        ],
+    )
+    .await
+}
+
 /// TODO(004) - integration tests
-pub(in crate::commerce) async fn update_product(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn update_product<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     client_locale: &SupportedLocale,
     product_key: &str,
     product_revision: &str,
@@ -78,6 +132,7 @@ pub(in crate::commerce) async fn update_product(
               _key: @product_key,
               _rev: @product_rev,
               images: @product_images,
+              image_cover: @product_image_cover,
               visibility: @product_visibility,
               addons: @product_addons,
               updated: DATE_ISO8601(DATE_NOW()),
@@ -106,6 +161,7 @@ pub(in crate::commerce) async fn update_product(
             "product_key" => product_key,
             "product_rev" => product_revision,
             "product_images" => images,
+            "product_image_cover" => product_multilingual_input.image_cover,
             "product_visibility" => product_multilingual_input.visibility(),
             "product_addons" => product_multilingual_input.addons(),
             "product_price_unit_amount" => product_multilingual_input.price.unit_amount,
@@ -116,8 +172,8 @@ pub(in crate::commerce) async fn update_product(
 }
 
 /// TODO(004) - integration tests
-pub(in crate::commerce) async fn publish_product(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn publish_product<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     product_key: &str,
     client_locale: &SupportedLocale,
 ) -> anyhow::Result<Product> {
@@ -154,8 +210,8 @@ pub(in crate::commerce) async fn publish_product(
 }
 
 /// TODO(004) - integration tests
-pub(in crate::commerce) async fn unpublish_product(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn unpublish_product<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     product_key: &str,
     client_locale: &SupportedLocale,
 ) -> anyhow::Result<Product> {
@@ -193,8 +249,8 @@ pub(in crate::commerce) async fn unpublish_product(
 
 /// Returns a single product (or error). It is possible to search for the products by their
 /// ArangoDB `_key` as well as by their `_id`.
-pub(in crate::commerce) async fn get_product_by_key_or_id(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn get_product_by_key_or_id<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     client_locale: &SupportedLocale,
     product_key_or_id: &str,
     product_published_only: &bool,
@@ -213,8 +269,8 @@ pub(in crate::commerce) async fn get_product_by_key_or_id(
     }
 }
 
-pub(in crate::commerce) async fn get_products_by_keys_or_ids(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn get_products_by_keys_or_ids<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     client_locale: &SupportedLocale,
     product_keys_or_ids: &[String],
     product_published_only: &bool,
@@ -250,14 +306,18 @@ pub(in crate::commerce) async fn get_products_by_keys_or_ids(
 /// Performs search of products based on the specified criteria and returns products with merged
 /// translations based on the eshop language.
 ///
+/// Products are sorted by price and then by their `_key` so the order is stable (products with
+/// the same price would otherwise end up in random order and the pages could overlap).
+///
 /// TODO(004) - integration tests
-pub(in crate::commerce) async fn search_products(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn search_products<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     client_locale: &SupportedLocale,
     price_sort_direction: &PriceSortDirection,
     search_all: &bool,
     visibility: &Option<ProductMultilingualInputVisibility>,
-) -> anyhow::Result<Vec<Option<Product>>> {
+    pagination: &Pagination,
+) -> anyhow::Result<PaginatedResult<Product>> {
     let sort_direction = match price_sort_direction {
         PriceSortDirection::LowToHigh => "ASC",
         PriceSortDirection::HighToLow => "DESC",
@@ -268,45 +328,64 @@ pub(in crate::commerce) async fn search_products(
         None => json!(null),
     };
 
-    resolve_aql_vector(
+    resolve_aql(
         pool,
         r#"
-            FOR product IN products
-              FILTER @search_all == true ? true : (product.is_published IN [true])
-              FILTER @visibility == null ? true : (@visibility IN product.visibility)
-              SORT product.price.unit_amount @price_sort_direction
+            LET total_count = FIRST(
+              FOR product IN products
+                FILTER @search_all == true ? true : (product.is_published IN [true])
+                FILTER @visibility == null ? true : (@visibility IN product.visibility)
+                COLLECT WITH COUNT INTO length
+                RETURN length
+            )
 
-              LET t = FIRST(
-                FOR t IN product.translations
-                  FILTER t.name != null AND t.locale == @client_locale
-                  RETURN t
-              )
+            LET items = (
+              FOR product IN products
+                FILTER @search_all == true ? true : (product.is_published IN [true])
+                FILTER @visibility == null ? true : (@visibility IN product.visibility)
+                SORT product.price.unit_amount @price_sort_direction, product._key ASC
+                LIMIT @offset, @limit
 
-              RETURN MERGE(
-                product,
-                { unit_label: DOCUMENT(product.unit_label)[@client_locale] },
-                { name: t.name, description: t.description }
-              )
+                LET t = FIRST(
+                  FOR t IN product.translations
+                    FILTER t.name != null AND t.locale == @client_locale
+                    RETURN t
+                )
+
+                RETURN MERGE(
+                  product,
+                  { unit_label: DOCUMENT(product.unit_label)[@client_locale] },
+                  { name: t.name, description: t.description }
+                )
+            )
+
+            RETURN { total_count, items }
         "#,
         hashmap_json![
             "client_locale" => client_locale,
             "search_all" => search_all,
             "price_sort_direction" => sort_direction,
             "visibility" => visibility,
+            "offset" => pagination.offset(),
+            "limit" => pagination.limit(),
         ],
     )
     .await
 }
 
+/// Same as `search_products` except it searches only in the specified categories. Products
+/// assigned to more than one of the categories are returned only once.
+///
 /// TODO(004) - integration tests
-pub(in crate::commerce) async fn search_products_in_categories(
-    pool: &ConnectionPool,
+pub(in crate::commerce) async fn search_products_in_categories<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     client_locale: &SupportedLocale,
     price_sort_direction: &PriceSortDirection,
     categories: &[juniper::ID],
     search_all: &bool,
     visibility: &Option<ProductMultilingualInputVisibility>,
-) -> anyhow::Result<Vec<Option<Product>>> {
+    pagination: &Pagination,
+) -> anyhow::Result<PaginatedResult<Product>> {
     let sort_direction = match price_sort_direction {
         PriceSortDirection::LowToHigh => "ASC",
         PriceSortDirection::HighToLow => "DESC",
@@ -317,14 +396,21 @@ pub(in crate::commerce) async fn search_products_in_categories(
         None => json!(null),
     };
 
-    resolve_aql_vector(
+    resolve_aql(
         pool,
         r#"
-            FOR category IN @categories
-              FOR product,e,p IN INBOUND category GRAPH product_categories
-                FILTER @search_all == true ? true : (product.is_published IN [true])
-                FILTER @visibility == null ? true : (@visibility IN product.visibility)
-                SORT product.price.unit_amount @price_sort_direction
+            LET products_in_categories = UNIQUE(
+              FOR category IN @categories
+                FOR product,e,p IN INBOUND category GRAPH product_categories
+                  FILTER @search_all == true ? true : (product.is_published IN [true])
+                  FILTER @visibility == null ? true : (@visibility IN product.visibility)
+                  RETURN product
+            )
+
+            LET items = (
+              FOR product IN products_in_categories
+                SORT product.price.unit_amount @price_sort_direction, product._key ASC
+                LIMIT @offset, @limit
 
                 LET t = FIRST(
                   FOR t IN product.translations
@@ -337,6 +423,9 @@ pub(in crate::commerce) async fn search_products_in_categories(
                   { unit_label: DOCUMENT(product.unit_label)[@client_locale] },
                   { name: t.name, description: t.description }
                 )
+            )
+
+            RETURN { total_count: LENGTH(products_in_categories), items }
         "#,
         hashmap_json![
             "client_locale" => client_locale,
@@ -344,20 +433,25 @@ pub(in crate::commerce) async fn search_products_in_categories(
             "search_all" => search_all,
             "visibility" => visibility,
             "price_sort_direction" => sort_direction,
+            "offset" => pagination.offset(),
+            "limit" => pagination.limit(),
         ],
     )
     .await
 }
 
-/// Important note: product should be moved into the archive before deleting it!
-pub(in crate::commerce) async fn delete_product(
-    pool: &ConnectionPool,
+/// Important note: product should be moved into the archive before deleting it (in the same
+/// transaction, see `crate::commerce::model::products::archive_product`)!
+///
+/// The product is removed together with its graph edges so they are not dangling. We cannot use
+/// the Gharial API (`remove_graph_vertex`) here because it doesn't support stream transactions.
+pub(in crate::commerce) async fn delete_product<C: ClientExt>(
+    transaction: &Transaction<C>,
     product_key: &str,
     client_locale: &SupportedLocale,
 ) -> anyhow::Result<Product> {
-    // First, fetch product that is being deleted so we can return it later:
-    let deleted_product = resolve_aql(
-        pool,
+    resolve_aql_in_transaction(
+        transaction,
         r#"
             LET unit_label_translated = DOCUMENT("product_units/piece")[@client_locale]
             LET product = DOCUMENT(products, @product_key)
@@ -368,6 +462,14 @@ pub(in crate::commerce) async fn delete_product(
                 RETURN t
             )
 
+            LET removed_edges = (
+              FOR edge IN product_categories_edges
+                FILTER edge._from == product._id
+                REMOVE edge IN product_categories_edges
+            )
+
+            REMOVE product IN products
+
             RETURN MERGE(
               product,
               { unit_label: unit_label_translated },
@@ -379,17 +481,73 @@ pub(in crate::commerce) async fn delete_product(
             "product_key" => product_key,
         ],
     )
-    .await;
-
-    // Remove the product via Gherial API so the graph edges are not dangling:
-    let db = pool.db().await;
-    db.remove_graph_vertex(
-        "product_categories", // graph
-        "products",           // name of the vertex collection the vertex belongs to
-        product_key,
-        true, // wait for sync
-    )
-    .await?;
+    .await
+}
 
-    deleted_product
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arango::client::fake::FakeClient;
+    use crate::arango::get_database_connection_pool_fake;
+    use http::Method;
+
+    fn product_json() -> serde_json::Value {
+        json!({
+            "_id": "products/42",
+            "_rev": "_fake_rev",
+            "_key": "42",
+            "name": "Product",
+            "description": null,
+            "images": [],
+            "image_cover": null,
+            "unit_label": "piece",
+            "is_published": false,
+            "visibility": ["ESHOP"],
+            "price": { "unit_amount": 10000, "unit_amount_currency": "MXN" },
+            "translations": [{ "locale": "en_US", "name": "Product", "description": null }],
+            "addons": [],
+            "stock": null,
+        })
+    }
+
+    #[tokio::test]
+    async fn restore_product_test() {
+        let client = FakeClient::default();
+        client.respond(
+            Method::POST,
+            "_api/transaction/begin",
+            200,
+            json!({ "error": false, "code": 200, "result": { "id": "1", "status": "running" } }),
+        );
+        client.respond_cursor(json!([product_json()]));
+        client.respond(
+            Method::PUT,
+            "_api/transaction/1",
+            200,
+            json!({ "error": false, "code": 200, "result": { "id": "1", "status": "committed" } }),
+        );
+
+        let pool = get_database_connection_pool_fake(&client);
+        let product: Product = serde_json::from_value(product_json()).unwrap();
+        let restored_product = pool
+            .run_in_transaction(&["products"], |transaction| async move {
+                restore_product(&transaction, &SupportedLocale::EnUS, &product).await
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            restored_product.key(),
+            juniper::ID::from(String::from("42"))
+        );
+
+        let requests = client.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[1].transaction_id, Some(String::from("1")));
+        assert_eq!(requests[1].body["bindVars"]["client_locale"], "en_US");
+        assert_eq!(requests[1].body["bindVars"]["product"]["_key"], "42");
+        assert_eq!(
+            requests[1].body["bindVars"]["product"]["visibility"],
+            json!(["ESHOP"])
+        );
+    }
 }
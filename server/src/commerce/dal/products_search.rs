@@ -0,0 +1,343 @@
+use crate::arango::analyzer::{
+    AnalyzerCase, AnalyzerFeature, AnalyzerInfo, NgramAnalyzerProperties, NgramStreamType,
+    TextAnalyzerProperties,
+};
+use crate::arango::client::ClientExt;
+use crate::arango::view::{ArangoSearchViewLink, ArangoSearchViewPropertiesOptions, ViewOptions};
+use crate::arango::{resolve_aql, ArangoError, ClientError, ConnectionPool};
+use crate::commerce::model::products::{
+    PriceSortDirection, Product, ProductMultilingualInputVisibility,
+};
+use crate::locale::SupportedLocale;
+use crate::pagination::{PaginatedResult, Pagination};
+use serde_json::json;
+use std::collections::HashMap;
+
+/// ArangoSearch view indexing translated product names and descriptions (keep it in sync with
+/// the AQL query in `search_products_fulltext`).
+const PRODUCTS_SEARCH_VIEW: &str = "products_search";
+
+const TEXT_EN_ANALYZER: &str = "products_text_en";
+const TEXT_ES_ANALYZER: &str = "products_text_es";
+const NGRAM_ANALYZER: &str = "products_ngram";
+
+/// Minimal similarity of the searched query and the product name (0.0 - 1.0) for the product to
+/// be considered a match even though it's misspelled.
+const NGRAM_SIMILARITY_THRESHOLD: f32 = 0.6;
+
+/// Creates the analyzers and the ArangoSearch view for full-text product search unless they exist
+/// already. Existing analyzers and views are never modified (drop them manually to recreate them
+/// with a new definition).
+pub(in crate::commerce) async fn ensure_products_search_view<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+) -> anyhow::Result<()> {
+    let db = pool.db().await;
+
+    for analyzer in products_search_analyzers() {
+        let name = analyzer_name(&analyzer);
+        match db.analyzer(&name).await {
+            Ok(_) => {}
+            Err(ClientError::Arango(ArangoError { code: 404, .. })) => {
+                tracing::info!("Creating '{}' analyzer", name);
+                db.create_analyzer(analyzer).await?;
+            }
+            Err(error) => anyhow::bail!(error),
+        }
+    }
+
+    match db.view(PRODUCTS_SEARCH_VIEW).await {
+        Ok(_) => {}
+        Err(ClientError::Arango(ArangoError { code: 404, .. })) => {
+            tracing::info!("Creating '{}' view", PRODUCTS_SEARCH_VIEW);
+            db.create_view(products_search_view()).await?;
+        }
+        Err(error) => anyhow::bail!(error),
+    }
+
+    Ok(())
+}
+
+fn analyzer_name(analyzer: &AnalyzerInfo) -> String {
+    match analyzer {
+        AnalyzerInfo::Identity { name, .. }
+        | AnalyzerInfo::Delimiter { name, .. }
+        | AnalyzerInfo::Stem { name, .. }
+        | AnalyzerInfo::Norm { name, .. }
+        | AnalyzerInfo::Ngram { name, .. }
+        | AnalyzerInfo::Text { name, .. } => name.to_owned(),
+    }
+}
+
+/// Text analyzers (one per supported locale) tokenize, lowercase, remove accents and stem the
+/// words so "cafés" matches "café", for example. The n-gram analyzer makes the search tolerant to
+/// typos. Features `frequency`, `norm` and `position` are required for BM25 ranking and
+/// `NGRAM_MATCH` respectively.
+fn products_search_analyzers() -> Vec<AnalyzerInfo> {
+    let text_analyzer = |name: &str, locale: &str| AnalyzerInfo::Text {
+        name: name.to_string(),
+        features: Some(vec![
+            AnalyzerFeature::Frequency,
+            AnalyzerFeature::Norm,
+            AnalyzerFeature::Position,
+        ]),
+        properties: Some(
+            TextAnalyzerProperties::builder()
+                .locale(locale.to_string())
+                .case(AnalyzerCase::Lower)
+                .accent(false)
+                .stemming(true)
+                .stopwords(vec![])
+                .build(),
+        ),
+    };
+
+    vec![
+        text_analyzer(TEXT_EN_ANALYZER, "en"),
+        text_analyzer(TEXT_ES_ANALYZER, "es"),
+        AnalyzerInfo::Ngram {
+            name: NGRAM_ANALYZER.to_string(),
+            features: Some(vec![
+                AnalyzerFeature::Frequency,
+                AnalyzerFeature::Norm,
+                AnalyzerFeature::Position,
+            ]),
+            properties: Some(
+                NgramAnalyzerProperties::builder()
+                    .min(3)
+                    .max(3)
+                    .preserve_original(true)
+                    .stream_type(NgramStreamType::Utf8)
+                    .build(),
+            ),
+        },
+    ]
+}
+
+fn products_search_view() -> ViewOptions {
+    let analyzed_field = || {
+        ArangoSearchViewLink::builder()
+            .analyzers(vec![
+                TEXT_EN_ANALYZER.to_string(),
+                TEXT_ES_ANALYZER.to_string(),
+                NGRAM_ANALYZER.to_string(),
+            ])
+            .build()
+    };
+
+    let translations_link = ArangoSearchViewLink::builder()
+        .fields(HashMap::from([
+            (String::from("name"), analyzed_field()),
+            (String::from("description"), analyzed_field()),
+        ]))
+        .build();
+
+    let products_link = ArangoSearchViewLink::builder()
+        .include_all_fields(false)
+        .fields(HashMap::from([(
+            String::from("translations"),
+            translations_link,
+        )]))
+        .build();
+
+    ViewOptions::builder()
+        .name(PRODUCTS_SEARCH_VIEW.to_string())
+        .properties(
+            ArangoSearchViewPropertiesOptions::builder()
+                .links(HashMap::from([(String::from("products"), products_link)]))
+                .build(),
+        )
+        .build()
+}
+
+/// Returns name of the text analyzer matching the language of the client (Spanish stemming for
+/// `es_MX` and English stemming for `en_US`).
+fn text_analyzer(client_locale: &SupportedLocale) -> &'static str {
+    match client_locale {
+        SupportedLocale::EnUS => TEXT_EN_ANALYZER,
+        SupportedLocale::EsMX => TEXT_ES_ANALYZER,
+    }
+}
+
+/// Full-text search of products by their translated names and descriptions. The products are
+/// ranked by BM25 (more relevant first) and the price is used only as a secondary sort.
+///
+/// Exact (stemmed) matches are searched in the client locale, misspelled product names are
+/// matched via n-grams. The view indexes all the translations together (the translations are not
+/// nested fields), so the matched products are additionally filtered by their client locale
+/// translation: products matching only in another language are not returned.
+///
+/// TODO(004) - integration tests
+#[allow(clippy::too_many_arguments)]
+pub(in crate::commerce) async fn search_products_fulltext<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    client_locale: &SupportedLocale,
+    query: &str,
+    price_sort_direction: &PriceSortDirection,
+    categories: &Option<Vec<juniper::ID>>,
+    search_all: &bool,
+    visibility: &Option<ProductMultilingualInputVisibility>,
+    pagination: &Pagination,
+) -> anyhow::Result<PaginatedResult<Product>> {
+    let sort_direction = match price_sort_direction {
+        PriceSortDirection::LowToHigh => "ASC",
+        PriceSortDirection::HighToLow => "DESC",
+    };
+
+    let visibility = match visibility {
+        Some(visibility) => json!(visibility),
+        None => json!(null),
+    };
+
+    resolve_aql(
+        pool,
+        r#"
+            LET matched_products = (
+              FOR product IN products_search
+                SEARCH ANALYZER(
+                  BOOST(product.translations.name IN TOKENS(@query, @text_analyzer), 2)
+                  OR product.translations.description IN TOKENS(@query, @text_analyzer),
+                  @text_analyzer
+                )
+                OR NGRAM_MATCH(
+                  product.translations.name,
+                  @query,
+                  @ngram_similarity_threshold,
+                  @ngram_analyzer
+                )
+
+                // only the client locale translation is relevant (see the function docs)
+                LET t_search = FIRST(
+                  FOR t IN product.translations
+                    FILTER t.name != null AND t.locale == @client_locale
+                    RETURN t
+                )
+                FILTER t_search != null
+                LET query_tokens = TOKENS(@query, @text_analyzer)
+                FILTER LENGTH(INTERSECTION(TOKENS(t_search.name, @text_analyzer), query_tokens)) > 0
+                  OR LENGTH(
+                    INTERSECTION(TOKENS(NOT_NULL(t_search.description, ""), @text_analyzer), query_tokens)
+                  ) > 0
+                  OR NGRAM_MATCH(t_search.name, @query, @ngram_similarity_threshold, @ngram_analyzer)
+
+                FILTER @search_all == true ? true : (product.is_published IN [true])
+                FILTER @visibility == null ? true : (@visibility IN product.visibility)
+                FILTER @categories == null ? true : LENGTH(
+                  INTERSECTION(
+                    @categories,
+                    (
+                      FOR category IN OUTBOUND product GRAPH product_categories
+                        RETURN category._id
+                    )
+                  )
+                ) > 0
+                SORT BM25(product) DESC, product.price.unit_amount @price_sort_direction, product._key ASC
+                RETURN product
+            )
+
+            LET items = (
+              FOR product IN matched_products
+                LIMIT @offset, @limit
+
+                LET t = FIRST(
+                  FOR t IN product.translations
+                    FILTER t.name != null AND t.locale == @client_locale
+                    RETURN t
+                )
+
+                RETURN MERGE(
+                  product,
+                  { unit_label: DOCUMENT(product.unit_label)[@client_locale] },
+                  { name: t.name, description: t.description }
+                )
+            )
+
+            RETURN { total_count: LENGTH(matched_products), items }
+        "#,
+        hashmap_json![
+            "client_locale" => client_locale,
+            "query" => query,
+            "text_analyzer" => text_analyzer(client_locale),
+            "ngram_analyzer" => NGRAM_ANALYZER,
+            "ngram_similarity_threshold" => NGRAM_SIMILARITY_THRESHOLD,
+            "categories" => categories,
+            "search_all" => search_all,
+            "visibility" => visibility,
+            "price_sort_direction" => sort_direction,
+            "offset" => pagination.offset(),
+            "limit" => pagination.limit(),
+        ],
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arango::client::fake::FakeClient;
+    use crate::arango::get_database_connection_pool_fake;
+
+    #[test]
+    fn text_analyzer_test() {
+        assert_eq!(text_analyzer(&SupportedLocale::EnUS), "products_text_en");
+        assert_eq!(text_analyzer(&SupportedLocale::EsMX), "products_text_es");
+    }
+
+    #[test]
+    fn products_search_view_test() {
+        // every analyzer used by the view must be created as well
+        let analyzer_names: Vec<String> = products_search_analyzers()
+            .iter()
+            .map(analyzer_name)
+            .collect();
+        assert_eq!(
+            analyzer_names,
+            vec!["products_text_en", "products_text_es", "products_ngram"]
+        );
+
+        let view = serde_json::to_value(products_search_view()).unwrap();
+        assert_eq!(view["name"], "products_search");
+        assert_eq!(view["type"], "arangosearch");
+        assert_eq!(
+            view["links"]["products"]["fields"]["translations"]["fields"]["name"]["analyzers"],
+            json!(analyzer_names)
+        );
+        assert_eq!(
+            view["links"]["products"]["fields"]["translations"]["fields"]["description"]
+                ["analyzers"],
+            json!(analyzer_names)
+        );
+    }
+
+    #[tokio::test]
+    async fn search_products_fulltext_client_locale_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([{ "total_count": 0, "items": [] }]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let result = search_products_fulltext(
+            &pool,
+            &SupportedLocale::EsMX,
+            "café",
+            &PriceSortDirection::LowToHigh,
+            &None,
+            &false,
+            &None,
+            &Pagination::new(&None, &None).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.total_count, 0);
+
+        // products matching only in another language are filtered out
+        let request = &client.requests()[0];
+        assert_eq!(request.body["bindVars"]["client_locale"], "es_MX");
+        assert_eq!(
+            request.body["bindVars"]["text_analyzer"],
+            "products_text_es"
+        );
+        assert!(request.body["query"]
+            .as_str()
+            .unwrap()
+            .contains("FILTER t_search != null"));
+    }
+}
@@ -1,3 +1,6 @@
+use crate::commerce::model::inventory::StockReservation;
+use crate::commerce::model::orders::OrderLineItem;
+use crate::commerce::model::products::Product;
 use crate::graphql_context::Context;
 use crate::locale::SupportedLocale;
 use crate::price::{Price, SupportedCurrency};
@@ -23,6 +26,8 @@ pub struct CheckoutSessionInput {
 ///
 /// We take these two parts (ordered items + Stripe session) and save them into our database with
 /// status "awaiting payment". The order should not be taken into account unless it's fully paid.
+/// However, the ordered product units are reserved in the stock right away so nobody else can buy
+/// them (they are released when the checkout session expires or the payment fails).
 ///
 /// The actual payment confirmation goes through a different channel: webhooks.
 ///
@@ -63,17 +68,10 @@ pub(crate) async fn create_checkout_session(
     )
     .await?;
 
-    for selected_product in &input.selected_products {
-        let db_product = &db_products
-            .iter()
-            .find(|product| product.id() == selected_product.product_id)
-            .unwrap_or_else(|| {
-                panic!(
-                    "Product with key {} doesn't exist in the database.",
-                    selected_product.product_id
-                )
-            });
+    // Unknown (or unpublished) products cannot be bought, so we fail early:
+    let selected_products = pair_selected_products(&input.selected_products, &db_products)?;
 
+    for (selected_product, db_product) in &selected_products {
         // Validate that the prices are still valid (or they changed in the meantime):
         if !db_product.compare_prices(Price {
             unit_amount: selected_product.product_price_unit_amount,
@@ -83,45 +81,130 @@ pub(crate) async fn create_checkout_session(
                 "The current product price is different and therefore the checkout could not be finished."
             )
         }
+    }
 
-        // TODO: validate products availability (stock)
+    // Products availability is validated when reserving the stock (no other checkout can take
+    // these units in the meantime):
+    let stock_reservations: Vec<StockReservation> = selected_products
+        .iter()
+        .map(|(selected_product, db_product)| StockReservation {
+            product_id: db_product.id().to_string(),
+            product_units: selected_product.product_units,
+        })
+        .collect();
+
+    crate::commerce::model::inventory::reserve_stock(&context.pool, &stock_reservations).await?;
+
+    match create_checkout_session_and_order(context, &selected_products, client_locale).await {
+        Ok(checkout_session) => Ok(checkout_session),
+        Err(error) => {
+            // The order was not created, so nobody is going to pay for the reserved units:
+            if let Err(release_error) =
+                crate::commerce::model::inventory::release_stock(&context.pool, &stock_reservations)
+                    .await
+            {
+                tracing::error!("Unable to release checkout stock: {:?}", release_error);
+            }
+            Err(error)
+        }
     }
+}
 
+/// Pairs every selected product with its DB product (the products can be selected by their KEY or
+/// ID). Returns an error when any of the selected products doesn't exist or isn't published.
+fn pair_selected_products<'a>(
+    selected_products: &'a [CheckoutSessionProductInput],
+    db_products: &'a [Product],
+) -> anyhow::Result<Vec<(&'a CheckoutSessionProductInput, &'a Product)>> {
+    selected_products
+        .iter()
+        .map(|selected_product| {
+            match db_products.iter().find(|product| {
+                product.id() == selected_product.product_id
+                    || product.key() == selected_product.product_id
+            }) {
+                Some(db_product) => Ok((selected_product, db_product)),
+                None => anyhow::bail!(
+                    "Product '{}' doesn't exist or it's not published.",
+                    selected_product.product_id
+                ),
+            }
+        })
+        .collect()
+}
+
+async fn create_checkout_session_and_order(
+    context: &Context,
+    selected_products: &[(&CheckoutSessionProductInput, &Product)],
+    client_locale: &SupportedLocale,
+) -> anyhow::Result<CheckoutSession> {
     // Everything should be validated at this point so let's call Stripe.com API and get the
     // checkout session URL (basically a payment URL):
     let stripe_restricted_api_key = context.global_configuration.stripe_restricted_api_key();
     let checkout_session = crate::stripe::checkout_session_create(
         &stripe_restricted_api_key,
         &StripeCheckoutSessionCreateInput {
-            selected_products: input
-                .selected_products
+            selected_products: selected_products
                 .iter()
-                .map(|selected_product| {
-                    let db_product = &db_products
-                        .iter()
-                        .find(|product| product.id() == selected_product.product_id)
-                        .unwrap_or_else(|| {
-                            panic!(
-                                "Product with key {} doesn't exist in the database.",
-                                selected_product.product_id
-                            )
-                        });
-
-                    StripeCheckoutSessionCreateProductInput {
+                .map(
+                    |(selected_product, db_product)| StripeCheckoutSessionCreateProductInput {
                         product_name: db_product.name(),
                         product_units: selected_product.product_units,
                         product_price_unit_amount: selected_product.product_price_unit_amount,
                         product_price_unit_amount_currency: selected_product
                             .product_price_unit_amount_currency,
-                    }
-                })
+                    },
+                )
                 .collect(),
         },
         client_locale,
     )
     .await?;
 
-    // TODO: save order to the database
+    // Stripe returns the checkout session ID which is later used to pair the incoming webhooks
+    // with our order:
+    let stripe_checkout_session_id = match &checkout_session.id {
+        Some(stripe_checkout_session_id) => stripe_checkout_session_id,
+        None => anyhow::bail!("Stripe.com didn't return ID of the created checkout session."),
+    };
+
+    // Finally, we save the order with all the line items into our database (awaiting payment):
+    let line_items: Vec<OrderLineItem> = selected_products
+        .iter()
+        .map(|(selected_product, db_product)| OrderLineItem {
+            product_id: db_product.id().to_string(),
+            product_name: db_product.name(),
+            product_units: selected_product.product_units,
+            product_price_unit_amount: selected_product.product_price_unit_amount,
+            product_price_unit_amount_currency: selected_product.product_price_unit_amount_currency,
+        })
+        .collect();
+
+    if let Err(error) = crate::commerce::dal::orders::create_unpaid_order(
+        &context.pool,
+        client_locale,
+        stripe_checkout_session_id,
+        &line_items,
+    )
+    .await
+    {
+        // There is no order to pair the payment with (and the reserved units are going to be
+        // released) so the checkout session must not be payable:
+        if let Err(expire_error) = crate::stripe::checkout_session_expire(
+            &stripe_restricted_api_key,
+            stripe_checkout_session_id,
+        )
+        .await
+        {
+            tracing::error!(
+                "Could not expire Stripe checkout session {}: {}",
+                stripe_checkout_session_id,
+                expire_error
+            );
+        }
+        return Err(error);
+    }
+
     // TODO: send an email (?) - maybe no and do it when the webhook arrives
 
     Ok(checkout_session)
@@ -155,4 +238,46 @@ mod tests {
             "There are 101 selected products but, unfortunately, maximum of 100 is allowed."
         )
     }
+
+    #[test]
+    fn pair_selected_products_test() {
+        let db_products: Vec<Product> = serde_json::from_value(serde_json::json!([{
+            "_id": "products/42",
+            "_rev": "_fake_rev",
+            "_key": "42",
+            "name": "Product",
+            "description": null,
+            "images": [],
+            "image_cover": null,
+            "unit_label": "piece",
+            "is_published": true,
+            "visibility": ["ESHOP"],
+            "price": { "unit_amount": 10000, "unit_amount_currency": "MXN" },
+            "translations": [{ "locale": "en_US", "name": "Product", "description": null }],
+            "addons": [],
+            "stock": null,
+        }]))
+        .unwrap();
+        let selected_product = |product_id: &str| CheckoutSessionProductInput {
+            product_id: juniper::ID::from(product_id.to_string()),
+            product_units: 1,
+            product_price_unit_amount: 10000,
+            product_price_unit_amount_currency: SupportedCurrency::MXN,
+        };
+
+        // products can be selected by their ID as well as by their KEY
+        let selected_products = vec![selected_product("products/42"), selected_product("42")];
+        let pairs = pair_selected_products(&selected_products, &db_products).unwrap();
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[1].1.key(), juniper::ID::from(String::from("42")));
+
+        // unknown (or unpublished) products are rejected instead of being silently dropped
+        let selected_products = vec![selected_product("42"), selected_product("products/0")];
+        assert_eq!(
+            pair_selected_products(&selected_products, &db_products)
+                .unwrap_err()
+                .to_string(),
+            "Product 'products/0' doesn't exist or it's not published."
+        );
+    }
 }
@@ -0,0 +1,185 @@
+use crate::arango::client::ClientExt;
+use crate::arango::transaction::Transaction;
+use crate::arango::ConnectionPool;
+use crate::auth::rbac;
+use crate::auth::rbac::Actions::Commerce;
+use crate::auth::rbac::CommerceActions::UpdateProductStock;
+use crate::commerce::model::products::Product;
+use crate::graphql_context::Context;
+use crate::locale::SupportedLocale;
+use serde::{Deserialize, Serialize};
+
+/// Number of product units to be reserved from the stock (or released back to the stock).
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct StockReservation {
+    /// Product ID (not KEY) of the reserved product.
+    pub(crate) product_id: String,
+    pub(crate) product_units: i32,
+}
+
+/// Sets the product stock to the exact number of units (typically after counting the inventory).
+/// Stock `None` disables the stock tracking for this product completely.
+pub(in crate::commerce) async fn set_product_stock(
+    context: &Context,
+    client_locale: &SupportedLocale,
+    product_key: &str,
+    stock: &Option<i32>,
+) -> anyhow::Result<Product> {
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(UpdateProductStock),
+    )
+    .await?;
+
+    if let Some(stock) = stock {
+        if *stock < 0 {
+            anyhow::bail!("product stock cannot be smaller than zero")
+        }
+    }
+
+    crate::commerce::dal::inventory::set_product_stock(
+        &context.pool,
+        client_locale,
+        product_key,
+        stock,
+    )
+    .await
+}
+
+/// Adds units to the product stock (when restocking) or subtracts them (when the product got
+/// damaged, for example). The stock cannot drop below zero.
+pub(in crate::commerce) async fn adjust_product_stock(
+    context: &Context,
+    client_locale: &SupportedLocale,
+    product_key: &str,
+    units_delta: &i32,
+) -> anyhow::Result<Product> {
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(UpdateProductStock),
+    )
+    .await?;
+
+    if *units_delta == 0 {
+        anyhow::bail!("product stock adjustment cannot be zero")
+    }
+
+    crate::commerce::dal::inventory::adjust_product_stock(
+        &context.pool,
+        client_locale,
+        product_key,
+        units_delta,
+    )
+    .await
+}
+
+/// Reserves the product units so they cannot be sold to anyone else. It fails when there is not
+/// enough units in stock for any of the products (nothing is reserved in such case).
+///
+/// There are no permission checks since the reservations are part of the checkouts (which check
+/// their own permissions).
+pub(in crate::commerce) async fn reserve_stock(
+    pool: &ConnectionPool,
+    stock_reservations: &[StockReservation],
+) -> anyhow::Result<()> {
+    validate_stock_reservations(stock_reservations)?;
+    crate::commerce::dal::inventory::reserve_stock(pool, stock_reservations).await
+}
+
+/// Releases the previously reserved product units back to the stock (when the order was not paid,
+/// for example).
+pub(in crate::commerce) async fn release_stock(
+    pool: &ConnectionPool,
+    stock_reservations: &[StockReservation],
+) -> anyhow::Result<()> {
+    validate_stock_reservations(stock_reservations)?;
+    crate::commerce::dal::inventory::release_stock(pool, stock_reservations).await
+}
+
+/// The same as `release_stock` but inside an existing transaction.
+pub(in crate::commerce) async fn release_stock_in_transaction<C: ClientExt>(
+    transaction: &Transaction<C>,
+    stock_reservations: &[StockReservation],
+) -> anyhow::Result<()> {
+    validate_stock_reservations(stock_reservations)?;
+    crate::commerce::dal::inventory::release_stock_in_transaction(transaction, stock_reservations)
+        .await
+}
+
+/// Zero or negative units would effectively turn reservations into releases (and vice versa).
+fn validate_stock_reservations(stock_reservations: &[StockReservation]) -> anyhow::Result<()> {
+    for stock_reservation in stock_reservations {
+        if stock_reservation.product_units <= 0 {
+            anyhow::bail!(
+                "Number of reserved units of product '{}' must be positive (got {}).",
+                stock_reservation.product_id,
+                stock_reservation.product_units
+            )
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn set_product_stock_unauthorized_test() {
+        assert_eq!(
+            set_product_stock(
+                &Context::create_mock(),
+                &SupportedLocale::EnUS,
+                "product_key_mock",
+                &Some(10)
+            )
+            .await
+            .unwrap_err()
+            .to_string(),
+            "user is not logged in (anonymous)"
+        );
+    }
+
+    #[tokio::test]
+    async fn adjust_product_stock_unauthorized_test() {
+        assert_eq!(
+            adjust_product_stock(
+                &Context::create_mock(),
+                &SupportedLocale::EnUS,
+                "product_key_mock",
+                &-1
+            )
+            .await
+            .unwrap_err()
+            .to_string(),
+            "user is not logged in (anonymous)"
+        );
+    }
+
+    #[test]
+    fn validate_stock_reservations_test() {
+        assert!(validate_stock_reservations(&[StockReservation {
+            product_id: String::from("products/1"),
+            product_units: 2,
+        }])
+        .is_ok());
+
+        assert_eq!(
+            validate_stock_reservations(&[StockReservation {
+                product_id: String::from("products/1"),
+                product_units: 0,
+            }])
+            .unwrap_err()
+            .to_string(),
+            "Number of reserved units of product 'products/1' must be positive (got 0)."
+        );
+
+        assert!(validate_stock_reservations(&[StockReservation {
+            product_id: String::from("products/1"),
+            product_units: -5,
+        }])
+        .is_err());
+    }
+}
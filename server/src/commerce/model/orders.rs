@@ -0,0 +1,355 @@
+use crate::arango::ConnectionPool;
+use crate::auth::rbac;
+use crate::auth::rbac::Actions::Commerce;
+use crate::auth::rbac::CommerceActions::GetAllOrders;
+use crate::commerce::model::inventory::StockReservation;
+use crate::graphql_context::Context;
+use crate::locale::SupportedLocale;
+use crate::price::SupportedCurrency;
+use serde::{Deserialize, Serialize};
+
+/// Lifecycle of an e-shop order. Orders are created as `AWAITING_PAYMENT` when the customer is
+/// redirected to Stripe.com and they are moved to other states only via Stripe webhooks.
+#[derive(juniper::GraphQLEnum, Copy, Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderStatus {
+    /// Checkout session was created but we didn't receive any payment confirmation yet.
+    AwaitingPayment,
+    /// Checkout session was completed and the order is fully paid (it can be fulfilled).
+    Paid,
+    /// Checkout session was completed but the payment is still pending (delayed payment methods
+    /// such as OXXO).
+    Unpaid,
+    /// Delayed payment (OXXO) was not received on time, and the order will never be paid.
+    PaymentFailed,
+    /// Checkout session expired before the customer completed it.
+    Expired,
+    /// Part of the order was refunded to the customer.
+    PartiallyRefunded,
+    /// The whole order was refunded to the customer.
+    Refunded,
+    /// Customer disputed the payment with their bank.
+    Disputed,
+}
+
+impl OrderStatus {
+    /// Returns all the statuses from which the order can be moved to this status. Any other status
+    /// change is ignored, which also protects us from Stripe.com delivering the webhooks out of
+    /// order (or more than once).
+    pub(in crate::commerce) fn allowed_previous_statuses(&self) -> Vec<OrderStatus> {
+        match self {
+            OrderStatus::AwaitingPayment => vec![],
+            OrderStatus::Paid => vec![OrderStatus::AwaitingPayment, OrderStatus::Unpaid],
+            OrderStatus::Unpaid => vec![OrderStatus::AwaitingPayment],
+            OrderStatus::PaymentFailed => vec![OrderStatus::Unpaid],
+            OrderStatus::Expired => vec![OrderStatus::AwaitingPayment],
+            OrderStatus::PartiallyRefunded => vec![
+                OrderStatus::Paid,
+                OrderStatus::PartiallyRefunded,
+                OrderStatus::Disputed,
+            ],
+            OrderStatus::Refunded => vec![
+                OrderStatus::Paid,
+                OrderStatus::PartiallyRefunded,
+                OrderStatus::Disputed,
+            ],
+            OrderStatus::Disputed => vec![OrderStatus::Paid, OrderStatus::PartiallyRefunded],
+        }
+    }
+
+    /// Orders in these statuses will never be paid, so their reserved product units should be
+    /// returned back to the stock.
+    pub(in crate::commerce) fn releases_stock(&self) -> bool {
+        matches!(self, OrderStatus::PaymentFailed | OrderStatus::Expired)
+    }
+}
+
+/// Different ways how to find the order from Stripe webhooks. Checkout session events carry the
+/// checkout session ID, whereas charge events (refunds, disputes) carry only the payment intent ID.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OrderLookup {
+    StripeCheckoutSessionId(String),
+    StripePaymentIntentId(String),
+}
+
+/// Order line items are copies of the products at the time of the checkout (so future changes of
+/// these products don't affect the order history).
+#[derive(juniper::GraphQLObject, Clone, Serialize, Deserialize, Debug)]
+pub struct OrderLineItem {
+    /// Original product ID (the product might be already archived).
+    pub(in crate::commerce) product_id: String,
+    pub(in crate::commerce) product_name: String,
+    pub(in crate::commerce) product_units: i32,
+    pub(in crate::commerce) product_price_unit_amount: i32,
+    pub(in crate::commerce) product_price_unit_amount_currency: SupportedCurrency,
+}
+
+impl From<&OrderLineItem> for StockReservation {
+    fn from(line_item: &OrderLineItem) -> Self {
+        StockReservation {
+            product_id: line_item.product_id.to_owned(),
+            product_units: line_item.product_units,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Order {
+    _id: String,
+    _rev: String,
+    _key: String,
+    status: OrderStatus,
+    client_locale: SupportedLocale,
+    line_items: Vec<OrderLineItem>,
+    stripe_checkout_session_id: String,
+    stripe_payment_intent_id: Option<String>, // available only after the checkout session is completed
+    amount_total: Option<i32>, // available only after the checkout session is completed
+    amount_subtotal: Option<i32>, // available only after the checkout session is completed
+    created: String,
+    updated: String,
+}
+
+#[juniper::graphql_object(context = Context)]
+impl Order {
+    fn id(&self) -> juniper::ID {
+        juniper::ID::from(self._id.to_owned())
+    }
+
+    fn status(&self) -> OrderStatus {
+        self.status.to_owned()
+    }
+
+    /// Locale which was used by the customer during the checkout.
+    fn client_locale(&self) -> SupportedLocale {
+        self.client_locale.to_owned()
+    }
+
+    fn line_items(&self) -> Vec<OrderLineItem> {
+        self.line_items.to_owned()
+    }
+
+    /// Identifier of the related Stripe.com checkout session (prefixed by `cs_*`).
+    fn stripe_checkout_session_id(&self) -> String {
+        self.stripe_checkout_session_id.to_owned()
+    }
+
+    /// Identifier of the related Stripe.com payment intent (prefixed by `pi_*`). Available only
+    /// after the checkout session is completed.
+    fn stripe_payment_intent_id(&self) -> Option<String> {
+        self.stripe_payment_intent_id.to_owned()
+    }
+
+    /// Total of all items AFTER discounts and taxes are applied (in centavo). Available only
+    /// after the checkout session is completed.
+    fn amount_total(&self) -> Option<i32> {
+        self.amount_total.to_owned()
+    }
+
+    /// Total of all items BEFORE discounts or taxes are applied (in centavo). Available only after
+    /// the checkout session is completed.
+    fn amount_subtotal(&self) -> Option<i32> {
+        self.amount_subtotal.to_owned()
+    }
+
+    /// Date and time (ISO 8601) when the order was created.
+    fn created(&self) -> String {
+        self.created.to_owned()
+    }
+
+    /// Date and time (ISO 8601) of the last order change.
+    fn updated(&self) -> String {
+        self.updated.to_owned()
+    }
+}
+
+/// Returns all e-shop orders (newest first). Only admins can see the orders.
+pub(in crate::commerce) async fn get_all_orders(context: &Context) -> anyhow::Result<Vec<Order>> {
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(GetAllOrders),
+    )
+    .await?;
+    crate::commerce::dal::orders::get_all_orders(&context.pool).await
+}
+
+/// Returns one e-shop order by its ID (or key). Only admins can see the orders.
+pub(in crate::commerce) async fn get_order_by_key_or_id(
+    context: &Context,
+    order_key_or_id: &str,
+) -> anyhow::Result<Order> {
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(GetAllOrders),
+    )
+    .await?;
+    crate::commerce::dal::orders::get_order_by_key_or_id(&context.pool, order_key_or_id).await
+}
+
+/// Moves the order which is awaiting payment to the new status once the related Stripe.com
+/// checkout session is completed. Orders in any other status are left untouched which makes this
+/// function idempotent (Stripe.com can deliver the same webhook more than once).
+///
+/// It returns `None` when there is no such order awaiting payment (for example, when the checkout
+/// session was not created by our e-shop or when the order was already processed).
+///
+/// There are no permission checks since this function is being called only from Stripe webhooks
+/// which are verified via Stripe signature.
+pub(in crate::commerce) async fn complete_order(
+    pool: &ConnectionPool,
+    stripe_checkout_session_id: &str,
+    stripe_payment_intent_id: &Option<String>,
+    new_status: &OrderStatus,
+    amount_total: &Option<i32>,
+    amount_subtotal: &Option<i32>,
+) -> anyhow::Result<Option<Order>> {
+    crate::commerce::dal::orders::complete_order(
+        pool,
+        stripe_checkout_session_id,
+        stripe_payment_intent_id,
+        new_status,
+        amount_total,
+        amount_subtotal,
+    )
+    .await
+}
+
+/// Moves the order to the new status in case it's allowed by `allowed_previous_statuses`. It
+/// returns `None` when there is no such order, or when the status change is not allowed (for
+/// example, when Stripe.com delivers the same webhook twice).
+///
+/// Product units reserved by the order are released back to the stock when the order can no
+/// longer be paid. This happens only once since the status change happens only once: both changes
+/// are committed in one transaction, so a failed release rolls back the status change as well (and
+/// Stripe's retry can finish it).
+///
+/// There are no permission checks since this function is being called only from Stripe webhooks
+/// which are verified via Stripe signature.
+pub(in crate::commerce) async fn update_order_status(
+    pool: &ConnectionPool,
+    order_lookup: &OrderLookup,
+    new_status: &OrderStatus,
+) -> anyhow::Result<Option<Order>> {
+    let allowed_previous_statuses = new_status.allowed_previous_statuses();
+    pool.run_in_transaction(&["orders", "products"], |transaction| async move {
+        let updated_order = crate::commerce::dal::orders::update_order_status(
+            &transaction,
+            order_lookup,
+            &allowed_previous_statuses,
+            new_status,
+        )
+        .await?;
+
+        if let Some(order) = &updated_order {
+            if new_status.releases_stock() {
+                crate::commerce::model::inventory::release_stock_in_transaction(
+                    &transaction,
+                    &order
+                        .line_items
+                        .iter()
+                        .map(StockReservation::from)
+                        .collect::<Vec<_>>(),
+                )
+                .await?;
+            }
+        }
+
+        Ok(updated_order)
+    })
+    .await
+}
+
+/// Checks whether the order exists at all (see `crate::stripe::webhook_handlers` which has to
+/// distinguish missing orders from the not allowed status changes).
+///
+/// There are no permission checks since this function is being called only from Stripe webhooks
+/// which are verified via Stripe signature.
+pub(in crate::commerce) async fn order_exists(
+    pool: &ConnectionPool,
+    order_lookup: &OrderLookup,
+) -> anyhow::Result<bool> {
+    crate::commerce::dal::orders::order_exists(pool, order_lookup).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn get_all_orders_unauthorized_test() {
+        assert_eq!(
+            get_all_orders(&Context::create_mock())
+                .await
+                .unwrap_err()
+                .to_string(),
+            "user is not logged in (anonymous)"
+        );
+    }
+
+    #[test]
+    fn order_status_serialization_test() {
+        assert_eq!(
+            serde_json::to_value(OrderStatus::AwaitingPayment).unwrap(),
+            serde_json::json!("AWAITING_PAYMENT")
+        );
+        assert_eq!(
+            serde_json::to_value(OrderStatus::Paid).unwrap(),
+            serde_json::json!("PAID")
+        );
+        assert_eq!(
+            serde_json::to_value(OrderStatus::Unpaid).unwrap(),
+            serde_json::json!("UNPAID")
+        );
+        assert_eq!(
+            serde_json::to_value(OrderStatus::PaymentFailed).unwrap(),
+            serde_json::json!("PAYMENT_FAILED")
+        );
+        assert_eq!(
+            serde_json::to_value(OrderStatus::PartiallyRefunded).unwrap(),
+            serde_json::json!("PARTIALLY_REFUNDED")
+        );
+    }
+
+    #[test]
+    fn order_status_releases_stock_test() {
+        assert!(OrderStatus::Expired.releases_stock());
+        assert!(OrderStatus::PaymentFailed.releases_stock());
+        assert!(!OrderStatus::Unpaid.releases_stock()); // OXXO payment can still arrive
+        assert!(!OrderStatus::Refunded.releases_stock()); // the product might not be returned
+    }
+
+    #[test]
+    fn order_status_allowed_previous_statuses_test() {
+        // new orders can be created only via checkout session
+        assert!(OrderStatus::AwaitingPayment
+            .allowed_previous_statuses()
+            .is_empty());
+
+        // OXXO payments are first unpaid and paid later
+        assert!(OrderStatus::Paid
+            .allowed_previous_statuses()
+            .contains(&OrderStatus::Unpaid));
+        assert!(OrderStatus::PaymentFailed
+            .allowed_previous_statuses()
+            .contains(&OrderStatus::Unpaid));
+
+        // expired checkout sessions cannot affect already paid orders
+        assert!(!OrderStatus::Expired
+            .allowed_previous_statuses()
+            .contains(&OrderStatus::Paid));
+
+        // only paid orders can be refunded or disputed
+        assert!(!OrderStatus::Refunded
+            .allowed_previous_statuses()
+            .contains(&OrderStatus::AwaitingPayment));
+        assert!(!OrderStatus::Disputed
+            .allowed_previous_statuses()
+            .contains(&OrderStatus::Unpaid));
+
+        // fully refunded orders are final
+        assert!(!OrderStatus::PartiallyRefunded
+            .allowed_previous_statuses()
+            .contains(&OrderStatus::Refunded));
+    }
+}
@@ -3,6 +3,7 @@ use crate::auth::rbac::Actions::Commerce;
 use crate::auth::rbac::CommerceActions::GetAllProductAddons;
 use crate::graphql_context::Context;
 use crate::locale::SupportedLocale;
+use crate::pagination::{PageInfo, PaginatedResult, Pagination};
 use crate::price::Price;
 use serde::Deserialize;
 
@@ -41,13 +42,63 @@ impl ProductAddon {
     }
 }
 
+/// An edge in a connection (Relay specification).
+#[derive(juniper::GraphQLObject)]
+pub struct ProductAddonEdge {
+    /// The item at the end of the edge.
+    node: ProductAddon,
+    /// A cursor for use in pagination.
+    cursor: String,
+}
+
+/// A list of product addons with pagination information (Relay specification).
+#[derive(juniper::GraphQLObject)]
+pub struct ProductAddonConnection {
+    edges: Vec<ProductAddonEdge>,
+    page_info: PageInfo,
+    /// Total number of product addons (ignoring the pagination).
+    total_count: i32,
+}
+
+impl ProductAddonConnection {
+    pub(in crate::commerce) fn new(
+        result: PaginatedResult<ProductAddon>,
+        pagination: &Pagination,
+    ) -> Self {
+        ProductAddonConnection {
+            page_info: pagination.page_info(result.items.len(), result.total_count),
+            total_count: result.total_count,
+            edges: result
+                .items
+                .into_iter()
+                .enumerate()
+                .map(|(index, product_addon)| ProductAddonEdge {
+                    node: product_addon,
+                    cursor: pagination.cursor(index),
+                })
+                .collect(),
+        }
+    }
+}
+
 pub(in crate::commerce) async fn search_all_product_addons(
     context: &Context,
     client_locale: &SupportedLocale,
-) -> anyhow::Result<Vec<Option<ProductAddon>>> {
-    rbac::verify_permissions(&context.user, &Commerce(GetAllProductAddons)).await?;
+    pagination: &Pagination,
+) -> anyhow::Result<PaginatedResult<ProductAddon>> {
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(GetAllProductAddons),
+    )
+    .await?;
 
-    crate::commerce::dal::product_addons::search_product_addons(&context.pool, client_locale).await
+    crate::commerce::dal::product_addons::search_product_addons(
+        &context.pool,
+        client_locale,
+        pagination,
+    )
+    .await
 }
 
 pub(in crate::commerce) async fn get_product_addons_by_ids(
@@ -55,7 +106,12 @@ pub(in crate::commerce) async fn get_product_addons_by_ids(
     client_locale: &SupportedLocale,
     product_addon_ids: &[String],
 ) -> anyhow::Result<Vec<Option<ProductAddon>>> {
-    rbac::verify_permissions(&context.user, &Commerce(GetAllProductAddons)).await?;
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(GetAllProductAddons),
+    )
+    .await?;
 
     crate::commerce::dal::product_addons::get_product_addons_by_ids(
         &context.pool,
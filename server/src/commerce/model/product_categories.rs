@@ -72,7 +72,12 @@ pub(in crate::commerce) async fn search_all_product_categories(
     context: &Context,
     client_locale: &SupportedLocale,
 ) -> anyhow::Result<Vec<Option<ProductCategory>>> {
-    rbac::verify_permissions(&context.user, &Commerce(GetAllProductCategories)).await?;
+    rbac::verify_permissions(
+        &context.pool,
+        &context.user,
+        &Commerce(GetAllProductCategories),
+    )
+    .await?;
 
     crate::commerce::dal::product_categories::search_all_product_categories(
         &context.pool,
@@ -86,7 +91,12 @@ pub(in crate::commerce) async fn get_product_categories_by_ids(
     client_locale: &SupportedLocale,
     product_category_ids: &[String],
 ) -> anyhow::Result<Vec<Option<ProductCategory>>> {
-    rbac::verify_permissions(&context.user, &Commerce(GetAllProductCategories)).await?;
+    rbac::verify_permissions(
+        &context.pool,
+        &context.user,
+        &Commerce(GetAllProductCategories),
+    )
+    .await?;
 
     crate::commerce::dal::product_categories::get_product_categories_by_ids(
         &context.pool,
@@ -101,7 +111,12 @@ pub(in crate::commerce) async fn get_assigned_product_categories(
     client_locale: &SupportedLocale,
     product_id: &str,
 ) -> anyhow::Result<Vec<Option<ProductCategory>>> {
-    rbac::verify_permissions(&context.user, &Commerce(GetAllProductCategories)).await?;
+    rbac::verify_permissions(
+        &context.pool,
+        &context.user,
+        &Commerce(GetAllProductCategories),
+    )
+    .await?;
 
     crate::commerce::dal::product_categories::get_assigned_product_categories(
         &context.pool,
@@ -1,8 +1,11 @@
-use crate::archive::archive_struct;
+use crate::arango::client::ClientExt;
+use crate::arango::ConnectionPool;
+use crate::archive::{archive_struct, ArchiveItem};
 use crate::auth::rbac;
 use crate::auth::rbac::Actions::{Commerce, Pos};
 use crate::auth::rbac::CommerceActions::{
-    ArchiveProduct, CreateProduct, GetAllProducts, PublishProduct, UnpublishProduct, UpdateProduct,
+    ArchiveProduct, CreateProduct, GetAllProducts, PublishProduct, RestoreProduct,
+    UnpublishProduct, UpdateProduct,
 };
 use crate::auth::rbac::PosActions::GetAllPublishedProducts;
 use crate::commerce::model::product_addons::ProductAddon;
@@ -14,6 +17,7 @@ use crate::graphql::AbacusGraphQLResult;
 use crate::graphql_context::Context;
 use crate::images::Image;
 use crate::locale::SupportedLocale;
+use crate::pagination::{PageInfo, PaginatedResult, Pagination};
 use crate::price::{Price, SupportedCurrency};
 use serde::{Deserialize, Serialize};
 
@@ -36,6 +40,8 @@ pub struct Product {
     /// Resolved product description (from translations based on the client locale).
     description: Option<String>,
     images: Vec<Image>,
+    /// Name of the image selected as the product cover (`None` for the first image).
+    image_cover: Option<String>,
     unit_label: String,
     /// Product should not be published until it has all the translations, pictures and other
     /// requirements fulfilled.
@@ -44,6 +50,7 @@ pub struct Product {
     price: Price,
     translations: Vec<ProductMultilingualTranslations>,
     addons: Option<Vec<String>>, // optional for BC (addons didn't exist at the beginning)
+    stock: Option<i32>,          // `None` means that the stock is not being tracked
 }
 
 impl std::fmt::Debug for Product {
@@ -117,16 +124,25 @@ impl Product {
         self.description.to_owned()
     }
 
-    /// A list of images for this product, meant to be displayable to the customer. You can get
-    /// image cover via `imageCover` field.
+    /// A list of images for this product (in the order specified when creating or updating the
+    /// product), meant to be displayable to the customer. You can get image cover via `imageCover`
+    /// field.
     pub(crate) fn images(&self) -> Vec<Image> {
         self.images.to_owned()
     }
 
-    /// Returns the most important image which should be displayed as a product cover. Other images
-    /// are available under field `images`.
+    /// Returns the most important image which should be displayed as a product cover (selected via
+    /// `imageCover` input, the first image otherwise). Other images are available under field
+    /// `images`.
     fn image_cover(&self) -> Option<&Image> {
-        self.images.first()
+        self.image_cover
+            .as_ref()
+            .and_then(|image_cover| {
+                self.images
+                    .iter()
+                    .find(|image| &image.name() == image_cover)
+            })
+            .or_else(|| self.images.first())
     }
 
     /// A label that represents units of this product in Stripe and on customers’ receipts and
@@ -139,6 +155,13 @@ impl Product {
         self.price.to_owned()
     }
 
+    /// Number of units available for sale (already reduced by the units reserved in unpaid
+    /// orders). Stock `null` means that the stock is not being tracked for this product, and it
+    /// can be sold without any limits.
+    fn stock(&self) -> Option<i32> {
+        self.stock.to_owned()
+    }
+
     fn is_published(&self) -> bool {
         self.is_published.to_owned()
     }
@@ -208,8 +231,13 @@ impl Product {
             crate::commerce::model::product_addons::search_all_product_addons(
                 context,
                 &client_locale,
+                &Pagination::unlimited(),
             )
-            .await?,
+            .await?
+            .items
+            .into_iter()
+            .map(Some)
+            .collect(),
         )
     }
 
@@ -244,6 +272,47 @@ impl Product {
     }
 }
 
+/// An edge in a connection (Relay specification).
+#[derive(juniper::GraphQLObject)]
+#[graphql(context = Context)]
+pub struct ProductEdge {
+    /// The item at the end of the edge.
+    node: Product,
+    /// A cursor for use in pagination.
+    cursor: String,
+}
+
+/// A list of products with pagination information (Relay specification).
+#[derive(juniper::GraphQLObject)]
+#[graphql(context = Context)]
+pub struct ProductConnection {
+    edges: Vec<ProductEdge>,
+    page_info: PageInfo,
+    /// Total number of products matching the search criteria (ignoring the pagination).
+    total_count: i32,
+}
+
+impl ProductConnection {
+    pub(in crate::commerce) fn new(
+        result: PaginatedResult<Product>,
+        pagination: &Pagination,
+    ) -> Self {
+        ProductConnection {
+            page_info: pagination.page_info(result.items.len(), result.total_count),
+            total_count: result.total_count,
+            edges: result
+                .items
+                .into_iter()
+                .enumerate()
+                .map(|(index, product)| ProductEdge {
+                    node: product,
+                    cursor: pagination.cursor(index),
+                })
+                .collect(),
+        }
+    }
+}
+
 /// This type should be used together with GraphQL uploads, and it should hold the file names being
 /// uploaded. It's used together with the actual uploaded files for validation purposes. Only files
 /// which are defined using this scalar will be processed.
@@ -264,6 +333,13 @@ impl std::fmt::Display for ProductImageUploadable {
     }
 }
 
+#[cfg(test)]
+impl From<&str> for ProductImageUploadable {
+    fn from(image_name: &str) -> Self {
+        ProductImageUploadable(image_name.to_string())
+    }
+}
+
 impl From<ProductImageUploadable> for serde_json::Value {
     fn from(f: ProductImageUploadable) -> Self {
         serde_json::Value::String(f.to_string())
@@ -297,7 +373,11 @@ pub enum ProductMultilingualInputVisibility {
 
 #[derive(juniper::GraphQLInputObject, Debug)]
 pub struct ProductMultilingualInput {
+    /// Names of the product images in the order they should be displayed.
     pub(crate) images: Vec<ProductImageUploadable>,
+    /// Name of the image which should be displayed as the product cover (it must be one of the
+    /// `images`). The first image is used when not specified.
+    pub(crate) image_cover: Option<ProductImageUploadable>,
     pub(in crate::commerce) price: ProductPriceInput,
     pub(in crate::commerce) translations: Vec<ProductMultilingualInputTranslations>,
     pub(in crate::commerce) visibility: Vec<ProductMultilingualInputVisibility>,
@@ -323,6 +403,7 @@ impl Default for ProductMultilingualInput {
     fn default() -> Self {
         ProductMultilingualInput {
             images: vec![],
+            image_cover: None,
             price: ProductPriceInput {
                 unit_amount: 0,
                 unit_amount_currency: SupportedCurrency::MXN,
@@ -360,14 +441,21 @@ pub(in crate::commerce) async fn search_all_products(
     context: &Context,
     client_locale: &SupportedLocale,
     price_sort_direction: &PriceSortDirection,
-) -> anyhow::Result<Vec<Option<Product>>> {
-    rbac::verify_permissions(&context.user, &Commerce(GetAllProducts)).await?;
+    pagination: &Pagination,
+) -> anyhow::Result<PaginatedResult<Product>> {
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(GetAllProducts),
+    )
+    .await?;
     crate::commerce::dal::products::search_products(
         &context.pool,
         client_locale,
         price_sort_direction,
         &true, // search all including unpublished ones
         &None, // no visibility restrictions
+        pagination,
     )
     .await
 }
@@ -379,8 +467,14 @@ pub(in crate::commerce) async fn search_all_products_in_categories(
     client_locale: &SupportedLocale,
     price_sort_direction: &PriceSortDirection,
     categories: &[juniper::ID],
-) -> anyhow::Result<Vec<Option<Product>>> {
-    rbac::verify_permissions(&context.user, &Commerce(GetAllProducts)).await?;
+    pagination: &Pagination,
+) -> anyhow::Result<PaginatedResult<Product>> {
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(GetAllProducts),
+    )
+    .await?;
     validate_product_categories(
         context,
         client_locale,
@@ -394,6 +488,7 @@ pub(in crate::commerce) async fn search_all_products_in_categories(
         categories,
         &true, // search all including unpublished ones
         &None, // no visibility restrictions
+        pagination,
     )
     .await
 }
@@ -404,10 +499,16 @@ pub(in crate::commerce) async fn search_all_published_products(
     client_locale: &SupportedLocale,
     price_sort_direction: &PriceSortDirection,
     visibility: &ProductMultilingualInputVisibility,
-) -> anyhow::Result<Vec<Option<Product>>> {
+    pagination: &Pagination,
+) -> anyhow::Result<PaginatedResult<Product>> {
     match visibility {
         ProductMultilingualInputVisibility::POS => {
-            rbac::verify_permissions(&context.user, &Pos(GetAllPublishedProducts)).await?;
+            rbac::verify_permissions(
+                &context.rbac_enforcer,
+                &context.user,
+                &Pos(GetAllPublishedProducts),
+            )
+            .await?;
         }
         ProductMultilingualInputVisibility::ESHOP => {
             // public
@@ -420,6 +521,7 @@ pub(in crate::commerce) async fn search_all_published_products(
         price_sort_direction,
         &false, // do not search all (published only)
         &Some(*visibility),
+        pagination,
     )
     .await
 }
@@ -431,11 +533,17 @@ pub(in crate::commerce) async fn search_all_published_products_in_categories(
     price_sort_direction: &PriceSortDirection,
     categories: &[juniper::ID],
     visibility: &ProductMultilingualInputVisibility,
-) -> anyhow::Result<Vec<Option<Product>>> {
+    pagination: &Pagination,
+) -> anyhow::Result<PaginatedResult<Product>> {
     // TODO: DRY with `search_all_published_products`
     match visibility {
         ProductMultilingualInputVisibility::POS => {
-            rbac::verify_permissions(&context.user, &Pos(GetAllPublishedProducts)).await?;
+            rbac::verify_permissions(
+                &context.rbac_enforcer,
+                &context.user,
+                &Pos(GetAllPublishedProducts),
+            )
+            .await?;
         }
         ProductMultilingualInputVisibility::ESHOP => {
             // public
@@ -456,10 +564,118 @@ pub(in crate::commerce) async fn search_all_published_products_in_categories(
         categories,
         &false, // do not search all (published only)
         &Some(*visibility),
+        pagination,
+    )
+    .await
+}
+
+/// Prepares the database for full-text product search (see `fulltext_search_all_products`). It's
+/// safe to call it repeatedly, nothing is changed when the search view already exists.
+pub(in crate::commerce) async fn ensure_products_search_view(
+    pool: &ConnectionPool,
+) -> anyhow::Result<()> {
+    crate::commerce::dal::products_search::ensure_products_search_view(pool).await
+}
+
+/// Full-text search of ALL products (published and unpublished) by their names and descriptions.
+/// Requires the same permissions as `search_all_products`.
+pub(in crate::commerce) async fn fulltext_search_all_products(
+    context: &Context,
+    client_locale: &SupportedLocale,
+    query: &str,
+    price_sort_direction: &PriceSortDirection,
+    categories: &Option<Vec<juniper::ID>>,
+    pagination: &Pagination,
+) -> anyhow::Result<PaginatedResult<Product>> {
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(GetAllProducts),
+    )
+    .await?;
+    let query = validate_search_query(query)?;
+    if let Some(categories) = categories {
+        validate_product_categories(
+            context,
+            client_locale,
+            &categories.iter().map(|id| id.to_string()).collect(),
+        )
+        .await?;
+    }
+
+    crate::commerce::dal::products_search::search_products_fulltext(
+        &context.pool,
+        client_locale,
+        query,
+        price_sort_direction,
+        categories,
+        &true, // search all including unpublished ones
+        &None, // no visibility restrictions
+        pagination,
+    )
+    .await
+}
+
+/// Full-text search of published products by their names and descriptions. Requires the same
+/// permissions as `search_all_published_products`.
+pub(in crate::commerce) async fn fulltext_search_published_products(
+    context: &Context,
+    client_locale: &SupportedLocale,
+    query: &str,
+    price_sort_direction: &PriceSortDirection,
+    categories: &Option<Vec<juniper::ID>>,
+    visibility: &ProductMultilingualInputVisibility,
+    pagination: &Pagination,
+) -> anyhow::Result<PaginatedResult<Product>> {
+    match visibility {
+        ProductMultilingualInputVisibility::POS => {
+            rbac::verify_permissions(
+                &context.rbac_enforcer,
+                &context.user,
+                &Pos(GetAllPublishedProducts),
+            )
+            .await?;
+        }
+        ProductMultilingualInputVisibility::ESHOP => {
+            // public
+        }
+    }
+
+    let query = validate_search_query(query)?;
+    if let Some(categories) = categories {
+        validate_product_categories(
+            context,
+            client_locale,
+            &categories.iter().map(|id| id.to_string()).collect(),
+        )
+        .await?;
+    }
+
+    crate::commerce::dal::products_search::search_products_fulltext(
+        &context.pool,
+        client_locale,
+        query,
+        price_sort_direction,
+        categories,
+        &false, // do not search all (published only)
+        &Some(*visibility),
+        pagination,
     )
     .await
 }
 
+/// Returns the trimmed search query or error when it's empty or unreasonably long.
+fn validate_search_query(query: &str) -> anyhow::Result<&str> {
+    let query = query.trim();
+    if query.is_empty() {
+        anyhow::bail!("search query cannot be empty")
+    }
+    if query.chars().count() > 200 {
+        anyhow::bail!("search query cannot be longer than 200 characters")
+    }
+    Ok(query)
+}
+
 pub(in crate::commerce) async fn get_published_product_by_key(
     context: &Context,
     client_locale: &SupportedLocale,
@@ -497,7 +713,12 @@ pub(in crate::commerce) async fn get_unpublished_product_by_key(
     client_locale: &SupportedLocale,
     product_key: &str,
 ) -> anyhow::Result<Product> {
-    rbac::verify_permissions(&context.user, &Commerce(GetAllProducts)).await?;
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(GetAllProducts),
+    )
+    .await?;
 
     crate::commerce::dal::products::get_product_by_key_or_id(
         &context.pool,
@@ -516,7 +737,12 @@ pub(in crate::commerce) async fn create_product(
     client_locale: &SupportedLocale,
     product_multilingual_input: &ProductMultilingualInput,
 ) -> anyhow::Result<Product> {
-    rbac::verify_permissions(&context.user, &Commerce(CreateProduct)).await?;
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(CreateProduct),
+    )
+    .await?;
 
     validate_product_multilingual_input(product_multilingual_input)?;
     validate_product_categories(
@@ -532,6 +758,7 @@ pub(in crate::commerce) async fn create_product(
     if context.uploadables.is_some() {
         images = crate::images::process_new_images(context, product_multilingual_input).await?;
     }
+    let images = order_images(images, product_multilingual_input);
 
     // Then, we create the product with the previously created images (and assigned addons).
     let created_product = crate::commerce::dal::products::create_product(
@@ -561,7 +788,12 @@ pub(in crate::commerce) async fn update_product(
     product_revision: &str,
     product_multilingual_input: &ProductMultilingualInput,
 ) -> anyhow::Result<Product> {
-    rbac::verify_permissions(&context.user, &Commerce(UpdateProduct)).await?;
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(UpdateProduct),
+    )
+    .await?;
 
     validate_product_multilingual_input(product_multilingual_input)?;
     validate_product_categories(
@@ -606,8 +838,9 @@ pub(in crate::commerce) async fn update_product(
         }
     }
 
-    // merge new (uploaded) images with the preserved images
+    // merge new (uploaded) images with the preserved images (in the order specified in the input)
     existing_images.extend(new_images);
+    let existing_images = order_images(existing_images, product_multilingual_input);
 
     // update the product (and assigned addons)
     let updated_product = crate::commerce::dal::products::update_product(
@@ -632,6 +865,20 @@ pub(in crate::commerce) async fn update_product(
     Ok(updated_product)
 }
 
+/// Orders the images the same way as they are specified in the GraphQL input.
+fn order_images(
+    mut images: Vec<Image>,
+    product_multilingual_input: &ProductMultilingualInput,
+) -> Vec<Image> {
+    images.sort_by_key(|image| {
+        product_multilingual_input
+            .images
+            .iter()
+            .position(|image_name| image_name.to_string() == image.name())
+    });
+    images
+}
+
 /// Any product can be published only when all the following requirements are met:
 /// - user is an admin
 /// - product has a name in EN and ES
@@ -644,7 +891,12 @@ pub(in crate::commerce) async fn publish_product(
     product_key: &str,
     client_locale: &SupportedLocale,
 ) -> anyhow::Result<Product> {
-    rbac::verify_permissions(&context.user, &Commerce(PublishProduct)).await?;
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(PublishProduct),
+    )
+    .await?;
 
     let product = crate::commerce::dal::products::get_product_by_key_or_id(
         &context.pool,
@@ -681,27 +933,53 @@ pub(in crate::commerce) async fn unpublish_product(
     product_key: &str,
     client_locale: &SupportedLocale,
 ) -> anyhow::Result<Product> {
-    rbac::verify_permissions(&context.user, &Commerce(UnpublishProduct)).await?;
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(UnpublishProduct),
+    )
+    .await?;
 
     // unpublish the product:
     crate::commerce::dal::products::unpublish_product(&context.pool, product_key, client_locale)
         .await
 }
 
+/// Product as it's stored in the archive. The product categories are graph edges which are removed
+/// together with the product so we have to archive them explicitly (products archived before don't
+/// have them).
+#[derive(Deserialize, Serialize)]
+struct ArchivedProduct {
+    #[serde(flatten)]
+    product: Product,
+    #[serde(default)]
+    categories: Vec<String>,
+}
+
 /// We need to perform the following steps when archiving the product:
 ///
-/// 1. retrieve it in a complete form from DB
+/// 1. retrieve it in a complete form from DB (including the assigned categories)
 /// 2. save copy of this data to the archive
-/// 3. remove all related pictures from S3
-/// 4. delete the actual product (only after it's been copied to the archive!)
+/// 3. delete the actual product (only after it's been copied to the archive!)
 ///
-/// TODO: it would be a good idea to call this in a DB transaction
+/// Steps 2 and 3 run in a single DB transaction so a failure midway cannot leave the product
+/// half-archived (archived but not deleted or vice versa).
+///
+/// The product images are kept so the product can be restored (see `restore_product`). They are
+/// deleted by the garbage collector once the archived product is gone (see
+/// `crate::images::collect_garbage`), never from within the transaction: S3 deletions cannot be
+/// rolled back.
 pub(in crate::commerce) async fn archive_product(
     context: &Context,
     product_key: &str,
     client_locale: &SupportedLocale,
 ) -> anyhow::Result<Product> {
-    rbac::verify_permissions(&context.user, &Commerce(ArchiveProduct)).await?;
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(ArchiveProduct),
+    )
+    .await?;
 
     // 1. get the old product
     let product_old = crate::commerce::dal::products::get_product_by_key_or_id(
@@ -711,22 +989,152 @@ pub(in crate::commerce) async fn archive_product(
         &false, // both published and unpublished
     )
     .await?;
+    let categories = crate::commerce::dal::product_categories::get_assigned_product_categories(
+        &context.pool,
+        client_locale,
+        &product_old._id,
+    )
+    .await?
+    .iter()
+    .flatten()
+    .map(|category| format!("product_categories/{}", category.key_ref()))
+    .collect();
+
+    let archived_product = ArchivedProduct {
+        product: product_old,
+        categories,
+    };
+    context
+        .pool
+        .run_in_transaction(
+            &["archive", "products", "product_categories_edges"],
+            |transaction| async move {
+                // 2. archive it
+                archive_struct(
+                    &transaction,
+                    &archived_product.product._id,
+                    "products",
+                    &archived_product,
+                )
+                .await?;
+
+                // 3. hard delete the product
+                crate::commerce::dal::products::delete_product(
+                    &transaction,
+                    product_key,
+                    client_locale,
+                )
+                .await
+            },
+        )
+        .await
+}
+
+/// Restores the archived product under its original KEY:
+///
+/// 1. re-insert the product (unpublished, it should be reviewed before publishing it again)
+/// 2. keep only the images which still exist (see `crate::images::reconcile_images`)
+/// 3. re-assign the product categories which still exist
+/// 4. delete the archived item (only after the product was restored!)
+///
+/// Steps 1, 3 and 4 run in a single DB transaction so a failure midway cannot leave the product
+/// restored while it's still archived (the restoration could never be retried then because of the
+/// KEY conflict).
+pub(in crate::commerce) async fn restore_product(
+    context: &Context,
+    archive_item_id: &str,
+    client_locale: &SupportedLocale,
+) -> anyhow::Result<Product> {
+    rbac::verify_permissions(
+        &context.rbac_enforcer,
+        &context.user,
+        &Commerce(RestoreProduct),
+    )
+    .await?;
 
-    // 2. archive it
-    archive_struct(&context.pool, &product_old._id, "products", &product_old).await?;
+    let archive_item = crate::archive::get_archive_item(&context.pool, archive_item_id).await?;
+    let ArchivedProduct {
+        mut product,
+        categories,
+    } = archive_item.original_payload("products")?;
 
-    // 3. delete all related pictures
-    for image in product_old.images() {
-        crate::images::delete_image(context, &image).await?;
+    // 2. keep only the existing images
+    product.images = crate::images::reconcile_images(context, product.images).await?;
+    if let Some(image_cover) = &product.image_cover {
+        if !product
+            .images
+            .iter()
+            .any(|image| &image.name() == image_cover)
+        {
+            product.image_cover = None;
+        }
     }
 
-    // 4. hard delete the product
-    crate::commerce::dal::products::delete_product(&context.pool, product_key, client_locale).await
+    // 3. only the existing categories (some of them might have been deleted meanwhile)
+    let existing_categories: Vec<String> =
+        crate::commerce::dal::product_categories::get_product_categories_by_ids(
+            &context.pool,
+            client_locale,
+            &categories,
+        )
+        .await?
+        .iter()
+        .flatten()
+        .map(|category| format!("product_categories/{}", category.key_ref()))
+        .collect();
+
+    restore_archived_product(
+        &context.pool,
+        &archive_item,
+        &product,
+        &existing_categories,
+        client_locale,
+    )
+    .await
+}
+
+async fn restore_archived_product<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    archive_item: &ArchiveItem,
+    product: &Product,
+    categories: &[String],
+    client_locale: &SupportedLocale,
+) -> anyhow::Result<Product> {
+    pool.run_in_transaction(
+        &["products", "product_categories_edges", "archive"],
+        |transaction| async move {
+            // 1. re-insert the product
+            let restored_product = crate::commerce::dal::products::restore_product(
+                &transaction,
+                client_locale,
+                product,
+            )
+            .await?;
+
+            // 3. re-assign the categories
+            crate::commerce::dal::product_categories::restore_product_categories(
+                &transaction,
+                &restored_product._id,
+                categories,
+            )
+            .await?;
+
+            // 4. the product is restored, so we no longer need the archived item
+            crate::archive::delete_archive_item(&transaction, archive_item).await?;
+
+            Ok(restored_product)
+        },
+    )
+    .await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::arango::client::fake::FakeClient;
+    use crate::arango::get_database_connection_pool_fake;
+    use http::Method;
+    use serde_json::json;
 
     #[tokio::test]
     async fn create_product_unauthorized_test() {
@@ -771,4 +1179,107 @@ mod tests {
             "user is not logged in (anonymous)"
         );
     }
+
+    #[tokio::test]
+    async fn fulltext_search_all_products_unauthorized_test() {
+        let context = Context::create_mock();
+        assert_eq!(
+            fulltext_search_all_products(
+                &context,
+                &SupportedLocale::EnUS,
+                "coffee",
+                &PriceSortDirection::LowToHigh,
+                &None,
+                &Pagination::new(&None, &None).unwrap(),
+            )
+            .await
+            .unwrap_err()
+            .to_string(),
+            "user is not logged in (anonymous)"
+        );
+    }
+
+    #[test]
+    fn validate_search_query_test() {
+        assert_eq!(
+            validate_search_query("  café con leche ").unwrap(),
+            "café con leche"
+        );
+        assert_eq!(
+            validate_search_query("   ").unwrap_err().to_string(),
+            "search query cannot be empty"
+        );
+        assert_eq!(
+            validate_search_query(&"a".repeat(201))
+                .unwrap_err()
+                .to_string(),
+            "search query cannot be longer than 200 characters"
+        );
+    }
+
+    fn product_json() -> serde_json::Value {
+        json!({
+            "_id": "products/42",
+            "_rev": "_fake_rev",
+            "_key": "42",
+            "name": "Product",
+            "description": null,
+            "images": [],
+            "image_cover": null,
+            "unit_label": "piece",
+            "is_published": false,
+            "visibility": ["ESHOP"],
+            "price": { "unit_amount": 10000, "unit_amount_currency": "MXN" },
+            "translations": [{ "locale": "en_US", "name": "Product", "description": null }],
+            "addons": [],
+            "stock": null,
+        })
+    }
+
+    #[tokio::test]
+    async fn restore_archived_product_failure_test() {
+        let client = FakeClient::default();
+        client.respond(
+            Method::POST,
+            "_api/transaction/begin",
+            200,
+            json!({ "error": false, "code": 200, "result": { "id": "1", "status": "running" } }),
+        );
+        client.respond_cursor(json!([product_json()])); // restore_product
+        client.respond_error(Method::POST, "_api/cursor", 500, 4, "internal server error"); // restore_product_categories
+        client.respond(
+            Method::DELETE,
+            "_api/transaction/1",
+            200,
+            json!({ "error": false, "code": 200, "result": { "id": "1", "status": "aborted" } }),
+        );
+
+        let pool = get_database_connection_pool_fake(&client);
+        let archive_item: ArchiveItem = serde_json::from_value(json!({
+            "_id": "archive/1",
+            "_rev": "_fake_rev",
+            "_key": "1",
+            "original_id": "products/42",
+            "original_collection_name": "products",
+            "original_payload": "{}",
+            "created": "2022-11-01T00:00:00.000Z",
+        }))
+        .unwrap();
+        let product: Product = serde_json::from_value(product_json()).unwrap();
+        assert!(restore_archived_product(
+            &pool,
+            &archive_item,
+            &product,
+            &[String::from("product_categories/1")],
+            &SupportedLocale::EnUS,
+        )
+        .await
+        .is_err());
+
+        // the restored product was rolled back (and the archived item was not deleted)
+        let requests = client.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[3].method, Method::DELETE);
+        assert!(client.is_exhausted());
+    }
 }
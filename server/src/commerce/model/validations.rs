@@ -7,6 +7,7 @@ use crate::locale::SupportedLocale;
 /// 1. There must be at least one translation variant available.
 /// 2. Each translation variant must have a name, description is optional (enforced by the input type).
 /// 3. Price cannot be bellow zero (must be positive).
+/// 4. Image names must be unique (they are used for ordering) and the cover image must be one of them.
 pub(in crate::commerce::model) fn validate_product_multilingual_input(
     product_multilingual_input: &ProductMultilingualInput,
 ) -> anyhow::Result<()> {
@@ -26,6 +27,21 @@ pub(in crate::commerce::model) fn validate_product_multilingual_input(
         anyhow::bail!("Product price cannot be smaller than zero.");
     }
 
+    let image_names = product_multilingual_input
+        .images
+        .iter()
+        .map(|image| image.to_string())
+        .collect::<Vec<_>>();
+    if (1..image_names.len()).any(|index| image_names[..index].contains(&image_names[index])) {
+        anyhow::bail!("Product image names must be unique.");
+    }
+
+    if let Some(image_cover) = &product_multilingual_input.image_cover {
+        if !image_names.contains(&image_cover.to_string()) {
+            anyhow::bail!("Product cover image must be one of the product images.");
+        }
+    }
+
     Ok(())
 }
 
@@ -87,7 +103,7 @@ pub(in crate::commerce::model) async fn validate_product_addons(
 mod tests {
     use super::*;
     use crate::commerce::model::products::{
-        ProductMultilingualInputTranslations, ProductPriceInput,
+        ProductImageUploadable, ProductMultilingualInputTranslations, ProductPriceInput,
     };
     use crate::locale::SupportedLocale;
     use crate::price::SupportedCurrency;
@@ -150,4 +166,51 @@ mod tests {
             "Product price cannot be smaller than zero."
         )
     }
+
+    #[test]
+    fn validate_product_multilingual_input_duplicate_images_test() {
+        assert_eq!(
+            validate_product_multilingual_input(&ProductMultilingualInput {
+                images: vec![
+                    ProductImageUploadable::from("a.png"),
+                    ProductImageUploadable::from("b.png"),
+                    ProductImageUploadable::from("a.png"),
+                ],
+                ..Default::default()
+            })
+            .unwrap_err()
+            .downcast::<&str>()
+            .unwrap(),
+            "Product image names must be unique."
+        )
+    }
+
+    #[test]
+    fn validate_product_multilingual_input_image_cover_test() {
+        let images = vec![
+            ProductImageUploadable::from("a.png"),
+            ProductImageUploadable::from("b.png"),
+        ];
+
+        assert!(
+            validate_product_multilingual_input(&ProductMultilingualInput {
+                images: images.clone(),
+                image_cover: Some(ProductImageUploadable::from("b.png")),
+                ..Default::default()
+            })
+            .is_ok()
+        );
+
+        assert_eq!(
+            validate_product_multilingual_input(&ProductMultilingualInput {
+                images,
+                image_cover: Some(ProductImageUploadable::from("c.png")),
+                ..Default::default()
+            })
+            .unwrap_err()
+            .downcast::<&str>()
+            .unwrap(),
+            "Product cover image must be one of the product images."
+        )
+    }
 }
@@ -1,7 +1,88 @@
+use crate::auth::oidc::OidcProviders;
+#[cfg(test)]
+use crate::images::LocalImageStorage;
+use crate::images::{ImageSize, ImageStorage};
+use axum::http::HeaderName;
+use std::sync::Arc;
+use std::time::Duration;
+
 #[derive(Clone)]
 pub struct GlobalConfiguration {
     pub stripe_restricted_api_key: Option<String>,
     pub stripe_webhook_secret: Option<String>,
+    pub http: HttpConfiguration,
+    pub image_storage: Arc<dyn ImageStorage>,
+    pub image_variants: ImageVariantsConfiguration,
+    pub image_limits: ImageLimitsConfiguration,
+    pub sessions: SessionsConfiguration,
+    pub oidc_providers: OidcProviders,
+}
+
+/// Configuration of the HTTP server middlewares (see `create_axum_server`).
+#[derive(Clone)]
+pub struct HttpConfiguration {
+    /// Header with the request ID (generated when the client doesn't send it).
+    pub request_id_header: HeaderName,
+    pub graphql_timeout: Duration,
+    pub graphql_body_limit: usize,
+    pub webhooks_timeout: Duration,
+    pub webhooks_body_limit: usize,
+    /// Whether the client IP address can be taken from `X-Forwarded-For` header (see
+    /// `get_session_device`).
+    pub trust_proxy: bool,
+}
+
+/// Maximum widths (in pixels) of the image variants generated during the upload. Images are never
+/// upscaled so the variants of small images can be narrower.
+#[derive(Clone)]
+pub struct ImageVariantsConfiguration {
+    pub thumbnail_width: u32,
+    pub card_width: u32,
+    pub full_width: u32,
+    /// Re-encode all the variants to WebP (the original image is always kept as is).
+    pub webp: bool,
+}
+
+impl ImageVariantsConfiguration {
+    pub(crate) fn max_width(&self, size: &ImageSize) -> u32 {
+        match size {
+            ImageSize::Thumbnail => self.thumbnail_width,
+            ImageSize::Card => self.card_width,
+            ImageSize::Full => self.full_width,
+        }
+    }
+}
+
+/// Limits of the uploaded images (images exceeding them are rejected).
+#[derive(Clone)]
+pub struct ImageLimitsConfiguration {
+    /// Maximum size of a single uploaded file in bytes.
+    pub max_file_size: usize,
+    /// Maximum width and height of the uploaded images in pixels.
+    pub max_dimension: u32,
+    pub max_images_per_product: usize,
+}
+
+impl ImageLimitsConfiguration {
+    /// Maximum size of a GraphQL multipart request uploading all the product images at once. Besides
+    /// the images, the request contains the GraphQL operation and the files map (1 MiB is reserved
+    /// for them).
+    pub(crate) fn max_upload_body_size(&self) -> usize {
+        const MULTIPART_OVERHEAD: usize = 1024 * 1024;
+        self.max_file_size
+            .saturating_mul(self.max_images_per_product)
+            .saturating_add(MULTIPART_OVERHEAD)
+    }
+}
+
+/// Expiration of the user sessions. Expired sessions are rejected and later deleted by the sweeper
+/// (see `spawn_expired_sessions_sweeper`).
+#[derive(Clone)]
+pub struct SessionsConfiguration {
+    /// Maximum age of a session (regardless of the activity).
+    pub max_lifetime: Duration,
+    /// Maximum time between two requests using the same session.
+    pub inactivity_timeout: Duration,
 }
 
 #[cfg(test)]
@@ -10,6 +91,62 @@ impl Default for GlobalConfiguration {
         GlobalConfiguration {
             stripe_restricted_api_key: Some("mocked".to_string()),
             stripe_webhook_secret: Some("mocked".to_string()),
+            http: HttpConfiguration::default(),
+            image_storage: Arc::new(LocalImageStorage::new(
+                std::env::temp_dir().join("abacus-images"),
+                "http://localhost:5000/images",
+            )),
+            image_variants: ImageVariantsConfiguration::default(),
+            image_limits: ImageLimitsConfiguration::default(),
+            sessions: SessionsConfiguration::default(),
+            oidc_providers: OidcProviders::new(vec![]),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Default for ImageLimitsConfiguration {
+    fn default() -> Self {
+        ImageLimitsConfiguration {
+            max_file_size: 2 * 1024 * 1024,
+            max_dimension: 8000,
+            max_images_per_product: 20,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Default for ImageVariantsConfiguration {
+    fn default() -> Self {
+        ImageVariantsConfiguration {
+            thumbnail_width: 160,
+            card_width: 640,
+            full_width: 1920,
+            webp: false,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Default for SessionsConfiguration {
+    fn default() -> Self {
+        SessionsConfiguration {
+            max_lifetime: Duration::from_secs(90 * 24 * 60 * 60),
+            inactivity_timeout: Duration::from_secs(30 * 24 * 60 * 60),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Default for HttpConfiguration {
+    fn default() -> Self {
+        HttpConfiguration {
+            request_id_header: HeaderName::from_static("x-request-id"),
+            graphql_timeout: Duration::from_secs(30),
+            graphql_body_limit: 2 * 1024 * 1024,
+            webhooks_timeout: Duration::from_secs(15),
+            webhooks_body_limit: 1024 * 1024,
+            trust_proxy: false,
         }
     }
 }
@@ -1,5 +1,7 @@
 #[cfg(test)]
 use crate::arango::get_database_connection_pool_mock;
+use crate::auth::rbac::RbacEnforcer;
+use crate::auth::session::SessionDevice;
 #[cfg(test)]
 use crate::auth::users::AnonymousUser;
 use crate::auth::users::User;
@@ -10,6 +12,28 @@ use std::collections::HashMap;
 pub enum ContextUploadableContentType {
     ImagePng,
     ImageJpeg,
+    ImageWebp,
+}
+
+impl ContextUploadableContentType {
+    /// Detects the content type from the file signature ("magic bytes") so we don't have to trust
+    /// the `Content-Type` sent by the client. Returns `None` for unsupported file types.
+    pub fn sniff(data: &[u8]) -> Option<Self> {
+        match image::guess_format(data) {
+            Ok(image::ImageFormat::Png) => Some(ContextUploadableContentType::ImagePng),
+            Ok(image::ImageFormat::Jpeg) => Some(ContextUploadableContentType::ImageJpeg),
+            Ok(image::ImageFormat::WebP) => Some(ContextUploadableContentType::ImageWebp),
+            _ => None,
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ContextUploadableContentType::ImagePng => "image/png",
+            ContextUploadableContentType::ImageJpeg => "image/jpeg",
+            ContextUploadableContentType::ImageWebp => "image/webp",
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -37,6 +61,9 @@ pub struct Context {
     pub pool: crate::arango::ConnectionPool,
     pub uploadables: Option<HashMap<String, ContextUploadable>>,
     pub user: User,
+    /// Device sending the request (recorded when creating new sessions).
+    pub session_device: SessionDevice,
+    pub rbac_enforcer: RbacEnforcer,
     pub global_configuration: GlobalConfiguration,
 }
 
@@ -49,9 +76,12 @@ impl Context {
             pool: get_database_connection_pool_mock(),
             uploadables: None,
             user: User::AnonymousUser(AnonymousUser::new()),
+            session_device: SessionDevice::default(),
+            rbac_enforcer: RbacEnforcer::new(get_database_connection_pool_mock()),
             global_configuration: GlobalConfiguration {
                 stripe_restricted_api_key: None,
                 stripe_webhook_secret: None,
+                ..Default::default()
             },
         }
     }
@@ -19,6 +19,10 @@ impl Query {
         crate::analytics::AnalyticsQuery {}
     }
 
+    fn archive() -> crate::archive::ArchiveQuery {
+        crate::archive::ArchiveQuery {}
+    }
+
     fn auth() -> crate::auth::api::AuthQuery {
         crate::auth::api::AuthQuery {}
     }
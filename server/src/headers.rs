@@ -1,6 +1,16 @@
 const BEARER_PREFIX: &str = "Bearer ";
+const API_KEY_PREFIX: &str = "ApiKey ";
 
-pub(crate) fn parse_authorization_header(header: &str) -> Result<String, String> {
+/// Credentials sent in the `Authorization` header.
+#[derive(Debug, PartialEq)]
+pub(crate) enum AuthorizationHeader {
+    /// Session token of a signed user (`Bearer XYZ`).
+    Bearer(String),
+    /// Machine API key of an integration or a device like POS kiosk (`ApiKey XYZ`).
+    ApiKey(String),
+}
+
+pub(crate) fn parse_authorization_header(header: &str) -> Result<AuthorizationHeader, String> {
     if header.is_empty() {
         return Err(String::from("empty authorization header"));
     }
@@ -10,11 +20,13 @@ pub(crate) fn parse_authorization_header(header: &str) -> Result<String, String>
         Err(_) => return Err(String::from("unable to parse the header string")),
     };
 
-    if !auth_header.starts_with(BEARER_PREFIX) {
-        return Err(String::from("only bearer token is supported"));
+    if let Some(session_token) = auth_header.strip_prefix(BEARER_PREFIX) {
+        Ok(AuthorizationHeader::Bearer(session_token.to_owned()))
+    } else if let Some(api_key) = auth_header.strip_prefix(API_KEY_PREFIX) {
+        Ok(AuthorizationHeader::ApiKey(api_key.to_owned()))
+    } else {
+        Err(String::from("only bearer token and API key are supported"))
     }
-
-    Ok(auth_header.trim_start_matches(BEARER_PREFIX).to_owned())
 }
 
 #[cfg(test)]
@@ -26,7 +38,15 @@ mod tests {
     fn parse_authorization_header_test_happy_path() {
         assert_eq!(
             parse_authorization_header("Bearer abcdefg").unwrap(),
-            "abcdefg"
+            AuthorizationHeader::Bearer(String::from("abcdefg"))
+        );
+    }
+
+    #[test]
+    fn parse_authorization_header_test_api_key() {
+        assert_eq!(
+            parse_authorization_header("ApiKey abcdefg").unwrap(),
+            AuthorizationHeader::ApiKey(String::from("abcdefg"))
         );
     }
 
@@ -34,7 +54,7 @@ mod tests {
     fn parse_authorization_header_test_invalid_scheme() {
         assert_eq!(
             parse_authorization_header("Basic abcdefg").unwrap_err(),
-            "only bearer token is supported"
+            "only bearer token and API key are supported"
         );
     }
 
@@ -54,7 +74,7 @@ mod tests {
         fn parse_authorization_header_proptest(token in "[a-zA-Z0-9-_.+]+") {
             println!("Bearer {}", token); // visible only with `cargo test -- --nocapture`
             let result = parse_authorization_header(format!("Bearer {}", token).as_str()).unwrap();
-            prop_assert_eq!(result, token)
+            prop_assert_eq!(result, AuthorizationHeader::Bearer(token))
         }
     }
 }
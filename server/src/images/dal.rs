@@ -0,0 +1,168 @@
+use crate::arango::client::ClientExt;
+use crate::arango::{resolve_aql, resolve_aql_vector, ConnectionPool};
+use crate::images::ImageVariant;
+use serde::{Deserialize, Serialize};
+
+/// Record of an image (and its variants) stored in the image storage. Images are stored under
+/// their content hash, so uploading the same image again reuses the already stored objects.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(in crate::images) struct StoredImage {
+    /// SHA-256 of the originally uploaded file (before sanitization).
+    #[serde(rename = "_key")]
+    pub(in crate::images) content_hash: String,
+    pub(in crate::images) name_s3: String,
+    pub(in crate::images) blurhash: String,
+    pub(in crate::images) variants: Vec<ImageVariant>,
+}
+
+/// Returns the already stored image with the same content hash (if any). The image is marked as
+/// recently used so it's not removed by the garbage collector before being assigned to a product.
+pub(in crate::images) async fn find_stored_image<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    content_hash: &str,
+) -> anyhow::Result<Option<StoredImage>> {
+    let stored_images = resolve_aql_vector(
+        pool,
+        r#"
+            FOR image IN images
+              FILTER image._key == @content_hash
+              UPDATE image WITH { used: DATE_ISO8601(DATE_NOW()) } IN images
+              RETURN NEW
+        "#,
+        hashmap_json![
+            "content_hash" => content_hash,
+        ],
+    )
+    .await?;
+
+    Ok(stored_images.into_iter().next())
+}
+
+pub(in crate::images) async fn create_stored_image<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    stored_image: &StoredImage,
+) -> anyhow::Result<StoredImage> {
+    resolve_aql(
+        pool,
+        r#"
+            UPSERT { _key: @stored_image._key }
+            INSERT MERGE(@stored_image, {
+              created: DATE_ISO8601(DATE_NOW()),
+              used: DATE_ISO8601(DATE_NOW()),
+            })
+            UPDATE { used: DATE_ISO8601(DATE_NOW()) }
+            IN images
+            RETURN NEW
+        "#,
+        hashmap_json![
+            "stored_image" => stored_image,
+        ],
+    )
+    .await
+}
+
+/// Removes records of the images which are not referenced by any product (or archived product)
+/// and which were not used for the specified grace period (so we don't remove images which were
+/// just uploaded, but the product wasn't saved yet). Returns the removed records so the images can
+/// be deleted from the image storage.
+pub(in crate::images) async fn remove_orphaned_stored_images<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    grace_period_seconds: u64,
+) -> anyhow::Result<Vec<StoredImage>> {
+    resolve_aql_vector(
+        pool,
+        r#"
+            LET referenced_hashes = UNION_DISTINCT(
+              FOR product IN products
+                FOR image IN product.images
+                  FILTER image.content_hash != null
+                  RETURN image.content_hash,
+              FOR item IN archive
+                FILTER item.original_collection_name == "products"
+                FOR image IN JSON_PARSE(item.original_payload).images
+                  FILTER image.content_hash != null
+                  RETURN image.content_hash
+            )
+
+            FOR image IN images
+              FILTER image.used < DATE_ISO8601(DATE_SUBTRACT(DATE_NOW(), @grace_period_seconds, "seconds"))
+              FILTER image._key NOT IN referenced_hashes
+              REMOVE image IN images
+              RETURN OLD
+        "#,
+        hashmap_json![
+            "grace_period_seconds" => grace_period_seconds,
+        ],
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arango::client::fake::FakeClient;
+    use crate::arango::get_database_connection_pool_fake;
+    use serde_json::json;
+
+    fn stored_image_json() -> serde_json::Value {
+        json!({
+            "_id": "images/abc",
+            "_rev": "_fake_rev",
+            "_key": "abc",
+            "name_s3": "abc.png",
+            "blurhash": "LEHV6nWB2yk8pyo0adR*.7kCMdnj",
+            "variants": [{
+                "size": "THUMBNAIL",
+                "name_s3": "abc-thumbnail.png",
+                "width": 160,
+                "height": 90,
+            }],
+            "created": "2022-11-01T00:00:00.000Z",
+            "used": "2022-11-01T00:00:00.000Z",
+        })
+    }
+
+    #[tokio::test]
+    async fn find_stored_image_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([stored_image_json()]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let stored_image = find_stored_image(&pool, "abc").await.unwrap().unwrap();
+        assert_eq!(stored_image.content_hash, "abc");
+        assert_eq!(stored_image.name_s3, "abc.png");
+        assert_eq!(stored_image.variants.len(), 1);
+
+        let requests = client.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].body["bindVars"],
+            json!({ "content_hash": "abc" })
+        );
+    }
+
+    #[tokio::test]
+    async fn find_stored_image_missing_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        assert!(find_stored_image(&pool, "abc").await.unwrap().is_none());
+        assert!(client.is_exhausted());
+    }
+
+    #[tokio::test]
+    async fn remove_orphaned_stored_images_test() {
+        let client = FakeClient::default();
+        client.respond_cursor(json!([stored_image_json()]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let removed = remove_orphaned_stored_images(&pool, 86400).await.unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].name_s3, "abc.png");
+        assert_eq!(
+            client.requests()[0].body["bindVars"],
+            json!({ "grace_period_seconds": 86400 })
+        );
+    }
+}
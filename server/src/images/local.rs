@@ -0,0 +1,152 @@
+use crate::graphql_context::ContextUploadableContentType;
+use crate::images::storage::ImageStorage;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Images stored in a local directory so the whole product flow can run without AWS credentials.
+/// The images are served by this server (see `/images/:name` route).
+pub(crate) struct LocalImageStorage {
+    directory: PathBuf,
+    public_base_url: String,
+}
+
+impl LocalImageStorage {
+    pub(crate) fn new(directory: impl Into<PathBuf>, public_base_url: &str) -> Self {
+        LocalImageStorage {
+            directory: directory.into(),
+            public_base_url: public_base_url.to_string(),
+        }
+    }
+
+    /// Returns path of the image or `None` for image names which could escape the directory.
+    fn image_path(&self, name: &str) -> Option<PathBuf> {
+        let is_valid_name = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || matches!(char, '.' | '-' | '_'));
+
+        if is_valid_name {
+            Some(self.directory.join(name))
+        } else {
+            None
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ImageStorage for LocalImageStorage {
+    async fn upload(
+        &self,
+        name: &str,
+        data: &[u8],
+        _content_type: &ContextUploadableContentType,
+    ) -> anyhow::Result<()> {
+        let path = match self.image_path(name) {
+            Some(path) => path,
+            None => anyhow::bail!("invalid image name '{}'", name),
+        };
+
+        tokio::fs::create_dir_all(&self.directory).await?;
+        tokio::fs::write(&path, data).await?;
+        tracing::debug!("Successfully stored image '{}'", path.display());
+        Ok(())
+    }
+
+    async fn delete(&self, name: &str) -> anyhow::Result<()> {
+        let path = match self.image_path(name) {
+            Some(path) => path,
+            None => anyhow::bail!("invalid image name '{}'", name),
+        };
+
+        match tokio::fs::remove_file(&path).await {
+            Ok(_) => Ok(()),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+            Err(error) => anyhow::bail!(error),
+        }
+    }
+
+    async fn read(&self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let path = match self.image_path(name) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        match tokio::fs::read(&path).await {
+            Ok(data) => Ok(Some(data)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => anyhow::bail!(error),
+        }
+    }
+
+    async fn exists(&self, name: &str) -> anyhow::Result<bool> {
+        match self.image_path(name) {
+            Some(path) => Ok(tokio::fs::try_exists(&path).await?),
+            None => Ok(false),
+        }
+    }
+
+    fn public_base_url(&self) -> &str {
+        &self.public_base_url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_storage() -> LocalImageStorage {
+        LocalImageStorage::new(
+            std::env::temp_dir().join(format!("abacus-images-{}", uuid::Uuid::new_v4())),
+            "http://localhost:5000/images",
+        )
+    }
+
+    #[tokio::test]
+    async fn upload_read_delete_test() {
+        let storage = temporary_storage();
+        let data = std::fs::read("src/images/tests/data/img.png").unwrap();
+
+        storage
+            .upload("image.png", &data, &ContextUploadableContentType::ImagePng)
+            .await
+            .unwrap();
+        assert_eq!(storage.read("image.png").await.unwrap(), Some(data));
+        assert!(storage.exists("image.png").await.unwrap());
+
+        storage.delete("image.png").await.unwrap();
+        assert_eq!(storage.read("image.png").await.unwrap(), None);
+        assert!(!storage.exists("image.png").await.unwrap());
+
+        // deleting already deleted image is OK
+        storage.delete("image.png").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn invalid_image_names_test() {
+        let storage = temporary_storage();
+        for name in [
+            "",
+            "../image.png",
+            ".image.png",
+            "dir/image.png",
+            "image.png\0",
+        ] {
+            assert_eq!(storage.read(name).await.unwrap(), None);
+            assert!(!storage.exists(name).await.unwrap());
+            assert!(storage
+                .upload(name, b"", &ContextUploadableContentType::ImagePng)
+                .await
+                .is_err());
+            assert!(storage.delete(name).await.is_err());
+        }
+    }
+
+    #[test]
+    fn public_url_test() {
+        assert_eq!(
+            temporary_storage().public_url("image.png"),
+            "http://localhost:5000/images/image.png"
+        );
+    }
+}
@@ -1,33 +1,70 @@
 /// This module is responsible for processing images:
 ///
 /// Uploading:
-///  1) generate blurhashes and save all the info into database
-///  2) strip EXIF images metadata (TODO)
-///  3) upload images to S3
+///  1) validate the images (file size, dimensions and count per product, see `ImageLimitsConfiguration`)
+///  2) rotate the images according to the EXIF orientation and strip all the metadata
+///  3) generate blurhashes and save all the info into database
+///  4) generate resized variants (thumbnail, card, full), optionally re-encoded to WebP
+///  5) upload images to the image storage (S3 or local directory, see `ImageStorage`)
 ///
+/// Images are stored under their content hash, so uploading the same image again (even for
+/// a different product) reuses the already stored objects (see `dal::StoredImage`). Stored images
+/// which are not referenced by any product or archived product are eventually deleted by the
+/// garbage collector (see `collect_garbage`).
+///
+use crate::arango::ConnectionPool;
 use crate::auth::rbac;
 use crate::auth::rbac::Actions::Files;
 use crate::auth::rbac::FilesActions::{DeleteFile, UploadFile};
 use crate::commerce::api::ProductMultilingualInput;
+use crate::global_configuration::{ImageLimitsConfiguration, ImageVariantsConfiguration};
 use crate::graphql_context::{Context, ContextUploadable, ContextUploadableContentType};
+use crate::images::dal::StoredImage;
+use crate::images::variants::EncodedImageVariant;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+pub(crate) use crate::images::local::LocalImageStorage;
+pub(crate) use crate::images::s3::S3ImageStorage;
+pub(crate) use crate::images::storage::ImageStorage;
+pub(crate) use crate::images::variants::ImageSize;
+
 mod blurhash;
-mod cloudfront;
+mod dal;
+mod local;
 mod s3;
+mod sanitize;
+mod storage;
+mod variants;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub(crate) struct Image {
-    /// UUID of the image stored in S3.
+    /// Name of the image stored in the image storage (S3 originally, hence the name).
     name_s3: String,
+    /// SHA-256 of the uploaded file (images uploaded before the deduplication was introduced
+    /// don't have it).
+    #[serde(default)]
+    content_hash: Option<String>,
     /// Original image name to be displayed on FE (not important for backend).
     name_original: String,
     /// See: https://blurha.sh/
     blurhash: String,
+    /// Resized variants of the image (images uploaded before the variants were introduced don't
+    /// have any, and we fall back to the original image).
+    #[serde(default)]
+    variants: Vec<ImageVariant>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct ImageVariant {
+    size: ImageSize,
+    /// Name of the variant stored in the image storage.
+    name_s3: String,
+    width: u32,
+    height: u32,
 }
 
-#[juniper::graphql_object]
+#[juniper::graphql_object(context = Context)]
 impl Image {
     pub(crate) fn name(&self) -> String {
         self.name_original.to_owned()
@@ -37,8 +74,38 @@ impl Image {
         self.blurhash.to_owned()
     }
 
-    fn url(&self) -> String {
-        cloudfront::resolve_cloudfront_url(self.name_s3.as_ref())
+    /// URL of the image variant with the given size (or the original image when the size is not
+    /// specified or the variant doesn't exist).
+    fn url(&self, context: &Context, size: Option<ImageSize>) -> String {
+        let name = size
+            .and_then(|size| self.variant(&size))
+            .map_or(&self.name_s3, |variant| &variant.name_s3);
+        context.global_configuration.image_storage.public_url(name)
+    }
+
+    /// Value for the `srcset` attribute of the `<img>` element (all the image variants with their
+    /// widths). It's just the original image URL when there are no variants.
+    fn srcset(&self, context: &Context) -> String {
+        let image_storage = &context.global_configuration.image_storage;
+        if self.variants.is_empty() {
+            return image_storage.public_url(&self.name_s3);
+        }
+
+        let mut variants = self.variants.iter().collect::<Vec<_>>();
+        variants.sort_by_key(|variant| variant.width);
+        // small images are not upscaled so more variants can have the same width:
+        variants.dedup_by_key(|variant| variant.width);
+        variants
+            .iter()
+            .map(|variant| {
+                format!(
+                    "{} {}w",
+                    image_storage.public_url(&variant.name_s3),
+                    variant.width
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
     }
 }
 
@@ -46,6 +113,20 @@ impl Image {
     fn s3name(&self) -> String {
         self.name_s3.to_owned()
     }
+
+    fn variant(&self, size: &ImageSize) -> Option<&ImageVariant> {
+        self.variants.iter().find(|variant| &variant.size == size)
+    }
+
+    fn from_stored_image(stored_image: StoredImage, name_original: &str) -> Self {
+        Image {
+            name_s3: stored_image.name_s3,
+            content_hash: Some(stored_image.content_hash),
+            name_original: name_original.to_string(),
+            blurhash: stored_image.blurhash,
+            variants: stored_image.variants,
+        }
+    }
 }
 
 /// Checks whether GraphQL images input corresponds with the uploadables (GraphQL input must match
@@ -71,6 +152,26 @@ fn validate_images_input(
     Ok(())
 }
 
+/// Checks whether the product doesn't have more images than allowed (this includes the already
+/// uploaded images when updating the product).
+fn validate_images_count(
+    context: &Context,
+    product_multilingual_input: &ProductMultilingualInput,
+) -> anyhow::Result<()> {
+    let max_images_per_product = context
+        .global_configuration
+        .image_limits
+        .max_images_per_product;
+    if product_multilingual_input.images.len() > max_images_per_product {
+        anyhow::bail!(
+            "product can have at most {} images ({} given)",
+            max_images_per_product,
+            product_multilingual_input.images.len(),
+        )
+    }
+    Ok(())
+}
+
 /// Checks whether `multipart/form-data` payload (uploadables) matches the GraphQL input. This
 /// validation should be called ALWAYS: every uploadable should have corresponding input.
 fn validate_uploadables(
@@ -112,10 +213,13 @@ pub(crate) async fn process_new_images(
     // Second, we check it the other way around - whether all uploadables are specified in the input:
     validate_uploadables(context, product_multilingual_input)?;
 
-    rbac::verify_permissions(&context.user, &Files(UploadFile)).await?;
+    // Third, we make sure the product doesn't have too many images:
+    validate_images_count(context, product_multilingual_input)?;
+
+    rbac::verify_permissions(&context.rbac_enforcer, &context.user, &Files(UploadFile)).await?;
 
     if let Some(uploadables) = &context.uploadables {
-        let images = process_new_images_authorized(uploadables).await?;
+        let images = process_new_images_authorized(context, uploadables).await?;
         Ok(images)
     } else {
         anyhow::bail!("there are no images to process")
@@ -134,60 +238,308 @@ pub(crate) async fn process_updated_images(
     product_multilingual_input: &ProductMultilingualInput,
 ) -> anyhow::Result<Vec<Image>> {
     validate_uploadables(context, product_multilingual_input)?;
-    rbac::verify_permissions(&context.user, &Files(UploadFile)).await?;
+    validate_images_count(context, product_multilingual_input)?;
+    rbac::verify_permissions(&context.rbac_enforcer, &context.user, &Files(UploadFile)).await?;
 
     if let Some(uploadables) = &context.uploadables {
-        let images = process_new_images_authorized(uploadables).await?;
+        let images = process_new_images_authorized(context, uploadables).await?;
         Ok(images)
     } else {
         anyhow::bail!("there are no images to process")
     }
 }
 
+fn file_extension(content_type: &ContextUploadableContentType) -> &'static str {
+    match content_type {
+        ContextUploadableContentType::ImagePng => "png",
+        ContextUploadableContentType::ImageJpeg => "jpeg",
+        ContextUploadableContentType::ImageWebp => "webp",
+    }
+}
+
+/// SHA-256 of the uploaded file (hex encoded).
+fn content_hash(data: &[u8]) -> String {
+    let hash = ring::digest::digest(&ring::digest::SHA256, data);
+    data_encoding::HEXLOWER.encode(hash.as_ref())
+}
+
+fn storage_name(content_hash: &str, content_type: &ContextUploadableContentType) -> String {
+    format!("{}.{}", content_hash, file_extension(content_type))
+}
+
+fn variant_storage_name(
+    content_hash: &str,
+    size: &ImageSize,
+    content_type: &ContextUploadableContentType,
+) -> String {
+    format!(
+        "{}-{}.{}",
+        content_hash,
+        size.name(),
+        file_extension(content_type)
+    )
+}
+
+/// Image which passed the validation and is ready to be stored.
+struct ProcessedImage {
+    /// The original image without metadata (see `sanitize::sanitize_image`).
+    data: Vec<u8>,
+    blurhash: String,
+    variants: Vec<EncodedImageVariant>,
+}
+
+/// Sanitizes the image, calculates Blurhash and generates the image variants. Decoding, resizing
+/// and encoding of the images is CPU heavy, so it's being done on a blocking thread.
+async fn process_image(
+    data: Vec<u8>,
+    content_type: ContextUploadableContentType,
+    limits: ImageLimitsConfiguration,
+    configuration: ImageVariantsConfiguration,
+) -> anyhow::Result<ProcessedImage> {
+    tokio::task::spawn_blocking(move || {
+        let sanitized = sanitize::sanitize_image(&data, &content_type, &limits)?;
+        let variants =
+            variants::generate_image_variants(&sanitized.image, &content_type, &configuration)?;
+        let blurhash = blurhash::calculate_image_blurhash(sanitized.image)
+            .map_err(|error| anyhow::anyhow!("cannot calculate image blurhash: {:?}", error))?;
+        Ok(ProcessedImage {
+            data: sanitized.data,
+            blurhash,
+            variants,
+        })
+    })
+    .await?
+}
+
+/// Uploaded image which is either stored already (uploaded earlier) or which has to be stored.
+enum UploadedImage {
+    Stored(StoredImage),
+    Processed {
+        content_hash: String,
+        content_type: ContextUploadableContentType,
+        processed_image: ProcessedImage,
+    },
+}
+
 async fn process_new_images_authorized(
+    context: &Context,
     uploadables: &HashMap<String, ContextUploadable>,
 ) -> anyhow::Result<Vec<Image>> {
-    let mut processed_images = vec![];
+    let global_configuration = &context.global_configuration;
+
+    // All the images are processed (validated) first so an invalid file doesn't leave the other
+    // images half-uploaded in the image storage:
+    let mut uploaded_images = vec![];
     for (filename, uploadable) in uploadables.iter() {
-        // First, we try to upload the image to S3:
-        match s3::upload_image(&uploadable.data(), &uploadable.content_type()).await {
-            Ok(s3_image) => {
-                // IF everything OK, we try to calculate Blurhash and return it:
-                let image_result = image::load_from_memory_with_format(
-                    &uploadable.data(),
-                    match &uploadable.content_type() {
-                        ContextUploadableContentType::ImagePng => image::ImageFormat::Png,
-                        ContextUploadableContentType::ImageJpeg => image::ImageFormat::Jpeg,
-                    },
-                );
-
-                match image_result {
-                    Ok(image) => {
-                        processed_images.push(Image {
-                            name_s3: s3_image.s3_filename,
-                            name_original: filename.to_string(),
-                            // TODO: do not unwrap the blurhash:
-                            blurhash: blurhash::calculate_image_blurhash(image).unwrap(),
-                        });
-                    }
-                    Err(error) => {
-                        tracing::error!("cannot load image from memory: {}", error);
-                        anyhow::bail!("cannot load image from memory");
-                    }
-                }
-            }
-            Err(s3_error) => anyhow::bail!(s3_error.message),
+        let content_hash = content_hash(&uploadable.data());
+        if let Some(stored_image) = dal::find_stored_image(&context.pool, &content_hash).await? {
+            uploaded_images.push((filename, UploadedImage::Stored(stored_image)));
+            continue;
         }
+
+        let processed_image = process_image(
+            uploadable.data(),
+            uploadable.content_type(),
+            global_configuration.image_limits.to_owned(),
+            global_configuration.image_variants.to_owned(),
+        )
+        .await
+        .map_err(|error| anyhow::anyhow!("invalid image '{}': {}", filename, error))?;
+        uploaded_images.push((
+            filename,
+            UploadedImage::Processed {
+                content_hash,
+                content_type: uploadable.content_type(),
+                processed_image,
+            },
+        ));
+    }
+
+    let mut images = vec![];
+    for (filename, uploaded_image) in uploaded_images {
+        let stored_image = match uploaded_image {
+            UploadedImage::Stored(stored_image) => stored_image,
+            UploadedImage::Processed {
+                content_hash,
+                content_type,
+                processed_image,
+            } => store_image(context, content_hash, &content_type, processed_image).await?,
+        };
+        images.push(Image::from_stored_image(stored_image, filename));
+    }
+    Ok(images)
+}
+
+/// Uploads the image (and its variants) to the image storage and creates its record in the
+/// database.
+async fn store_image(
+    context: &Context,
+    content_hash: String,
+    content_type: &ContextUploadableContentType,
+    processed_image: ProcessedImage,
+) -> anyhow::Result<StoredImage> {
+    let image_storage = &context.global_configuration.image_storage;
+
+    let name_s3 = storage_name(&content_hash, content_type);
+    image_storage
+        .upload(&name_s3, &processed_image.data, content_type)
+        .await?;
+
+    let mut variants = vec![];
+    for encoded_variant in processed_image.variants {
+        let variant_name_s3 = variant_storage_name(
+            &content_hash,
+            &encoded_variant.size,
+            &encoded_variant.content_type,
+        );
+        image_storage
+            .upload(
+                &variant_name_s3,
+                &encoded_variant.data,
+                &encoded_variant.content_type,
+            )
+            .await?;
+        variants.push(ImageVariant {
+            size: encoded_variant.size,
+            name_s3: variant_name_s3,
+            width: encoded_variant.width,
+            height: encoded_variant.height,
+        });
     }
-    Ok(processed_images)
+
+    dal::create_stored_image(
+        &context.pool,
+        &StoredImage {
+            content_hash,
+            name_s3,
+            blurhash: processed_image.blurhash,
+            variants,
+        },
+    )
+    .await
 }
 
-/// Only admin can delete images.
+/// Only admin can delete images. Images with a content hash can be shared by more products (and
+/// they are referenced by the archived products as well), so they are not deleted immediately but
+/// later by the garbage collector (see `collect_garbage`). Older images are deleted right away.
 pub(crate) async fn delete_image(context: &Context, image: &Image) -> anyhow::Result<Image> {
-    rbac::verify_permissions(&context.user, &Files(DeleteFile)).await?;
+    rbac::verify_permissions(&context.rbac_enforcer, &context.user, &Files(DeleteFile)).await?;
+
+    if image.content_hash.is_some() {
+        return Ok(image.clone());
+    }
+
+    context
+        .global_configuration
+        .image_storage
+        .delete(&image.s3name())
+        .await?;
+    for variant in &image.variants {
+        context
+            .global_configuration
+            .image_storage
+            .delete(&variant.name_s3)
+            .await?;
+    }
+    Ok(image.clone())
+}
+
+/// Returns only the images (and their variants) which are still stored. It's used when restoring
+/// archived products: images of products archived before the archive kept them were deleted, and
+/// the unreferenced images could have been deleted by the garbage collector.
+pub(crate) async fn reconcile_images(
+    context: &Context,
+    images: Vec<Image>,
+) -> anyhow::Result<Vec<Image>> {
+    let image_storage = &context.global_configuration.image_storage;
+    let mut reconciled_images = vec![];
+    for mut image in images {
+        if let Some(content_hash) = &image.content_hash {
+            // marks the image as used so it's not removed by the garbage collector meanwhile
+            dal::find_stored_image(&context.pool, content_hash).await?;
+        }
+
+        if !image_storage.exists(&image.name_s3).await? {
+            tracing::warn!("Image '{}' doesn't exist anymore (skipping)", image.name_s3);
+            continue;
+        }
+
+        let mut variants = vec![];
+        for variant in image.variants {
+            if image_storage.exists(&variant.name_s3).await? {
+                variants.push(variant);
+            }
+        }
+        image.variants = variants;
+        reconciled_images.push(image);
+    }
+    Ok(reconciled_images)
+}
+
+/// Unreferenced images are kept for a while so we don't delete images which were just uploaded
+/// but the product wasn't saved yet.
+const GARBAGE_COLLECTION_GRACE_PERIOD_SECONDS: u64 = 24 * 60 * 60;
+
+/// Deletes the stored images which are not referenced by any product nor archived product anymore.
+/// Returns names of the deleted objects.
+pub(crate) async fn collect_garbage(
+    pool: &ConnectionPool,
+    image_storage: &dyn ImageStorage,
+) -> anyhow::Result<Vec<String>> {
+    let orphaned_images =
+        dal::remove_orphaned_stored_images(pool, GARBAGE_COLLECTION_GRACE_PERIOD_SECONDS).await?;
+
+    let mut deleted_names = vec![];
+    for orphaned_image in orphaned_images {
+        let names = std::iter::once(orphaned_image.name_s3)
+            .chain(orphaned_image.variants.into_iter().map(|v| v.name_s3));
+        for name in names {
+            // The database record is gone already, so we just log the failures (the object
+            // leaks in the storage but no product is broken):
+            match image_storage.delete(&name).await {
+                Ok(_) => deleted_names.push(name),
+                Err(error) => tracing::error!("Unable to delete image '{}': {}", name, error),
+            }
+        }
+    }
+    Ok(deleted_names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_names_test() {
+        let content_hash = content_hash(b"");
+        assert_eq!(
+            content_hash,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            storage_name(&content_hash, &ContextUploadableContentType::ImageJpeg),
+            format!("{}.jpeg", content_hash)
+        );
+        assert_eq!(
+            variant_storage_name(
+                &content_hash,
+                &ImageSize::Thumbnail,
+                &ContextUploadableContentType::ImageWebp
+            ),
+            format!("{}-thumbnail.webp", content_hash)
+        );
+    }
 
-    match s3::delete_image(&image.s3name()).await {
-        Ok(_) => Ok(image.clone()),
-        Err(s3_error) => anyhow::bail!(s3_error.message),
+    #[test]
+    fn image_without_content_hash_test() {
+        // images stored before the deduplication (and variants) were introduced
+        let image: Image = serde_json::from_value(serde_json::json!({
+            "name_s3": "8ae3cc2c-3a9e-4a2d-8b0b-6ad3d1ee0d8b.png",
+            "name_original": "img.png",
+            "blurhash": "LEHV6nWB2yk8pyo0adR*.7kCMdnj",
+        }))
+        .unwrap();
+        assert!(image.content_hash.is_none());
+        assert!(image.variants.is_empty());
     }
 }
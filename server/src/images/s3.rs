@@ -1,85 +1,161 @@
 use crate::graphql_context::ContextUploadableContentType;
-use rusoto_core::Region;
-use rusoto_s3::{DeleteObjectRequest, PutObjectRequest, S3Client, S3};
+use crate::images::storage::ImageStorage;
+use rusoto_core::{Region, RusotoError};
+use rusoto_s3::{
+    DeleteObjectRequest, HeadObjectError, HeadObjectRequest, PutObjectRequest, S3Client, S3,
+};
+use std::str::FromStr;
 
-const BUCKED_NAME: &str = "abacus-images-58276402-e657-493a-b0b9-d8c278f3e01d";
+/// Images stored in AWS S3 bucket (or any other S3-compatible storage such as MinIO when the
+/// custom endpoint is specified). The images are publicly accessible directly from the bucket (or
+/// from the CDN in front of it) so they are never served by this server.
+pub(crate) struct S3ImageStorage {
+    s3_client: S3Client,
+    bucket: String,
+    public_base_url: String,
+}
+
+impl S3ImageStorage {
+    /// When the public base URL is not specified, the images are served directly from the bucket.
+    pub(crate) fn new(
+        bucket: &str,
+        region: &str,
+        endpoint: Option<&str>,
+        public_base_url: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let region = match endpoint {
+            Some(endpoint) => Region::Custom {
+                name: region.to_string(),
+                endpoint: endpoint.trim_end_matches('/').to_string(),
+            },
+            None => Region::from_str(region)?,
+        };
+
+        let public_base_url = match public_base_url {
+            Some(public_base_url) => public_base_url.to_string(),
+            None => default_public_base_url(&region, bucket),
+        };
 
-pub(in crate::images) struct S3Object {
-    pub(crate) s3_filename: String,
+        Ok(S3ImageStorage {
+            s3_client: S3Client::new(region),
+            bucket: bucket.to_string(),
+            public_base_url,
+        })
+    }
 }
 
-pub(in crate::images) struct S3Error {
-    pub(crate) message: String,
+fn default_public_base_url(region: &Region, bucket: &str) -> String {
+    match region {
+        Region::Custom { endpoint, .. } => format!("{}/{}", endpoint, bucket),
+        region => format!("https://{}.s3.{}.amazonaws.com", bucket, region.name()),
+    }
 }
 
-/// Tries to upload an image to S3 and returns `S3Object` with the newly created `s3_filename` and
-/// original image bytes vector (or error if it fails).
-///
-/// See: https://docs.aws.amazon.com/AmazonS3/latest/API/API_PutObject.html
-pub(in crate::images) async fn upload_image(
-    image: &Vec<u8>,
-    content_type: &ContextUploadableContentType,
-) -> Result<S3Object, S3Error> {
-    let s3_client = S3Client::new(Region::UsWest1);
-    let s3_filename_extension = match content_type {
-        // TODO: DRY with the part of the code which converts it the other way around
-        ContextUploadableContentType::ImagePng => "png",
-        ContextUploadableContentType::ImageJpeg => "jpeg",
-    };
-    let s3_filename = format!("{}.{}", uuid::Uuid::new_v4(), s3_filename_extension);
-    match s3_client
-        .put_object(PutObjectRequest {
-            // The uploaded images must be publicly accessible (https://docs.aws.amazon.com/AmazonS3/latest/userguide/acl-overview.html#canned-acl):
-            acl: Some(String::from("public-read")),
-            bucket: String::from(BUCKED_NAME),
-            key: s3_filename.to_string(),
-            body: Some(image.clone().into()),
-            content_type: Some(match content_type {
-                // TODO: DRY with the part of the code which converts it the other way around
-                ContextUploadableContentType::ImagePng => "image/png".to_string(),
-                ContextUploadableContentType::ImageJpeg => "image/jpeg".to_string(),
-            }),
-            ..PutObjectRequest::default()
-        })
-        .await
-    {
-        Ok(_) => {
-            tracing::debug!("Successfully uploaded image '{}' to S3", s3_filename);
-            Ok(S3Object {
-                s3_filename: s3_filename.to_string(),
-            })
-        }
-        Err(error) => {
-            tracing::error!("Unable to upload image '{}' from S3", s3_filename);
-            Err(S3Error {
-                message: format!("{}", error),
+#[async_trait::async_trait]
+impl ImageStorage for S3ImageStorage {
+    /// See: https://docs.aws.amazon.com/AmazonS3/latest/API/API_PutObject.html
+    async fn upload(
+        &self,
+        name: &str,
+        data: &[u8],
+        content_type: &ContextUploadableContentType,
+    ) -> anyhow::Result<()> {
+        match self
+            .s3_client
+            .put_object(PutObjectRequest {
+                // The uploaded images must be publicly accessible (https://docs.aws.amazon.com/AmazonS3/latest/userguide/acl-overview.html#canned-acl):
+                acl: Some(String::from("public-read")),
+                bucket: self.bucket.to_owned(),
+                key: name.to_string(),
+                body: Some(data.to_vec().into()),
+                content_type: Some(content_type.mime_type().to_string()),
+                ..PutObjectRequest::default()
             })
+            .await
+        {
+            Ok(_) => {
+                tracing::debug!("Successfully uploaded image '{}' to S3", name);
+                Ok(())
+            }
+            Err(error) => {
+                tracing::error!("Unable to upload image '{}' to S3", name);
+                anyhow::bail!("{}", error)
+            }
         }
     }
-}
 
-/// See: https://docs.aws.amazon.com/AmazonS3/latest/API/API_DeleteObject.html
-pub(in crate::images) async fn delete_image(s3_filename: &str) -> Result<S3Object, S3Error> {
-    let s3_client = S3Client::new(Region::UsWest1);
-    match s3_client
-        .delete_object(DeleteObjectRequest {
-            bucket: String::from(BUCKED_NAME),
-            key: s3_filename.to_string(),
-            ..DeleteObjectRequest::default()
-        })
-        .await
-    {
-        Ok(_) => {
-            tracing::debug!("Successfully deleted image '{}' from S3", s3_filename);
-            Ok(S3Object {
-                s3_filename: s3_filename.to_owned(),
+    /// See: https://docs.aws.amazon.com/AmazonS3/latest/API/API_DeleteObject.html
+    async fn delete(&self, name: &str) -> anyhow::Result<()> {
+        match self
+            .s3_client
+            .delete_object(DeleteObjectRequest {
+                bucket: self.bucket.to_owned(),
+                key: name.to_string(),
+                ..DeleteObjectRequest::default()
             })
+            .await
+        {
+            Ok(_) => {
+                tracing::debug!("Successfully deleted image '{}' from S3", name);
+                Ok(())
+            }
+            Err(error) => {
+                tracing::error!("Unable to delete image '{}' from S3", name);
+                anyhow::bail!("{}", error)
+            }
         }
-        Err(error) => {
-            tracing::error!("Unable to delete image '{}' from S3", s3_filename);
-            Err(S3Error {
-                message: format!("{}", error),
+    }
+
+    async fn read(&self, _name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(None) // served directly from S3 (or CDN)
+    }
+
+    /// See: https://docs.aws.amazon.com/AmazonS3/latest/API/API_HeadObject.html
+    async fn exists(&self, name: &str) -> anyhow::Result<bool> {
+        match self
+            .s3_client
+            .head_object(HeadObjectRequest {
+                bucket: self.bucket.to_owned(),
+                key: name.to_string(),
+                ..HeadObjectRequest::default()
             })
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => Ok(false),
+            // HEAD responses have no body so the missing objects are usually not recognized:
+            Err(RusotoError::Unknown(response)) if response.status.as_u16() == 404 => Ok(false),
+            Err(error) => {
+                tracing::error!("Unable to check image '{}' in S3", name);
+                anyhow::bail!("{}", error)
+            }
         }
     }
+
+    fn public_base_url(&self) -> &str {
+        &self.public_base_url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_public_base_url_test() {
+        assert_eq!(
+            default_public_base_url(&Region::UsWest1, "abacus-images"),
+            "https://abacus-images.s3.us-west-1.amazonaws.com"
+        );
+        assert_eq!(
+            default_public_base_url(
+                &Region::Custom {
+                    name: String::from("us-east-1"),
+                    endpoint: String::from("http://localhost:9000"),
+                },
+                "abacus-images"
+            ),
+            "http://localhost:9000/abacus-images"
+        );
+    }
 }
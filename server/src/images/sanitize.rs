@@ -0,0 +1,165 @@
+use crate::global_configuration::ImageLimitsConfiguration;
+use crate::graphql_context::ContextUploadableContentType;
+use crate::images::variants::{encode_image, image_format};
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use std::io::Cursor;
+
+/// Uploaded image without any metadata (EXIF, XMP, PNG text chunks, …) which is safe to be stored
+/// and publicly served.
+pub(in crate::images) struct SanitizedImage {
+    pub(in crate::images) image: DynamicImage,
+    pub(in crate::images) data: Vec<u8>,
+}
+
+/// Decodes the uploaded image, rotates it according to the EXIF orientation and re-encodes it
+/// without any metadata. Photos taken by phones often contain GPS coordinates which must never
+/// leave the server. The image is rejected when it exceeds the configured limits (the file size
+/// and dimensions are checked before decoding the whole image).
+///
+/// It's CPU heavy so it should not be called directly from async code (see `spawn_blocking`).
+pub(in crate::images) fn sanitize_image(
+    data: &[u8],
+    content_type: &ContextUploadableContentType,
+    limits: &ImageLimitsConfiguration,
+) -> anyhow::Result<SanitizedImage> {
+    if data.len() > limits.max_file_size {
+        anyhow::bail!(
+            "image is too large ({} bytes, maximum is {} bytes)",
+            data.len(),
+            limits.max_file_size
+        );
+    }
+
+    let mut decoder = ImageReader::with_format(Cursor::new(data), image_format(content_type))
+        .into_decoder()
+        .map_err(|error| anyhow::anyhow!("cannot decode image: {}", error))?;
+
+    let (width, height) = decoder.dimensions();
+    if width > limits.max_dimension || height > limits.max_dimension {
+        anyhow::bail!(
+            "image dimensions are too large ({}x{} pixels, maximum is {} pixels per side)",
+            width,
+            height,
+            limits.max_dimension
+        );
+    }
+
+    let orientation = decoder
+        .orientation()
+        .map_err(|error| anyhow::anyhow!("cannot read image orientation: {}", error))?;
+    let mut image = DynamicImage::from_decoder(decoder)
+        .map_err(|error| anyhow::anyhow!("cannot decode image: {}", error))?;
+    image.apply_orientation(orientation);
+
+    // Re-encoding the decoded pixels drops all the metadata of the original file:
+    let data = encode_image(&image, content_type)?;
+    Ok(SanitizedImage { image, data })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageFormat;
+
+    fn limits() -> ImageLimitsConfiguration {
+        ImageLimitsConfiguration {
+            max_file_size: 1024 * 1024,
+            max_dimension: 1000,
+            max_images_per_product: 10,
+        }
+    }
+
+    /// Creates 4x2 JPEG image with EXIF metadata (orientation "rotate 90° CW" and GPS IFD pointer).
+    fn jpeg_with_exif() -> Vec<u8> {
+        let mut jpeg = Cursor::new(vec![]);
+        DynamicImage::new_rgb8(4, 2)
+            .write_to(&mut jpeg, ImageFormat::Jpeg)
+            .unwrap();
+        let jpeg = jpeg.into_inner();
+
+        #[rustfmt::skip]
+        let exif: &[u8] = &[
+            b'E', b'x', b'i', b'f', 0, 0,
+            b'M', b'M', 0, 42, 0, 0, 0, 8, // TIFF header (big endian, IFD at offset 8)
+            0, 2, // 2 IFD entries:
+            0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, // orientation = 6
+            0x88, 0x25, 0, 4, 0, 0, 0, 1, 0, 0, 0, 0, // GPS IFD pointer
+            0, 0, 0, 0, // no next IFD
+        ];
+
+        let mut result = jpeg[..2].to_vec(); // SOI marker
+        result.extend_from_slice(&[0xFF, 0xE1]); // APP1 marker
+        result.extend_from_slice(&((exif.len() + 2) as u16).to_be_bytes());
+        result.extend_from_slice(exif);
+        result.extend_from_slice(&jpeg[2..]);
+        result
+    }
+
+    #[test]
+    fn sanitize_image_strips_exif_test() {
+        let data = jpeg_with_exif();
+        assert!(data.windows(4).any(|window| window == b"Exif"));
+
+        let sanitized =
+            sanitize_image(&data, &ContextUploadableContentType::ImageJpeg, &limits()).unwrap();
+
+        // the image was auto-rotated (orientation 6) before stripping the metadata
+        assert_eq!((sanitized.image.width(), sanitized.image.height()), (2, 4));
+        assert!(!sanitized.data.windows(4).any(|window| window == b"Exif"));
+        assert_eq!(
+            image::guess_format(&sanitized.data).unwrap(),
+            ImageFormat::Jpeg
+        );
+    }
+
+    #[test]
+    fn sanitize_image_png_test() {
+        let data = std::fs::read("src/images/tests/data/img.png").unwrap();
+        let sanitized =
+            sanitize_image(&data, &ContextUploadableContentType::ImagePng, &limits()).unwrap();
+        assert_eq!(
+            image::guess_format(&sanitized.data).unwrap(),
+            ImageFormat::Png
+        );
+    }
+
+    #[test]
+    fn sanitize_image_file_size_limit_test() {
+        let data = jpeg_with_exif();
+        let limits = ImageLimitsConfiguration {
+            max_file_size: data.len() - 1,
+            ..limits()
+        };
+        assert!(
+            sanitize_image(&data, &ContextUploadableContentType::ImageJpeg, &limits)
+                .unwrap_err()
+                .to_string()
+                .starts_with("image is too large")
+        );
+    }
+
+    #[test]
+    fn sanitize_image_dimensions_limit_test() {
+        let data = jpeg_with_exif();
+        let limits = ImageLimitsConfiguration {
+            max_dimension: 3,
+            ..limits()
+        };
+        assert!(
+            sanitize_image(&data, &ContextUploadableContentType::ImageJpeg, &limits)
+                .unwrap_err()
+                .to_string()
+                .starts_with("image dimensions are too large")
+        );
+    }
+
+    #[test]
+    fn sanitize_image_invalid_data_test() {
+        assert!(sanitize_image(
+            b"\x89PNG\r\n\x1a\nnot really",
+            &ContextUploadableContentType::ImagePng,
+            &limits()
+        )
+        .is_err());
+    }
+}
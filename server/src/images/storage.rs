@@ -0,0 +1,61 @@
+use crate::graphql_context::ContextUploadableContentType;
+
+/// Storage of the uploaded images. Images are stored in S3 in production (see `S3ImageStorage`)
+/// and in a local directory during development (see `LocalImageStorage`).
+#[async_trait::async_trait]
+pub(crate) trait ImageStorage: Send + Sync {
+    /// Stores the image under the specified (unique) name.
+    async fn upload(
+        &self,
+        name: &str,
+        data: &[u8],
+        content_type: &ContextUploadableContentType,
+    ) -> anyhow::Result<()>;
+
+    /// Deletes the image. Deleting an image which doesn't exist is not an error.
+    async fn delete(&self, name: &str) -> anyhow::Result<()>;
+
+    /// Returns the image in case the images are being served by this server (see `/images/:name`
+    /// route). Returns `None` when the image doesn't exist or when the storage serves the images
+    /// itself (S3).
+    async fn read(&self, name: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// Checks whether the image is stored (it might have been deleted in the meantime).
+    async fn exists(&self, name: &str) -> anyhow::Result<bool>;
+
+    /// Base URL of the publicly accessible images (CDN, S3 bucket or this server).
+    fn public_base_url(&self) -> &str;
+
+    fn public_url(&self, name: &str) -> String {
+        resolve_public_url(self.public_base_url(), name)
+    }
+}
+
+fn resolve_public_url(base_url: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        base_url.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_public_url_happy_path() {
+        insta::assert_snapshot!(resolve_public_url(
+            "https://d3nujwlesxo9e6.cloudfront.net/",
+            "test.png"
+        ))
+    }
+
+    #[test]
+    fn resolve_public_url_leading_slash() {
+        insta::assert_snapshot!(resolve_public_url(
+            "https://d3nujwlesxo9e6.cloudfront.net/",
+            "/test.png"
+        ))
+    }
+}
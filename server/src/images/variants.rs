@@ -0,0 +1,184 @@
+use crate::global_configuration::ImageVariantsConfiguration;
+use crate::graphql_context::ContextUploadableContentType;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+/// Responsive variants of the uploaded images (their maximum widths are configurable).
+#[derive(juniper::GraphQLEnum, Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub(crate) enum ImageSize {
+    /// Small preview (product lists, POS tiles).
+    Thumbnail,
+    /// Medium size image (product cards).
+    Card,
+    /// Large image (product detail).
+    Full,
+}
+
+impl ImageSize {
+    pub(crate) const ALL: [ImageSize; 3] = [ImageSize::Thumbnail, ImageSize::Card, ImageSize::Full];
+
+    /// Name of the size used in the names of the stored variants.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ImageSize::Thumbnail => "thumbnail",
+            ImageSize::Card => "card",
+            ImageSize::Full => "full",
+        }
+    }
+}
+
+/// Resized and re-encoded image which is not stored yet.
+pub(in crate::images) struct EncodedImageVariant {
+    pub(in crate::images) size: ImageSize,
+    pub(in crate::images) data: Vec<u8>,
+    pub(in crate::images) content_type: ContextUploadableContentType,
+    pub(in crate::images) width: u32,
+    pub(in crate::images) height: u32,
+}
+
+pub(in crate::images) fn image_format(content_type: &ContextUploadableContentType) -> ImageFormat {
+    match content_type {
+        ContextUploadableContentType::ImagePng => ImageFormat::Png,
+        ContextUploadableContentType::ImageJpeg => ImageFormat::Jpeg,
+        ContextUploadableContentType::ImageWebp => ImageFormat::WebP,
+    }
+}
+
+/// Generates all the image variants. Variants keep the format of the original image unless the
+/// WebP re-encoding is enabled. Images are never upscaled (smaller images keep their dimensions).
+///
+/// It's CPU heavy so it should not be called directly from async code (see `spawn_blocking`).
+pub(in crate::images) fn generate_image_variants(
+    image: &DynamicImage,
+    original_content_type: &ContextUploadableContentType,
+    configuration: &ImageVariantsConfiguration,
+) -> anyhow::Result<Vec<EncodedImageVariant>> {
+    let content_type = if configuration.webp {
+        ContextUploadableContentType::ImageWebp
+    } else {
+        original_content_type.to_owned()
+    };
+
+    ImageSize::ALL
+        .iter()
+        .map(|size| {
+            generate_image_variant(image, size, configuration.max_width(size), &content_type)
+        })
+        .collect()
+}
+
+fn generate_image_variant(
+    image: &DynamicImage,
+    size: &ImageSize,
+    max_width: u32,
+    content_type: &ContextUploadableContentType,
+) -> anyhow::Result<EncodedImageVariant> {
+    let resized_image = if image.width() > max_width {
+        image.resize(max_width, image.height(), FilterType::Lanczos3)
+    } else {
+        image.to_owned()
+    };
+
+    Ok(EncodedImageVariant {
+        size: size.to_owned(),
+        data: encode_image(&resized_image, content_type)?,
+        content_type: content_type.to_owned(),
+        width: resized_image.width(),
+        height: resized_image.height(),
+    })
+}
+
+pub(in crate::images) fn encode_image(
+    image: &DynamicImage,
+    content_type: &ContextUploadableContentType,
+) -> anyhow::Result<Vec<u8>> {
+    // JPEG doesn't support transparency and WebP encoder supports only RGB(A) images:
+    let image = match content_type {
+        ContextUploadableContentType::ImagePng => image.to_owned(),
+        ContextUploadableContentType::ImageJpeg => DynamicImage::ImageRgb8(image.to_rgb8()),
+        ContextUploadableContentType::ImageWebp => DynamicImage::ImageRgba8(image.to_rgba8()),
+    };
+
+    let mut data = Cursor::new(vec![]);
+    image.write_to(&mut data, image_format(content_type))?;
+    Ok(data.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configuration(webp: bool) -> ImageVariantsConfiguration {
+        ImageVariantsConfiguration {
+            thumbnail_width: 16,
+            card_width: 64,
+            full_width: 100_000,
+            webp,
+        }
+    }
+
+    #[test]
+    fn generate_image_variants_test() {
+        let image = image::open("src/images/tests/data/img.png").unwrap();
+        let variants = generate_image_variants(
+            &image,
+            &ContextUploadableContentType::ImagePng,
+            &configuration(false),
+        )
+        .unwrap();
+
+        assert_eq!(variants.len(), 3);
+        assert_eq!(variants[0].size, ImageSize::Thumbnail);
+        assert_eq!(variants[0].width, 16);
+        assert_eq!(variants[1].size, ImageSize::Card);
+        assert_eq!(variants[1].width, 64);
+
+        // the full variant is never upscaled
+        assert_eq!(variants[2].size, ImageSize::Full);
+        assert_eq!(variants[2].width, image.width());
+        assert_eq!(variants[2].height, image.height());
+
+        for variant in &variants {
+            // the aspect ratio is preserved (with rounding)
+            let expected_height =
+                image.height() as f64 * variant.width as f64 / image.width() as f64;
+            assert!((variant.height as f64 - expected_height).abs() <= 1.0);
+            assert_eq!(
+                image::guess_format(&variant.data).unwrap(),
+                ImageFormat::Png
+            );
+        }
+    }
+
+    #[test]
+    fn generate_image_variants_webp_test() {
+        let image = image::open("src/images/tests/data/img.jpg").unwrap();
+        let variants = generate_image_variants(
+            &image,
+            &ContextUploadableContentType::ImageJpeg,
+            &configuration(true),
+        )
+        .unwrap();
+
+        for variant in &variants {
+            assert!(matches!(
+                variant.content_type,
+                ContextUploadableContentType::ImageWebp
+            ));
+            assert_eq!(
+                image::guess_format(&variant.data).unwrap(),
+                ImageFormat::WebP
+            );
+        }
+    }
+
+    #[test]
+    fn encode_transparent_image_as_jpeg_test() {
+        let image = DynamicImage::new_rgba8(10, 10);
+        let data = encode_image(&image, &ContextUploadableContentType::ImageJpeg).unwrap();
+        assert_eq!(image::guess_format(&data).unwrap(), ImageFormat::Jpeg);
+    }
+}
@@ -6,10 +6,20 @@
 mod global_macros;
 
 use crate::arango::get_database_connection_pool;
+use crate::auth::oidc::{OidcProvider, OidcProviders};
 use crate::axum_server::create_axum_server;
 use crate::clap::generate_clap_app;
-use crate::global_configuration::GlobalConfiguration;
+use crate::global_configuration::{
+    GlobalConfiguration, HttpConfiguration, ImageLimitsConfiguration, ImageVariantsConfiguration,
+    SessionsConfiguration,
+};
+use crate::images::{ImageStorage, LocalImageStorage, S3ImageStorage};
+use axum::http::HeaderName;
 use clap_complete::shells::{Bash, Zsh};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::EnvFilter;
@@ -29,6 +39,8 @@ mod graphql_schema;
 mod headers;
 mod images;
 mod locale;
+mod migrations;
+mod pagination;
 mod pos;
 mod price;
 mod stripe;
@@ -96,6 +108,130 @@ async fn main() {
         cli_matches.get_one::<String>("arangodb-password").unwrap(),
     );
 
+    if let Some(subcommand_match) = cli_matches.subcommand_matches("migrate") {
+        let result = match subcommand_match.subcommand_name() {
+            Some("up") => crate::migrations::migrate_up(&pool).await,
+            Some("status") => crate::migrations::migrate_status(&pool).await,
+            _ => panic!("Unknown migrate subcommand."),
+        };
+        match result {
+            Ok(migration_statuses) => {
+                if migration_statuses.is_empty() {
+                    println!("Nothing to migrate.");
+                }
+                for migration_status in migration_statuses {
+                    println!(
+                        "{:>4} {:<30} {}",
+                        migration_status.version,
+                        migration_status.name,
+                        migration_status.applied.as_deref().unwrap_or("pending")
+                    );
+                }
+                std::process::exit(0);
+            }
+            Err(error) => {
+                tracing::error!("Migration failed: {}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // The code expects the current DB schema (collections, indexes and the RBAC policies) so we
+    // refuse to start until all the migrations are applied (or when we cannot verify it):
+    match crate::migrations::migrate_status(&pool).await {
+        Ok(migration_statuses) => {
+            let pending_versions: Vec<String> = migration_statuses
+                .iter()
+                .filter(|migration_status| migration_status.applied.is_none())
+                .map(|migration_status| migration_status.version.to_string())
+                .collect();
+            if !pending_versions.is_empty() {
+                tracing::error!(
+                    "There are pending migrations ({}), run 'migrate up' first.",
+                    pending_versions.join(", ")
+                );
+                std::process::exit(1);
+            }
+        }
+        Err(error) => {
+            // the schema might be outdated as well, so it's not safe to serve any requests
+            tracing::error!("Could not check the migrations status: {}", error);
+            std::process::exit(1);
+        }
+    }
+
+    if let Err(error) = crate::commerce::api::ensure_products_search_view(&pool).await {
+        tracing::error!("Could not prepare full-text product search: {}", error);
+    }
+
+    let images_public_base_url = cli_matches
+        .get_one::<String>("images-public-base-url")
+        .map(String::as_str);
+    let image_storage: Arc<dyn ImageStorage> = match cli_matches
+        .get_one::<String>("images-storage")
+        .map(String::as_str)
+    {
+        Some("local") => Arc::new(LocalImageStorage::new(
+            cli_matches
+                .get_one::<String>("images-local-directory")
+                .unwrap(),
+            images_public_base_url.unwrap_or("http://localhost:5000/images"),
+        )),
+        _ => Arc::new(
+            S3ImageStorage::new(
+                cli_matches.get_one::<String>("images-s3-bucket").unwrap(),
+                cli_matches.get_one::<String>("images-s3-region").unwrap(),
+                cli_matches
+                    .get_one::<String>("images-s3-endpoint")
+                    .map(String::as_str),
+                images_public_base_url,
+            )
+            .expect("Invalid S3 images storage configuration."),
+        ),
+    };
+
+    if let Some(subcommand_match) = cli_matches.subcommand_matches("images") {
+        let result = match subcommand_match.subcommand_name() {
+            Some("gc") => crate::images::collect_garbage(&pool, image_storage.as_ref()).await,
+            _ => panic!("Unknown images subcommand."),
+        };
+        match result {
+            Ok(deleted_names) => {
+                for deleted_name in &deleted_names {
+                    println!("deleted {}", deleted_name);
+                }
+                println!("Deleted {} images.", deleted_names.len());
+                std::process::exit(0);
+            }
+            Err(error) => {
+                tracing::error!("Images garbage collection failed: {}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let oidc_providers = OidcProviders::new(
+        cli_matches
+            .get_many::<String>("oidc-provider")
+            .into_iter()
+            .flatten()
+            .map(|oidc_provider| {
+                OidcProvider::from_str(oidc_provider)
+                    .expect("Invalid OpenID Connect provider configuration.")
+            })
+            .collect(),
+    );
+
+    let image_limits = ImageLimitsConfiguration {
+        max_file_size: *cli_matches
+            .get_one::<usize>("images-max-file-size")
+            .unwrap(),
+        max_dimension: *cli_matches.get_one::<u32>("images-max-dimension").unwrap(),
+        max_images_per_product: *cli_matches
+            .get_one::<usize>("images-max-per-product")
+            .unwrap(),
+    };
+
     let global_configuration = GlobalConfiguration {
         stripe_restricted_api_key: cli_matches
             .get_one::<String>("stripe-restricted-api-key")
@@ -103,10 +239,57 @@ async fn main() {
         stripe_webhook_secret: cli_matches
             .get_one::<String>("stripe-webhook-secret")
             .map(String::from),
+        http: HttpConfiguration {
+            request_id_header: HeaderName::try_from(
+                cli_matches.get_one::<String>("request-id-header").unwrap(),
+            )
+            .expect("Request ID header must be a valid HTTP header name."),
+            graphql_timeout: Duration::from_secs(
+                *cli_matches.get_one::<u64>("graphql-timeout").unwrap(),
+            ),
+            // the limit is raised when needed so all the product images fit into one upload
+            graphql_body_limit: (*cli_matches.get_one::<usize>("graphql-body-limit").unwrap())
+                .max(image_limits.max_upload_body_size()),
+            webhooks_timeout: Duration::from_secs(
+                *cli_matches.get_one::<u64>("webhooks-timeout").unwrap(),
+            ),
+            webhooks_body_limit: *cli_matches.get_one::<usize>("webhooks-body-limit").unwrap(),
+            trust_proxy: cli_matches.get_flag("trust-proxy"),
+        },
+        image_storage,
+        image_variants: ImageVariantsConfiguration {
+            thumbnail_width: *cli_matches
+                .get_one::<u32>("images-thumbnail-width")
+                .unwrap(),
+            card_width: *cli_matches.get_one::<u32>("images-card-width").unwrap(),
+            full_width: *cli_matches.get_one::<u32>("images-full-width").unwrap(),
+            webp: cli_matches.get_flag("images-webp"),
+        },
+        image_limits: image_limits.to_owned(),
+        sessions: SessionsConfiguration {
+            max_lifetime: Duration::from_secs(
+                *cli_matches.get_one::<u64>("sessions-max-lifetime").unwrap(),
+            ),
+            inactivity_timeout: Duration::from_secs(
+                *cli_matches
+                    .get_one::<u64>("sessions-inactivity-timeout")
+                    .unwrap(),
+            ),
+        },
+        oidc_providers,
     };
 
+    crate::auth::spawn_expired_sessions_sweeper(
+        pool.to_owned(),
+        global_configuration.sessions.to_owned(),
+    );
+
     let listener = tokio::net::TcpListener::bind("0.0.0.0:5000").await.unwrap();
-    axum::serve(listener, create_axum_server(pool, global_configuration))
-        .await
-        .unwrap()
+    axum::serve(
+        listener,
+        create_axum_server(pool, global_configuration)
+            .into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap()
 }
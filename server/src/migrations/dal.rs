@@ -0,0 +1,43 @@
+use crate::arango::{resolve_aql, resolve_aql_vector, ConnectionPool};
+use crate::migrations::AppliedMigration;
+
+pub(in crate::migrations) async fn get_applied_migrations(
+    pool: &ConnectionPool,
+) -> anyhow::Result<Vec<AppliedMigration>> {
+    resolve_aql_vector(
+        pool,
+        r#"
+            FOR migration IN _migrations
+              SORT migration.version ASC
+              RETURN migration
+        "#,
+        hashmap_json![],
+    )
+    .await
+}
+
+/// Records the migration as applied. The migration version is used as a document key so the same
+/// migration cannot be recorded twice (when running two `migrate up` concurrently, for example).
+pub(in crate::migrations) async fn record_applied_migration(
+    pool: &ConnectionPool,
+    version: &u32,
+    name: &str,
+) -> anyhow::Result<AppliedMigration> {
+    resolve_aql(
+        pool,
+        r#"
+            INSERT {
+              _key: TO_STRING(@version),
+              version: @version,
+              name: @name,
+              applied: DATE_ISO8601(DATE_NOW()),
+            } INTO _migrations
+            RETURN NEW
+        "#,
+        hashmap_json![
+            "version" => version,
+            "name" => name,
+        ],
+    )
+    .await
+}
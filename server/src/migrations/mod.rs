@@ -0,0 +1,252 @@
+use crate::arango::collection::options::CreateOptions;
+use crate::arango::document::options::InsertOptions;
+use crate::arango::graph::Graph;
+use crate::arango::index::Index;
+use crate::arango::{ArangoError, ClientError, ConnectionPool};
+use futures::future::BoxFuture;
+use serde::Deserialize;
+
+mod dal;
+mod versions;
+
+/// Name of the (system) collection where we keep track of the applied migrations.
+const MIGRATIONS_COLLECTION: &str = "_migrations";
+
+/// One versioned change of the database structure. Migrations are applied in the order of their
+/// versions and each of them only once. Never change (or remove) migrations which were already
+/// applied somewhere - write a new migration instead.
+pub(crate) struct Migration {
+    version: u32,
+    name: &'static str,
+    steps: Vec<MigrationStep>,
+}
+
+/// Single operation of a migration. All the steps are idempotent: objects which exist already
+/// (collections, graphs, documents, …) are silently skipped so the migrations can be safely applied
+/// to databases which were created manually before the migrations existed.
+pub(crate) enum MigrationStep {
+    CreateCollection(&'static str),
+    CreateEdgeCollection(&'static str),
+    CreateGraph(Graph),
+    CreateIndex {
+        collection: &'static str,
+        index: Index,
+    },
+    InsertDocument {
+        collection: &'static str,
+        document: serde_json::Value,
+    },
+    /// Arbitrary code for the cases which are not covered by the steps above (ArangoSearch views,
+    /// for example). It must be idempotent as well.
+    Run(fn(&ConnectionPool) -> BoxFuture<'_, anyhow::Result<()>>),
+}
+
+impl MigrationStep {
+    async fn apply(&self, pool: &ConnectionPool) -> anyhow::Result<()> {
+        let db = pool.db().await;
+        let result = match self {
+            MigrationStep::CreateCollection(name) => db.create_collection(name).await.map(|_| ()),
+            MigrationStep::CreateEdgeCollection(name) => {
+                db.create_edge_collection(name).await.map(|_| ())
+            }
+            MigrationStep::CreateGraph(graph) => {
+                db.create_graph(graph.to_owned(), true).await.map(|_| ())
+            }
+            MigrationStep::CreateIndex { collection, index } => {
+                // ArangoDB returns the existing index when an identical index exists already.
+                db.create_index(collection, index).await.map(|_| ())
+            }
+            MigrationStep::InsertDocument {
+                collection,
+                document,
+            } => match db.collection(collection).await {
+                Ok(collection) => collection
+                    .create_document(document.to_owned(), InsertOptions::default())
+                    .await
+                    .map(|_| ()),
+                Err(error) => Err(error),
+            },
+            MigrationStep::Run(run) => return run(pool).await,
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(ClientError::Arango(ArangoError { code: 409, .. })) => {
+                tracing::info!("{} exists already (skipping)", self);
+                Ok(())
+            }
+            Err(error) => anyhow::bail!("{} failed: {}", self, error),
+        }
+    }
+}
+
+impl std::fmt::Display for MigrationStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationStep::CreateCollection(name) => write!(f, "collection '{}'", name),
+            MigrationStep::CreateEdgeCollection(name) => write!(f, "edge collection '{}'", name),
+            MigrationStep::CreateGraph(graph) => write!(f, "graph '{}'", graph.name),
+            MigrationStep::CreateIndex { collection, index } => {
+                write!(f, "index '{}' on '{}'", index.name, collection)
+            }
+            MigrationStep::InsertDocument {
+                collection,
+                document,
+            } => write!(
+                f,
+                "document '{}/{}'",
+                collection,
+                document["_key"].as_str().unwrap_or_default()
+            ),
+            MigrationStep::Run(_) => write!(f, "custom step"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct AppliedMigration {
+    version: u32,
+    applied: String,
+}
+
+/// State of one known migration (`applied` is `None` when the migration is still pending).
+#[derive(Debug, PartialEq)]
+pub(crate) struct MigrationStatus {
+    pub(crate) version: u32,
+    pub(crate) name: &'static str,
+    pub(crate) applied: Option<String>,
+}
+
+/// Applies all the pending migrations (in order) and returns the statuses of the newly applied
+/// ones. It stops on the first failure; the failed migration is not recorded so it will be
+/// retried next time.
+pub(crate) async fn migrate_up(pool: &ConnectionPool) -> anyhow::Result<Vec<MigrationStatus>> {
+    ensure_migrations_collection(pool).await?;
+    let applied_migrations = dal::get_applied_migrations(pool).await?;
+
+    let mut newly_applied = vec![];
+    for migration in pending_migrations(versions::migrations(), &applied_migrations) {
+        tracing::info!(
+            "Applying migration {} ({})…",
+            migration.version,
+            migration.name
+        );
+        for step in &migration.steps {
+            step.apply(pool).await?;
+        }
+        let applied_migration =
+            dal::record_applied_migration(pool, &migration.version, migration.name).await?;
+
+        newly_applied.push(MigrationStatus {
+            version: migration.version,
+            name: migration.name,
+            applied: Some(applied_migration.applied),
+        });
+    }
+
+    Ok(newly_applied)
+}
+
+/// Returns all known migrations together with the information whether (and when) they were
+/// applied to the database.
+pub(crate) async fn migrate_status(pool: &ConnectionPool) -> anyhow::Result<Vec<MigrationStatus>> {
+    ensure_migrations_collection(pool).await?;
+    let applied_migrations = dal::get_applied_migrations(pool).await?;
+
+    Ok(versions::migrations()
+        .iter()
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            name: migration.name,
+            applied: applied_migrations
+                .iter()
+                .find(|applied| applied.version == migration.version)
+                .map(|applied| applied.applied.to_owned()),
+        })
+        .collect())
+}
+
+fn pending_migrations(
+    migrations: Vec<Migration>,
+    applied_migrations: &[AppliedMigration],
+) -> Vec<Migration> {
+    migrations
+        .into_iter()
+        .filter(|migration| {
+            !applied_migrations
+                .iter()
+                .any(|applied| applied.version == migration.version)
+        })
+        .collect()
+}
+
+async fn ensure_migrations_collection(pool: &ConnectionPool) -> anyhow::Result<()> {
+    let db = pool.db().await;
+    match db
+        .create_collection_with_options(
+            CreateOptions::builder()
+                .name(MIGRATIONS_COLLECTION)
+                .is_system(true)
+                .build(),
+            Default::default(),
+        )
+        .await
+    {
+        Ok(_) => {
+            tracing::info!("Created '{}' collection", MIGRATIONS_COLLECTION);
+            Ok(())
+        }
+        Err(ClientError::Arango(ArangoError { code: 409, .. })) => Ok(()),
+        Err(error) => anyhow::bail!(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migration_versions_test() {
+        let migrations = versions::migrations();
+        assert!(!migrations.is_empty());
+        for (index, migration) in migrations.iter().enumerate() {
+            // versions must be sequential (no gaps, no duplicates) so the order is obvious
+            assert_eq!(migration.version, index as u32 + 1);
+            assert!(!migration.name.is_empty());
+            assert!(!migration.steps.is_empty());
+        }
+    }
+
+    #[test]
+    fn pending_migrations_test() {
+        let applied_migrations = vec![AppliedMigration {
+            version: 1,
+            applied: String::from("2022-11-01T00:00:00.000Z"),
+        }];
+
+        let pending_versions: Vec<u32> =
+            pending_migrations(versions::migrations(), &applied_migrations)
+                .iter()
+                .map(|migration| migration.version)
+                .collect();
+
+        assert!(!pending_versions.contains(&1));
+        assert_eq!(pending_versions.len(), versions::migrations().len() - 1);
+    }
+
+    #[test]
+    fn migration_step_display_test() {
+        assert_eq!(
+            MigrationStep::CreateCollection("products").to_string(),
+            "collection 'products'"
+        );
+        assert_eq!(
+            MigrationStep::InsertDocument {
+                collection: "product_units",
+                document: serde_json::json!({ "_key": "piece" }),
+            }
+            .to_string(),
+            "document 'product_units/piece'"
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use crate::arango::graph::{EdgeDefinition, Graph};
+use crate::arango::index::{Index, IndexSettings};
+use crate::arango::ConnectionPool;
+use crate::migrations::{Migration, MigrationStep};
+use futures::future::BoxFuture;
+use serde_json::json;
+
+/// All the migrations in the order they should be applied. New migrations must be always appended
+/// at the end (with the next version number).
+pub(in crate::migrations) fn migrations() -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 1,
+            name: "initial_collections",
+            steps: vec![
+                MigrationStep::CreateCollection("accounts"),
+                MigrationStep::CreateCollection("analytics_redirects"),
+                MigrationStep::CreateCollection("archive"),
+                MigrationStep::CreateCollection("cats"),
+                MigrationStep::CreateCollection("orders"),
+                MigrationStep::CreateCollection("pos_checkouts"),
+                MigrationStep::CreateCollection("product_addons"),
+                MigrationStep::CreateCollection("product_categories"),
+                MigrationStep::CreateCollection("product_units"),
+                MigrationStep::CreateCollection("products"),
+                MigrationStep::CreateCollection("sessions"),
+                MigrationStep::CreateCollection("users"),
+                MigrationStep::CreateCollection("webhook_events_stripe"),
+                MigrationStep::CreateEdgeCollection("product_categories_edges"),
+                MigrationStep::CreateEdgeCollection("user_accounts"),
+                MigrationStep::CreateEdgeCollection("user_sessions"),
+            ],
+        },
+        Migration {
+            version: 2,
+            name: "initial_graphs",
+            steps: vec![
+                MigrationStep::CreateGraph(graph(
+                    "product_categories",
+                    "product_categories_edges",
+                    "products",
+                    "product_categories",
+                )),
+                MigrationStep::CreateGraph(graph("sessions", "user_sessions", "users", "sessions")),
+                MigrationStep::CreateGraph(graph(
+                    "users_in_accounts",
+                    "user_accounts",
+                    "users",
+                    "accounts",
+                )),
+            ],
+        },
+        Migration {
+            version: 3,
+            name: "initial_indexes",
+            steps: vec![
+                MigrationStep::CreateIndex {
+                    collection: "orders",
+                    index: sparse_index(
+                        "orders_stripe_checkout_session_id",
+                        "stripe_checkout_session_id",
+                    ),
+                },
+                MigrationStep::CreateIndex {
+                    collection: "orders",
+                    index: sparse_index(
+                        "orders_stripe_payment_intent_id",
+                        "stripe_payment_intent_id",
+                    ),
+                },
+                MigrationStep::CreateIndex {
+                    collection: "users",
+                    index: sparse_index("users_google_sub", "google.sub"),
+                },
+            ],
+        },
+        Migration {
+            version: 4,
+            name: "initial_documents",
+            steps: vec![
+                MigrationStep::InsertDocument {
+                    collection: "product_units",
+                    document: json!({
+                        "_key": "piece",
+                        "en_US": "piece",
+                        "es_MX": "pieza",
+                    }),
+                },
+                MigrationStep::InsertDocument {
+                    // hardcoded anonymous user (see `AnonymousUser`)
+                    collection: "users",
+                    document: json!({
+                        "_key": "1",
+                        "is_active": false,
+                    }),
+                },
+            ],
+        },
+        Migration {
+            version: 5,
+            name: "products_search_view",
+            steps: vec![MigrationStep::Run(ensure_products_search_view)],
+        },
+        Migration {
+            version: 6,
+            name: "images_collection",
+            steps: vec![MigrationStep::CreateCollection("images")],
+        },
+        Migration {
+            version: 7,
+            name: "rbac_policies",
+            steps: vec![
+                MigrationStep::CreateCollection("casbin_rules"),
+                MigrationStep::Run(seed_rbac_policies),
+            ],
+        },
+        Migration {
+            version: 8,
+            name: "users_admin_policies",
+            steps: vec![MigrationStep::Run(ensure_users_admin_policies)],
+        },
+        Migration {
+            version: 9,
+            name: "sessions_expiration",
+            steps: vec![
+                MigrationStep::Run(backfill_sessions),
+                MigrationStep::CreateIndex {
+                    collection: "sessions",
+                    index: persistent_index("sessions_created", "created"),
+                },
+                MigrationStep::CreateIndex {
+                    collection: "sessions",
+                    index: persistent_index("sessions_last_access", "last_access"),
+                },
+            ],
+        },
+        Migration {
+            version: 10,
+            name: "risc_events_collection",
+            steps: vec![MigrationStep::CreateCollection("risc_events")],
+        },
+        Migration {
+            version: 11,
+            name: "api_keys",
+            steps: vec![
+                MigrationStep::CreateCollection("api_keys"),
+                MigrationStep::Run(ensure_api_keys_admin_policies),
+            ],
+        },
+        Migration {
+            version: 12,
+            name: "risc_events_jti",
+            steps: vec![MigrationStep::CreateIndex {
+                collection: "risc_events",
+                index: unique_index("risc_events_jti", &["jti", "event_type"]),
+            }],
+        },
+    ]
+}
+
+fn graph(name: &str, edge_collection: &str, from: &str, to: &str) -> Graph {
+    Graph::builder()
+        .name(name.to_string())
+        .edge_definitions(vec![EdgeDefinition {
+            collection: edge_collection.to_string(),
+            from: vec![from.to_string()],
+            to: vec![to.to_string()],
+        }])
+        .build()
+}
+
+fn sparse_index(name: &str, field: &str) -> Index {
+    Index::builder()
+        .name(name)
+        .fields(vec![field.to_string()])
+        .settings(IndexSettings::Persistent {
+            unique: false,
+            sparse: true,
+            deduplicate: false,
+        })
+        .build()
+}
+
+fn persistent_index(name: &str, field: &str) -> Index {
+    Index::builder()
+        .name(name)
+        .fields(vec![field.to_string()])
+        .settings(IndexSettings::Persistent {
+            unique: false,
+            sparse: false,
+            deduplicate: false,
+        })
+        .build()
+}
+
+fn unique_index(name: &str, fields: &[&str]) -> Index {
+    Index::builder()
+        .name(name)
+        .fields(fields.iter().map(|field| field.to_string()).collect())
+        .settings(IndexSettings::Persistent {
+            unique: true,
+            sparse: false,
+            deduplicate: false,
+        })
+        .build()
+}
+
+fn ensure_products_search_view(pool: &ConnectionPool) -> BoxFuture<'_, anyhow::Result<()>> {
+    Box::pin(crate::commerce::api::ensure_products_search_view(pool))
+}
+
+fn seed_rbac_policies(pool: &ConnectionPool) -> BoxFuture<'_, anyhow::Result<()>> {
+    Box::pin(crate::auth::rbac::seed_policies(pool))
+}
+
+fn ensure_users_admin_policies(pool: &ConnectionPool) -> BoxFuture<'_, anyhow::Result<()>> {
+    Box::pin(crate::auth::rbac::ensure_policies(
+        pool,
+        r#"
+            p, users_admin, users, activate_user, allow
+            p, users_admin, users, deactivate_user, allow
+            p, users_admin, users, assign_user_role, allow
+            p, users_admin, users, unassign_user_role, allow
+            p, users_admin, users, get_user_permissions, allow
+            p, users_admin, users, revoke_user_sessions, allow
+        "#,
+    ))
+}
+
+fn ensure_api_keys_admin_policies(pool: &ConnectionPool) -> BoxFuture<'_, anyhow::Result<()>> {
+    Box::pin(crate::auth::rbac::ensure_policies(
+        pool,
+        r#"
+            p, users_admin, users, list_api_keys, allow
+            p, users_admin, users, create_api_key, allow
+            p, users_admin, users, revoke_api_key, allow
+        "#,
+    ))
+}
+
+fn backfill_sessions(pool: &ConnectionPool) -> BoxFuture<'_, anyhow::Result<()>> {
+    Box::pin(crate::auth::backfill_sessions(pool))
+}
@@ -0,0 +1,226 @@
+use serde::Deserialize;
+
+/// Number of items returned when the client doesn't specify `first` argument.
+const DEFAULT_PAGE_SIZE: i32 = 20;
+
+/// Maximum number of items the client can request at once.
+const MAX_PAGE_SIZE: i32 = 100;
+
+/// Information about pagination in a connection (Relay specification).
+///
+/// See: https://relay.dev/graphql/connections.htm#sec-undefined.PageInfo
+#[derive(juniper::GraphQLObject, Clone, Debug, PartialEq)]
+pub(crate) struct PageInfo {
+    /// When paginating forwards, are there more items?
+    pub(crate) has_next_page: bool,
+    /// When paginating backwards, are there more items?
+    pub(crate) has_previous_page: bool,
+    /// When paginating backwards, the cursor to continue.
+    pub(crate) start_cursor: Option<String>,
+    /// When paginating forwards, the cursor to continue.
+    pub(crate) end_cursor: Option<String>,
+}
+
+/// Forward-only pagination derived from Relay `first`/`after` arguments. The cursors are opaque
+/// for the clients, however, internally they are just encoded offsets which are translated to AQL
+/// `LIMIT @offset, @limit`. The queries must therefore always use a stable sort (with a unique
+/// tiebreaker such as `_key`) otherwise the pages could overlap.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Pagination {
+    offset: i32,
+    limit: i32,
+}
+
+impl Pagination {
+    pub(crate) fn new(first: &Option<i32>, after: &Option<String>) -> anyhow::Result<Self> {
+        let limit = match first {
+            Some(first) if *first < 0 => anyhow::bail!("argument `first` cannot be negative"),
+            Some(first) if *first > MAX_PAGE_SIZE => anyhow::bail!(
+                "argument `first` cannot be higher than {} (got {})",
+                MAX_PAGE_SIZE,
+                first
+            ),
+            Some(first) => *first,
+            None => DEFAULT_PAGE_SIZE,
+        };
+
+        // The whole page must stay addressable by cursors (see `cursor` and `page_info`):
+        let offset = match after {
+            Some(after) => match decode_cursor(after)?
+                .checked_add(1)
+                .filter(|offset| offset.checked_add(limit).is_some())
+            {
+                Some(offset) => offset,
+                None => anyhow::bail!("invalid pagination cursor: {}", after),
+            },
+            None => 0,
+        };
+
+        Ok(Pagination { offset, limit })
+    }
+
+    /// Returns everything from the very beginning. It exists only for the deprecated queries
+    /// which are returning all the items at once.
+    pub(crate) fn unlimited() -> Self {
+        Pagination {
+            offset: 0,
+            limit: i32::MAX,
+        }
+    }
+
+    pub(crate) fn offset(&self) -> i32 {
+        self.offset
+    }
+
+    pub(crate) fn limit(&self) -> i32 {
+        self.limit
+    }
+
+    /// Returns cursor of the item on the specified position in the current page.
+    pub(crate) fn cursor(&self, index: usize) -> String {
+        encode_cursor(self.offset + index as i32)
+    }
+
+    pub(crate) fn page_info(&self, items_count: usize, total_count: i32) -> PageInfo {
+        let has_items = items_count > 0;
+        PageInfo {
+            has_next_page: self.offset + (items_count as i32) < total_count,
+            has_previous_page: self.offset > 0,
+            start_cursor: has_items.then(|| self.cursor(0)),
+            end_cursor: has_items.then(|| self.cursor(items_count - 1)),
+        }
+    }
+}
+
+/// One page of items as returned from the database together with the total number of items
+/// (ignoring the pagination). AQL queries should return it like this:
+///
+/// ```aql
+/// RETURN { total_count: …, items: … }
+/// ```
+#[derive(Deserialize, Debug)]
+pub(crate) struct PaginatedResult<T> {
+    pub(crate) total_count: i32,
+    pub(crate) items: Vec<T>,
+}
+
+fn encode_cursor(offset: i32) -> String {
+    data_encoding::BASE64.encode(format!("offset:{}", offset).as_bytes())
+}
+
+fn decode_cursor(cursor: &str) -> anyhow::Result<i32> {
+    let decoded_cursor = match data_encoding::BASE64.decode(cursor.as_bytes()) {
+        Ok(decoded_cursor) => String::from_utf8(decoded_cursor).unwrap_or_default(),
+        Err(_) => anyhow::bail!("invalid pagination cursor: {}", cursor),
+    };
+
+    match decoded_cursor
+        .strip_prefix("offset:")
+        .and_then(|offset| offset.parse::<i32>().ok())
+    {
+        Some(offset) if offset >= 0 => Ok(offset),
+        _ => anyhow::bail!("invalid pagination cursor: {}", cursor),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_roundtrip_test() {
+        assert_eq!(encode_cursor(0), "b2Zmc2V0OjA=");
+        assert_eq!(decode_cursor(&encode_cursor(0)).unwrap(), 0);
+        assert_eq!(decode_cursor(&encode_cursor(42)).unwrap(), 42);
+    }
+
+    #[test]
+    fn cursor_invalid_test() {
+        assert!(decode_cursor("nonsense").is_err());
+        assert!(decode_cursor(&data_encoding::BASE64.encode(b"offset:-1")).is_err());
+        assert!(decode_cursor(&data_encoding::BASE64.encode(b"offset:abc")).is_err());
+        assert!(decode_cursor(&data_encoding::BASE64.encode(b"42")).is_err());
+    }
+
+    #[test]
+    fn pagination_arguments_test() {
+        assert_eq!(
+            Pagination::new(&None, &None).unwrap(),
+            Pagination {
+                offset: 0,
+                limit: DEFAULT_PAGE_SIZE
+            }
+        );
+        assert_eq!(
+            Pagination::new(&Some(5), &Some(encode_cursor(9))).unwrap(),
+            Pagination {
+                offset: 10,
+                limit: 5
+            }
+        );
+        assert_eq!(
+            Pagination::new(&Some(-1), &None).unwrap_err().to_string(),
+            "argument `first` cannot be negative"
+        );
+        assert_eq!(
+            Pagination::new(&Some(101), &None).unwrap_err().to_string(),
+            "argument `first` cannot be higher than 100 (got 101)"
+        );
+    }
+
+    #[test]
+    fn pagination_cursor_overflow_test() {
+        let last_cursor = encode_cursor(i32::MAX);
+        assert_eq!(
+            Pagination::new(&None, &Some(last_cursor.to_owned()))
+                .unwrap_err()
+                .to_string(),
+            format!("invalid pagination cursor: {}", last_cursor)
+        );
+
+        // the next page would not fit either
+        let cursor = encode_cursor(i32::MAX - 10);
+        assert_eq!(
+            Pagination::new(&Some(20), &Some(cursor.to_owned()))
+                .unwrap_err()
+                .to_string(),
+            format!("invalid pagination cursor: {}", cursor)
+        );
+        assert!(Pagination::new(&Some(5), &Some(cursor)).is_ok());
+    }
+
+    #[test]
+    fn page_info_test() {
+        let first_page = Pagination::new(&Some(2), &None).unwrap();
+        assert_eq!(
+            first_page.page_info(2, 3),
+            PageInfo {
+                has_next_page: true,
+                has_previous_page: false,
+                start_cursor: Some(encode_cursor(0)),
+                end_cursor: Some(encode_cursor(1)),
+            }
+        );
+
+        let last_page = Pagination::new(&Some(2), &first_page.page_info(2, 3).end_cursor).unwrap();
+        assert_eq!(
+            last_page.page_info(1, 3),
+            PageInfo {
+                has_next_page: false,
+                has_previous_page: true,
+                start_cursor: Some(encode_cursor(2)),
+                end_cursor: Some(encode_cursor(2)),
+            }
+        );
+
+        assert_eq!(
+            Pagination::new(&None, &None).unwrap().page_info(0, 0),
+            PageInfo {
+                has_next_page: false,
+                has_previous_page: false,
+                start_cursor: None,
+                end_cursor: None,
+            }
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::arango::client::ClientExt;
 #[cfg(test)]
 use crate::arango::resolve_aql_vector;
 use crate::arango::{resolve_aql, ConnectionPool};
@@ -59,8 +60,8 @@ pub(in crate::pos) struct PosCheckoutInput {
 /// - products sold (not only IDs but the whole expanded products so future changes of these
 ///   products don't affect this POS history)
 /// - price for each product at the time of the sale (again, preserving historic state)
-pub(in crate::pos) async fn create_checkout(
-    pool: &ConnectionPool,
+pub(in crate::pos) async fn create_checkout<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     input: &PosCheckoutInput,
 ) -> anyhow::Result<PosCheckout> {
     resolve_aql(
@@ -80,8 +81,8 @@ pub(in crate::pos) async fn create_checkout(
 }
 
 #[cfg(test)]
-pub(in crate::pos) async fn get_all_checkouts(
-    pool: &ConnectionPool,
+pub(in crate::pos) async fn get_all_checkouts<C: ClientExt>(
+    pool: &ConnectionPool<C>,
 ) -> anyhow::Result<Vec<PosCheckout>> {
     resolve_aql_vector(
         pool,
@@ -93,3 +94,62 @@ pub(in crate::pos) async fn get_all_checkouts(
     )
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arango::client::fake::FakeClient;
+    use crate::arango::get_database_connection_pool_fake;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn create_checkout_test() {
+        let client = FakeClient::default();
+        client.respond_with(http::Method::POST, "_api/cursor", |request| {
+            (
+                201,
+                json!({
+                    "error": false,
+                    "code": 201,
+                    "cached": false,
+                    "hasMore": false,
+                    "result": [{
+                        "_id": "pos_checkouts/1",
+                        "created_date": "2022-11-01T00:00:00.000Z",
+                        "selected_products": request.body["bindVars"]["selected_products"],
+                    }],
+                }),
+            )
+        });
+        client.respond_cursor(json!([{
+            "_id": "pos_checkouts/1",
+            "created_date": "2022-11-01T00:00:00.000Z",
+            "selected_products": [],
+        }]));
+
+        let pool = get_database_connection_pool_fake(&client);
+        let checkout = create_checkout(
+            &pool,
+            &PosCheckoutInput {
+                selected_products: vec![PosCheckoutProductInput {
+                    product_id: String::from("products/1"),
+                    product_name: String::from("Product"),
+                    product_units: 2,
+                    product_price_unit_amount: 10000,
+                    product_price_unit_amount_currency: SupportedCurrency::MXN,
+                    product_addons: None,
+                }],
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(checkout.id(), "pos_checkouts/1");
+        assert_eq!(checkout.selected_products.len(), 1);
+        assert_eq!(checkout.selected_products[0].product_units, 2);
+
+        let checkouts = get_all_checkouts(&pool).await.unwrap();
+        assert_eq!(checkouts.len(), 1);
+        assert!(client.is_exhausted());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::auth::rbac;
 use crate::auth::rbac::Actions::Pos;
 use crate::auth::rbac::PosActions::Checkout;
+use crate::commerce::api::StockReservation;
 use crate::graphql_context::Context;
 use crate::locale::SupportedLocale;
 use crate::pos::api::dal::{
@@ -61,6 +62,9 @@ impl POSMutation {
     /// cashier accepts the money, the product is sold for the given price and there is not time for
     /// price adjustments (customers would be angry if we would say "oh, actually it just got more
     /// expensive").
+    ///
+    /// Sold units are subtracted from the product stock (the checkout fails when there is not
+    /// enough units in stock).
     async fn checkout(
         context: &Context,
         input: PosCheckoutInput,
@@ -82,7 +86,7 @@ impl POSMutation {
 
         // Let's take all products from the GraphQL input, iterate them and create a structure
         // expected by DBAL (+ add more information from our DB about the products).
-        let selected_products = input
+        let selected_products: Vec<PosCheckoutProductDalInput> = input
             .selected_products
             .iter()
             .map(|selected_product| {
@@ -116,17 +120,43 @@ impl POSMutation {
             })
             .collect();
 
-        match rbac::verify_permissions(&context.user, &Pos(Checkout)).await {
+        let stock_reservations: Vec<StockReservation> = selected_products
+            .iter()
+            .map(|selected_product| StockReservation {
+                product_id: selected_product.product_id.to_owned(),
+                product_units: selected_product.product_units,
+            })
+            .collect();
+
+        match rbac::verify_permissions(&context.rbac_enforcer, &context.user, &Pos(Checkout)).await
+        {
             Ok(_) => {
+                if let Err(e) =
+                    crate::commerce::api::reserve_stock(&context.pool, &stock_reservations).await
+                {
+                    return PosCheckoutPayloadOrError::Error(PosCheckoutError {
+                        message: format!("{:?}", e),
+                    });
+                }
+
                 match create_checkout(&context.pool, &PosCheckoutDalInput { selected_products })
                     .await
                 {
                     Ok(checkout) => PosCheckoutPayloadOrError::Payload(PosCheckoutPayload {
                         id: juniper::ID::from(checkout.id()),
                     }),
-                    Err(e) => PosCheckoutPayloadOrError::Error(PosCheckoutError {
-                        message: format!("{:?}", e),
-                    }),
+                    Err(e) => {
+                        // The sale was not recorded, so the units must be returned to the stock:
+                        if let Err(release_error) =
+                            crate::commerce::api::release_stock(&context.pool, &stock_reservations)
+                                .await
+                        {
+                            tracing::error!("Unable to release POS stock: {:?}", release_error);
+                        }
+                        PosCheckoutPayloadOrError::Error(PosCheckoutError {
+                            message: format!("{:?}", e),
+                        })
+                    }
                 }
             }
             Err(_) => PosCheckoutPayloadOrError::Error(PosCheckoutError {
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+/// Only a small subset of the charge object fields is currently supported.
+///
+/// See: https://stripe.com/docs/api/charges/object
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Charge {
+    /// Unique identifier for the object.
+    /// Example: "ch_..."
+    pub id: String,
+
+    /// Amount intended to be collected by this payment (in centavo).
+    pub amount: i32,
+
+    /// Amount (in centavo) refunded (can be less than the amount attribute on the charge if a
+    /// partial refund was issued).
+    pub amount_refunded: i32,
+
+    /// Whether the charge has been fully refunded. If the charge is only partially refunded,
+    /// this attribute will still be false.
+    pub refunded: bool,
+
+    /// ID of the PaymentIntent associated with this charge, if one exists.
+    /// Example: "pi_..."
+    pub payment_intent: Option<String>,
+}
@@ -110,6 +110,12 @@ pub struct CheckoutSession {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub payment_status: Option<CheckoutSessionPaymentStatus>,
 
+    /// The ID of the PaymentIntent for Checkout Sessions in `payment` mode. It's the only link
+    /// between the checkout session and the related charges (refunds, disputes).
+    /// Example: "pi_..."
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_intent: Option<String>,
+
     /// A list of items the customer is purchasing.
     ///
     /// For `payment` mode, there is a maximum of 100 line items, however it is recommended to
@@ -147,6 +153,7 @@ impl Default for CheckoutSession {
             mode: CheckoutSessionMode::Payment,
             payment_method_types: vec![],
             payment_status: None,
+            payment_intent: None,
             line_items: None,
             locale: None,
             shipping_rates: None,
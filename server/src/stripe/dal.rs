@@ -1,4 +1,5 @@
-use crate::arango::{resolve_aql, ConnectionPool, Document};
+use crate::arango::client::ClientExt;
+use crate::arango::{resolve_aql, resolve_aql_vector, ConnectionPool, Document};
 use crate::stripe::webhook::StripeWebhookPayload;
 
 /// Records the complete Stripe webhook payload.
@@ -10,9 +11,10 @@ use crate::stripe::webhook::StripeWebhookPayload;
 /// existed, `RETURN NEW` will return `null` (TODO: change the implementation to always return?).
 ///
 /// The aforementioned behavior is important in case Stripe sends the same event with the same event
-/// ID twice (it happened before).
-pub(crate) async fn record_webhook_call(
-    pool: &ConnectionPool,
+/// ID twice (it happened before): `None` means that the event was already received, and it should
+/// not be processed again.
+pub(crate) async fn record_webhook_call<C: ClientExt>(
+    pool: &ConnectionPool<C>,
     stripe_webhook_payload: &StripeWebhookPayload,
 ) -> anyhow::Result<Option<Document<StripeWebhookPayload>>> {
     resolve_aql(
@@ -38,3 +40,23 @@ pub(crate) async fn record_webhook_call(
     )
     .await
 }
+
+/// Removes the previously recorded Stripe webhook payload. It should be called when the webhook
+/// handler fails so that Stripe.com can retry the same event later (otherwise it would be ignored
+/// as a duplicate).
+pub(crate) async fn forget_webhook_call<C: ClientExt>(
+    pool: &ConnectionPool<C>,
+    stripe_webhook_payload: &StripeWebhookPayload,
+) -> anyhow::Result<()> {
+    resolve_aql_vector::<serde_json::Value, _>(
+        pool,
+        r#"
+            REMOVE @webhook_key IN webhook_events_stripe OPTIONS { ignoreErrors: true }
+        "#,
+        hashmap_json![
+            "webhook_key" => stripe_webhook_payload.id,
+        ],
+    )
+    .await?;
+    Ok(())
+}
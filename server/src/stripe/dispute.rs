@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+/// Only a small subset of the dispute object fields is currently supported.
+///
+/// See: https://stripe.com/docs/api/disputes/object
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Dispute {
+    /// Unique identifier for the object.
+    /// Example: "dp_..."
+    pub id: String,
+
+    /// Disputed amount (in centavo). Usually the amount of the charge, but can differ (usually
+    /// because of currency fluctuation or because only part of the order is disputed).
+    pub amount: i32,
+
+    /// ID of the charge that was disputed.
+    /// Example: "ch_..."
+    pub charge: String,
+
+    /// ID of the PaymentIntent that was disputed.
+    /// Example: "pi_..."
+    pub payment_intent: Option<String>,
+
+    /// Reason given by cardholder for dispute (`fraudulent`, `product_not_received`, …).
+    pub reason: String,
+}
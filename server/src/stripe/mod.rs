@@ -1,16 +1,20 @@
 use crate::locale::SupportedLocale;
 use crate::price::SupportedCurrency;
+pub use crate::stripe::charge::Charge;
 pub use crate::stripe::checkout::CheckoutSession;
 use crate::stripe::checkout::{
     CheckoutSessionItem, CheckoutSessionMode, CheckoutSessionPaymentMethodTypes,
     CheckoutSessionShippingAddressCollection, PriceData, ProductData,
 };
+pub use crate::stripe::dispute::Dispute;
 use crate::stripe::supported_countries::StripeSupportedCountries;
 use crate::stripe::supported_currencies::StripeSupportedCurrency;
 use crate::stripe::supported_locales::StripeSupportedLocales;
 use reqwest::{header, Client};
 
+mod charge;
 mod checkout;
+mod dispute;
 mod supported_countries;
 mod supported_currencies;
 mod supported_locales;
@@ -102,6 +106,33 @@ pub(crate) async fn checkout_session_create(
     }
 }
 
+/// Expires the checkout session so it can no longer be paid (only open sessions can be expired).
+///
+/// See: https://stripe.com/docs/api/checkout/sessions/expire
+pub(crate) async fn checkout_session_expire(
+    stripe_restricted_api_key: &str,
+    stripe_checkout_session_id: &str,
+) -> anyhow::Result<CheckoutSession> {
+    let client = create_stripe_api_client(stripe_restricted_api_key)?;
+    let resp = client
+        .post(format!(
+            "https://api.stripe.com/v1/checkout/sessions/{}/expire",
+            stripe_checkout_session_id
+        ))
+        .send()
+        .await?;
+
+    match resp.error_for_status() {
+        Ok(resp) => {
+            let json: CheckoutSession = resp.json().await?;
+            Ok(json)
+        }
+        Err(error) => {
+            anyhow::bail!(error)
+        }
+    }
+}
+
 fn create_stripe_api_client(stripe_restricted_api_key: &str) -> anyhow::Result<Client> {
     let mut headers = header::HeaderMap::new();
 
@@ -1,5 +1,16 @@
+use crate::arango::client::fake::FakeClient;
+use crate::arango::get_database_connection_pool_fake;
+use crate::commerce::api::{OrderLookup, OrderStatus};
+use crate::stripe::checkout::CheckoutSessionPaymentStatus;
+use crate::stripe::dal::record_webhook_call;
 use crate::stripe::webhook::{StripeWebhookPayload, StripeWebhookType};
-use crate::stripe::CheckoutSession;
+use crate::stripe::webhook_handlers::charge::{resolve_dispute_created, resolve_refunded};
+use crate::stripe::webhook_handlers::checkout_session::{
+    resolve_async_payment_failed, resolve_async_payment_succeeded, resolve_expired,
+    resolve_order_status,
+};
+use crate::stripe::{Charge, CheckoutSession, Dispute};
+use serde_json::json;
 
 // Tests parsing of webhook event type `checkout.session.completed` in "payment" mode.
 #[test]
@@ -20,6 +31,31 @@ fn test_checkout_session_parsing_mode_payment() {
     );
 }
 
+// Tests that completed checkout session in "payment" mode marks our order as paid.
+#[test]
+fn test_checkout_session_completed_order_status() {
+    let webhook_payload = serde_json::from_str::<StripeWebhookPayload>(include_str!(
+        "fixtures/checkout.session.completed/mode_payment.json"
+    ))
+    .unwrap();
+
+    let checkout_session =
+        serde_json::from_value::<CheckoutSession>(webhook_payload.data.object).unwrap();
+
+    assert_eq!(
+        resolve_order_status(&checkout_session.payment_status).unwrap(),
+        OrderStatus::Paid
+    );
+    assert_eq!(checkout_session.amount_total, Some(10000));
+    assert_eq!(checkout_session.amount_subtotal, Some(10000));
+
+    assert_eq!(
+        resolve_order_status(&Some(CheckoutSessionPaymentStatus::Unpaid)).unwrap(),
+        OrderStatus::Unpaid
+    );
+    assert!(resolve_order_status(&None).is_err());
+}
+
 // Tests parsing of webhook event type `checkout.session.completed` in "subscription" mode.
 #[test]
 fn test_checkout_session_completed_mode_subscription() {
@@ -39,6 +75,144 @@ fn test_checkout_session_completed_mode_subscription() {
     );
 }
 
+// Tests that delayed payment (OXXO) which finally succeeded marks our order as paid.
+#[test]
+fn test_checkout_session_async_payment_succeeded() {
+    let webhook_payload = serde_json::from_str::<StripeWebhookPayload>(include_str!(
+        "fixtures/checkout.session.async_payment_succeeded.json"
+    ))
+    .unwrap();
+
+    assert!(matches!(
+        webhook_payload.r#type,
+        StripeWebhookType::CheckoutSessionAsyncPaymentSucceeded { .. }
+    ));
+
+    let checkout_session =
+        serde_json::from_value::<CheckoutSession>(webhook_payload.data.object).unwrap();
+
+    assert_eq!(
+        resolve_async_payment_succeeded(&checkout_session).unwrap(),
+        (
+            OrderLookup::StripeCheckoutSessionId(String::from("cs_live_REDACTED")),
+            OrderStatus::Paid
+        )
+    );
+}
+
+// Tests that delayed payment (OXXO) which never arrived marks our order as failed.
+#[test]
+fn test_checkout_session_async_payment_failed() {
+    let webhook_payload = serde_json::from_str::<StripeWebhookPayload>(include_str!(
+        "fixtures/checkout.session.async_payment_failed.json"
+    ))
+    .unwrap();
+
+    assert!(matches!(
+        webhook_payload.r#type,
+        StripeWebhookType::CheckoutSessionAsyncPaymentFailed { .. }
+    ));
+
+    let checkout_session =
+        serde_json::from_value::<CheckoutSession>(webhook_payload.data.object).unwrap();
+
+    assert_eq!(
+        resolve_async_payment_failed(&checkout_session).unwrap(),
+        (
+            OrderLookup::StripeCheckoutSessionId(String::from("cs_live_REDACTED")),
+            OrderStatus::PaymentFailed
+        )
+    );
+}
+
+// Tests that expired checkout session (without any payment intent) marks our order as expired.
+#[test]
+fn test_checkout_session_expired() {
+    let webhook_payload = serde_json::from_str::<StripeWebhookPayload>(include_str!(
+        "fixtures/checkout.session.expired.json"
+    ))
+    .unwrap();
+
+    assert!(matches!(
+        webhook_payload.r#type,
+        StripeWebhookType::CheckoutSessionExpired { .. }
+    ));
+
+    let checkout_session =
+        serde_json::from_value::<CheckoutSession>(webhook_payload.data.object).unwrap();
+
+    assert_eq!(checkout_session.payment_intent, None);
+    assert_eq!(
+        resolve_expired(&checkout_session).unwrap(),
+        (
+            OrderLookup::StripeCheckoutSessionId(String::from("cs_live_REDACTED")),
+            OrderStatus::Expired
+        )
+    );
+}
+
+// Tests that fully refunded charge marks our order as refunded (found via payment intent).
+#[test]
+fn test_charge_refunded() {
+    let webhook_payload =
+        serde_json::from_str::<StripeWebhookPayload>(include_str!("fixtures/charge.refunded.json"))
+            .unwrap();
+
+    assert!(matches!(
+        webhook_payload.r#type,
+        StripeWebhookType::ChargeRefunded { .. }
+    ));
+
+    let mut charge = serde_json::from_value::<Charge>(webhook_payload.data.object).unwrap();
+
+    assert_eq!(
+        resolve_refunded(&charge).unwrap(),
+        (
+            OrderLookup::StripePaymentIntentId(String::from("pi_REDACTED")),
+            OrderStatus::Refunded
+        )
+    );
+
+    // partial refunds are still marked as `refunded: false` by Stripe
+    charge.refunded = false;
+    charge.amount_refunded = 5000;
+    assert_eq!(
+        resolve_refunded(&charge).unwrap(),
+        (
+            OrderLookup::StripePaymentIntentId(String::from("pi_REDACTED")),
+            OrderStatus::PartiallyRefunded
+        )
+    );
+
+    // charges without payment intent cannot be our orders
+    charge.payment_intent = None;
+    assert!(resolve_refunded(&charge).is_err());
+}
+
+// Tests that disputed charge marks our order as disputed (found via payment intent).
+#[test]
+fn test_charge_dispute_created() {
+    let webhook_payload = serde_json::from_str::<StripeWebhookPayload>(include_str!(
+        "fixtures/charge.dispute.created.json"
+    ))
+    .unwrap();
+
+    assert!(matches!(
+        webhook_payload.r#type,
+        StripeWebhookType::ChargeDisputeCreated { .. }
+    ));
+
+    let dispute = serde_json::from_value::<Dispute>(webhook_payload.data.object).unwrap();
+
+    assert_eq!(
+        resolve_dispute_created(&dispute).unwrap(),
+        (
+            OrderLookup::StripePaymentIntentId(String::from("pi_REDACTED")),
+            OrderStatus::Disputed
+        )
+    );
+}
+
 #[test]
 fn test_credit_note_created() {
     let webhook_payload = serde_json::from_str::<StripeWebhookPayload>(include_str!(
@@ -76,3 +250,52 @@ fn test_payment_intent_requires_action() {
         StripeWebhookType::PaymentIntentRequiresAction { .. }
     ));
 }
+
+// Tests that a new webhook event is recorded (under the Stripe event ID).
+#[tokio::test]
+async fn test_record_webhook_call() {
+    let webhook_payload =
+        serde_json::from_str::<StripeWebhookPayload>(include_str!("fixtures/invoice.paid.json"))
+            .unwrap();
+
+    let client = FakeClient::default();
+    client.respond_cursor(json!([{
+        "_id": format!("webhook_events_stripe/{}", webhook_payload.id),
+        "_key": webhook_payload.id,
+        "_rev": "_fake_rev",
+        "id": webhook_payload.id,
+        "api_version": webhook_payload.api_version,
+        "created": webhook_payload.created,
+        "type": webhook_payload.r#type,
+        "data": webhook_payload.data,
+    }]));
+
+    let pool = get_database_connection_pool_fake(&client);
+    let recorded = record_webhook_call(&pool, &webhook_payload)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(recorded.header._key, webhook_payload.id);
+    assert_eq!(
+        client.requests()[0].body["bindVars"]["webhook_key"],
+        json!(webhook_payload.id)
+    );
+}
+
+// Tests that an already received webhook event is not recorded (nor processed) again.
+#[tokio::test]
+async fn test_record_webhook_call_duplicate() {
+    let webhook_payload =
+        serde_json::from_str::<StripeWebhookPayload>(include_str!("fixtures/invoice.paid.json"))
+            .unwrap();
+
+    let client = FakeClient::default();
+    client.respond_cursor(json!([null]));
+
+    let pool = get_database_connection_pool_fake(&client);
+    assert!(record_webhook_call(&pool, &webhook_payload)
+        .await
+        .unwrap()
+        .is_none());
+}
@@ -0,0 +1,51 @@
+use crate::arango::ConnectionPool;
+use crate::commerce::api::{OrderLookup, OrderStatus};
+use crate::stripe::{Charge, Dispute};
+
+/// Processes `charge.refunded` webhook from Stripe.com. The charge is related to our order only
+/// via its payment intent (there is no checkout session ID).
+pub(crate) async fn refunded(pool: &ConnectionPool, data: &Charge) -> anyhow::Result<()> {
+    let (order_lookup, new_order_status) = resolve_refunded(data)?;
+    super::update_order_status(pool, &order_lookup, &new_order_status).await
+}
+
+/// Processes `charge.dispute.created` webhook from Stripe.com. Disputed orders should not be
+/// fulfilled (if they weren't already).
+pub(crate) async fn dispute_created(pool: &ConnectionPool, data: &Dispute) -> anyhow::Result<()> {
+    let (order_lookup, new_order_status) = resolve_dispute_created(data)?;
+    super::update_order_status(pool, &order_lookup, &new_order_status).await
+
+    // TODO: send email to us
+}
+
+pub(in crate::stripe) fn resolve_refunded(
+    data: &Charge,
+) -> anyhow::Result<(OrderLookup, OrderStatus)> {
+    let order_lookup = match &data.payment_intent {
+        Some(stripe_payment_intent_id) => {
+            OrderLookup::StripePaymentIntentId(stripe_payment_intent_id.to_owned())
+        }
+        None => anyhow::bail!("Stripe charge '{}' has no payment intent.", data.id),
+    };
+
+    // `refunded` is true only when the whole charge was refunded
+    let new_order_status = if data.refunded {
+        OrderStatus::Refunded
+    } else {
+        OrderStatus::PartiallyRefunded
+    };
+
+    Ok((order_lookup, new_order_status))
+}
+
+pub(in crate::stripe) fn resolve_dispute_created(
+    data: &Dispute,
+) -> anyhow::Result<(OrderLookup, OrderStatus)> {
+    match &data.payment_intent {
+        Some(stripe_payment_intent_id) => Ok((
+            OrderLookup::StripePaymentIntentId(stripe_payment_intent_id.to_owned()),
+            OrderStatus::Disputed,
+        )),
+        None => anyhow::bail!("Stripe dispute '{}' has no payment intent.", data.id),
+    }
+}
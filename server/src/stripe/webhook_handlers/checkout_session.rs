@@ -1,4 +1,6 @@
 use crate::arango::ConnectionPool;
+use crate::commerce::api::{OrderLookup, OrderStatus};
+use crate::stripe::checkout::CheckoutSessionPaymentStatus;
 use crate::stripe::CheckoutSession;
 
 /// Processes `checkout.session.completed` webhook from Stripe.com.
@@ -9,43 +11,113 @@ use crate::stripe::CheckoutSession;
 ///  - send email to us about new order to be fulfilled
 ///
 /// See: https://stripe.com/docs/payments/checkout/fulfill-orders#fulfill
-pub(crate) async fn completed(
-    _pool: &ConnectionPool,
+pub(crate) async fn completed(pool: &ConnectionPool, data: &CheckoutSession) -> anyhow::Result<()> {
+    let stripe_checkout_session_id = match &data.id {
+        Some(stripe_checkout_session_id) => stripe_checkout_session_id,
+        None => anyhow::bail!("Stripe checkout session ID is missing in the webhook payload."),
+    };
+
+    let new_order_status = resolve_order_status(&data.payment_status)?;
+
+    // Only orders awaiting payment are updated so repeated webhook calls do not change anything.
+    match crate::commerce::api::complete_order(
+        pool,
+        stripe_checkout_session_id,
+        &data.payment_intent,
+        &new_order_status,
+        &data.amount_total,
+        &data.amount_subtotal,
+    )
+    .await?
+    {
+        Some(_) => {
+            tracing::info!(
+                "Order with Stripe checkout session '{}' was moved to '{:?}' status.",
+                stripe_checkout_session_id,
+                new_order_status
+            );
+        }
+        None => {
+            // Careful with donations and similar payments - not every checkout session is our order.
+            tracing::warn!(
+                "There is no order awaiting payment for Stripe checkout session '{}' (ignoring).",
+                stripe_checkout_session_id
+            );
+        }
+    }
+
+    // TODO: send email to our customer
+    // TODO: send email to us
+
+    Ok(())
+}
+
+/// Converts Stripe checkout session payment status to our order status. Unpaid checkout sessions
+/// are still valid: delayed payment methods (OXXO) are confirmed later via async payment webhooks.
+pub(in crate::stripe) fn resolve_order_status(
+    payment_status: &Option<CheckoutSessionPaymentStatus>,
+) -> anyhow::Result<OrderStatus> {
+    match payment_status {
+        Some(CheckoutSessionPaymentStatus::Paid) => Ok(OrderStatus::Paid),
+        Some(CheckoutSessionPaymentStatus::NoPaymentRequired) => Ok(OrderStatus::Paid),
+        Some(CheckoutSessionPaymentStatus::Unpaid) => Ok(OrderStatus::Unpaid),
+        None => anyhow::bail!("Stripe checkout session payment status is missing."),
+    }
+}
+
+/// Processes `checkout.session.async_payment_succeeded` webhook from Stripe.com. This webhook is
+/// sent when the delayed payment (OXXO) of previously completed checkout session arrives.
+pub(crate) async fn async_payment_succeeded(
+    pool: &ConnectionPool,
     data: &CheckoutSession,
 ) -> anyhow::Result<()> {
-    // TODO: update our order in the database (careful with donations - not everything is an order)
+    let (order_lookup, new_order_status) = resolve_async_payment_succeeded(data)?;
+    super::update_order_status(pool, &order_lookup, &new_order_status).await
+
     // TODO: send email to our customer
     // TODO: send email to us
+}
+
+/// Processes `checkout.session.async_payment_failed` webhook from Stripe.com. This webhook is
+/// sent when the delayed payment (OXXO) of previously completed checkout session never arrives.
+pub(crate) async fn async_payment_failed(
+    pool: &ConnectionPool,
+    data: &CheckoutSession,
+) -> anyhow::Result<()> {
+    let (order_lookup, new_order_status) = resolve_async_payment_failed(data)?;
+    super::update_order_status(pool, &order_lookup, &new_order_status).await
+}
 
-    // [server/src/stripe/webhook_handlers/checkout_session.rs:17] &data = CheckoutSession {
-    //     id: Some(
-    //         "cs_test_a171PaJrYTDxKEefgP2Vk4mrcAlwzZuI8lA8912xjc5g4LJP9bb7ocPCMa",
-    //     ),
-    //     amount_total: Some(
-    //         3000,
-    //     ),
-    //     amount_subtotal: Some(
-    //         3000,
-    //     ),
-    //     currency: Some(
-    //         Usd,
-    //     ),
-    //     success_url: "https://httpbin.org/post",
-    //     cancel_url: "https://httpbin.org/post",
-    //     mode: Payment,
-    //     payment_method_types: [
-    //         Card,
-    //     ],
-    //     payment_status: Some(
-    //         Paid,
-    //     ),
-    //     line_items: None,
-    //     locale: None,
-    //     shipping_rates: None,
-    //     shipping_address_collection: None,
-    //     url: None,
-    // }
-    dbg!(&data);
+/// Processes `checkout.session.expired` webhook from Stripe.com. This webhook is sent when the
+/// customer didn't complete the checkout session on time (abandoned carts).
+pub(crate) async fn expired(pool: &ConnectionPool, data: &CheckoutSession) -> anyhow::Result<()> {
+    let (order_lookup, new_order_status) = resolve_expired(data)?;
+    super::update_order_status(pool, &order_lookup, &new_order_status).await
+}
 
-    Ok(())
+pub(in crate::stripe) fn resolve_async_payment_succeeded(
+    data: &CheckoutSession,
+) -> anyhow::Result<(OrderLookup, OrderStatus)> {
+    Ok((resolve_order_lookup(data)?, OrderStatus::Paid))
+}
+
+pub(in crate::stripe) fn resolve_async_payment_failed(
+    data: &CheckoutSession,
+) -> anyhow::Result<(OrderLookup, OrderStatus)> {
+    Ok((resolve_order_lookup(data)?, OrderStatus::PaymentFailed))
+}
+
+pub(in crate::stripe) fn resolve_expired(
+    data: &CheckoutSession,
+) -> anyhow::Result<(OrderLookup, OrderStatus)> {
+    Ok((resolve_order_lookup(data)?, OrderStatus::Expired))
+}
+
+fn resolve_order_lookup(data: &CheckoutSession) -> anyhow::Result<OrderLookup> {
+    match &data.id {
+        Some(stripe_checkout_session_id) => Ok(OrderLookup::StripeCheckoutSessionId(
+            stripe_checkout_session_id.to_owned(),
+        )),
+        None => anyhow::bail!("Stripe checkout session ID is missing in the webhook payload."),
+    }
 }
@@ -1 +1,104 @@
+use crate::arango::ConnectionPool;
+use crate::commerce::api::{OrderLookup, OrderStatus};
+use crate::stripe::webhook::{StripeWebhookPayload, StripeWebhookType};
+use crate::stripe::{Charge, CheckoutSession, Dispute};
+use serde::de::DeserializeOwned;
+
+pub(crate) mod charge;
 pub(crate) mod checkout_session;
+
+/// Registry of all Stripe webhook handlers. Every supported webhook type is routed to its handler
+/// here (with the payload object converted to the appropriate struct). Webhook types without any
+/// handler are ignored.
+///
+/// To support a new webhook type, make sure it's enabled in Stripe.com dashboard as well.
+pub(crate) async fn handle_webhook(
+    pool: &ConnectionPool,
+    payload: &StripeWebhookPayload,
+) -> anyhow::Result<()> {
+    match payload.r#type {
+        StripeWebhookType::CheckoutSessionCompleted => {
+            checkout_session::completed(pool, &parse_object::<CheckoutSession>(payload)?).await
+        }
+        StripeWebhookType::CheckoutSessionAsyncPaymentSucceeded => {
+            checkout_session::async_payment_succeeded(
+                pool,
+                &parse_object::<CheckoutSession>(payload)?,
+            )
+            .await
+        }
+        StripeWebhookType::CheckoutSessionAsyncPaymentFailed => {
+            checkout_session::async_payment_failed(pool, &parse_object::<CheckoutSession>(payload)?)
+                .await
+        }
+        StripeWebhookType::CheckoutSessionExpired => {
+            checkout_session::expired(pool, &parse_object::<CheckoutSession>(payload)?).await
+        }
+        StripeWebhookType::ChargeRefunded => {
+            charge::refunded(pool, &parse_object::<Charge>(payload)?).await
+        }
+        StripeWebhookType::ChargeDisputeCreated => {
+            charge::dispute_created(pool, &parse_object::<Dispute>(payload)?).await
+        }
+        _ => {
+            tracing::warn!(
+                "Stripe webhook with type '{:?}' was called but there is no handler for it (ignoring).",
+                payload.r#type
+            );
+            Ok(())
+        }
+    }
+}
+
+fn parse_object<T: DeserializeOwned>(payload: &StripeWebhookPayload) -> anyhow::Result<T> {
+    match serde_json::from_value::<T>(payload.data.object.to_owned()) {
+        Ok(object) => Ok(object),
+        Err(error) => anyhow::bail!(
+            "Unable to parse Stripe webhook object of type '{:?}': {}",
+            payload.r#type,
+            error
+        ),
+    }
+}
+
+/// Common status update of our order for all the webhook handlers. Not allowed status changes are
+/// not an error (redelivered or out of order webhooks). Missing orders are not an error either when
+/// looking them up by the checkout session: not every Stripe.com payment is our order (donations,
+/// subscriptions, …).
+///
+/// However, the payment intent ID is assigned to the order only by `checkout.session.completed`
+/// webhook and Stripe doesn't guarantee the order of webhooks. So when there is no order with the
+/// payment intent (yet), we return an error and Stripe retries the webhook later (for up to
+/// 3 days, so charges unrelated to our orders are eventually given up).
+async fn update_order_status(
+    pool: &ConnectionPool,
+    order_lookup: &OrderLookup,
+    new_order_status: &OrderStatus,
+) -> anyhow::Result<()> {
+    match crate::commerce::api::update_order_status(pool, order_lookup, new_order_status).await? {
+        Some(_) => {
+            tracing::info!(
+                "Order with {:?} was moved to '{:?}' status.",
+                order_lookup,
+                new_order_status
+            );
+        }
+        None => {
+            if let OrderLookup::StripePaymentIntentId(_) = order_lookup {
+                if !crate::commerce::api::order_exists(pool, order_lookup).await? {
+                    anyhow::bail!(
+                        "There is no order with {:?} (yet) which could be moved to '{:?}' status.",
+                        order_lookup,
+                        new_order_status
+                    );
+                }
+            }
+            tracing::warn!(
+                "There is no order with {:?} which could be moved to '{:?}' status (ignoring).",
+                order_lookup,
+                new_order_status
+            );
+        }
+    }
+    Ok(())
+}